@@ -27,7 +27,6 @@ limitations under the License.
 
 const DEBUG: bool = false;
 
-use ignore::gitignore;
 use serde_json;
 use std::env;
 use std::path::Path;
@@ -37,15 +36,23 @@ use crate::buffer_state::BufferOpenMode;
 use crate::buffer_state::BufferState;
 use crate::buffer_state::BufferStateS;
 use crate::buffer_state_observer::BufferStateObserver;
+use crate::folding::FoldRange;
 use crate::fuzzy_index::FuzzyIndex;
 use crate::fuzzy_index_trait::FuzzyIndexTrait;
-use crate::fuzzy_view_item::file_list_to_items;
+use crate::fuzzy_view_item::{file_list_to_items, ViewItem};
+use crate::file_index_cache;
+use crate::file_index_cache::{CacheKey, CachedIndex};
+use crate::file_indexer;
+use crate::file_watcher::FileWatcher;
+use crate::interface::InterfaceNotifier;
 
 use crate::content_provider;
 use crate::content_provider::RopeBasedContentProvider;
+use crate::disk_check;
+use crate::disk_check::{DiskChange, DiskCheck};
 use cursive;
 use std::cell::{Ref, RefCell};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error;
 use std::io;
 use std::io::Write;
@@ -58,11 +65,23 @@ use core::borrow::Borrow;
 use crate::dir_tree::TreeNode;
 use crate::dir_tree::TreeNodeRef;
 use crate::settings::Settings;
+use crate::syntax_loader::SyntaxLoader;
+use crate::syntax_loader::PLAIN_TEXT_SYNTAX;
+use crate::recovery;
+use crate::recovery::RecoveryRecord;
+use crate::safe_save;
+use crate::session;
+use crate::session::Session;
+use crate::text_diff::TextDiff;
+use crate::utils::{config_dir, expand_home, file_stamp, is_read_only, FileStamp};
+use ropey::Rope;
 use std::cell::Cell;
 use std::collections::VecDeque;
 use std::io::Error;
 use std::path::PathBuf;
 use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
 use crate::view_handle::ViewHandle;
@@ -83,8 +102,42 @@ pub struct AppState {
     directories: Vec<PathBuf>,
     /* it's a straigthforward copy of arguments used to guess "workspace" parameter for
      * languageserver */
+    enable_gitignore: bool,
     loaded_buffers: HashMap<BufferId, BufferStateRef>,
+    // ids of loaded_buffers in order of opening.
+    buffer_order: Vec<BufferId>,
+    // ids of loaded_buffers, most recently focused first. Never focused ones are at the end.
+    buffers_by_focus: Vec<BufferId>,
     settings: Rc<RefCell<Settings>>,
+    syntax_loader: Rc<SyntaxLoader>,
+    inot_op: Option<InterfaceNotifier>,
+    // None disables recovery files.
+    recovery_dir: Option<PathBuf>,
+    // text read from standard input ("-" on command line), it becomes the first buffer.
+    stdin_text: Option<String>,
+    // (line, column) cursor is placed at in files given on command line, both counted from 1.
+    start_positions: HashMap<PathBuf, (usize, usize)>,
+    // None disables sessions (see session.rs).
+    session_path: Option<PathBuf>,
+    // state of files on disk as of last load, save or noticed change, None if file is missing.
+    disk_stamps: HashMap<BufferId, Option<FileStamp>>,
+    // running disk_check worker, see start_disk_check.
+    disk_check_worker: Option<usize>,
+    // None disables file index cache (see file_index_cache.rs).
+    file_index_cache_dir: Option<PathBuf>,
+    // files of project directories as read from cache, until their indexing is finished.
+    cached_files: HashMap<PathBuf, HashSet<PathBuf>>,
+    // files of project directories found by file indexer so far.
+    indexed_files: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+/// File of a buffer changed on disk, see AppState::apply_disk_changes.
+pub enum ExternalChange {
+    /// Buffer was not modified, so it was reloaded. Old content and diff are there for views to
+    /// adjust cursors.
+    Reloaded(BufferId, Rope, TextDiff),
+    /// Buffer was modified, user has to decide what to do.
+    Conflict(BufferId),
 }
 
 impl AppState {
@@ -93,7 +146,7 @@ impl AppState {
         // TODO(njskalski): add cache.
 
         let observers: Vec<BufferStateObserver> =
-            self.loaded_buffers.values().map(|v| BufferStateObserver::new(v.clone())).collect();
+            self.mru_buffers().iter().filter_map(|id| self.buffer_obs(id)).collect();
 
         Arc::new(RefCell::new(BufferIndex::new(observers)))
     }
@@ -102,9 +155,22 @@ impl AppState {
         &self.filesystem
     }
 
-    /// Returns list of buffers. Rather stable.
+    /// Returns list of buffers, in order of opening.
     pub fn get_buffers(&self) -> Vec<BufferId> {
-        self.loaded_buffers.keys().map(|k| k.clone()).collect()
+        self.buffer_order.clone()
+    }
+
+    /// Returns list of buffers, most recently focused first.
+    pub fn mru_buffers(&self) -> Vec<BufferId> {
+        self.buffers_by_focus.clone()
+    }
+
+    /// Records that buffer was focused, see mru_buffers.
+    pub fn mark_focused(&mut self, id: &BufferId) {
+        if let Some(pos) = self.buffers_by_focus.iter().position(|other| other == id) {
+            let id = self.buffers_by_focus.remove(pos);
+            self.buffers_by_focus.insert(0, id);
+        }
     }
 
     /// Returns list of BufferIds associated with given path.
@@ -126,6 +192,82 @@ impl AppState {
         self.file_index.clone()
     }
 
+    /// Returns watcher of files in project directory root, that starts from known files (found by
+    /// indexer), see file_watcher.rs.
+    pub fn file_watcher(&self, root: &Path, known: Vec<PathBuf>) -> FileWatcher {
+        FileWatcher::new(
+            self.filesystem.clone(),
+            root.to_path_buf(),
+            self.enable_gitignore,
+            self.settings_ref().file_index_limit(),
+            known,
+        )
+    }
+
+    /// Starts indexing project directories in background, see file_indexer.rs. Returns ids of
+    /// workers.
+    pub fn start_indexing(&self, inot: InterfaceNotifier) -> Vec<usize> {
+        file_indexer::start(
+            self.filesystem.clone(),
+            self.directories.clone(),
+            self.enable_gitignore,
+            self.settings_ref().file_index_limit(),
+            inot,
+        )
+    }
+
+    /// Adds files found by file indexer in root to file index.
+    pub fn add_indexed_files(&mut self, root: &Path, files: Vec<PathBuf>) {
+        self.apply_file_changes(&files, &Vec::new());
+        self.indexed_files.entry(root.to_path_buf()).or_default().extend(files);
+    }
+
+    /// Indexing of root is done. Cached files that were not found are removed from file index,
+    /// and if anything changed since cache was written, it's written again. Returns files found.
+    pub fn finish_indexing(&mut self, root: &Path) -> Vec<PathBuf> {
+        let found: Vec<PathBuf> = self.indexed_files.remove(root).unwrap_or_default();
+        let cached: HashSet<PathBuf> = self.cached_files.remove(root).unwrap_or_default();
+
+        let found_set: HashSet<&PathBuf> = found.iter().collect();
+        let removed: Vec<PathBuf> =
+            cached.iter().filter(|path| !found_set.contains(path)).cloned().collect();
+        let unchanged = removed.is_empty() && found_set.len() == cached.len();
+
+        if !removed.is_empty() {
+            self.apply_file_changes(&Vec::new(), &removed);
+        }
+        if unchanged {
+            return found;
+        }
+        if let Some(ref dir) = self.file_index_cache_dir {
+            let key = file_index_cache_key(root, self.enable_gitignore, &self.settings_ref());
+            file_index_cache::write_cache_in_background(
+                self.filesystem.clone(),
+                dir.clone(),
+                key,
+                found.clone(),
+            );
+        }
+        found
+    }
+
+    /// Sets status displayed in file bar, like indexing progress.
+    pub fn set_file_index_status(&mut self, status: Option<String>) {
+        self.file_index.borrow_mut().set_status(status);
+    }
+
+    /// Updates file index with files created and removed on disk.
+    pub fn apply_file_changes(&mut self, created: &Vec<PathBuf>, removed: &Vec<PathBuf>) {
+        let removed_markers: Vec<String> =
+            removed.iter().map(|path| path.to_string_lossy().to_string()).collect();
+        self.file_index.borrow_mut().update(file_list_to_items(created), removed_markers);
+    }
+
+    /// Rebuilds file index if it collected changes long enough ago, see FuzzyIndex::update.
+    pub fn refresh_file_index(&mut self, now: Instant) {
+        self.file_index.borrow_mut().rebuild_if_due(now);
+    }
+
     pub fn get_dir_tree(&self) -> TreeNodeRef {
         self.dir_and_files_tree.clone()
     }
@@ -139,15 +281,356 @@ impl AppState {
     }
 
     pub fn save_buffer(&mut self, id: &BufferId) -> Result<(), io::Error> {
-        let buffer_ptr = self.loaded_buffers.get(id).unwrap();
-        let mut buffer = (**buffer_ptr).borrow_mut();
-        buffer.save(&self.filesystem, None)
+        let backup = self.settings_ref().get_bool("files/backup_on_save");
+        {
+            let buffer_ptr = self.loaded_buffers.get(id).unwrap();
+            let mut buffer = (**buffer_ptr).borrow_mut();
+            buffer.save(&self.filesystem, None, backup)?;
+        }
+        self.on_buffer_saved(id);
+        Ok(())
     }
 
     pub fn save_buffer_as(&mut self, id: &BufferId, path: PathBuf) -> Result<(), io::Error> {
-        let buffer_ptr = self.loaded_buffers.get(id).unwrap();
-        let mut buffer = (**buffer_ptr).borrow_mut();
-        buffer.save(&self.filesystem, Some(path))
+        let backup = self.settings_ref().get_bool("files/backup_on_save");
+        {
+            let buffer_ptr = self.loaded_buffers.get(id).unwrap();
+            let mut buffer = (**buffer_ptr).borrow_mut();
+            buffer.save(&self.filesystem, Some(path), backup)?;
+        }
+        self.on_buffer_saved(id);
+        Ok(())
+    }
+
+    /// Content on disk is up to date now, so there is nothing to recover.
+    fn on_buffer_saved(&mut self, id: &BufferId) {
+        self.update_disk_stamp(id);
+        if let Some(ref dir) = self.recovery_dir {
+            recovery::remove_record(&self.filesystem, dir, id);
+        }
+    }
+
+    /// Remembers current state of buffer's file on disk, so only later changes are noticed.
+    fn update_disk_stamp(&mut self, id: &BufferId) {
+        let path_op = self.loaded_buffers.get(id).and_then(|b| (**b).borrow().get_path());
+        match path_op {
+            Some(path) => {
+                let stamp = file_stamp(&self.filesystem, &path);
+                self.disk_stamps.insert(id.clone(), stamp);
+            }
+            None => {
+                self.disk_stamps.remove(id);
+            }
+        }
+    }
+
+    /// Starts looking for files changed on disk since they were loaded, saved or last checked,
+    /// see disk_check.rs. Returns id of the worker, or None if previous check is still running.
+    pub fn start_disk_check(&mut self, inot: InterfaceNotifier) -> Option<usize> {
+        if self.disk_check_worker.is_some() {
+            return None;
+        }
+
+        let mut checks: Vec<DiskCheck> = Vec::new();
+        for (id, stamp) in self.disk_stamps.iter() {
+            let buffer = match self.loaded_buffers.get(id) {
+                Some(buffer) => (**buffer).borrow(),
+                None => continue,
+            };
+            let path = match buffer.get_path() {
+                Some(path) => path,
+                None => continue,
+            };
+            let follows_file = !buffer.modified() && !buffer.is_binary();
+            let content_op = if follows_file {
+                let content = buffer.get_content();
+                Some((content.version(), content.get_lines().clone()))
+            } else {
+                None
+            };
+            checks.push(DiskCheck {
+                buffer_id: id.clone(),
+                path,
+                stamp: stamp.clone(),
+                content: content_op,
+            });
+        }
+
+        let worker_id = disk_check::start(self.filesystem.clone(), checks, inot);
+        self.disk_check_worker = Some(worker_id);
+        Some(worker_id)
+    }
+
+    /// Applies results of check started by start_disk_check. Unmodified buffers are reloaded,
+    /// modified ones are marked out of sync and reported as conflicts. Removed files only mark
+    /// buffers out of sync. Buffers saved, reloaded or edited since the check started are left
+    /// for the next one.
+    pub fn apply_disk_changes(&mut self, changes: Vec<DiskChange>) -> Vec<ExternalChange> {
+        self.disk_check_worker = None;
+
+        let mut result: Vec<ExternalChange> = Vec::new();
+        for change in changes {
+            let id = change.buffer_id;
+            let buffer = match self.loaded_buffers.get(&id) {
+                Some(buffer) => buffer.clone(),
+                None => continue,
+            };
+            if self.disk_stamps.get(&id) != Some(&change.old_stamp) {
+                continue;
+            }
+
+            let mut buffer = (*buffer).borrow_mut();
+            if change.new_stamp.is_none() || buffer.is_binary() || buffer.modified() {
+                buffer.set_out_of_sync(true);
+                if change.new_stamp.is_some() && buffer.modified() {
+                    result.push(ExternalChange::Conflict(id.clone()));
+                }
+                self.disk_stamps.insert(id, change.new_stamp);
+                continue;
+            }
+
+            match change.reload {
+                Some(reload) => {
+                    if reload.version != buffer.get_content().version() {
+                        // edited and undone since, diff does not fit.
+                        continue;
+                    }
+                    let (old, diff) =
+                        buffer.reload_text_with_diff(&reload.text, reload.bom, reload.diff);
+                    result.push(ExternalChange::Reloaded(id.clone(), old, diff));
+                }
+                None => buffer.set_out_of_sync(true),
+            }
+            self.disk_stamps.insert(id, change.new_stamp);
+        }
+
+        result
+    }
+
+    /// Replaces content of buffer with file on disk, discarding modifications ("take theirs").
+    pub fn reload_buffer(&mut self, id: &BufferId) -> Option<(Rope, TextDiff)> {
+        let buffer = self.loaded_buffers.get(id)?.clone();
+        let path = (*buffer).borrow().get_path()?;
+        let (text, bom) = self.read_text(&path)?;
+        let result = (*buffer).borrow_mut().reload_text(&text, bom);
+        self.update_disk_stamp(id);
+        Some(result)
+    }
+
+    /// Returns unified diff between buffer content and file on disk.
+    pub fn diff_with_disk(&self, id: &BufferId) -> Option<String> {
+        let buffer = self.loaded_buffers.get(id)?;
+        let buffer = (**buffer).borrow();
+        let path = buffer.get_path()?;
+        let (text, _) = self.read_text(&path)?;
+
+        let mine = buffer.get_content().get_lines();
+        let theirs = Rope::from_str(&text);
+        let name = path.to_string_lossy();
+        Some(TextDiff::new(mine, &theirs).unified(
+            mine,
+            &theirs,
+            &format!("{} (buffer)", name),
+            &format!("{} (disk)", name),
+            3,
+        ))
+    }
+
+    /// Reads file as text, returns None if it's missing or not UTF-8.
+    fn read_text(&self, path: &Path) -> Option<(String, bool)> {
+        disk_check::read_text(&self.filesystem, path)
+    }
+
+    /// Saves all modified buffers that have a path and can be written. Returns errors along with
+    /// ids of buffers that failed to save.
+    pub fn autosave_all(&mut self) -> Vec<(BufferId, io::Error)> {
+        let ids: Vec<BufferId> = self.loaded_buffers.keys().cloned().collect();
+        let mut errors: Vec<(BufferId, io::Error)> = Vec::new();
+        for id in ids {
+            if let Err(e) = self.autosave_buffer(&id) {
+                errors.push((id, e));
+            }
+        }
+        errors
+    }
+
+    /// Saves buffer if it's modified, has a path and can be written, does nothing otherwise.
+    pub fn autosave_buffer(&mut self, id: &BufferId) -> Result<(), io::Error> {
+        let should_save = match self.loaded_buffers.get(id) {
+            Some(buffer) => {
+                let buffer = (**buffer).borrow();
+                buffer.modified()
+                    && buffer.get_path().is_some()
+                    && !buffer.read_only()
+                    && !buffer.is_binary()
+            }
+            None => false,
+        };
+
+        if should_save {
+            self.save_buffer(id)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes recovery files of all modified buffers, and removes ones of buffers that are no
+    /// longer modified.
+    pub fn write_recovery_files(&self) {
+        let dir = match self.recovery_dir {
+            Some(ref dir) => dir,
+            None => return,
+        };
+
+        for (id, buffer) in self.loaded_buffers.iter() {
+            let buffer = (**buffer).borrow();
+            if buffer.modified() && !buffer.is_binary() {
+                let record = RecoveryRecord::new(
+                    buffer.serializable_state().clone(),
+                    buffer.get_content().get_lines().to_string(),
+                );
+                if let Err(e) = recovery::write_record(&self.filesystem, dir, id, &record) {
+                    warn!("failed to write recovery file of {}, because \"{}\"", id, e);
+                }
+            } else {
+                recovery::remove_record(&self.filesystem, dir, id);
+            }
+        }
+    }
+
+    /// Recovery files are disabled under test, unless a directory is set here.
+    #[cfg(test)]
+    pub fn set_recovery_dir(&mut self, dir: PathBuf) {
+        self.recovery_dir = Some(dir);
+    }
+
+    /// File index cache is disabled under test, unless a directory is set here. Caches found
+    /// there replace file index, like they would on start.
+    #[cfg(test)]
+    pub fn set_file_index_cache_dir(&mut self, dir: PathBuf) {
+        let (cached_indexes, cached_files) = read_file_index_caches(
+            &self.filesystem,
+            &dir,
+            &self.directories,
+            self.enable_gitignore,
+            &self.settings_ref(),
+        );
+        let files: Vec<PathBuf> = self.buffers_to_load.iter().cloned().collect();
+        *self.file_index.borrow_mut() = initial_file_index(cached_indexes, &files);
+        self.cached_files = cached_files;
+        self.file_index_cache_dir = Some(dir);
+    }
+
+    /// Removes recovery files of all loaded buffers. Called on clean exit.
+    pub fn remove_recovery_files(&self) {
+        if let Some(ref dir) = self.recovery_dir {
+            for id in self.loaded_buffers.keys() {
+                recovery::remove_record(&self.filesystem, dir, id);
+            }
+        }
+    }
+
+    /// Returns recovery files left by previous sessions that hold content newer than files on
+    /// disk. Outdated ones are removed, ones of sly processes still running are skipped.
+    pub fn find_recovery_files(&self) -> Vec<(PathBuf, RecoveryRecord)> {
+        let dir = match self.recovery_dir {
+            Some(ref dir) => dir,
+            None => return Vec::new(),
+        };
+
+        let mut result: Vec<(PathBuf, RecoveryRecord)> = Vec::new();
+        for (path, record) in recovery::read_records(&self.filesystem, dir) {
+            if record.is_owner_alive() {
+                debug!("skipping recovery file {:?} of running process {}", &path, record.pid);
+                continue;
+            }
+            if record.is_newer_than_file(&self.filesystem) {
+                result.push((path, record));
+            } else {
+                debug!("removing outdated recovery file {:?}", &path);
+                self.discard_recovery_file(&path);
+            }
+        }
+        result
+    }
+
+    /// Opens buffer from recovery file, and removes the file (new one is written for the new
+    /// buffer, as it's modified). Returns None if file is gone, that is other sly process
+    /// restored or discarded it meanwhile.
+    pub fn restore_from_recovery(
+        &mut self,
+        path: &Path,
+        record: RecoveryRecord,
+    ) -> Option<BufferId> {
+        if !self.discard_recovery_file(path) {
+            return None;
+        }
+        let buffer =
+            BufferState::from_recovery(record.state, &record.content, &self.syntax_loader);
+        Some(self.add_buffer(Rc::new(RefCell::new(buffer))))
+    }
+
+    /// Returns false if file could not be removed. Removal is what claims a record, only one
+    /// process succeeds.
+    pub fn discard_recovery_file(&self, path: &Path) -> bool {
+        match self.filesystem.remove_file(path) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("failed to remove recovery file {:?}, because \"{}\"", path, e);
+                false
+            }
+        }
+    }
+
+    /// Starts saving buffer with helper from "files/privileged_save_command" (like "sudo -A tee"),
+    /// for files user has no permission to write. Password is handed to helper through askpass.
+    /// Returns id of the worker, it ends with IEvent::PrivilegedSaved, and finish_privileged_save
+    /// is to be called then.
+    pub fn start_privileged_save(
+        &mut self,
+        id: &BufferId,
+        path: Option<PathBuf>,
+        password: Option<String>,
+        inot: InterfaceNotifier,
+    ) -> Result<usize, io::Error> {
+        let command = self.settings_ref().get_string_list("files/privileged_save_command");
+        let (path, version, bytes) = match self.loaded_buffers.get(id) {
+            Some(buffer) => (**buffer).borrow().privileged_save_content(path)?,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "Buffer is closed.")),
+        };
+        let worker_id = safe_save::start_privileged_save(
+            command,
+            id.clone(),
+            path,
+            version,
+            bytes,
+            password,
+            inot,
+        );
+        Ok(worker_id)
+    }
+
+    /// Applies result of worker started by start_privileged_save. Buffer stays modified if helper
+    /// failed, or if it was edited meanwhile.
+    pub fn finish_privileged_save(
+        &mut self,
+        id: &BufferId,
+        path: PathBuf,
+        version: usize,
+        result: Result<(), String>,
+    ) -> Result<(), String> {
+        result?;
+        let saved = match self.loaded_buffers.get(id) {
+            Some(buffer) => (**buffer).borrow_mut().finish_privileged_save(path, version),
+            // closed while saving.
+            None => return Ok(()),
+        };
+        if saved {
+            self.on_buffer_saved(id);
+        } else {
+            self.update_disk_stamp(id);
+        }
+        Ok(())
     }
 
     /// As of this time, it does not re-open file that is already opened, just returns buffer id
@@ -172,10 +655,222 @@ impl AppState {
     fn open_file(&mut self, path: &Path) -> Result<BufferId, io::Error> {
         // TODO(njskalski): add delayed load (promise)
         let autohighlight: bool = self.settings_ref().auto_highlighting_enabled();
-        let buffer = Rc::new(RefCell::new(BufferState::open(&self.filesystem, path, ExistPolicy::MustExist)?));
+        let buffer = Rc::new(RefCell::new(BufferState::open(
+            &self.filesystem,
+            path,
+            ExistPolicy::MustExist,
+            &self.syntax_loader,
+        )?));
+        Ok(self.add_buffer(buffer))
+    }
+
+    /// Opens all files given on command line, except the first one (see get_first_buffer).
+    /// Returns them along with results.
+    pub fn open_scheduled_files(&mut self) -> Vec<(PathBuf, Result<BufferId, io::Error>)> {
+        let mut result: Vec<(PathBuf, Result<BufferId, io::Error>)> = Vec::new();
+        while let Some(file_path) = self.buffers_to_load.pop_front() {
+            let buffer_id_res = self.open_given_file(&file_path);
+            result.push((file_path, buffer_id_res));
+        }
+        result
+    }
+
+    /// Opens file given on command line. Missing file becomes a new (empty) buffer, and big one
+    /// is read in background (see open_file_in_background).
+    fn open_given_file(&mut self, path: &Path) -> Result<BufferId, io::Error> {
+        let background_min_bytes = self.settings_ref().get_usize("files/background_load_min_bytes");
+        let is_big = file_stamp(&self.filesystem, path)
+            .map(|stamp| stamp.len >= background_min_bytes as u64)
+            == Some(true);
+
+        if is_big {
+            if let Some(inot) = self.inot_op.clone() {
+                return Ok(self.open_file_in_background(path, inot));
+            }
+        }
+
+        let buffer = BufferState::open(
+            &self.filesystem,
+            path,
+            ExistPolicy::CanExist,
+            &self.syntax_loader,
+        )?;
+        Ok(self.add_buffer(Rc::new(RefCell::new(buffer))))
+    }
+
+    /// Creates placeholder buffer of path and reads the file on a separate thread. Interface gets
+    /// IEvent::FileLoaded when it's done, and calls finish_loading.
+    fn open_file_in_background(&mut self, path: &Path, inot: InterfaceNotifier) -> BufferId {
+        let id = self.add_buffer(Rc::new(RefCell::new(BufferState::loading_placeholder(path))));
+
+        let fs = self.filesystem.clone();
+        let path = path.to_owned();
+        let buffer_id = id.clone();
+        thread::spawn(move || {
+            let result = fs.read_file(&path).map_err(|e| e.to_string());
+            inot.file_loaded(buffer_id, result);
+        });
+
+        id
+    }
+
+    /// Puts content read by open_file_in_background into placeholder buffer. Returns error if
+    /// file could not be read, placeholder is to be closed then.
+    pub fn finish_loading(
+        &mut self,
+        id: &BufferId,
+        result: Result<Vec<u8>, String>,
+    ) -> Result<(), String> {
+        let bytes = result?;
+        let buffer = match self.loaded_buffers.get(id) {
+            // closed while loading.
+            None => return Ok(()),
+            Some(buffer) => buffer.clone(),
+        };
+
+        let path = (*buffer).borrow().get_path().unwrap_or_default();
+        let read_only = is_read_only(&self.filesystem, &path);
+        (*buffer).borrow_mut().finish_loading(bytes, read_only, &self.syntax_loader);
+        self.prepare_buffer(&buffer);
+        self.update_disk_stamp(id);
+        Ok(())
+    }
+
+    /// Sets text read from standard input. It's opened as the first (unnamed) buffer, before
+    /// files given on command line.
+    pub fn set_stdin_text(&mut self, text: String) {
+        self.stdin_text = Some(text);
+    }
+
+    /// Sets where cursor is placed once file given on command line is opened.
+    pub fn set_start_position(&mut self, path: PathBuf, line: usize, column: usize) {
+        self.start_positions.insert(path, (line, column));
+    }
+
+    /// Returns (line, column) given on command line for file of buffer, both counted from 1.
+    /// It's given out once.
+    pub fn take_start_position(&mut self, id: &BufferId) -> Option<(usize, usize)> {
+        let path = self.loaded_buffers.get(id).and_then(|b| (**b).borrow().get_path())?;
+        self.start_positions.remove(&path)
+    }
+
+    /// Applies settings and read-only rules to buffer, and connects it with interface.
+    fn prepare_buffer(&self, buffer: &BufferStateRef) {
+        // only defaults, changed per buffer later on.
+        if self.settings_ref().get_bool("text_view/soft_wrap") {
+            (**buffer).borrow_mut().set_soft_wrap(true);
+        }
+        let tab_width = self.settings_ref().get_usize("text_view/tab_width");
+        (**buffer).borrow_mut().set_tab_width(tab_width);
+        if self.settings_ref().get_bool("text_view/open_binary_as_text") {
+            (**buffer).borrow_mut().reopen_as_lossy_text();
+        }
+        // dependencies (like the ones language server navigates to) are not to be edited.
+        let path_op = (**buffer).borrow().get_path();
+        if path_op.map(|path| self.is_read_only_path(&path)) == Some(true) {
+            (**buffer).borrow_mut().set_read_only(true);
+        }
+        if let Some(ref inot) = self.inot_op {
+            (**buffer).borrow_mut().set_interface_notifier(inot.clone());
+        }
+    }
+
+    /// Registers a freshly created buffer. All buffers should go through here.
+    fn add_buffer(&mut self, buffer: BufferStateRef) -> BufferId {
+        self.prepare_buffer(&buffer);
         let id = (*buffer).borrow().id();
         self.loaded_buffers.insert(id.clone(), buffer);
-        Ok(id)
+        self.buffer_order.push(id.clone());
+        self.buffers_by_focus.push(id.clone());
+        self.update_disk_stamp(&id);
+        id
+    }
+
+    /// Returns whether path is a log (extension listed in "files/read_only_extensions") or lies
+    /// within one of "files/read_only_paths". Other files within project directories are never
+    /// considered read-only.
+    pub fn is_read_only_path(&self, path: &Path) -> bool {
+        let extension_op = path.extension().map(|ext| ext.to_string_lossy().to_string());
+        if let Some(extension) = extension_op {
+            let extensions = self.settings_ref().get_string_list("files/read_only_extensions");
+            if extensions.contains(&extension) {
+                return true;
+            }
+        }
+        if self.directories.iter().any(|dir| path.starts_with(dir)) {
+            return false;
+        }
+        self.settings_ref()
+            .get_string_list("files/read_only_paths")
+            .iter()
+            .any(|prefix| path.starts_with(expand_home(prefix)))
+    }
+
+    /// Creates an empty buffer without a name.
+    pub fn new_empty_buffer(&mut self) -> BufferId {
+        self.add_buffer(Rc::new(RefCell::new(BufferState::new())))
+    }
+
+    /// Returns buffers with unsaved changes, ordered by path (unnamed ones last).
+    pub fn modified_buffers(&self) -> Vec<BufferId> {
+        let mut modified: Vec<(Option<PathBuf>, BufferId)> = self
+            .loaded_buffers
+            .iter()
+            .filter(|(_, buffer)| (***buffer).borrow().modified())
+            .map(|(id, buffer)| ((***buffer).borrow().get_path(), id.clone()))
+            .collect();
+        modified.sort_by_key(|(path_op, _)| (path_op.is_none(), path_op.clone()));
+        modified.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Forgets buffer, along with its unsaved changes and recovery file. Returns false if there
+    /// was no such buffer.
+    pub fn close_buffer(&mut self, id: &BufferId) -> bool {
+        if self.loaded_buffers.remove(id).is_none() {
+            return false;
+        }
+        self.buffer_order.retain(|other| other != id);
+        self.buffers_by_focus.retain(|other| other != id);
+        self.disk_stamps.remove(id);
+        if let Some(ref dir) = self.recovery_dir {
+            recovery::remove_record(&self.filesystem, dir, id);
+        }
+        true
+    }
+
+    /// Sets file session is read from and written to, None disables sessions.
+    pub fn set_session_path(&mut self, session_path: Option<PathBuf>) {
+        self.session_path = session_path;
+    }
+
+    /// Returns session of this project saved on last exit, if any.
+    pub fn load_session(&self) -> Option<Session> {
+        let path = self.session_path.as_ref()?;
+        session::read_session(&self.filesystem, path, &self.directories)
+    }
+
+    pub fn save_session(&self, session: &Session) {
+        if let Some(ref path) = self.session_path {
+            if let Err(e) = session::write_session(&self.filesystem, path, session) {
+                warn!("failed to write session file {:?}, because \"{}\"", path, e);
+            }
+        }
+    }
+
+    /// Opens text that is not backed by a file (like logs) in a read-only buffer.
+    pub fn open_read_only_text(&mut self, text: &str) -> BufferId {
+        let mut buffer = BufferState::from_text(text);
+        buffer.set_read_only(true);
+        self.add_buffer(Rc::new(RefCell::new(buffer)))
+    }
+
+    /// Sets notifier used by background workers (like syntax highlighting) to wake up the
+    /// interface. Should be called before first buffer is created.
+    pub fn set_interface_notifier(&mut self, inot: InterfaceNotifier) {
+        for buffer in self.loaded_buffers.values() {
+            (**buffer).borrow_mut().set_interface_notifier(inot.clone());
+        }
+        self.inot_op = Some(inot);
     }
 
     /// This method is called while constructing interface, to determine content of first edit view.
@@ -185,17 +880,16 @@ impl AppState {
         }
         self.get_first_buffer_guard.set(true);
 
-        let buffer: BufferStateRef = if self.buffers_to_load.is_empty() {
-            /// if there is no buffer to load, we create an unnamed one.
-            Rc::new(RefCell::new(BufferState::new()))
+        let id = if let Some(text) = self.stdin_text.take() {
+            self.add_buffer(Rc::new(RefCell::new(BufferState::from_text(text))))
         } else {
-            let file_path = self.buffers_to_load.pop_front().unwrap();
-            Rc::new(RefCell::new(BufferState::open(&self.filesystem, &file_path, ExistPolicy::CanExist)?))
+            match self.buffers_to_load.pop_front() {
+                Some(file_path) => self.open_given_file(&file_path)?,
+                /// if there is no buffer to load, we create an unnamed one.
+                None => self.add_buffer(Rc::new(RefCell::new(BufferState::new()))),
+            }
         };
 
-        let id = (*buffer).borrow().id();
-        self.loaded_buffers.insert(id.clone(), buffer);
-
         Ok(self.buffer_obs(&id).unwrap())
     }
 
@@ -211,35 +905,112 @@ impl AppState {
             &directories, &files, enable_gitignore
         );
         let settings = Settings::load_default();
-        let mut files_to_index: Vec<PathBuf> = files.to_owned();
-
-        let file_index_limit = settings.file_index_limit();
-
-        for dir in &directories {
-            build_file_index(
-                &fs,
-                &mut files_to_index,
-                dir,
-                enable_gitignore,
-                None,
-                file_index_limit,
-            );
-        }
 
-        //        dbg!(&files);
+        // tests should not depend on what is in user's home directory.
+        let user_syntax_dir: Option<PathBuf> =
+            if cfg!(test) { None } else { config_dir().map(|dir| dir.join("syntaxes")) };
+        let syntax_loader =
+            SyntaxLoader::new(&settings, user_syntax_dir.as_ref().map(|d| d.as_path()));
+        // same goes for leftovers of crashed sessions.
+        let recovery_dir: Option<PathBuf> =
+            if cfg!(test) { None } else { recovery::recovery_dir() };
+        let session_path: Option<PathBuf> = if cfg!(test) {
+            None
+        } else {
+            session::sessions_dir().and_then(|dir| session::session_path(&dir, &directories))
+        };
+
+        // tests should not leave file index caches behind either.
+        let file_index_cache_dir: Option<PathBuf> =
+            if cfg!(test) { None } else { file_index_cache::file_index_cache_dir() };
+        let (cached_indexes, cached_files) = match file_index_cache_dir {
+            Some(ref dir) => {
+                read_file_index_caches(&fs, dir, &directories, enable_gitignore, &settings)
+            }
+            None => (Vec::new(), HashMap::new()),
+        };
 
-        let file_index_items = file_list_to_items(&files_to_index);
+        // the rest of files in directories is added as they are indexed, see start_indexing.
+        let file_index = initial_file_index(cached_indexes, &files);
         let buffers_to_load: VecDeque<PathBuf> = files.iter().map(|x| x.clone()).collect();
 
         AppState {
             buffers_to_load: buffers_to_load,
             loaded_buffers: HashMap::new(),
-            file_index: Arc::new(RefCell::new(FuzzyIndex::new(file_index_items))),
+            buffer_order: Vec::new(),
+            buffers_by_focus: Vec::new(),
+            file_index: Arc::new(RefCell::new(file_index)),
             filesystem: fs,
             dir_and_files_tree: dir_and_files_tree,
             get_first_buffer_guard: Cell::new(false),
             directories: directories,
+            enable_gitignore,
             settings: Rc::new(RefCell::new(settings)),
+            syntax_loader: Rc::new(syntax_loader),
+            inot_op: None,
+            recovery_dir,
+            stdin_text: None,
+            start_positions: HashMap::new(),
+            session_path,
+            disk_stamps: HashMap::new(),
+            disk_check_worker: None,
+            file_index_cache_dir,
+            cached_files,
+            indexed_files: HashMap::new(),
+        }
+    }
+
+    pub fn syntax_loader(&self) -> &Rc<SyntaxLoader> {
+        &self.syntax_loader
+    }
+
+    /// Sets syntax of given buffer by name. Returns false if syntax is unknown. Plain text is
+    /// always known, and it disables highlighting.
+    pub fn set_buffer_syntax(&mut self, id: &BufferId, syntax_name: &str) -> bool {
+        let highlight_settings_op = if syntax_name == PLAIN_TEXT_SYNTAX {
+            None
+        } else {
+            match self.syntax_loader.by_name(syntax_name) {
+                Some(hs) => Some(hs),
+                None => return false,
+            }
+        };
+
+        match self.loaded_buffers.get(id) {
+            Some(buffer) => {
+                (**buffer).borrow_mut().set_highlight_settings(highlight_settings_op);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Turns binary buffer into (read-only) text one, see BufferState::reopen_as_lossy_text.
+    pub fn reopen_as_lossy_text(&mut self, id: &BufferId) {
+        match self.loaded_buffers.get(id) {
+            Some(buffer) => (**buffer).borrow_mut().reopen_as_lossy_text(),
+            None => debug!("reopen_as_lossy_text: unknown buffer {}", id),
+        }
+    }
+
+    /// Sets folding ranges provided by language server. Ranges computed for other version of
+    /// content than the current one are dropped, newer ones are on their way.
+    pub fn set_lsp_folding_ranges(
+        &mut self,
+        id: &BufferId,
+        content_version: usize,
+        ranges: Vec<FoldRange>,
+    ) {
+        match self.loaded_buffers.get(id) {
+            Some(buffer) => {
+                let mut buffer = (**buffer).borrow_mut();
+                if buffer.get_content().version() == content_version {
+                    buffer.set_lsp_folding_ranges(ranges);
+                } else {
+                    debug!("dropping folding ranges of {} computed for outdated content", id);
+                }
+            }
+            None => debug!("got folding ranges for unknown buffer {}", id),
         }
     }
 
@@ -256,89 +1027,50 @@ impl AppState {
     }
 }
 
-/// this method takes into account .git and other directives set in .gitignore. However it only
-/// takes into account most recent .gitignore
-fn build_file_index(
+fn file_index_cache_key(root: &Path, enable_gitignore: bool, settings: &Settings) -> CacheKey {
+    CacheKey {
+        root: root.to_path_buf(),
+        enable_gitignore,
+        file_index_limit: settings.file_index_limit(),
+    }
+}
+
+/// Reads caches of file index of directories, returns them along with files of each directory.
+fn read_file_index_caches(
     fs: &FileSystemType,
-    mut index: &mut Vec<PathBuf>,
     dir: &Path,
+    directories: &Vec<PathBuf>,
     enable_gitignore: bool,
-    gi_op: Option<&gitignore::Gitignore>,
-    file_index_limit: usize,
-) {
-    if index.len() >= file_index_limit {
-        return;
-    }
-
-    //    assert!(fs.is_dir(&dir));
-
-    match fs.read_dir(dir) {
-        Ok(read_dir) => {
-            let gitignore_op: Option<gitignore::Gitignore> = if enable_gitignore {
-                let pathbuf = dir.join(Path::new(".gitignore"));
-                let gitignore_path = pathbuf.as_path();
-                if fs.is_file(gitignore_path) {
-                    let (gi, error_op) = gitignore::Gitignore::new(&gitignore_path);
-                    if let Some(error) = error_op {
-                        info!(
-                            "Error while parsing gitignore file {:?} : {:}",
-                            gitignore_path, error
-                        );
-                    }
-                    Some(gi)
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
+    settings: &Settings,
+) -> (Vec<CachedIndex>, HashMap<PathBuf, HashSet<PathBuf>>) {
+    let mut cached_indexes: Vec<CachedIndex> = Vec::new();
+    let mut cached_files: HashMap<PathBuf, HashSet<PathBuf>> = HashMap::new();
+    for root in directories.iter() {
+        let key = file_index_cache_key(root, enable_gitignore, settings);
+        if let Some(index) = file_index_cache::read_cache(fs, dir, &key) {
+            cached_files.insert(root.clone(), index.all_files().cloned().collect());
+            cached_indexes.push(index);
+        }
+    }
+    (cached_indexes, cached_files)
+}
 
-            for entry_res in read_dir {
-                if index.len() >= file_index_limit {
-                    break;
-                }
+/// Creates file index out of cached indexes of project directories and given files.
+fn initial_file_index(cached_indexes: Vec<CachedIndex>, files: &Vec<PathBuf>) -> FuzzyIndex {
+    if cached_indexes.is_empty() {
+        return FuzzyIndex::new(file_list_to_items(files));
+    }
 
-                match entry_res {
-                    Ok(entry) => {
-                        let path_buf = entry.path();
-                        let path = path_buf.as_path();
-
-                        if enable_gitignore {
-                            if path.ends_with(Path::new(".git")) {
-                                return;
-                            }
-                        }
-
-                        if fs.is_file(path) {
-                            if let Some(ref gitignore) = &gitignore_op {
-                                if gitignore.matched(path, false).is_ignore() {
-                                    continue;
-                                };
-                            };
-                            index.push(path.to_path_buf()); //TODO(njskalski): move instead of copy.
-                        } else {
-                            if let Some(ref gitignore) = &gitignore_op {
-                                if gitignore.matched(path, true).is_ignore() {
-                                    continue;
-                                };
-                            };
-
-                            let most_recent_gitignore =
-                                if gitignore_op.is_some() { gitignore_op.as_ref() } else { gi_op };
-                            build_file_index(
-                                fs,
-                                &mut index,
-                                &path,
-                                enable_gitignore,
-                                most_recent_gitignore,
-                                file_index_limit,
-                            );
-                        }
-                    }
-                    Err(e) => error!("error listing directory \"{:?}\": {:?}. Skipping.", dir, e),
-                } //match
-            } //for
+    match CachedIndex::merge(cached_indexes) {
+        Ok(cached) => {
+            let items: Vec<Vec<ViewItem>> = cached.files.iter().map(file_list_to_items).collect();
+            let mut index = FuzzyIndex::from_map(cached.map, items);
+            index.update(file_list_to_items(files), Vec::new());
+            index
+        }
+        Err(e) => {
+            warn!("unable to merge file index caches, because \"{}\"", e);
+            FuzzyIndex::new(file_list_to_items(files))
         }
-        Err(e) => warn!("unable to open dir \"{:?}\".", dir),
     }
 }
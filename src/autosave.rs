@@ -0,0 +1,166 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Decides when buffers are saved without user asking for it, and when recovery files are written
+// (see recovery.rs). Interface reports edits and ticks (IEvent::Tick, sent every second by ticker
+// thread), and asks here what to do.
+
+use std::time::{Duration, Instant};
+
+use crate::settings::Settings;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AutosavePolicy {
+    Off,
+    /// Save when there were no edits for given time.
+    Idle(Duration),
+    /// Save buffer when user switches to another one.
+    FocusChange,
+}
+
+impl AutosavePolicy {
+    pub fn from_settings(settings: &Settings) -> Self {
+        match settings.get_string("files/autosave").as_str() {
+            "idle" => AutosavePolicy::Idle(Duration::from_secs(
+                settings.get_usize("files/autosave_idle_seconds") as u64,
+            )),
+            "focus_change" => AutosavePolicy::FocusChange,
+            "off" => AutosavePolicy::Off,
+            other => {
+                warn!("unknown autosave policy {:?}, autosave is off.", other);
+                AutosavePolicy::Off
+            }
+        }
+    }
+}
+
+pub struct AutosaveTimer {
+    policy: AutosavePolicy,
+    /// None disables recovery files.
+    recovery_interval: Option<Duration>,
+    last_edit: Option<Instant>,
+    unsaved_edits: bool,
+    unrecovered_edits: bool,
+    last_recovery_write: Instant,
+}
+
+impl AutosaveTimer {
+    pub fn new(policy: AutosavePolicy, recovery_interval: Option<Duration>, now: Instant) -> Self {
+        AutosaveTimer {
+            policy,
+            recovery_interval,
+            last_edit: None,
+            unsaved_edits: false,
+            unrecovered_edits: false,
+            last_recovery_write: now,
+        }
+    }
+
+    pub fn from_settings(settings: &Settings, now: Instant) -> Self {
+        let interval = settings.get_usize("files/recovery_interval_seconds") as u64;
+        let recovery_interval =
+            if interval > 0 { Some(Duration::from_secs(interval)) } else { None };
+        AutosaveTimer::new(AutosavePolicy::from_settings(settings), recovery_interval, now)
+    }
+
+    pub fn policy(&self) -> AutosavePolicy {
+        self.policy
+    }
+
+    pub fn on_edit(&mut self, now: Instant) {
+        self.last_edit = Some(now);
+        self.unsaved_edits = true;
+        self.unrecovered_edits = true;
+    }
+
+    /// Returns true once per series of edits, when user stopped typing for long enough.
+    pub fn should_autosave(&mut self, now: Instant) -> bool {
+        let idle_time = match self.policy {
+            AutosavePolicy::Idle(idle_time) => idle_time,
+            _ => return false,
+        };
+        let idle = self.last_edit.map(|last| now.duration_since(last) >= idle_time);
+        if self.unsaved_edits && idle == Some(true) {
+            self.unsaved_edits = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns true if there were edits since recovery files were last written, and it's time to
+    /// write them again.
+    pub fn should_write_recovery(&mut self, now: Instant) -> bool {
+        let interval = match self.recovery_interval {
+            Some(interval) => interval,
+            None => return false,
+        };
+        if self.unrecovered_edits && now.duration_since(self.last_recovery_write) >= interval {
+            self.unrecovered_edits = false;
+            self.last_recovery_write = now;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn idle_autosave_waits_for_pause() {
+        let start = Instant::now();
+        let mut timer = AutosaveTimer::new(AutosavePolicy::Idle(secs(5)), None, start);
+        assert!(!timer.should_autosave(start + secs(10)));
+
+        timer.on_edit(start + secs(1));
+        assert!(!timer.should_autosave(start + secs(3)));
+        timer.on_edit(start + secs(4));
+        assert!(!timer.should_autosave(start + secs(8)));
+        assert!(timer.should_autosave(start + secs(9)));
+        // only once.
+        assert!(!timer.should_autosave(start + secs(20)));
+    }
+
+    #[test]
+    fn other_policies_never_autosave_on_tick() {
+        let start = Instant::now();
+        for policy in vec![AutosavePolicy::Off, AutosavePolicy::FocusChange] {
+            let mut timer = AutosaveTimer::new(policy, None, start);
+            timer.on_edit(start);
+            assert!(!timer.should_autosave(start + secs(100)));
+        }
+    }
+
+    #[test]
+    fn recovery_is_written_periodically_after_edits() {
+        let start = Instant::now();
+        let mut timer = AutosaveTimer::new(AutosavePolicy::Off, Some(secs(10)), start);
+        assert!(!timer.should_write_recovery(start + secs(20)));
+
+        timer.on_edit(start + secs(21));
+        assert!(timer.should_write_recovery(start + secs(22)));
+        timer.on_edit(start + secs(23));
+        assert!(!timer.should_write_recovery(start + secs(25)));
+        assert!(timer.should_write_recovery(start + secs(32)));
+    }
+}
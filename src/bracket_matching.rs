@@ -0,0 +1,278 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Bracket matching works on chars of Rope. Brackets that are not "code" (that is, are within
+// strings or comments according to syntect scopes, see RichLine::is_code_at) are skipped. Caller
+// provides this information via is_code closure, so this module knows nothing about highlighting.
+//
+// Searches are limited to MAX_BRACKET_SEARCH chars, so a lonely bracket at the beginning of a huge
+// file does not freeze drawing. Still, that's a lot to do on every redraw, so views keep results
+// in BracketCache until content (or what is known to be code) changes.
+
+use ropey::Rope;
+use std::collections::HashMap;
+use std::ops::Range;
+
+pub const MAX_BRACKET_SEARCH: usize = 20000;
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Returns (opening, closing, is_opening) if c is a bracket.
+pub fn bracket_pair(c: char) -> Option<(char, char, bool)> {
+    for &(open, close) in BRACKETS.iter() {
+        if c == open {
+            return Some((open, close, true));
+        }
+        if c == close {
+            return Some((open, close, false));
+        }
+    }
+    None
+}
+
+/// Returns offset of bracket matching the one at offset, if there is one at offset and it's code.
+pub fn find_matching_bracket<F: Fn(usize) -> bool>(
+    rope: &Rope,
+    offset: usize,
+    is_code: F,
+) -> Option<usize> {
+    if offset >= rope.len_chars() || !is_code(offset) {
+        return None;
+    }
+
+    let (open, close, is_opening) = bracket_pair(rope.char(offset))?;
+    let mut depth: usize = 0;
+
+    if is_opening {
+        let limit = std::cmp::min(rope.len_chars(), offset + MAX_BRACKET_SEARCH);
+        for (idx, c) in rope.slice(offset..limit).chars().enumerate() {
+            if c != open && c != close {
+                continue;
+            }
+            if !is_code(offset + idx) {
+                continue;
+            }
+            if c == open {
+                depth += 1;
+            } else {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(offset + idx);
+                }
+            }
+        }
+    } else {
+        let limit = offset.saturating_sub(MAX_BRACKET_SEARCH);
+        for pos in (limit..offset + 1).rev() {
+            let c = rope.char(pos);
+            if c != open && c != close {
+                continue;
+            }
+            if !is_code(pos) {
+                continue;
+            }
+            if c == close {
+                depth += 1;
+            } else {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns the bracket "under" cursor at anchor: the one at anchor, or if there is none, the one
+/// just before it (so cursor right after closing bracket also shows its pair).
+pub fn bracket_near_anchor(rope: &Rope, anchor: usize) -> Option<usize> {
+    if anchor < rope.len_chars() && bracket_pair(rope.char(anchor)).is_some() {
+        return Some(anchor);
+    }
+    if anchor > 0 && anchor <= rope.len_chars() && bracket_pair(rope.char(anchor - 1)).is_some() {
+        return Some(anchor - 1);
+    }
+    None
+}
+
+/// Returns nesting depth (0 based) of every code bracket within range, for rainbow colouring.
+/// Depth is counted from MAX_BRACKET_SEARCH chars before range, not from the beginning of the file,
+/// so in huge files it's an approximation. Unbalanced closing brackets get depth 0.
+pub fn bracket_depths<F: Fn(usize) -> bool>(
+    rope: &Rope,
+    range: Range<usize>,
+    is_code: F,
+) -> HashMap<usize, usize> {
+    let mut result: HashMap<usize, usize> = HashMap::new();
+    let end = std::cmp::min(range.end, rope.len_chars());
+    if range.start >= end {
+        return result;
+    }
+
+    let begin = range.start.saturating_sub(MAX_BRACKET_SEARCH);
+    let mut depth: usize = 0;
+
+    for (idx, c) in rope.slice(begin..end).chars().enumerate() {
+        let pos = begin + idx;
+        let is_opening = match bracket_pair(c) {
+            Some((_, _, is_opening)) => is_opening,
+            None => continue,
+        };
+        if !is_code(pos) {
+            continue;
+        }
+
+        if is_opening {
+            if pos >= range.start {
+                result.insert(pos, depth);
+            }
+            depth += 1;
+        } else {
+            depth = depth.saturating_sub(1);
+            if pos >= range.start {
+                result.insert(pos, depth);
+            }
+        }
+    }
+
+    result
+}
+
+/// Results of find_matching_bracket and bracket_depths for one revision of content (see
+/// RopeBasedContentProvider::code_revision).
+pub struct BracketCache {
+    revision: Option<(usize, Option<usize>)>,
+    matches: HashMap<usize, Option<usize>>,
+    depths: Option<(Range<usize>, HashMap<usize, usize>)>,
+}
+
+impl BracketCache {
+    pub fn new() -> Self {
+        BracketCache { revision: None, matches: HashMap::new(), depths: None }
+    }
+
+    fn set_revision(&mut self, revision: (usize, Option<usize>)) {
+        if self.revision != Some(revision) {
+            self.revision = Some(revision);
+            self.matches.clear();
+            self.depths = None;
+        }
+    }
+
+    /// Cached find_matching_bracket.
+    pub fn matching_bracket<F: Fn(usize) -> bool>(
+        &mut self,
+        revision: (usize, Option<usize>),
+        rope: &Rope,
+        offset: usize,
+        is_code: F,
+    ) -> Option<usize> {
+        self.set_revision(revision);
+        *self.matches.entry(offset).or_insert_with(|| find_matching_bracket(rope, offset, is_code))
+    }
+
+    /// Cached bracket_depths, only the last range is kept.
+    pub fn bracket_depths<F: Fn(usize) -> bool>(
+        &mut self,
+        revision: (usize, Option<usize>),
+        rope: &Rope,
+        range: Range<usize>,
+        is_code: F,
+    ) -> HashMap<usize, usize> {
+        self.set_revision(revision);
+        match self.depths {
+            Some((ref cached_range, ref depths)) if *cached_range == range => depths.clone(),
+            _ => {
+                let depths = bracket_depths(rope, range.clone(), is_code);
+                self.depths = Some((range, depths.clone()));
+                depths
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_code(_: usize) -> bool {
+        true
+    }
+
+    #[test]
+    fn finds_matching_brackets() {
+        let rope = Rope::from_str("f(a[1], {b})");
+        assert_eq!(find_matching_bracket(&rope, 1, all_code), Some(11));
+        assert_eq!(find_matching_bracket(&rope, 11, all_code), Some(1));
+        assert_eq!(find_matching_bracket(&rope, 3, all_code), Some(5));
+        assert_eq!(find_matching_bracket(&rope, 8, all_code), Some(10));
+        assert_eq!(find_matching_bracket(&rope, 0, all_code), None);
+    }
+
+    #[test]
+    fn skips_non_code_brackets() {
+        let text = "(\")\")";
+        let rope = Rope::from_str(text);
+        // brackets in quotes are "strings".
+        let is_code = |pos: usize| pos == 0 || pos == 4;
+        assert_eq!(find_matching_bracket(&rope, 0, is_code), Some(4));
+        assert_eq!(find_matching_bracket(&rope, 0, all_code), Some(2));
+        assert_eq!(find_matching_bracket(&rope, 2, is_code), None);
+    }
+
+    #[test]
+    fn unbalanced_has_no_match() {
+        let rope = Rope::from_str("((x)");
+        assert_eq!(find_matching_bracket(&rope, 0, all_code), None);
+    }
+
+    #[test]
+    fn bracket_near_anchor_test() {
+        let rope = Rope::from_str("a(b)");
+        assert_eq!(bracket_near_anchor(&rope, 1), Some(1));
+        assert_eq!(bracket_near_anchor(&rope, 4), Some(3));
+        assert_eq!(bracket_near_anchor(&rope, 0), None);
+    }
+
+    #[test]
+    fn bracket_depths_test() {
+        let rope = Rope::from_str("{[()]}");
+        let depths = bracket_depths(&rope, 1..5, all_code);
+        assert_eq!(depths.len(), 4);
+        assert_eq!(depths[&1], 1);
+        assert_eq!(depths[&2], 2);
+        assert_eq!(depths[&3], 2);
+        assert_eq!(depths[&4], 1);
+    }
+
+    #[test]
+    fn cache_is_dropped_with_revision() {
+        let mut cache = BracketCache::new();
+        let rope = Rope::from_str("(a)");
+        assert_eq!(cache.matching_bracket((0, None), &rope, 0, all_code), Some(2));
+
+        // same revision, so result is not computed again.
+        let edited = Rope::from_str("(a");
+        assert_eq!(cache.matching_bracket((0, None), &edited, 0, all_code), Some(2));
+        assert_eq!(cache.matching_bracket((1, None), &edited, 0, all_code), None);
+
+        assert_eq!(cache.bracket_depths((1, None), &edited, 0..2, all_code).len(), 1);
+        // more lines got highlighted, the bracket turned out to be in a comment.
+        assert!(cache.bracket_depths((1, Some(1)), &edited, 0..2, |_| false).is_empty());
+    }
+}
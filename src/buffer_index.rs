@@ -14,20 +14,28 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+// Index of open buffers for the buffer list. Buffers are given most recently focused first.
+// Query is matched against file name and path (or header of unnamed buffers), results are ordered
+// by score of the better of the two, and equally good ones by recency.
+
 use crate::buffer_state_observer::BufferStateObserver;
 use crate::fuzzy_index_trait::FuzzyIndexTrait;
+use crate::fuzzy_score::fuzzy_match;
 use crate::interface::InterfaceNotifier;
-use std::cmp;
 use std::fmt;
 use std::rc::Rc;
 use crate::fuzzy_view_item::ViewItem;
 
+// matches in file name count more than the same matches somewhere in the path.
+const FILENAME_BONUS: i64 = 16;
+
 pub struct BufferIndex {
     buffers: Vec<BufferStateObserver>,
     items: Vec<Rc<ViewItem>>,
 }
 
 impl BufferIndex {
+    /// Buffers are expected in order of last focus, most recent first.
     pub fn new(buffers: Vec<BufferStateObserver>) -> Self {
         let items = buffers.iter().map(|buffer| Rc::new(buffer_to_item(buffer))).collect();
         BufferIndex { buffers: buffers, items: items }
@@ -47,11 +55,31 @@ impl FuzzyIndexTrait for BufferIndex {
         limit_op: Option<usize>,
         _: Option<InterfaceNotifier>,
     ) -> Vec<Rc<ViewItem>> {
+        let mut scored: Vec<(i64, Rc<ViewItem>)> = self
+            .items
+            .iter()
+            .filter_map(|item| item_score(query, item).map(|score| (score, item.clone())))
+            .collect();
+
+        // stable, so items keep order of recency within the same score.
+        scored.sort_by(|a, b| b.0.cmp(&a.0));
+
         if let Some(limit) = limit_op {
-            self.items[..cmp::min(limit, self.items.len())].to_vec()
-        } else {
-            self.items.clone()
+            scored.truncate(limit);
         }
+        scored.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+/// Score of the better match of query: in header (file name) or in description (path).
+fn item_score(query: &str, item: &ViewItem) -> Option<i64> {
+    let header_score = fuzzy_match(query, item.get_header()).map(|m| m.score + FILENAME_BONUS);
+    let path_score =
+        item.get_description().as_ref().and_then(|path| fuzzy_match(query, path)).map(|m| m.score);
+
+    match (header_score, path_score) {
+        (Some(h), Some(p)) => Some(h.max(p)),
+        (h, p) => h.or(p),
     }
 }
 
@@ -74,3 +102,25 @@ fn buffer_to_item(buffer: &BufferStateObserver) -> ViewItem {
         None,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(header: &str, path: &str) -> ViewItem {
+        ViewItem::new(header.to_string(), Some(path.to_string()), path.to_string(), None)
+    }
+
+    #[test]
+    fn matches_file_name_and_path() {
+        let main = item("main.rs", "/home/laura/project/src/main.rs");
+        let readme = item("README.md", "/home/laura/project/README.md");
+
+        assert!(item_score("mrs", &main).is_some());
+        assert!(item_score("mrs", &readme).is_none());
+        // only in path.
+        assert!(item_score("src", &main).is_some());
+        // match in file name wins over the same one in path.
+        assert!(item_score("main", &main).unwrap() > item_score("proj", &main).unwrap());
+    }
+}
@@ -18,7 +18,18 @@ use crate::buffer_id::BufferId;
 use crate::buffer_state_observer::BufferStateObserver;
 use crate::content_provider::EditEvent;
 use crate::content_provider::RopeBasedContentProvider;
-use crate::utils::highlight_settings_from_path;
+use crate::folding::{FoldRange, FoldState};
+use crate::interface::InterfaceNotifier;
+use crate::layout::DEFAULT_TAB_WIDTH;
+use crate::line_endings::{
+    convert_line_endings, detect_line_ending, strip_bom, LineEnding, UTF8_BOM,
+};
+use crate::rich_content::HighlightSettings;
+use crate::safe_save::save_file;
+use crate::text_diff::TextDiff;
+use ropey::Rope;
+use crate::syntax_loader::SyntaxLoader;
+use crate::utils::is_read_only;
 use crate::view_handle::ViewHandle;
 
 use cursive;
@@ -29,11 +40,14 @@ use std::env;
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Arc;
 
 use filesystem::*;
 use std::borrow::Borrow;
 use crate::FileSystemType;
 
+const BINARY_SNIFF_LENGTH: usize = 8000;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BufferOpenMode {
     ReadOnly,
@@ -48,10 +62,25 @@ pub enum ExistPolicy {
 }
 
 /// This struct represents serializable part of BufferState.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BufferStateS {
     /// Path can be None. This represents a buffer which has no file name set.
     path: Option<PathBuf>,
+    #[serde(default)]
+    line_ending: LineEnding,
+    /// Whether file begins with UTF-8 byte order mark. BOM is not a part of content.
+    #[serde(default)]
+    bom: bool,
+}
+
+impl BufferStateS {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        BufferStateS { path, line_ending: LineEnding::default(), bom: false }
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
 }
 
 pub type BufferStateRef = Rc<RefCell<BufferState>>;
@@ -62,35 +91,156 @@ pub struct BufferState {
     modified: bool,
     mode: BufferOpenMode,
     content: RopeBasedContentProvider,
+    folds: FoldState,
+    soft_wrap: bool,
+    tab_width: usize,
+    // Raw content of files that are not UTF-8 text, shown in HexView. Text content is empty then.
+    binary: Option<Arc<Vec<u8>>>,
+    // File was changed (or removed) on disk after it was loaded or saved, and the change was not
+    // taken in.
+    out_of_sync: bool,
+    // File is being read in background, buffer is an empty, read-only placeholder until then.
+    loading: bool,
+    // Buffer is read-only because user cannot write its file, not because they asked for it.
+    file_not_writable: bool,
 }
 
 impl BufferState {
     pub fn new() -> BufferState {
         BufferState {
             id: BufferId::new(),
-            ss: BufferStateS { path: None },
+            ss: BufferStateS::new(None),
             modified: false,
             content: RopeBasedContentProvider::new(None, None),
             mode: BufferOpenMode::ReadWrite,
+            folds: FoldState::new(),
+            soft_wrap: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            binary: None,
+            out_of_sync: false,
+            loading: false,
+            file_not_writable: false,
         }
     }
 
     pub fn from_text<T: AsRef<str>>(s : T) -> BufferState {
         let text = s.as_ref();
+        let mut ss = BufferStateS::new(None);
+        ss.line_ending = detect_line_ending(text.as_bytes());
 
         BufferState {
             id: BufferId::new(),
-            ss: BufferStateS { path: None },
+            ss,
             modified: false,
             content: RopeBasedContentProvider::new(Some(text.as_bytes().to_vec()), None),
             mode: BufferOpenMode::ReadWrite,
+            folds: FoldState::new(),
+            soft_wrap: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            binary: None,
+            out_of_sync: false,
+            loading: false,
+            file_not_writable: false,
         }
     }
 
+    /// Creates empty placeholder of file that is read in background. See finish_loading.
+    pub fn loading_placeholder(file_path: &Path) -> BufferState {
+        let mut buffer = BufferState::new();
+        buffer.ss = BufferStateS::new(Some(file_path.to_owned()));
+        buffer.loading = true;
+        buffer
+    }
+
+    /// Replaces placeholder (see loading_placeholder) with content of file, read as bytes.
+    /// File's read_only flag comes from the filesystem.
+    pub fn finish_loading(
+        &mut self,
+        bytes: Vec<u8>,
+        read_only: bool,
+        syntax_loader: &SyntaxLoader,
+    ) {
+        let path = self.ss.path.clone().unwrap_or_default();
+        let loaded = BufferState::from_bytes(Some(&path), Some(bytes), read_only, syntax_loader);
+        *self = BufferState {
+            id: self.id.clone(),
+            soft_wrap: self.soft_wrap,
+            tab_width: self.tab_width,
+            ..loaded
+        };
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading
+    }
+
+    /// Recreates unsaved buffer from recovery file (see recovery.rs). It's modified, as its
+    /// content is not what's on disk.
+    pub fn from_recovery(
+        ss: BufferStateS,
+        content: &str,
+        syntax_loader: &SyntaxLoader,
+    ) -> BufferState {
+        let mut buffer = BufferState::from_bytes(
+            ss.path().map(|p| p.as_path()),
+            Some(content.as_bytes().to_vec()),
+            false,
+            syntax_loader,
+        );
+        // line ending and BOM of the file, not guessed from content.
+        buffer.ss = ss;
+        buffer.modified = true;
+        buffer
+    }
+
     pub fn modified(&self) -> bool {
         self.modified
     }
 
+    pub fn out_of_sync(&self) -> bool {
+        self.out_of_sync
+    }
+
+    pub fn set_out_of_sync(&mut self, out_of_sync: bool) {
+        self.out_of_sync = out_of_sync;
+    }
+
+    /// Replaces content with text of file changed on disk. It's applied as edits computed with
+    /// diff, so folds and highlighting of unchanged parts survive. Returns previous content and
+    /// the diff, so views can move their cursors. Buffer is not modified afterwards.
+    pub fn reload_text(&mut self, text: &str, bom: bool) -> (Rope, TextDiff) {
+        let diff = TextDiff::new(self.content.get_lines(), &Rope::from_str(text));
+        self.reload_text_with_diff(text, bom, diff)
+    }
+
+    /// Same as reload_text, with diff from current content to text computed already (see
+    /// disk_check.rs).
+    pub fn reload_text_with_diff(
+        &mut self,
+        text: &str,
+        bom: bool,
+        diff: TextDiff,
+    ) -> (Rope, TextDiff) {
+        let old = self.content.get_lines().clone();
+        let new = Rope::from_str(text);
+
+        if !diff.is_empty() {
+            let old_len_lines = self.content.len_lines();
+            let first_changed_line = self.content.submit_events(diff.edit_events(&old, &new));
+            self.folds.on_edit(first_changed_line, old_len_lines, self.content.len_lines());
+        }
+
+        self.ss.bom = bom;
+        self.ss.line_ending = detect_line_ending(text.as_bytes());
+        self.modified = false;
+        self.out_of_sync = false;
+        (old, diff)
+    }
+
+    pub fn serializable_state(&self) -> &BufferStateS {
+        &self.ss
+    }
+
     pub fn id(&self) -> BufferId {
         self.id.clone()
     }
@@ -99,6 +249,7 @@ impl BufferState {
         fs: &FileSystemType,
         file_path: &Path,
         creation_policy: ExistPolicy,
+        syntax_loader: &SyntaxLoader,
     ) -> Result<Self, io::Error> {
         debug!("reading file {:?}, creation_policy = {:?}", file_path, creation_policy);
 
@@ -118,17 +269,66 @@ impl BufferState {
             ));
         }
 
-        let highlight_settings_op = highlight_settings_from_path(file_path);
+        let bytes_op = if exists { Some(fs.read_file(&file_path)?) } else { None };
+        let read_only = exists && is_read_only(fs, file_path);
+        Ok(BufferState::from_bytes(Some(file_path), bytes_op, read_only, syntax_loader))
+    }
+
+    /// Creates buffer of file_path (None for unnamed buffer) with given content (None if file does
+    /// not exist yet).
+    fn from_bytes(
+        file_path_op: Option<&Path>,
+        bytes_op: Option<Vec<u8>>,
+        read_only: bool,
+        syntax_loader: &SyntaxLoader,
+    ) -> BufferState {
+        let mut ss = BufferStateS::new(file_path_op.map(|p| p.to_owned()));
+        let mut binary: Option<Arc<Vec<u8>>> = None;
+        let contents = if let Some(bytes) = bytes_op {
+            let (bom, bytes) = strip_bom(bytes);
+            if looks_binary(&bytes) {
+                debug!("{:?} is not UTF-8 text, opening as binary.", file_path_op);
+                let mut raw = if bom { UTF8_BOM.to_vec() } else { Vec::new() };
+                raw.extend(bytes);
+                binary = Some(Arc::new(raw));
+                None
+            } else {
+                ss.bom = bom;
+                ss.line_ending = detect_line_ending(&bytes);
+                Some(bytes)
+            }
+        } else {
+            None
+        };
 
-        let contents = if exists { Some(fs.read_file(&file_path)?) } else { None };
+        let first_line_op: Option<String> = contents.as_ref().map(|bytes| {
+            let first_line = bytes.split(|b| *b == b'\n').next().unwrap_or(&[]);
+            String::from_utf8_lossy(first_line).to_string()
+        });
+        let highlight_settings_op = if binary.is_some() {
+            None
+        } else {
+            syntax_loader.detect(file_path_op, first_line_op.as_ref().map(|s| s.as_str()))
+        };
+        // binary files are never edited as text, and files we cannot write are not edited at all.
+        let file_not_writable = binary.is_none() && read_only;
+        let read_only = binary.is_some() || read_only;
+        let mode = if read_only { BufferOpenMode::ReadOnly } else { BufferOpenMode::ReadWrite };
 
-        Ok(BufferState {
+        BufferState {
             id: BufferId::new(),
-            ss: BufferStateS { path: Some(file_path.to_owned()) },
+            ss,
             modified: false,
             content: RopeBasedContentProvider::new(contents, highlight_settings_op),
-            mode: BufferOpenMode::ReadWrite,
-        })
+            mode,
+            folds: FoldState::new(),
+            soft_wrap: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            binary,
+            out_of_sync: false,
+            loading: false,
+            file_not_writable,
+        }
     }
 
     pub fn get_content(&self) -> &RopeBasedContentProvider {
@@ -139,8 +339,144 @@ impl BufferState {
         &mut self.content
     }
 
+    /// Sets syntax used to highlight this buffer. None means plain text.
+    pub fn set_highlight_settings(
+        &mut self,
+        highlight_settings_op: Option<Arc<HighlightSettings>>,
+    ) {
+        self.content.set_highlight_settings(highlight_settings_op);
+    }
+
+    pub fn set_interface_notifier(&mut self, inot: InterfaceNotifier) {
+        self.content.set_interface_notifier(inot);
+    }
+
+    /// Whether long lines of this buffer are wrapped when displayed.
+    pub fn soft_wrap(&self) -> bool {
+        self.soft_wrap
+    }
+
+    pub fn set_soft_wrap(&mut self, soft_wrap: bool) {
+        self.soft_wrap = soft_wrap;
+    }
+
+    /// Number of columns between tab stops, used when laying out this buffer.
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = std::cmp::max(1, tab_width);
+    }
+
+    pub fn folds(&self) -> &FoldState {
+        &self.folds
+    }
+
+    pub fn fold_at(&mut self, line_no: usize) -> bool {
+        self.folds.fold_at(&self.content, line_no)
+    }
+
+    pub fn unfold_at(&mut self, line_no: usize) -> bool {
+        self.folds.unfold_at(line_no)
+    }
+
+    pub fn fold_all(&mut self) {
+        self.folds.fold_all(&self.content)
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.folds.unfold_all()
+    }
+
+    pub fn set_lsp_folding_ranges(&mut self, ranges: Vec<FoldRange>) {
+        self.folds.set_lsp_ranges(ranges)
+    }
+
+    /// Returns raw content if buffer holds a binary (or non UTF-8) file.
+    pub fn binary(&self) -> Option<Arc<Vec<u8>>> {
+        self.binary.clone()
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.binary.is_some()
+    }
+
+    pub fn mode(&self) -> &BufferOpenMode {
+        &self.mode
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.mode == BufferOpenMode::ReadOnly || self.loading
+    }
+
+    /// Returns whether buffer is read-only only because user cannot write its file, so it can
+    /// still be saved with privileges (see safe_save::save_file_privileged).
+    pub fn read_only_by_file(&self) -> bool {
+        self.mode == BufferOpenMode::ReadOnly && self.file_not_writable
+    }
+
+    /// Returns false if mode cannot be changed: binary buffers are always read-only.
+    pub fn set_read_only(&mut self, read_only: bool) -> bool {
+        if !read_only && self.binary.is_some() {
+            return false;
+        }
+        // from now on it's what user asked for.
+        self.file_not_writable = false;
+        self.mode = if read_only { BufferOpenMode::ReadOnly } else { BufferOpenMode::ReadWrite };
+        true
+    }
+
+    /// Replaces binary content with text, invalid UTF-8 sequences become U+FFFD. Buffer stays
+    /// read-only, saving it would not write back the original bytes.
+    pub fn reopen_as_lossy_text(&mut self) {
+        let bytes = match self.binary.take() {
+            Some(bytes) => bytes,
+            None => return,
+        };
+        let text = String::from_utf8_lossy(&bytes).to_string();
+        self.ss.line_ending = detect_line_ending(text.as_bytes());
+        self.content = RopeBasedContentProvider::new(Some(text.into_bytes()), None);
+        self.folds = FoldState::new();
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.ss.line_ending
+    }
+
+    pub fn has_bom(&self) -> bool {
+        self.ss.bom
+    }
+
+    /// Rewrites all line endings of content to line_ending. It's an ordinary edit, so it can be
+    /// undone, and buffer becomes modified.
+    pub fn convert_line_endings(&mut self, line_ending: LineEnding) {
+        if self.read_only() {
+            return;
+        }
+        let text = self.content.get_lines().to_string();
+        let converted = convert_line_endings(&text, line_ending);
+        if converted != text {
+            let length = self.content.get_lines().len_chars();
+            self.submit_edit_events(vec![EditEvent::Change {
+                offset: 0,
+                length,
+                content: converted,
+            }]);
+        }
+        self.ss.line_ending = line_ending;
+    }
+
     pub fn submit_edit_events(&mut self, events: Vec<EditEvent>) {
-        self.content.submit_events(events);
+        // views are expected not to send edits to read-only buffers, this is the last line.
+        if self.read_only() {
+            error!("ignoring {} edit events sent to read-only buffer {}", events.len(), self.id);
+            return;
+        }
+
+        let old_len_lines = self.content.len_lines();
+        let first_changed_line = self.content.submit_events(events);
+        self.folds.on_edit(first_changed_line, old_len_lines, self.content.len_lines());
         self.modified = true; // TODO modified should be moved to history.
     }
 
@@ -160,7 +496,21 @@ impl BufferState {
         self.get_path().map_or(false, |path| fs.is_file(path))
     }
 
-    pub fn save(&mut self, fs: &FileSystemType, path: Option<PathBuf>) -> Result<(), io::Error> {
+    /// Writes content to path (or path buffer was opened from). Save is atomic, see safe_save.
+    /// With backup set, previous version of file is kept as "file~".
+    pub fn save(
+        &mut self,
+        fs: &FileSystemType,
+        path: Option<PathBuf>,
+        backup: bool,
+    ) -> Result<(), io::Error> {
+        if self.binary.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Binary files cannot be saved.",
+            ));
+        }
+
         if path.is_none() && self.ss.path.is_none() {
             return Err(io::Error::new(io::ErrorKind::NotFound, "No path provided."));
         }
@@ -170,25 +520,189 @@ impl BufferState {
             return Ok(());
         }
 
+        // read-only buffer can be saved only under a different name ("save as").
+        if self.read_only() && (path.is_none() || path == self.ss.path) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Buffer is read-only."));
+        }
+
         let final_path: PathBuf = match path {
             Some(p) => p,
             None => self.get_path().unwrap(),
         };
 
-        let mut buf: Vec<u8> = Vec::new();
-        buf.reserve(self.content.get_lines().len_bytes());
-        self.content.get_lines().write_to(&mut buf);
+        save_file(fs, &final_path, &self.encoded_content(), backup)?;
 
-        if fs.is_file(&final_path) {
-            fs.remove_file(&final_path)?;
+        // it's a copy user just wrote, so it's theirs to edit.
+        self.mode = BufferOpenMode::ReadWrite;
+        self.mark_saved(final_path);
+        Ok(())
+    }
+
+    /// Returns what external helper (like "sudo -A tee", see safe_save::save_file_privileged) is
+    /// to write: path (given or the one buffer was opened from), version of content and its bytes.
+    pub fn privileged_save_content(
+        &self,
+        path: Option<PathBuf>,
+    ) -> Result<(PathBuf, usize, Vec<u8>), io::Error> {
+        if self.binary.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Binary files cannot be saved.",
+            ));
         }
 
-        fs.create_file(&final_path, &buf)?;
+        let path = match path.or(self.get_path()) {
+            Some(path) => path,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "No path provided.")),
+        };
+
+        Ok((path, self.content.version(), self.encoded_content()))
+    }
+
+    /// Marks buffer as written to path by helper. Returns false if it was edited since version
+    /// of content that was written, then it stays modified.
+    pub fn finish_privileged_save(&mut self, path: PathBuf, version: usize) -> bool {
+        // user chose to write it as root, so they are allowed to edit it as well.
+        self.mode = BufferOpenMode::ReadWrite;
+        if self.content.version() != version {
+            self.ss.path = Some(path);
+            return false;
+        }
+        self.mark_saved(path);
+        true
+    }
 
-        self.ss.path = Some(final_path);
+    /// Returns bytes to be written to file: content with BOM, if file had one.
+    fn encoded_content(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = Vec::new();
+        buf.reserve(self.content.get_lines().len_bytes() + UTF8_BOM.len());
+        if self.ss.bom {
+            buf.extend_from_slice(&UTF8_BOM);
+        }
+        self.content.get_lines().write_to(&mut buf);
+        buf
+    }
 
+    fn mark_saved(&mut self, path: PathBuf) {
+        self.ss.path = Some(path);
         self.modified = false;
+        self.out_of_sync = false;
         debug!("{:?} saved.", &self.ss.path);
-        Ok(())
+    }
+}
+
+/// Returns whether bytes should not be edited as text: they are not valid UTF-8, or they contain
+/// NUL within the first BINARY_SNIFF_LENGTH bytes (like git does).
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    let sniffed = &bytes[..std::cmp::min(bytes.len(), BINARY_SNIFF_LENGTH)];
+    sniffed.contains(&0u8) || std::str::from_utf8(bytes).is_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+
+    #[test]
+    fn looks_binary_test() {
+        assert!(!looks_binary(b"fn main() {}\n"));
+        assert!(!looks_binary("za\u{17c}\u{f3}\u{142}\u{107}".as_bytes()));
+        assert!(looks_binary(b"\x89PNG\r\n\x1a\n\x00\x00"));
+        assert!(looks_binary(b"caf\xe9")); // latin-1
+    }
+
+    #[test]
+    fn read_only_buffer_ignores_edits() {
+        let mut buffer = BufferState::from_text("abc");
+        assert!(buffer.set_read_only(true));
+        buffer.submit_edit_events(vec![EditEvent::Insert { offset: 0, content: "x".to_string() }]);
+        assert_eq!(buffer.get_content().get_lines().to_string(), "abc");
+        assert!(!buffer.modified());
+
+        assert!(buffer.set_read_only(false));
+        buffer.submit_edit_events(vec![EditEvent::Insert { offset: 0, content: "x".to_string() }]);
+        assert_eq!(buffer.get_content().get_lines().to_string(), "xabc");
+    }
+
+    #[test]
+    fn privileged_save_makes_buffer_editable() {
+        let path = PathBuf::from("/etc/hosts");
+        let mut buffer = BufferState::from_text("abc");
+        buffer.submit_edit_events(vec![EditEvent::Insert { offset: 0, content: "x".to_string() }]);
+        assert!(buffer.set_read_only(true));
+
+        let (target, version, bytes) = buffer.privileged_save_content(Some(path.clone())).unwrap();
+        assert_eq!(target, path);
+        assert_eq!(bytes, b"xabc".to_vec());
+
+        assert!(buffer.finish_privileged_save(target, version));
+        assert_eq!(buffer.get_path(), Some(path));
+        assert!(!buffer.read_only());
+        assert!(!buffer.modified());
+    }
+
+    #[test]
+    fn buffer_edited_during_privileged_save_stays_modified() {
+        let mut buffer = BufferState::from_text("abc");
+        buffer.submit_edit_events(vec![EditEvent::Insert { offset: 0, content: "x".to_string() }]);
+        let (path, version, _) =
+            buffer.privileged_save_content(Some(PathBuf::from("/etc/hosts"))).unwrap();
+
+        buffer.submit_edit_events(vec![EditEvent::Insert { offset: 0, content: "y".to_string() }]);
+        assert!(!buffer.finish_privileged_save(path.clone(), version));
+        assert_eq!(buffer.get_path(), Some(path));
+        assert!(buffer.modified());
+    }
+
+    #[test]
+    fn recovered_buffer_keeps_state_of_file() {
+        let syntax_loader = SyntaxLoader::new(&Settings::load_default(), None);
+        let mut ss = BufferStateS::new(Some(PathBuf::from("/project/a.rs")));
+        ss.line_ending = LineEnding::CrLf;
+        ss.bom = true;
+
+        let buffer = BufferState::from_recovery(ss, "fn main() {}\n", &syntax_loader);
+        assert!(buffer.modified());
+        assert!(!buffer.read_only());
+        assert_eq!(buffer.get_path(), Some(PathBuf::from("/project/a.rs")));
+        assert_eq!(buffer.line_ending(), LineEnding::CrLf);
+        assert!(buffer.has_bom());
+        assert_eq!(buffer.get_content().get_lines().to_string(), "fn main() {}\n");
+    }
+
+    #[test]
+    fn placeholder_becomes_loaded_file() {
+        let syntax_loader = SyntaxLoader::new(&Settings::load_default(), None);
+        let mut buffer = BufferState::loading_placeholder(Path::new("/project/big.txt"));
+        let id = buffer.id();
+        assert!(buffer.is_loading());
+        assert!(buffer.read_only());
+        buffer.set_tab_width(8);
+
+        buffer.finish_loading(b"line\r\n".to_vec(), false, &syntax_loader);
+        assert!(!buffer.is_loading());
+        assert!(!buffer.read_only());
+        assert_eq!(buffer.id(), id);
+        assert_eq!(buffer.tab_width(), 8);
+        assert_eq!(buffer.line_ending(), LineEnding::CrLf);
+        assert_eq!(buffer.get_content().get_lines().to_string(), "line\r\n");
+    }
+
+    #[test]
+    fn read_only_by_file_is_not_read_only_by_hand() {
+        let syntax_loader = SyntaxLoader::new(&Settings::load_default(), None);
+        let mut buffer = BufferState::loading_placeholder(Path::new("/etc/hosts"));
+        buffer.finish_loading(b"127.0.0.1 localhost\n".to_vec(), true, &syntax_loader);
+        assert!(buffer.read_only());
+        assert!(buffer.read_only_by_file());
+
+        assert!(buffer.set_read_only(false));
+        assert!(buffer.set_read_only(true));
+        assert!(buffer.read_only());
+        assert!(!buffer.read_only_by_file());
+
+        let mut binary = BufferState::loading_placeholder(Path::new("/bin/ls"));
+        binary.finish_loading(b"\x7fELF\x00".to_vec(), true, &syntax_loader);
+        assert!(!binary.read_only_by_file());
     }
 }
@@ -17,12 +17,15 @@ limitations under the License.
 use std::cell::Ref;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use std::ffi::OsString;
 use std::path::PathBuf;
 
 use crate::buffer_state::BufferState;
 use crate::content_provider::RopeBasedContentProvider;
+use crate::folding::FoldState;
+use crate::line_endings::LineEnding;
 
 use crate::buffer_id::BufferId;
 use crate::buffer_state::BufferStateRef;
@@ -45,7 +48,43 @@ impl BufferStateObserver {
     }
 
     pub fn modified(&self) -> bool {
-        self.borrow_state().modified()
+        self.buffer_state.borrow().modified()
+    }
+
+    pub fn soft_wrap(&self) -> bool {
+        self.buffer_state.borrow().soft_wrap()
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.buffer_state.borrow().tab_width()
+    }
+
+    pub fn out_of_sync(&self) -> bool {
+        self.buffer_state.borrow().out_of_sync()
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.buffer_state.borrow().is_loading()
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.buffer_state.borrow().read_only()
+    }
+
+    pub fn read_only_by_file(&self) -> bool {
+        self.buffer_state.borrow().read_only_by_file()
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.buffer_state.borrow().line_ending()
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.buffer_state.borrow().is_binary()
+    }
+
+    pub fn binary(&self) -> Option<Arc<Vec<u8>>> {
+        self.buffer_state.borrow().binary()
     }
 
     pub fn buffer_id(&self) -> BufferId {
@@ -57,6 +96,11 @@ impl BufferStateObserver {
         Ref::map(self.buffer_state.borrow(), |x| x.get_content())
     }
 
+    /// borrows unmutably folds
+    pub fn borrow_folds(&self) -> Ref<FoldState> {
+        Ref::map(self.buffer_state.borrow(), |x| x.folds())
+    }
+
     pub fn borrow_state(&self) -> RefMut<BufferState> {
         self.buffer_state.borrow_mut()
     }
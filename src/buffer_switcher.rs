@@ -0,0 +1,164 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// List of buffers ordered by last focus, the way ctrl-tab works in GUI editors. It opens with the
+// previously focused buffer selected, so switching and hitting enter goes back and forth between
+// two buffers. Terminals do not report releasing a key, so instead of releasing ctrl, selection is
+// confirmed with enter. Repeating the switch command moves selection further (Interface calls
+// select_next), arrows move it too and esc cancels.
+
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::ColorStyle;
+use cursive::vec::Vec2;
+use cursive::view::View;
+use cursive::views::IdView;
+use cursive::Printer;
+use std::error;
+use std::fmt;
+
+use crate::buffer_id::BufferId;
+use crate::overlay_dialog::OverlayDialog;
+use crate::sly_view::SlyView;
+use crate::view_handle::ViewHandle;
+
+const MARGIN: usize = 2;
+const TITLE: &'static str = "Buffers";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BufferSwitcherResult {
+    Selected(BufferId),
+    Cancel,
+}
+
+#[derive(Clone, Debug)]
+pub struct BufferSwitcherError;
+
+impl fmt::Display for BufferSwitcherError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BufferSwitcherError (not defined)")
+    }
+}
+
+impl std::error::Error for BufferSwitcherError {
+    fn description(&self) -> &str {
+        "BufferSwitcherError (not defined)"
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+}
+
+pub struct BufferSwitcher {
+    buffers: Vec<(BufferId, String)>,
+    selected: usize,
+    handle: ViewHandle,
+    result: Option<Result<BufferSwitcherResult, BufferSwitcherError>>,
+}
+
+impl BufferSwitcher {
+    /// Buffers are (id, label) pairs, most recently focused first.
+    pub fn new(buffers: Vec<(BufferId, String)>) -> IdView<Self> {
+        let view = BufferSwitcher {
+            selected: if buffers.len() > 1 { 1 } else { 0 },
+            buffers,
+            handle: ViewHandle::new(),
+            result: None,
+        };
+
+        IdView::new(view.handle(), view)
+    }
+
+    /// Moves selection down, wrapping around.
+    pub fn select_next(&mut self) {
+        if !self.buffers.is_empty() {
+            self.selected = (self.selected + 1) % self.buffers.len();
+        }
+    }
+
+    fn select_previous(&mut self) {
+        if !self.buffers.is_empty() {
+            self.selected = (self.selected + self.buffers.len() - 1) % self.buffers.len();
+        }
+    }
+}
+
+impl View for BufferSwitcher {
+    fn draw(&self, printer: &Printer) {
+        printer.print_box((0, 0), printer.size, false);
+
+        printer.with_color(ColorStyle::title_primary(), |printer| {
+            printer.print((MARGIN, 0), &format!(" {} ", TITLE));
+        });
+
+        for (idx, (_, label)) in self.buffers.iter().enumerate() {
+            let style =
+                if idx == self.selected { ColorStyle::highlight() } else { ColorStyle::primary() };
+            printer.with_color(style, |printer| {
+                printer.print((MARGIN, idx + 1), label);
+            });
+        }
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        let widest_label = self.buffers.iter().map(|(_, label)| label.chars().count()).max();
+        let content_width = widest_label.unwrap_or(0).max(TITLE.len() + 2);
+
+        let size = Vec2::new(content_width + 2 * MARGIN, self.buffers.len() + 2);
+        size.zip_map(constraint, |a, b| a.min(b))
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Key(Key::Up) => self.select_previous(),
+            Event::Key(Key::Down) | Event::Key(Key::Tab) => self.select_next(),
+            Event::Key(Key::Enter) if !self.buffers.is_empty() => {
+                let buffer_id = self.buffers[self.selected].0.clone();
+                self.result = Some(Ok(BufferSwitcherResult::Selected(buffer_id)));
+            }
+            Event::Key(Key::Esc) => self.cancel(),
+            _ => {
+                debug!("buffer switcher got unhandled event {:?}", &event);
+                return EventResult::Ignored;
+            }
+        }
+        EventResult::Consumed(None)
+    }
+}
+
+impl OverlayDialog<BufferSwitcherResult, BufferSwitcherError> for BufferSwitcher {
+    fn is_displayed(&self) -> bool {
+        self.result.is_none()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+
+    fn get_result(&self) -> Option<Result<BufferSwitcherResult, BufferSwitcherError>> {
+        self.result.clone()
+    }
+
+    fn cancel(&mut self) {
+        self.result = Some(Ok(BufferSwitcherResult::Cancel))
+    }
+}
+
+impl SlyView for BufferSwitcher {
+    fn handle(&self) -> ViewHandle {
+        self.handle.clone()
+    }
+}
@@ -0,0 +1,236 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Small modal question with a fixed set of answers, like "save as root?". Every answer has a key
+// that selects it directly, they can also be selected with arrows and enter. Esc cancels.
+//
+// Created with ChoiceDialog::password, it asks for a password instead: typed characters are shown
+// as asterisks, enter accepts.
+//
+// Dialog does not know what the question was about, Interface remembers that along with handle
+// and acts on the key of chosen answer.
+
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::ColorStyle;
+use cursive::vec::Vec2;
+use cursive::view::View;
+use cursive::views::IdView;
+use cursive::Printer;
+use std::error;
+use std::fmt;
+
+use crate::overlay_dialog::OverlayDialog;
+use crate::sly_view::SlyView;
+use crate::view_handle::ViewHandle;
+
+const MARGIN: usize = 2;
+const PASSWORD_WIDTH: usize = 30;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChoiceDialogResult {
+    Chosen(char),
+    Entered(String),
+    Cancel,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChoiceDialogError;
+
+impl fmt::Display for ChoiceDialogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ChoiceDialogError (not defined)")
+    }
+}
+
+impl std::error::Error for ChoiceDialogError {
+    fn description(&self) -> &str {
+        "ChoiceDialogError (not defined)"
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+}
+
+pub struct ChoiceDialog {
+    title: String,
+    message: Vec<String>,
+    choices: Vec<(char, String)>,
+    selected: usize,
+    // Some if dialog asks for password (then there are no choices).
+    password: Option<String>,
+    handle: ViewHandle,
+    result: Option<Result<ChoiceDialogResult, ChoiceDialogError>>,
+}
+
+impl ChoiceDialog {
+    /// Message can have many lines. Choices are (key, label) pairs, keys should be lowercase.
+    pub fn new(title: String, message: &str, choices: Vec<(char, String)>) -> IdView<Self> {
+        ChoiceDialog::create(title, message, choices, None)
+    }
+
+    /// Asks for password, result is ChoiceDialogResult::Entered.
+    pub fn password(title: String, message: &str) -> IdView<Self> {
+        ChoiceDialog::create(title, message, vec![], Some(String::new()))
+    }
+
+    fn create(
+        title: String,
+        message: &str,
+        choices: Vec<(char, String)>,
+        password: Option<String>,
+    ) -> IdView<Self> {
+        let view = ChoiceDialog {
+            title,
+            message: message.lines().map(|line| line.to_string()).collect(),
+            choices,
+            selected: 0,
+            password,
+            handle: ViewHandle::new(),
+            result: None,
+        };
+
+        IdView::new(view.handle(), view)
+    }
+
+    fn choice_desc(key: char, label: &str) -> String {
+        format!("[{}] {}", key, label)
+    }
+
+    fn choose(&mut self, idx: usize) {
+        self.result = Some(Ok(ChoiceDialogResult::Chosen(self.choices[idx].0)));
+    }
+}
+
+impl View for ChoiceDialog {
+    fn draw(&self, printer: &Printer) {
+        let size = printer.size;
+        printer.print_box((0, 0), size, false);
+
+        printer.with_color(ColorStyle::title_primary(), |printer| {
+            printer.print((MARGIN, 0), &format!(" {} ", self.title));
+        });
+
+        let mut y = 1;
+        for line in self.message.iter() {
+            printer.print((MARGIN, y), line);
+            y += 1;
+        }
+        y += 1;
+
+        if let Some(ref password) = self.password {
+            printer.with_color(ColorStyle::highlight(), |printer| {
+                let width = printer.size.x.saturating_sub(2 * MARGIN);
+                let masked: String = "*".repeat(password.chars().count());
+                printer.print((MARGIN, y), &format!("{:width$}", masked, width = width));
+            });
+        }
+
+        for (idx, (key, label)) in self.choices.iter().enumerate() {
+            let style =
+                if idx == self.selected { ColorStyle::highlight() } else { ColorStyle::primary() };
+            printer.with_color(style, |printer| {
+                printer.print((MARGIN, y), &ChoiceDialog::choice_desc(*key, label));
+            });
+            y += 1;
+        }
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        let widest_message = self.message.iter().map(|line| line.chars().count()).max();
+        let widest_choice = self
+            .choices
+            .iter()
+            .map(|(key, label)| ChoiceDialog::choice_desc(*key, label).chars().count())
+            .max();
+        let content_width = widest_message
+            .unwrap_or(0)
+            .max(widest_choice.unwrap_or(0))
+            .max(self.title.chars().count() + 2);
+
+        // password is entered in a single line.
+        let (content_width, num_input_lines) = match self.password {
+            Some(_) => (content_width.max(PASSWORD_WIDTH), 1),
+            None => (content_width, self.choices.len()),
+        };
+
+        // borders, empty line between message and choices.
+        let size = Vec2::new(content_width + 2 * MARGIN, self.message.len() + num_input_lines + 3);
+        size.zip_map(constraint, |a, b| a.min(b))
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        if let Some(ref mut password) = self.password {
+            match event {
+                Event::Char(c) => password.push(c),
+                Event::Key(Key::Backspace) => {
+                    password.pop();
+                }
+                Event::Key(Key::Enter) => {
+                    self.result = Some(Ok(ChoiceDialogResult::Entered(password.clone())))
+                }
+                Event::Key(Key::Esc) => self.result = Some(Ok(ChoiceDialogResult::Cancel)),
+                _ => return EventResult::Ignored,
+            }
+            return EventResult::Consumed(None);
+        }
+
+        match event {
+            Event::Char(c) => match self.choices.iter().position(|(key, _)| *key == c) {
+                Some(idx) => self.choose(idx),
+                None => return EventResult::Ignored,
+            },
+            Event::Key(Key::Up) => self.selected = self.selected.saturating_sub(1),
+            Event::Key(Key::Down) => {
+                self.selected = (self.selected + 1).min(self.choices.len().saturating_sub(1))
+            }
+            Event::Key(Key::Enter) if !self.choices.is_empty() => {
+                let selected = self.selected;
+                self.choose(selected);
+            }
+            Event::Key(Key::Esc) => self.cancel(),
+            _ => {
+                debug!("choice dialog got unhandled event {:?}", &event);
+                return EventResult::Ignored;
+            }
+        }
+        EventResult::Consumed(None)
+    }
+}
+
+impl OverlayDialog<ChoiceDialogResult, ChoiceDialogError> for ChoiceDialog {
+    fn is_displayed(&self) -> bool {
+        self.result.is_none()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+
+    fn get_result(&self) -> Option<Result<ChoiceDialogResult, ChoiceDialogError>> {
+        self.result.clone()
+    }
+
+    fn cancel(&mut self) {
+        self.result = Some(Ok(ChoiceDialogResult::Cancel))
+    }
+}
+
+impl SlyView for ChoiceDialog {
+    fn handle(&self) -> ViewHandle {
+        self.handle.clone()
+    }
+}
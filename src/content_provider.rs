@@ -17,10 +17,11 @@ use ropey::Rope;
 use serde_json as sj;
 use std::io;
 use std::io::{BufRead, Read};
-use std::rc::Rc;
+use std::sync::Arc;
 use time;
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::interface::InterfaceNotifier;
 use crate::rich_content::HighlightSettings;
 use crate::rich_content::RichContent;
 use crate::rich_content::RichLine;
@@ -46,7 +47,10 @@ struct RopeBasedContent {
 impl RopeBasedContent {
     pub fn new(contents: Option<Vec<u8>>) -> Self {
         let rope: Rope = match contents {
-            Some(contents) => Rope::from_reader(&contents[..]).unwrap(),
+            // BufferState does not pass invalid UTF-8 here, but if it happens, we rather show
+            // replacement characters than crash.
+            Some(contents) => Rope::from_reader(&contents[..])
+                .unwrap_or_else(|_| Rope::from_str(&String::from_utf8_lossy(&contents))),
             None => Rope::new(),
         };
 
@@ -61,18 +65,25 @@ impl RopeBasedContent {
 pub struct RopeBasedContentProvider {
     history: Vec<RopeBasedContent>,
     current: usize,
-    // Contract: we do not version rich content. It doesn't make sense: redrawing screen
-    // has a similar complexity to syntax highlighting, provided it's implemented properly.
+    // Contract: we do not keep rich content for history. RichContent versions itself and is
+    // computed in background, see highlight_worker.rs.
     rich_content: Option<RichContent>,
-    highlight_settings_op: Option<Rc<HighlightSettings>>,
+    highlight_settings_op: Option<Arc<HighlightSettings>>,
+    // Used by highlight worker to wake up the interface when new lines are highlighted.
+    inot_op: Option<InterfaceNotifier>,
+    // Bumped on every change of content, used to invalidate caches.
+    version: usize,
 }
 
 // Applies events to RopeBasedContent producing new one, and returning *number of lines common* to
-// both new and original contents.
+// both new and original contents, and the line where the first change happened.
 // Now events are applied one after another in order they were issued.
 //TODO in some combinations offsets should be recomputed. But I expect no such combinations appear.
 // I should however check it just in case.
-fn apply_events(c: &RopeBasedContent, events: &Vec<EditEvent>) -> (RopeBasedContent, usize) {
+fn apply_events(
+    c: &RopeBasedContent,
+    events: &Vec<EditEvent>,
+) -> (RopeBasedContent, usize, usize) {
     let mut new_lines: Rope = c.lines.clone();
 
     // Offset is in CHARS, and since it's common, it's valid in both new and old contents.
@@ -93,6 +104,8 @@ fn apply_events(c: &RopeBasedContent, events: &Vec<EditEvent>) -> (RopeBasedCont
         }
     }
 
+    let line_of_first_change = c.lines.char_to_line(first_change_pos);
+
     // If first_change_pos is 0 (literally first character of file), obviously there are no
     // common lines betwen old and new version.
     // In other case (first_change_pos > 0), we ask of line_of_first_change. If it's not the first
@@ -100,7 +113,6 @@ fn apply_events(c: &RopeBasedContent, events: &Vec<EditEvent>) -> (RopeBasedCont
     let num_common_lines = if first_change_pos == 0 {
         0
     } else {
-        let line_of_first_change = c.lines.char_to_line(first_change_pos);
         if line_of_first_change > 0 {
             line_of_first_change - 1
         } else {
@@ -108,19 +120,43 @@ fn apply_events(c: &RopeBasedContent, events: &Vec<EditEvent>) -> (RopeBasedCont
         }
     };
 
-    (RopeBasedContent { lines: new_lines, timestamp: time::now() }, num_common_lines)
+    (
+        RopeBasedContent { lines: new_lines, timestamp: time::now() },
+        num_common_lines,
+        line_of_first_change,
+    )
 }
 
 impl RopeBasedContentProvider {
     pub fn new(
         contents: Option<Vec<u8>>,
-        highlight_settings_op: Option<Rc<HighlightSettings>>,
+        highlight_settings_op: Option<Arc<HighlightSettings>>,
     ) -> Self {
         RopeBasedContentProvider {
             history: vec![RopeBasedContent::new(contents)],
             current: 0,
             rich_content: None,
             highlight_settings_op: highlight_settings_op,
+            inot_op: None,
+            version: 0,
+        }
+    }
+
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Changes whenever results of is_code_at may change: on edits and when background
+    /// highlighting gets further.
+    pub fn code_revision(&self) -> (usize, Option<usize>) {
+        (self.version, self.rich_content.as_ref().map(|rich_content| rich_content.fresh_lines()))
+    }
+
+    /// Sets notifier used to request redraw when background highlighting progresses.
+    pub fn set_interface_notifier(&mut self, inot: InterfaceNotifier) {
+        self.inot_op = Some(inot);
+        if self.is_rich_content_enabled() {
+            self.set_rich_content_enabled(true); // restarting worker, so it gets the notifier.
         }
     }
 
@@ -131,16 +167,29 @@ impl RopeBasedContentProvider {
             self.rich_content = None;
             false
         } else {
-            // This costs O(1), but if content provider changes, it needs
-            // update.
+            // This costs O(1) (highlighting happens in background), but if content provider
+            // changes, it needs update.
+            let rope = self.get_lines().clone();
+            let inot_op = self.inot_op.clone();
             self.rich_content = self
                 .highlight_settings_op
                 .as_ref()
-                .map(|s| RichContent::new(s.clone(), self.get_lines().clone()));
+                .map(|s| RichContent::new(s.clone(), rope, inot_op));
             self.rich_content.is_some()
         }
     }
 
+    /// Replaces syntax used for highlighting and rebuilds RichContent. Since this is called on
+    /// explicit user request, rich content gets enabled if settings are provided.
+    pub fn set_highlight_settings(
+        &mut self,
+        highlight_settings_op: Option<Arc<HighlightSettings>>,
+    ) {
+        let enabled = highlight_settings_op.is_some();
+        self.highlight_settings_op = highlight_settings_op;
+        self.set_rich_content_enabled(enabled);
+    }
+
     pub fn is_rich_content_enabled(&self) -> bool {
         self.rich_content.is_some()
     }
@@ -157,10 +206,36 @@ impl RopeBasedContentProvider {
         self.history[self.current].lines.len_lines()
     }
 
-    pub fn get_rich_line(&self, line_no: usize) -> Option<Rc<RichLine>> {
+    /// Never blocks. Returned line can be stale (computed before the latest edit) or missing, if
+    /// highlighting did not catch up yet.
+    pub fn get_rich_line(&self, line_no: usize) -> Option<Arc<RichLine>> {
         self.rich_content.as_ref().and_then(|rich_content| rich_content.get_line(line_no))
     }
 
+    /// Returns number of lines (from the beginning) highlighted according to current content.
+    pub fn fresh_rich_lines(&self) -> usize {
+        self.rich_content.as_ref().map(|rich_content| rich_content.fresh_lines()).unwrap_or(0)
+    }
+
+    /// Returns rich line only if it's up-to-date. Does not request highlighting.
+    pub fn get_fresh_rich_line(&self, line_no: usize) -> Option<Arc<RichLine>> {
+        self.rich_content.as_ref().and_then(|rich_content| rich_content.get_fresh_line(line_no))
+    }
+
+    /// Returns false if char at offset is known to be a part of string or comment. Without syntax
+    /// highlighting everything is code.
+    pub fn is_code_at(&self, char_offset: usize) -> bool {
+        let rope = self.get_lines();
+        if char_offset >= rope.len_chars() {
+            return true;
+        }
+        let line_no = rope.char_to_line(char_offset);
+        match self.get_rich_line(line_no) {
+            Some(rich_line) => rich_line.is_code_at(char_offset - rope.line_to_char(line_no)),
+            None => true,
+        }
+    }
+
     pub fn can_undo(&self) -> bool {
         self.current > 0
     }
@@ -169,19 +244,23 @@ impl RopeBasedContentProvider {
         self.current < self.history.len() - 1
     }
 
-    pub fn submit_events(&mut self, events: Vec<EditEvent>) {
+    /// Returns the line where the first change happened.
+    pub fn submit_events(&mut self, events: Vec<EditEvent>) -> usize {
         debug!("got events {:?}", events);
-        let (new_content, num_common_lines) = apply_events(&self.history[self.current], &events);
+        let (new_content, num_common_lines, line_of_first_change) =
+            apply_events(&self.history[self.current], &events);
         let rope = new_content.lines.clone(); // O(1)
 
         self.history.truncate(self.current + 1); //droping redo's
         self.history.push(new_content);
         self.current += 1;
+        self.version += 1;
 
-        // Dropping outdated lines of RichContent. They will be regenerated on-demand.
+        // Outdated lines of RichContent are marked stale. They will be regenerated on-demand.
         self.rich_content.as_mut().map(|rich_content| {
-            rich_content.drop_lines(num_common_lines);
-            rich_content.update_raw_content(rope);
+            rich_content.update_raw_content(rope, num_common_lines);
         });
+
+        line_of_first_change
     }
 }
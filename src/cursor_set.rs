@@ -29,19 +29,26 @@ limitations under the License.
 use ropey::Rope;
 use crate::fuzzy_query_view::FuzzyQueryResult::Selected;
 use crate::buffer_state::BufferState;
+use crate::bracket_matching::{bracket_near_anchor, find_matching_bracket};
+use crate::line_endings::line_end;
+use crate::text_diff::TextDiff;
+use crate::content_provider::EditEvent;
+use crate::layout::{
+    char_at_column, column_of, layout_rope_line, line_wrap_points, next_grapheme_boundary,
+    prev_grapheme_boundary, row_of, Glyph,
+};
 use std::borrow::Borrow;
 use std::collections::HashSet;
 use serde::de::Unexpected::NewtypeStruct;
 
-const NEWLINE_LENGTH : usize = 1; // TODO(njskalski): add support for multisymbol newlines?
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Selection {
     pub b : usize, //begin inclusive
     pub e : usize, //end EXCLUSIVE (as *everywhere*)
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Cursor {
     pub s : Option<Selection>, // selection
     pub a: usize, //anchor
@@ -88,7 +95,7 @@ impl Into<Cursor> for usize {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct CursorSet {
     set : Vec<Cursor>
 }
@@ -105,20 +112,95 @@ impl CursorSet {
     pub fn set(&self) -> &Vec<Cursor> {
         &self.set
     }
+
+    /// Moves cursors to corresponding places of text reloaded from disk (new), using diff from
+    /// the old text. Cursors keep their position within line, as long as it's there.
+    pub fn adjust_after_reload(&mut self, old : &Rope, new : &Rope, diff : &TextDiff) {
+        let map = |offset : usize| -> usize {
+            let offset = std::cmp::min(offset, old.len_chars());
+            let old_line = old.char_to_line(offset);
+            let column = offset - old.line_to_char(old_line);
+
+            let new_line = std::cmp::min(diff.map_line(old_line), new.len_lines() - 1);
+            let begin = new.line_to_char(new_line);
+            std::cmp::min(begin + column, line_end(new, new_line))
+        };
+
+        for c in self.set.iter_mut() {
+            c.a = map(c.a);
+            c.s = c.s.map(|s| Selection { b : map(s.b), e : map(s.e) });
+            c.clear_pc();
+        }
+    }
+
+    /// Moves cursors to follow text they pointed at, after edits made elsewhere (another editor of
+    /// the same buffer). Cursors within changed text land at its beginning.
+    pub fn adjust_after_edits(&mut self, events : &[EditEvent]) {
+        for event in events {
+            let (offset, removed, inserted) = match event {
+                EditEvent::Insert { offset, content } => (*offset, 0, content.chars().count()),
+                EditEvent::Change { offset, length, content } => {
+                    (*offset, *length, content.chars().count())
+                }
+            };
+            let map = |pos : usize| -> usize {
+                if pos < offset {
+                    pos
+                } else if pos >= offset + removed {
+                    pos - removed + inserted
+                } else {
+                    offset
+                }
+            };
+
+            for c in self.set.iter_mut() {
+                c.a = map(c.a);
+                c.s = c.s.map(|s| Selection { b : map(s.b), e : map(s.e) });
+                c.clear_pc();
+            }
+        }
+
+        self.reduce();
+    }
+
+    /// Moves cursors (and selection ends) beyond len chars to the end of text. Used for cursors
+    /// restored from session, as file could have changed since.
+    pub fn limit_to(&mut self, len : usize) {
+        if self.set.is_empty() {
+            self.set.push(Cursor::single());
+        }
+
+        for c in self.set.iter_mut() {
+            c.a = std::cmp::min(c.a, len);
+            c.s = c.s.map(|s| Selection {
+                b : std::cmp::min(s.b, len),
+                e : std::cmp::min(s.e, len),
+            });
+            c.clear_pc();
+        }
+
+        self.reduce();
+    }
 }
 
 impl CursorSet {
 
-    pub fn move_left(&mut self) {
-        self.move_left_by(1);
+    pub fn move_left(&mut self, bs : &BufferState) {
+        self.move_left_by(bs, 1);
     }
 
-    pub fn move_left_by(&mut self, l : usize) {
+    /// Moves by l grapheme clusters, so a cursor never lands within one.
+    pub fn move_left_by(&mut self, bs : &BufferState, l : usize) {
+        let rope : &Rope = bs.get_content().get_lines();
+
         for mut c in &mut self.set {
             c.clear_both();
-            if c.a > 0 {
-                c.a -= std::cmp::min(c.a, l);
-            };
+            for _ in 0..l {
+                if c.a == 0 {
+                    break;
+                }
+                c.a = prev_grapheme_boundary(rope, c.a);
+            }
         }
     }
 
@@ -126,25 +208,30 @@ impl CursorSet {
         self.move_right_by(bs, 1);
     }
 
+    /// Moves by l grapheme clusters, so a cursor never lands within one.
     pub fn move_right_by(&mut self, bs : &BufferState, l : usize) {
-        let len = bs.get_content().get_lines().len_chars();
+        let rope : &Rope = bs.get_content().get_lines();
+        let len = rope.len_chars();
 
         for mut c in &mut self.set {
             c.clear_both();
             //we allow anchor after last char (so you can backspace last char)
-            if c.a < len {
-                c.a = std::cmp::min(c.a + l, len);
-            };
+            for _ in 0..l {
+                if c.a >= len {
+                    break;
+                }
+                c.a = next_grapheme_boundary(rope, c.a);
+            }
         }
     }
 
+    /// Moves cursors by l lines. Preferred column is counted in screen columns (see layout.rs),
+    /// so moving over tabs or wide characters keeps cursor visually in place.
     pub fn move_vertically_by(&mut self, bs : &BufferState, l : isize) {
         if l == 0 {
             return;
         }
 
-        let string = bs.get_content().get_lines().to_string();
-
         let rope : &Rope = bs.get_content().get_lines();
         let last_line_idx = rope.len_lines() - 1;
 
@@ -153,62 +240,109 @@ impl CursorSet {
             let cur_line_idx = rope.char_to_line(c.a);
             let cur_line_begin_char_idx = rope.line_to_char(cur_line_idx);
             let current_char_idx = c.a - cur_line_begin_char_idx;
+            let current_column = column_of(
+                &layout_rope_line(rope, cur_line_idx, bs.tab_width()), current_char_idx);
+
+            // folded lines are skipped, so target is counted in visible lines.
+            let target_line_idx = bs.folds().move_by_visible(cur_line_idx, l, rope.len_lines());
 
-            if cur_line_idx as isize + l > last_line_idx as isize/* && l > 0, checked before */ {
-                c.preferred_column = Some(current_char_idx);
+            if target_line_idx > last_line_idx as isize/* && l > 0, checked before */ {
+                c.preferred_column = Some(current_column);
                 c.a = rope.len_chars(); // pointing to index higher than last valid one.
                 continue;
             }
 
-            if cur_line_idx as isize + l < 0 {
-                c.preferred_column = Some(current_char_idx);
+            if target_line_idx < 0 {
+                c.preferred_column = Some(current_column);
                 c.a = 0;
                 continue;
             }
 
             // at this point we know that 0 <= cur_line_idx <= last_line_idx
-            debug_assert!(0 <= cur_line_idx);
             debug_assert!(cur_line_idx <= last_line_idx);
-            let new_line_idx = (cur_line_idx as isize + l) as usize;
-
-            // This is actually right. Ropey counts '\n' as last character of current line.
-            let last_char_idx_in_new_line = if new_line_idx == last_line_idx {
-                //this corresponds to a notion of "potential new character" beyond the buffer. It's a valid cursor position.
-                rope.len_chars()
-            } else {
-                rope.line_to_char(new_line_idx+1) - NEWLINE_LENGTH
-            };
-
+            let new_line_idx = target_line_idx as usize;
             let new_line_begin = rope.line_to_char(new_line_idx);
-            let new_line_num_chars = last_char_idx_in_new_line + 1 - new_line_begin ;
+
+            // Line break ("\n" or "\r\n") is the last position in a line. Last line has none, so
+            // position after the last character ("potential new character") is valid there.
+            let last_char_idx_in_new_line = line_end(rope, new_line_idx) - new_line_begin;
 
             //setting data
-            
             c.clear_selection();
 
-            if let Some(preferred_column) = c.preferred_column {
-                debug_assert!(preferred_column >= current_char_idx);
-                if preferred_column <= new_line_num_chars {
-                    c.clear_pc();
-                    c.a = new_line_begin + preferred_column;
-                } else {
-                    c.a = new_line_begin + new_line_num_chars;
-                }
-            } else {
-                let addon = if new_line_idx == last_line_idx { 1 } else { 0 };
-                // inequality below is interesting.
-                // The line with characters 012 is 3 characters long. So if current char idx is 3
-                // it means that line below needs at least 4 character to host it without shift left.
-                // "addon" is there to make sure that last line is counted as "one character longer"
-                // than it actually is, so we can position cursor one character behind buffer
-                // (appending).
-                if new_line_num_chars + addon <= current_char_idx {
-                    c.a = new_line_begin + new_line_num_chars - 1; //this -1 is needed.
-                    c.preferred_column = Some(current_char_idx);
+            let glyphs = layout_rope_line(rope, new_line_idx, bs.tab_width());
+            let column = c.preferred_column.unwrap_or(current_column);
+            let c_op = place_at_column(&glyphs, column, last_char_idx_in_new_line);
+            c.a = new_line_begin + c_op.0;
+            c.preferred_column = c_op.1;
+        }
+    }
+
+    /// Like move_vertically_by, but moves between visual rows of lines wrapped at width columns
+    /// (see layout.rs). Preferred column is counted from the beginning of a row.
+    pub fn move_visually_by(&mut self, bs : &BufferState, l : isize, width : usize) {
+        if l == 0 {
+            return;
+        }
+
+        let rope : &Rope = bs.get_content().get_lines();
+        let len_lines = rope.len_lines();
+        let tab_width = bs.tab_width();
+
+        for mut c in &mut self.set {
+            let mut line_idx = rope.char_to_line(c.a);
+            let mut points = line_wrap_points(rope, line_idx, width, tab_width);
+            let mut glyphs = layout_rope_line(rope, line_idx, tab_width);
+            let char_idx = c.a - rope.line_to_char(line_idx);
+            let mut row_idx = row_of(&points, char_idx);
+            let column = c.preferred_column.unwrap_or(
+                column_of(&glyphs, char_idx) - column_of(&glyphs, points[row_idx]));
+
+            c.clear_selection();
+
+            let mut steps_left = l.abs();
+            let mut out_of_buffer = false;
+            while steps_left > 0 {
+                if l > 0 && row_idx + 1 < points.len() {
+                    row_idx += 1;
+                } else if l < 0 && row_idx > 0 {
+                    row_idx -= 1;
                 } else {
-                    c.a = new_line_begin + current_char_idx;
+                    let step = if l > 0 { 1 } else { -1 };
+                    let next_line = bs.folds().move_by_visible(line_idx, step, len_lines);
+                    if next_line < 0 || next_line >= len_lines as isize {
+                        out_of_buffer = true;
+                        break;
+                    }
+                    line_idx = next_line as usize;
+                    points = line_wrap_points(rope, line_idx, width, tab_width);
+                    glyphs = layout_rope_line(rope, line_idx, tab_width);
+                    row_idx = if l > 0 { 0 } else { points.len() - 1 };
                 }
+                steps_left -= 1;
             }
+
+            if out_of_buffer {
+                c.preferred_column = Some(column);
+                c.a = if l > 0 { rope.len_chars() } else { 0 };
+                continue;
+            }
+
+            let line_begin = rope.line_to_char(line_idx);
+            let line_len = line_end(rope, line_idx) - line_begin;
+
+            let row_begin_column = column_of(&glyphs, points[row_idx]);
+            // the last glyph in a row that is not the last one belongs to the next row.
+            let row_end = if row_idx + 1 < points.len() {
+                char_at_column(&glyphs, column_of(&glyphs, points[row_idx + 1]) - 1)
+            } else {
+                line_len
+            };
+
+            let (a, preferred_column) =
+                place_at_column(&glyphs, row_begin_column + column, row_end);
+            c.a = line_begin + a;
+            c.preferred_column = preferred_column.map(|_| column);
         }
     }
 
@@ -243,4 +377,62 @@ impl CursorSet {
 //        self.set.dedup();
     }
 
-}
\ No newline at end of file
+    /// Moves cursors out of folded (hidden) lines, to the end of the fold's header line.
+    pub fn leave_folded_regions(&mut self, bs : &BufferState) {
+        let rope : &Rope = bs.get_content().get_lines();
+
+        for mut c in &mut self.set {
+            let line_idx = rope.char_to_line(c.a);
+            if !bs.folds().is_hidden(line_idx) {
+                continue;
+            }
+
+            let header_op = bs.folds().folded().iter()
+                .filter(|range| range.hides(line_idx))
+                .map(|range| range.first_line)
+                .min();
+
+            if let Some(header) = header_op {
+                c.clear_both();
+                c.a = line_end(rope, header);
+            }
+        }
+    }
+
+    /// Moves every cursor standing at (or right after) a bracket to the matching one. Cursors
+    /// with no matching bracket stay where they are.
+    pub fn jump_to_matching_bracket(&mut self, bs : &BufferState) {
+        let content = bs.get_content();
+        let rope : &Rope = content.get_lines();
+
+        for mut c in &mut self.set {
+            let bracket_op = bracket_near_anchor(rope, c.a);
+            let match_op = bracket_op.and_then(|bracket| {
+                find_matching_bracket(rope, bracket, |pos| content.is_code_at(pos))
+            });
+
+            if let Some(matching) = match_op {
+                c.clear_both();
+                c.a = matching;
+            }
+        }
+    }
+
+}
+
+/// Returns (char index within line, preferred column) of position closest to column, not beyond
+/// max_char_idx. Preferred column is kept only if position is not exactly at column (line is
+/// too short, or column points to the middle of a wide glyph).
+fn place_at_column(glyphs : &[Glyph], column : usize, max_char_idx : usize)
+    -> (usize, Option<usize>) {
+    if column > column_of(glyphs, max_char_idx) {
+        return (max_char_idx, Some(column));
+    }
+
+    let char_idx = char_at_column(glyphs, column);
+    if column_of(glyphs, char_idx) == column {
+        (char_idx, None)
+    } else {
+        (char_idx, Some(column))
+    }
+}
@@ -28,7 +28,15 @@ const default_settings_string: &'static str = r####"
       "redo" : ["ctrl","Z"]
     },
     "text_view" : {
-      "toggle_syntax_highlighting" : ["ctrl","h"]
+      "toggle_syntax_highlighting" : ["ctrl","h"],
+      "jump_to_matching_bracket" : ["ctrl","b"],
+      "fold" : ["alt","f"],
+      "unfold" : ["alt","u"],
+      "fold_all" : ["alt","F"],
+      "unfold_all" : ["alt","U"],
+      "toggle_soft_wrap" : ["alt","w"],
+      "convert_line_endings" : ["alt","l"],
+      "toggle_read_only" : ["alt","r"]
     },
     "global" : {
       "all_commands_bar" : ["ctrl", "y"],
@@ -36,24 +44,97 @@ const default_settings_string: &'static str = r####"
       "show_buffer_list" : ["ctrl", "o"],
       "command_mode" : ["ctrl", "e"],
       "quit" : ["ctrl", "q"],
+      "close_buffer" : ["alt", "c"],
       "close_window" : ["esc"],
+      "split_vertically" : ["alt", "v"],
+      "split_horizontally" : ["alt", "h"],
+      "close_pane" : ["alt", "x"],
+      "next_pane" : ["alt", "n"],
+      "previous_pane" : ["alt", "p"],
+      "grow_pane" : ["alt", "g"],
+      "shrink_pane" : ["alt", "G"],
+      "next_buffer" : ["alt", "N"],
+      "previous_buffer" : ["alt", "P"],
+      "buffer_switcher" : ["alt", "b"],
+      "toggle_tab_bar" : ["alt", "t"],
       "save" : ["ctrl", "s"],
       "save_as" : ["ctrl","w"],
       "open_file_dialog" : ["ctrl", "d"],
-      "start_lsp" : ["ctrl", "g"]
+      "start_lsp" : ["ctrl", "g"],
+      "set_syntax" : ["alt", "s"]
     },
     "file_bar" : {
     }
   },
+  "syntax" : {
+    "filenames" : {
+      "Makefile" : "Makefile",
+      "GNUmakefile" : "Makefile",
+      "makefile" : "Makefile",
+      "Dockerfile" : ["Dockerfile", "Bourne Again Shell (bash)"],
+      "Cargo.lock" : ["TOML", "INI"],
+      "Gemfile" : "Ruby",
+      "Rakefile" : "Ruby",
+      ".bashrc" : "Bourne Again Shell (bash)",
+      ".zshrc" : "Bourne Again Shell (bash)"
+    },
+    "overrides" : {
+    }
+  },
+  "text_view" : {
+    "highlight_current_line" : true,
+    "rainbow_brackets" : true,
+    "soft_wrap" : false,
+    "wrap_column" : 0,
+    "tab_width" : 4,
+    "whitespace" : "trailing",
+    "indent_guides" : false,
+    "open_binary_as_text" : false,
+    "whitespace_symbols" : {
+      "space" : "\u00b7",
+      "tab" : "\u2192",
+      "nbsp" : "\u237d",
+      "cr" : "\u240d",
+      "newline" : "\u21b5",
+      "indent_guide" : "\u2502"
+    }
+  },
+  "files" : {
+    "backup_on_save" : false,
+    "privileged_save_command" : ["sudo", "-A", "tee"],
+    "autosave" : "off",
+    "autosave_idle_seconds" : 5,
+    "recovery_interval_seconds" : 10,
+    "check_external_changes" : true,
+    "watch_project_files" : true,
+    "background_load_min_bytes" : 1048576,
+    "read_only_paths" : ["~/.cargo/registry", "~/.cargo/git", "~/.rustup/toolchains"],
+    "read_only_extensions" : ["log"]
+  },
   "performance" : {
     "auto_highlighting" : true,
     "max_files_indexed" : 1000
   },
+  "interface" : {
+    "tab_bar" : false
+  },
   "theme" : {
     "text_view" : {
       "background_color" : "#1d1d1d",
       "primary_text_color" : "#e5e5e5",
-      "secondary_text_color" : "#7f7f7f"
+      "secondary_text_color" : "#7f7f7f",
+      "current_line_background_color" : "#262626",
+      "matching_bracket_text_color" : "#ffffff",
+      "matching_bracket_background_color" : "#4a4a4a",
+      "rainbow_bracket_colors" : ["#ffd700", "#da70d6", "#179fff"],
+      "whitespace" : {
+        "space_color" : "#4a4a4a",
+        "tab_color" : "#4a4a4a",
+        "nbsp_color" : "#d7875f",
+        "cr_color" : "#d7875f",
+        "newline_color" : "#7f7f7f",
+        "indent_guide_color" : "#3a3a3a"
+      }
     },
     "file_view" :{
       "non_selected_background" : "#282C34",
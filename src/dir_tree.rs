@@ -21,7 +21,9 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::rc::Rc;
 
-// TODO(njskalski) add RefCell<Vec<LazyTreeNode>> cache, refresh "on file change"
+// Nodes are not cached, children are listed on every call, so the tree follows changes on disk by
+// itself. Views displaying it have to be refreshed though, FileDialog::refresh is called when
+// FileWatcher reports changes.
 // TODO(njskalski) add hotloading directories (but remember to keep tests working!)
 // TODO(njskalski) create fourth category for out-of-folders files (second argument of constructor).
 
@@ -0,0 +1,169 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Looking for files of open buffers changed on disk by other programs. It's done on a worker
+// thread: AppState hands over what it knows (stamps of files and content of buffers that follow
+// their files), worker stats the files, reads the changed ones and diffs them with the buffer
+// content. Results come back as IEvent::DiskChecked and are applied by AppState, unless buffer
+// was saved, reloaded or edited meanwhile (then the next check takes care of it).
+
+use filesystem::FileSystem;
+use ropey::Rope;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::buffer_id::BufferId;
+use crate::interface::InterfaceNotifier;
+use crate::line_endings::strip_bom;
+use crate::text_diff::TextDiff;
+use crate::utils::{file_stamp, FileStamp};
+use crate::FileSystemType;
+
+//docs: https://docs.rs/uid/0.1.4/uid/struct.Id.html
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct WorkerIdType(());
+
+type Id = uid::Id<WorkerIdType>;
+
+/// File of a buffer to check.
+pub struct DiskCheck {
+    pub buffer_id: BufferId,
+    pub path: PathBuf,
+    /// Stamp of the file when it was loaded, saved or last checked.
+    pub stamp: Option<FileStamp>,
+    /// Version and content of buffer that is to follow its file (unmodified text), None otherwise.
+    pub content: Option<(usize, Rope)>,
+}
+
+/// File changed on disk.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiskChange {
+    pub buffer_id: BufferId,
+    /// Stamp the check started from.
+    pub old_stamp: Option<FileStamp>,
+    /// None if file is gone.
+    pub new_stamp: Option<FileStamp>,
+    /// New content, for buffers that follow their files and files that are text.
+    pub reload: Option<Reload>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Reload {
+    /// Version of buffer content the diff was computed against.
+    pub version: usize,
+    pub text: String,
+    pub bom: bool,
+    pub diff: TextDiff,
+}
+
+/// Checks files on a separate thread, returns id of the worker. It ends with IEvent::DiskChecked.
+pub fn start(fs: FileSystemType, checks: Vec<DiskCheck>, inot: InterfaceNotifier) -> usize {
+    let worker_id: usize = Id::new().get();
+    thread::spawn(move || {
+        let changes = check(&fs, checks);
+        inot.disk_checked(worker_id, changes);
+    });
+    worker_id
+}
+
+pub fn check(fs: &FileSystemType, checks: Vec<DiskCheck>) -> Vec<DiskChange> {
+    let mut changes: Vec<DiskChange> = Vec::new();
+    for check in checks {
+        let new_stamp = file_stamp(fs, &check.path);
+        if new_stamp == check.stamp {
+            continue;
+        }
+        debug!("{:?} changed on disk.", &check.path);
+
+        let reload = match (&new_stamp, check.content) {
+            (Some(_), Some((version, old))) => read_text(fs, &check.path).map(|(text, bom)| {
+                let diff = TextDiff::new(&old, &Rope::from_str(&text));
+                Reload { version, text, bom, diff }
+            }),
+            _ => None,
+        };
+
+        changes.push(DiskChange {
+            buffer_id: check.buffer_id,
+            old_stamp: check.stamp,
+            new_stamp,
+            reload,
+        });
+    }
+    changes
+}
+
+/// Reads file as text, returns None if it's missing or not UTF-8.
+pub fn read_text(fs: &FileSystemType, path: &Path) -> Option<(String, bool)> {
+    let bytes = match fs.read_file(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("failed to read {:?}, because \"{}\"", path, e);
+            return None;
+        }
+    };
+    let (bom, bytes) = strip_bom(bytes);
+    String::from_utf8(bytes).ok().map(|text| (text, bom))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_changed_files_are_reported() {
+        let fs = FileSystemType::new();
+        fs.create_dir_all("/project").unwrap();
+        fs.create_file("/project/same.txt", "same").unwrap();
+        fs.create_file("/project/changed.txt", "a\nb\n").unwrap();
+
+        let stamp = |path: &str| file_stamp(&fs, &PathBuf::from(path));
+        let same = DiskCheck {
+            buffer_id: BufferId::new(),
+            path: PathBuf::from("/project/same.txt"),
+            stamp: stamp("/project/same.txt"),
+            content: Some((0, Rope::from_str("same"))),
+        };
+        let changed_id = BufferId::new();
+        let changed = DiskCheck {
+            buffer_id: changed_id.clone(),
+            path: PathBuf::from("/project/changed.txt"),
+            stamp: stamp("/project/changed.txt"),
+            content: Some((3, Rope::from_str("a\nb\n"))),
+        };
+        let removed = DiskCheck {
+            buffer_id: BufferId::new(),
+            path: PathBuf::from("/project/removed.txt"),
+            stamp: stamp("/project/changed.txt"),
+            content: None,
+        };
+
+        fs.remove_file("/project/changed.txt").unwrap();
+        fs.create_file("/project/changed.txt", "a\nc\nb\n").unwrap();
+
+        let changes = check(&fs, vec![same, changed, removed]);
+        assert_eq!(changes.len(), 2);
+
+        assert_eq!(changes[0].buffer_id, changed_id);
+        let reload = changes[0].reload.as_ref().unwrap();
+        assert_eq!(reload.version, 3);
+        assert_eq!(reload.text, "a\nc\nb\n");
+        assert_eq!(reload.diff.hunks().len(), 1);
+
+        assert!(changes[1].new_stamp.is_none());
+        assert!(changes[1].reload.is_none());
+    }
+}
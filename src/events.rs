@@ -16,10 +16,13 @@ limitations under the License.
 
 use crate::buffer_id::BufferId;
 use crate::content_provider;
+use crate::disk_check::DiskChange;
+use crate::folding::FoldRange;
 use serde_json as sj;
 use std::path::PathBuf;
 use std::sync::mpsc;
 use crate::view_handle::ViewHandle;
+use crate::split_view::SplitOrientation;
 
 pub type IChannel = mpsc::Sender<IEvent>;
 
@@ -32,8 +35,10 @@ pub enum IEvent {
     //Interface event
     AllCommandsBar,
     QuitSly,
+    CloseCurrentBuffer,
     ShowFileBar,
     ShowBufferList,
+    ShowSyntaxList,
     ShowSaveAs(BufferId, Option<PathBuf>),
     OpenFileDialog,
     SaveCurrentBuffer,
@@ -44,10 +49,32 @@ pub enum IEvent {
     //    WorkerRefresh(usize),
     WorkerFinished(usize),
     CloseWindow,
+    SplitPane(SplitOrientation),
+    ClosePane,
+    FocusNextPane,
+    FocusPreviousPane,
+    ResizePane(isize), // in percent of split, see SplitView::resize_focused.
+    NextBuffer,
+    PreviousBuffer,
+    ShowBufferSwitcher,
+    ToggleTabBar,
+    Tick, // sent every TICK_INTERVAL by ticker thread, drives autosave.
 
     // Buffer edit events are now in the same queue, not sure yet if that's final.
-    BufferEditEvent(BufferId, Vec<content_provider::EditEvent>),
+    // ViewHandle is of editor that made the edit, others of the same buffer have to follow it.
+    BufferEditEvent(BufferId, ViewHandle, Vec<content_provider::EditEvent>),
     EnableLSP,
+    FoldingRanges(BufferId, usize, Vec<FoldRange>), // version of content they were computed for.
+    LspInitialized,
+    // file read in background, see AppState::open_file_in_background.
+    FileLoaded(BufferId, Result<Vec<u8>, String>),
+    FilesChanged(Vec<PathBuf>, Vec<PathBuf>), // created, removed. Sent by file watcher.
+    FilesIndexed(PathBuf, Vec<PathBuf>), // root, batch of files found in it by file indexer.
+    // worker id, root, number of files indexed, whether file_index_limit was hit.
+    IndexingFinished(usize, PathBuf, usize, bool),
+    DiskChecked(usize, Vec<DiskChange>), // worker id, files changed on disk, see disk_check.rs.
+    // worker id, buffer, path and version of content written, see safe_save::start_privileged_save.
+    PrivilegedSaved(usize, BufferId, PathBuf, usize, Result<(), String>),
 
     Proto(String), //for quick hacking.
 }
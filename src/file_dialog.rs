@@ -385,6 +385,31 @@ fn get_on_file_edit_save_submit(file_dialog_handle: ViewHandle) -> impl Fn(&mut
     }
 }
 
+/// Returns paths of expanded directories, in order of rows. A directory is expanded if the row
+/// below is its subdirectory (collapsing removes children, see
+/// get_dir_tree_on_collapse_switch_callback).
+fn expanded_dirs(tree_view: &TreeViewType) -> Vec<PathBuf> {
+    let mut result: Vec<PathBuf> = Vec::new();
+    for row in 1..tree_view.len() {
+        let item = tree_view.borrow_item(row - 1).unwrap().clone();
+        let next = tree_view.borrow_item(row).unwrap().clone();
+        match (item.path(), next.path().and_then(|path| path.parent())) {
+            (Some(path), Some(parent)) if item.is_dir() && path == parent => {
+                result.push(path.to_owned())
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+fn find_dir_row(tree_view: &TreeViewType, path: &Path) -> Option<usize> {
+    (0..tree_view.len()).find(|row| {
+        let item = tree_view.borrow_item(*row).unwrap();
+        item.is_dir() && item.path() == Some(path)
+    })
+}
+
 pub fn expand_tree(
     tree_view: &mut TreeViewType,
     file_list_view: &mut SelectViewType,
@@ -538,6 +563,54 @@ impl FileDialog {
             .call_on(&view::Selector::Id(EDIT_VIEW_ID), views::IdView::<EditView>::get_mut)
             .unwrap()
     }
+
+    /// Lists directories and files again, after they changed on disk. Directories that were
+    /// expanded and still exist are expanded again, selection stays on the selected directory (or
+    /// its closest remaining parent), and file list shows its current content.
+    pub fn refresh(&mut self) {
+        let mut tree_view_ref = self.tree_view();
+        let mut file_list_view_ref = self.file_list_view();
+        let tree_view = tree_view_ref.borrow_mut();
+        let file_list_view = file_list_view_ref.borrow_mut();
+
+        let selected_path_op: Option<PathBuf> = tree_view.row().and_then(|row| {
+            let item = tree_view.borrow_item(row).unwrap().clone();
+            if item.is_dir() {
+                item.path().map(|path| path.to_owned())
+            } else {
+                None
+            }
+        });
+        let root_expanded = tree_view.len() > 1;
+        let expanded = expanded_dirs(tree_view);
+
+        // see the comment about set_collapsed in get_dir_tree_on_collapse_switch_callback.
+        tree_view.set_collapsed(0, false);
+        tree_view.remove_children(0);
+        tree_view.set_collapsed(0, true);
+        tree_view.set_selected_row(0);
+        file_list_view.clear();
+
+        if !root_expanded {
+            return;
+        }
+        tree_view.expand_item(0);
+        expand_row_add_children(tree_view, 0, false);
+        // parents come before their subdirectories, so they are expanded first.
+        for path in expanded.iter() {
+            if let Some(row) = find_dir_row(tree_view, path) {
+                tree_view.expand_item(row);
+                expand_row_add_children(tree_view, row, false);
+            }
+        }
+
+        let selected_row_op = selected_path_op
+            .as_ref()
+            .and_then(|path| path.ancestors().filter_map(|p| find_dir_row(tree_view, p)).next());
+        let selected_row = selected_row_op.unwrap_or(0);
+        tree_view.set_selected_row(selected_row);
+        dir_tree_on_select(tree_view, file_list_view, selected_row);
+    }
 }
 
 // TODO(njskalski) maybe just use ViewWrapper?
@@ -0,0 +1,510 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// On-disk cache of file index, so big projects are searchable right after start. For every project
+// directory (root) there is a pointer file in cache_dir, named after a hash of CacheKey (root and
+// settings that affect what is indexed), holding name of the directory with current version of
+// the cache, "<hash>.<pid>.<number>". The directory has two files:
+// - index.fst is fst::Map from file name to position in item table,
+// - items.json is the item table (paths of files, grouped by name) with CacheKey, format version
+//   and checksum of index.fst.
+//
+// A new version is written to its own directory (so sly processes of the same project don't mix
+// their files), then a new pointer is renamed over the old one, a single rename that replaces a
+// file. Readers see either complete old or complete new cache, never none. The writer removes the
+// version it replaced, so a reader that finds files of its version gone reads the pointer again.
+// Two processes writing at once can leave a version nobody points to, it's removed by a later
+// write once its writer is not running.
+//
+// A cache that does not belong to its key, is of other version or doesn't add up (checksum, fst
+// pointing outside of item table, file names not matching) is removed and treated as missing.
+//
+// Cache is only a head start: project directories are indexed anyway (see file_indexer.rs), and
+// once that's done AppState reconciles the index and writes new cache if anything changed.
+
+use filesystem::{DirEntry, FileSystem};
+use fst::map::OpBuilder;
+use fst::{Map, MapBuilder, Streamer};
+use serde_json;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::utils::{cache_dir, is_process_alive, stable_hash};
+use crate::FileSystemType;
+
+// bump when format changes, older caches are discarded.
+const CACHE_VERSION: u32 = 1;
+
+/// What cached index depends on.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub root: PathBuf,
+    pub enable_gitignore: bool,
+    pub file_index_limit: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ItemTable {
+    version: u32,
+    key: CacheKey,
+    fst_checksum: u64,
+    /// files[value in fst] are paths of files with that name.
+    files: Vec<Vec<PathBuf>>,
+}
+
+/// Index of files of one root.
+pub struct CachedIndex {
+    pub map: Map,
+    pub files: Vec<Vec<PathBuf>>,
+}
+
+impl CachedIndex {
+    pub fn build(files: &Vec<PathBuf>) -> Result<CachedIndex, fst::Error> {
+        let mut by_name: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for path in files {
+            if let Some(name) = path.file_name() {
+                by_name.entry(name.to_string_lossy().to_string()).or_default().push(path.clone());
+            }
+        }
+
+        let mut names: Vec<(String, Vec<PathBuf>)> = by_name.into_iter().collect();
+        names.sort();
+
+        let mut builder = MapBuilder::memory();
+        let mut files: Vec<Vec<PathBuf>> = Vec::new();
+        for (name, paths) in names {
+            builder.insert(name, files.len() as u64)?;
+            files.push(paths);
+        }
+
+        Ok(CachedIndex { map: Map::from_bytes(builder.into_inner()?)?, files })
+    }
+
+    /// Merges indexes of several roots into one, without sorting the names again.
+    pub fn merge(mut indexes: Vec<CachedIndex>) -> Result<CachedIndex, fst::Error> {
+        if indexes.len() == 1 {
+            return Ok(indexes.remove(0));
+        }
+
+        let mut op = OpBuilder::new();
+        for index in indexes.iter() {
+            op.push(&index.map);
+        }
+        let mut union = op.union();
+
+        let mut builder = MapBuilder::memory();
+        let mut files: Vec<Vec<PathBuf>> = Vec::new();
+        while let Some((name, values)) = union.next() {
+            // union doesn't keep order of maps.
+            let mut values = values.to_vec();
+            values.sort_by_key(|indexed_value| indexed_value.index);
+
+            let mut paths: Vec<PathBuf> = Vec::new();
+            for indexed_value in values {
+                let index = &indexes[indexed_value.index];
+                paths.extend(index.files[indexed_value.value as usize].iter().cloned());
+            }
+            builder.insert(name, files.len() as u64)?;
+            files.push(paths);
+        }
+
+        Ok(CachedIndex { map: Map::from_bytes(builder.into_inner()?)?, files })
+    }
+
+    pub fn all_files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.files.iter().flatten()
+    }
+
+    /// Checks that fst and item table describe the same files.
+    fn is_consistent(&self) -> bool {
+        if self.map.len() != self.files.len() {
+            return false;
+        }
+        let mut stream = self.map.stream();
+        while let Some((name, value)) = stream.next() {
+            let paths = match self.files.get(value as usize) {
+                Some(paths) => paths,
+                None => return false,
+            };
+            let name_matches = |path: &PathBuf| {
+                path.file_name().map(|n| n.to_string_lossy().as_bytes() == name).unwrap_or(false)
+            };
+            if paths.is_empty() || !paths.iter().all(name_matches) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Returns $XDG_CACHE_HOME/sly/file_index (see utils::cache_dir).
+pub fn file_index_cache_dir() -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join("file_index"))
+}
+
+const FST_FILE: &str = "index.fst";
+const TABLE_FILE: &str = "items.json";
+
+// numbers versions written by this process, its threads can write cache of the same root at once.
+static VERSIONS_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns name of pointer to current version of cache of key.
+fn cache_name(key: &CacheKey) -> String {
+    let id = format!(
+        "{}\n{}\n{}",
+        key.root.to_string_lossy(),
+        key.enable_gitignore,
+        key.file_index_limit
+    );
+    format!("{:016x}", stable_hash(id.as_bytes()))
+}
+
+fn remove_dir_if_exists(fs: &FileSystemType, path: &Path) -> Result<(), io::Error> {
+    if fs.is_dir(path) {
+        fs.remove_dir_all(path)?;
+    }
+    Ok(())
+}
+
+/// Returns name of version directory the pointer names, if it's a valid one.
+fn read_pointer(fs: &FileSystemType, pointer: &Path, name: &str) -> Option<String> {
+    if !fs.is_file(pointer) {
+        return None;
+    }
+    let version = String::from_utf8(fs.read_file(pointer).ok()?).ok()?;
+    let is_version_of_name =
+        version.starts_with(&format!("{}.", name)) && !version.contains(std::path::is_separator);
+    if is_version_of_name {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+pub fn write_cache(
+    fs: &FileSystemType,
+    dir: &Path,
+    key: &CacheKey,
+    index: &CachedIndex,
+) -> Result<(), io::Error> {
+    let name = cache_name(key);
+    let pointer = dir.join(&name);
+    let version = format!(
+        "{}.{}.{}",
+        name,
+        process::id(),
+        VERSIONS_WRITTEN.fetch_add(1, Ordering::SeqCst)
+    );
+    let version_dir = dir.join(&version);
+    let temp_pointer = dir.join(format!("{}.tmp", version));
+
+    // leftovers of a crashed write of a process that had the same pid.
+    remove_dir_if_exists(fs, &version_dir)?;
+    // older caches had a directory in place of the pointer.
+    remove_dir_if_exists(fs, &pointer)?;
+
+    let result = write_cache_files(fs, &version_dir, key, index).and_then(|_| {
+        let replaced = read_pointer(fs, &pointer, &name);
+        fs.create_file(&temp_pointer, version.as_bytes())?;
+        fs.rename(&temp_pointer, &pointer)?;
+        Ok(replaced)
+    });
+    match result {
+        Ok(replaced) => {
+            if let Some(replaced) = replaced {
+                remove_dir_if_exists(fs, &dir.join(replaced)).ok();
+            }
+        }
+        Err(_) => {
+            remove_dir_if_exists(fs, &version_dir).ok();
+            fs.remove_file(&temp_pointer).ok();
+        }
+    }
+    remove_leftovers(fs, dir, &name);
+    result.map(|_| ())
+}
+
+/// Removes versions of cache (and pointers being written) left by processes that don't run, except
+/// the current one.
+fn remove_leftovers(fs: &FileSystemType, dir: &Path, name: &str) {
+    let current = read_pointer(fs, &dir.join(name), name);
+    let entries = match fs.read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let prefix = format!("{}.", name);
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let file_name = match path.file_name() {
+            Some(file_name) => file_name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        if !file_name.starts_with(&prefix) || current.as_ref() == Some(&file_name) {
+            continue;
+        }
+        let pid: u32 = match file_name[prefix.len()..].split('.').next().map(str::parse) {
+            Some(Ok(pid)) => pid,
+            _ => continue,
+        };
+        if pid == process::id() || is_process_alive(pid) {
+            continue;
+        }
+        let removed = if fs.is_dir(&path) {
+            fs.remove_dir_all(&path)
+        } else {
+            fs.remove_file(&path)
+        };
+        if let Err(e) = removed {
+            warn!("failed to remove file index cache leftover {:?}: {}", &path, e);
+        }
+    }
+}
+
+fn write_cache_files(
+    fs: &FileSystemType,
+    cache_dir: &Path,
+    key: &CacheKey,
+    index: &CachedIndex,
+) -> Result<(), io::Error> {
+    fs.create_dir_all(cache_dir)?;
+    let fst_bytes = index.map.as_fst().as_bytes();
+    fs.create_file(cache_dir.join(FST_FILE), fst_bytes)?;
+
+    let table = ItemTable {
+        version: CACHE_VERSION,
+        key: key.clone(),
+        fst_checksum: stable_hash(fst_bytes),
+        files: index.files.clone(),
+    };
+    let json = serde_json::to_vec(&table)?;
+    fs.create_file(cache_dir.join(TABLE_FILE), json)
+}
+
+/// Builds and writes cache of files on a separate thread.
+pub fn write_cache_in_background(
+    fs: FileSystemType,
+    dir: PathBuf,
+    key: CacheKey,
+    files: Vec<PathBuf>,
+) {
+    thread::spawn(move || {
+        let result = CachedIndex::build(&files)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+            .and_then(|index| write_cache(&fs, &dir, &key, &index));
+        match result {
+            Ok(()) => debug!("file index cache of {:?} written, {} files", &key.root, files.len()),
+            Err(e) => warn!("unable to write file index cache of {:?}: {}", &key.root, e),
+        }
+    });
+}
+
+/// Returns cached index of key, if there is a valid one. Invalid cache is removed.
+pub fn read_cache(fs: &FileSystemType, dir: &Path, key: &CacheKey) -> Option<CachedIndex> {
+    let name = cache_name(key);
+    let version = read_pointer(fs, &dir.join(&name), &name)?;
+    read_version(fs, dir, key, version)
+}
+
+/// Reads version of cache of key, or the one that replaced it meanwhile.
+fn read_version(
+    fs: &FileSystemType,
+    dir: &Path,
+    key: &CacheKey,
+    mut version: String,
+) -> Option<CachedIndex> {
+    let name = cache_name(key);
+    let pointer = dir.join(&name);
+    loop {
+        let reason = match read_cache_files(fs, &dir.join(&version), key) {
+            Ok(index) => return Some(index),
+            Err(reason) => reason,
+        };
+        match read_pointer(fs, &pointer, &name) {
+            // version was replaced (and removed) while it was read.
+            Some(newer) if newer != version => version = newer,
+            _ => {
+                warn!("discarding file index cache of {:?}, because \"{}\"", &key.root, reason);
+                fs.remove_file(&pointer).ok();
+                remove_dir_if_exists(fs, &dir.join(&version)).ok();
+                return None;
+            }
+        }
+    }
+}
+
+fn read_cache_files(
+    fs: &FileSystemType,
+    cache_dir: &Path,
+    key: &CacheKey,
+) -> Result<CachedIndex, String> {
+    let table_bytes = fs.read_file(cache_dir.join(TABLE_FILE)).map_err(|e| e.to_string())?;
+    let table: ItemTable = serde_json::from_slice(&table_bytes).map_err(|e| e.to_string())?;
+    if table.version != CACHE_VERSION {
+        return Err(format!("unsupported version {}", table.version));
+    }
+    if table.key != *key {
+        return Err("it belongs to other directory or settings".to_string());
+    }
+
+    let fst_bytes = fs.read_file(cache_dir.join(FST_FILE)).map_err(|e| e.to_string())?;
+    if stable_hash(&fst_bytes) != table.fst_checksum {
+        return Err("checksum mismatch".to_string());
+    }
+    let map = Map::from_bytes(fst_bytes).map_err(|e| e.to_string())?;
+
+    let index = CachedIndex { map, files: table.files };
+    if !index.is_consistent() {
+        return Err("index does not match item table".to_string());
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(root: &str) -> CacheKey {
+        CacheKey { root: PathBuf::from(root), enable_gitignore: true, file_index_limit: 100 }
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    fn version_dir(fs: &FileSystemType, dir: &Path, key: &CacheKey) -> PathBuf {
+        let name = cache_name(key);
+        dir.join(read_pointer(fs, &dir.join(&name), &name).unwrap())
+    }
+
+    fn dir_names(fs: &FileSystemType, dir: &Path) -> Vec<PathBuf> {
+        let mut names: Vec<PathBuf> =
+            fs.read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn cache_round_trip_and_merge() {
+        let fs = FileSystemType::new();
+        let dir = Path::new("/cache");
+        let a = paths(&["/a/main.rs", "/a/src/main.rs", "/a/lib.rs"]);
+        let b = paths(&["/b/main.rs", "/b/zed.rs"]);
+
+        write_cache(&fs, dir, &key("/a"), &CachedIndex::build(&a).unwrap()).unwrap();
+        write_cache(&fs, dir, &key("/b"), &CachedIndex::build(&b).unwrap()).unwrap();
+
+        let read_a = read_cache(&fs, dir, &key("/a")).unwrap();
+        assert_eq!(read_a.map.get("main.rs"), Some(1));
+        assert_eq!(read_a.files[1], paths(&["/a/main.rs", "/a/src/main.rs"]));
+
+        // written again, old cache is replaced.
+        let replaced = version_dir(&fs, dir, &key("/a"));
+        write_cache(&fs, dir, &key("/a"), &CachedIndex::build(&a).unwrap()).unwrap();
+        assert!(read_cache(&fs, dir, &key("/a")).is_some());
+        assert_ne!(version_dir(&fs, dir, &key("/a")), replaced);
+        let mut expected: Vec<PathBuf> = vec![
+            dir.join(cache_name(&key("/a"))),
+            version_dir(&fs, dir, &key("/a")),
+            dir.join(cache_name(&key("/b"))),
+            version_dir(&fs, dir, &key("/b")),
+        ];
+        expected.sort();
+        assert_eq!(dir_names(&fs, dir), expected);
+
+        let read_b = read_cache(&fs, dir, &key("/b")).unwrap();
+        let merged = CachedIndex::merge(vec![read_a, read_b]).unwrap();
+        assert!(merged.is_consistent());
+        let main = merged.map.get("main.rs").unwrap() as usize;
+        assert_eq!(merged.files[main], paths(&["/a/main.rs", "/a/src/main.rs", "/b/main.rs"]));
+        assert_eq!(merged.all_files().count(), 5);
+
+        // other settings, other cache.
+        let mut other_key = key("/a");
+        other_key.file_index_limit = 10;
+        assert!(read_cache(&fs, dir, &other_key).is_none());
+    }
+
+    #[test]
+    fn corrupt_cache_is_discarded() {
+        let fs = FileSystemType::new();
+        let dir = Path::new("/cache");
+        let index = CachedIndex::build(&paths(&["/a/main.rs"])).unwrap();
+        write_cache(&fs, dir, &key("/a"), &index).unwrap();
+
+        let cache_dir = version_dir(&fs, dir, &key("/a"));
+        let fst_path = cache_dir.join(FST_FILE);
+        let mut fst_bytes = fs.read_file(&fst_path).unwrap();
+        fst_bytes[0] ^= 0xff;
+        fs.write_file(&fst_path, fst_bytes).unwrap();
+
+        assert!(read_cache(&fs, dir, &key("/a")).is_none());
+        assert!(!fs.is_dir(&cache_dir));
+        assert!(dir_names(&fs, dir).is_empty());
+
+        // table not matching fst.
+        write_cache(&fs, dir, &key("/a"), &index).unwrap();
+        let table_path = version_dir(&fs, dir, &key("/a")).join(TABLE_FILE);
+        fs.write_file(&table_path, b"{\"version\": 1}".to_vec()).unwrap();
+        assert!(read_cache(&fs, dir, &key("/a")).is_none());
+        assert!(!fs.is_file(&table_path));
+    }
+
+    #[test]
+    fn reader_follows_version_replaced_while_reading() {
+        let fs = FileSystemType::new();
+        let dir = Path::new("/cache");
+        let index = CachedIndex::build(&paths(&["/a/main.rs"])).unwrap();
+        write_cache(&fs, dir, &key("/a"), &index).unwrap();
+        // what pointer named before it was replaced, the version is gone.
+        let replaced = format!("{}.1.0", cache_name(&key("/a")));
+
+        let read = read_version(&fs, dir, &key("/a"), replaced);
+
+        assert_eq!(read.unwrap().files, index.files);
+        assert!(fs.is_dir(version_dir(&fs, dir, &key("/a"))));
+    }
+
+    #[test]
+    fn leftovers_of_processes_not_running_are_removed() {
+        let fs = FileSystemType::new();
+        let dir = Path::new("/cache");
+        let name = cache_name(&key("/a"));
+        let gone_pid = u32::max_value();
+        let own_pid = process::id();
+        // old format cache, abandoned version and pointer, one being written, other root's.
+        fs.create_dir_all(dir.join(&name)).unwrap();
+        fs.create_dir_all(dir.join(format!("{}.{}.3", name, gone_pid))).unwrap();
+        fs.create_file(dir.join(format!("{}.{}.4.tmp", name, gone_pid)), "").unwrap();
+        fs.create_dir_all(dir.join(format!("{}.{}.1000", name, own_pid))).unwrap();
+        fs.create_dir_all(dir.join(format!("{}.{}.0", cache_name(&key("/b")), gone_pid))).unwrap();
+
+        let index = CachedIndex::build(&paths(&["/a/main.rs"])).unwrap();
+        write_cache(&fs, dir, &key("/a"), &index).unwrap();
+
+        let mut expected: Vec<PathBuf> = vec![
+            dir.join(&name),
+            version_dir(&fs, dir, &key("/a")),
+            dir.join(format!("{}.{}.1000", name, own_pid)),
+            dir.join(format!("{}.{}.0", cache_name(&key("/b")), gone_pid)),
+        ];
+        expected.sort();
+        assert_eq!(dir_names(&fs, dir), expected);
+    }
+}
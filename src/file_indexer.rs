@@ -0,0 +1,357 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Indexing of project directories for the file bar. Every root is indexed by its own worker thread,
+// found files are sent to Interface in batches as IEvent::FilesIndexed, so the file bar fills up
+// while indexing goes on. Each root gets at most file_index_limit files, IEvent::IndexingFinished
+// tells whether the limit was hit.
+//
+// Ignore rules are the ones of `ignore` crate: .gitignore files at every level, .ignore files,
+// .git/info/exclude and global excludes. .git directories are never indexed.
+//
+// Files are listed through WalkSupport, implemented for OsFileSystem and (under test) for
+// FakeFileSystem, so interface tests run the real workers on the fake tree. FakeFileSystem has no
+// ignore rules, it only skips .git.
+//
+// Every root lists its files in order of paths. That way a root that hits the limit always gets
+// the same files, so FileWatcher (which lists roots the same way) does not see files coming and
+// going with every poll. On disk, entries of the root are taken one by one in order of names,
+// each directory among them is walked in parallel and its files sorted before they are handed
+// over. A root that hits the limit walks at most one directory more than it keeps.
+
+use filesystem::OsFileSystem;
+#[cfg(test)]
+use filesystem::{DirEntry as _, FakeFileSystem, FileSystem};
+use ignore::{DirEntry, WalkBuilder, WalkState};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::interface::InterfaceNotifier;
+use crate::FileSystemType;
+
+// files are sent to interface in batches of this size.
+const BATCH_SIZE: usize = 256;
+
+//docs: https://docs.rs/uid/0.1.4/uid/struct.Id.html
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct WorkerIdType(());
+
+type Id = uid::Id<WorkerIdType>;
+
+/// Starts indexing every root on a separate thread. Returns ids of these workers, each of them
+/// ends with IEvent::IndexingFinished.
+pub fn start(
+    fs: FileSystemType,
+    roots: Vec<PathBuf>,
+    enable_gitignore: bool,
+    file_index_limit: usize,
+    inot: InterfaceNotifier,
+) -> Vec<usize> {
+    let mut worker_ids: Vec<usize> = Vec::new();
+
+    for root in roots {
+        let worker_id: usize = Id::new().get();
+        worker_ids.push(worker_id);
+
+        let fs = fs.clone();
+        let inot = inot.clone();
+        thread::spawn(move || {
+            debug!("indexing worker {} started for {:?}", worker_id, &root);
+            let mut batch: Vec<PathBuf> = Vec::new();
+            let mut num_files: usize = 0;
+
+            let limit_hit = fs.walk_files(&root, enable_gitignore, file_index_limit, &mut |path| {
+                batch.push(path);
+                num_files += 1;
+                if batch.len() >= BATCH_SIZE {
+                    inot.files_indexed(root.clone(), mem::replace(&mut batch, Vec::new()));
+                }
+            });
+            if !batch.is_empty() {
+                inot.files_indexed(root.clone(), batch);
+            }
+
+            debug!("indexing worker {} finished, {} files", worker_id, num_files);
+            inot.indexing_finished(worker_id, root, num_files, limit_hit);
+        });
+    }
+
+    worker_ids
+}
+
+/// Lists files to be indexed in root, at most file_index_limit of them. Blocks, see start.
+pub fn list_files<F: WalkSupport>(
+    fs: &F,
+    root: &Path,
+    enable_gitignore: bool,
+    file_index_limit: usize,
+) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    fs.walk_files(root, enable_gitignore, file_index_limit, &mut |path| files.push(path));
+    files
+}
+
+/// Walking directories for the indexer, which FileSystem trait does not cover.
+pub trait WalkSupport {
+    /// Calls on_file with files in root that are not ignored, at most limit of them, in order of
+    /// paths. Returns true if there was more.
+    fn walk_files(
+        &self,
+        root: &Path,
+        enable_gitignore: bool,
+        limit: usize,
+        on_file: &mut dyn FnMut(PathBuf),
+    ) -> bool;
+}
+
+impl WalkSupport for OsFileSystem {
+    fn walk_files(
+        &self,
+        root: &Path,
+        enable_gitignore: bool,
+        limit: usize,
+        on_file: &mut dyn FnMut(PathBuf),
+    ) -> bool {
+        let top_level = walker(root, enable_gitignore)
+            .max_depth(Some(1))
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+
+        let mut num_files: usize = 0;
+        for entry_res in top_level {
+            let entry: DirEntry = match entry_res {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("error while indexing: {}", e);
+                    continue;
+                }
+            };
+            let files = if entry.depth() == 0 {
+                continue;
+            } else if entry.file_type().map(|file_type| file_type.is_dir()) == Some(true) {
+                walk_dir_in_parallel(entry.path(), enable_gitignore)
+            } else if is_file(&entry) {
+                vec![entry.into_path()]
+            } else {
+                continue;
+            };
+
+            for file in files {
+                if num_files == limit {
+                    return true;
+                }
+                on_file(file);
+                num_files += 1;
+            }
+        }
+        false
+    }
+}
+
+/// Returns walker of path with ignore rules of the indexer (see top of the file).
+fn walker(path: &Path, enable_gitignore: bool) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(path);
+    builder
+        .hidden(false)
+        .parents(enable_gitignore)
+        .ignore(enable_gitignore)
+        .git_ignore(enable_gitignore)
+        .git_global(enable_gitignore)
+        .git_exclude(enable_gitignore)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != ".git");
+    builder
+}
+
+fn is_file(entry: &DirEntry) -> bool {
+    match entry.file_type() {
+        Some(file_type) if file_type.is_symlink() => entry.path().is_file(),
+        Some(file_type) => file_type.is_file(),
+        None => false,
+    }
+}
+
+/// Returns all files in dir that are not ignored, sorted. Ignore files of directories above dir
+/// apply as well (WalkBuilder::parents), so the result is the same as of walking its parent.
+fn walk_dir_in_parallel(dir: &Path, enable_gitignore: bool) -> Vec<PathBuf> {
+    let found: Arc<Mutex<Vec<PathBuf>>> = Arc::new(Mutex::new(Vec::new()));
+    walker(dir, enable_gitignore).build_parallel().run(|| {
+        let found = found.clone();
+        Box::new(move |entry_res| {
+            match entry_res {
+                Ok(entry) => {
+                    if is_file(&entry) {
+                        found.lock().unwrap().push(entry.into_path());
+                    }
+                }
+                Err(e) => warn!("error while indexing: {}", e),
+            }
+            WalkState::Continue
+        })
+    });
+
+    let mut files = mem::replace(&mut *found.lock().unwrap(), Vec::new());
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+impl WalkSupport for FakeFileSystem {
+    fn walk_files(
+        &self,
+        root: &Path,
+        _enable_gitignore: bool,
+        limit: usize,
+        on_file: &mut dyn FnMut(PathBuf),
+    ) -> bool {
+        let mut num_files: usize = 0;
+        walk_fake_dir(self, root, limit, &mut num_files, on_file)
+    }
+}
+
+#[cfg(test)]
+fn walk_fake_dir(
+    fs: &FakeFileSystem,
+    dir: &Path,
+    limit: usize,
+    num_files: &mut usize,
+    on_file: &mut dyn FnMut(PathBuf),
+) -> bool {
+    let mut paths: Vec<PathBuf> = match fs.read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(_) => return false,
+    };
+    paths.sort();
+
+    for path in paths {
+        if path.file_name().map(|name| name == ".git") == Some(true) {
+            continue;
+        }
+        if fs.is_dir(&path) {
+            if walk_fake_dir(fs, &path, limit, num_files, on_file) {
+                return true;
+            }
+        } else if fs.is_file(&path) {
+            if *num_files == limit {
+                return true;
+            }
+            on_file(path);
+            *num_files += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir::temp_dir;
+    use std::fs;
+
+    #[test]
+    fn ignore_files_apply_to_their_directories() {
+        let root = temp_dir("indexer_ignore");
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("src/target")).unwrap();
+        fs::write(root.join(".git/config"), "").unwrap();
+        fs::write(root.join(".ignore"), "*.tmp\n").unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("a.rs"), "").unwrap();
+        fs::write(root.join("a.tmp"), "").unwrap();
+        fs::write(root.join("a.log"), "").unwrap();
+        fs::write(root.join("src/.gitignore"), "target/\n!keep.log\n").unwrap();
+        fs::write(root.join("src/b.rs"), "").unwrap();
+        fs::write(root.join("src/keep.log"), "").unwrap();
+        fs::write(root.join("src/target/c.rs"), "").unwrap();
+
+        let os = OsFileSystem::new();
+        let mut files = list_files(&os, &root, true, 100);
+        let all = list_files(&os, &root, false, 100);
+        fs::remove_dir_all(&root).unwrap();
+
+        files.sort();
+        let expected: Vec<PathBuf> =
+            vec![".gitignore", ".ignore", "a.rs", "src/.gitignore", "src/b.rs", "src/keep.log"]
+                .into_iter()
+                .map(|path| root.join(path))
+                .collect();
+        assert_eq!(files, expected);
+
+        // .git is skipped even without ignore rules.
+        assert_eq!(all.len(), 9);
+        assert!(!all.contains(&root.join(".git/config")));
+    }
+
+    #[test]
+    fn limit_keeps_the_same_files() {
+        let root = temp_dir("indexer_limit");
+        for dir in &["b", "a", "c"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            for file in &["3.rs", "1.rs", "2.rs"] {
+                fs::write(root.join(dir).join(file), "").unwrap();
+            }
+        }
+        // walked in parallel with the rest of "a".
+        fs::create_dir_all(root.join("a/sub")).unwrap();
+        fs::write(root.join("a/sub/0.rs"), "").unwrap();
+
+        let os = OsFileSystem::new();
+        let mut limit_hit = false;
+        let mut first: Vec<PathBuf> = Vec::new();
+        for _ in 0..5 {
+            let mut files: Vec<PathBuf> = Vec::new();
+            limit_hit = os.walk_files(&root, true, 4, &mut |path| files.push(path));
+            if first.is_empty() {
+                first = files;
+            } else {
+                assert_eq!(files, first);
+            }
+        }
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(limit_hit);
+        let expected: Vec<PathBuf> = vec!["a/1.rs", "a/2.rs", "a/3.rs", "a/sub/0.rs"]
+            .into_iter()
+            .map(|path| root.join(path))
+            .collect();
+        assert_eq!(first, expected);
+    }
+
+    #[test]
+    fn fake_filesystem_is_walked_in_order() {
+        let fake = FakeFileSystem::new();
+        fake.create_dir_all("/root/b/.git").unwrap();
+        fake.create_dir_all("/root/a/x").unwrap();
+        for path in &["/root/b/.git/config", "/root/b/1.rs", "/root/a/x/1.rs", "/root/a/2.rs"] {
+            fake.create_file(path, "").unwrap();
+        }
+        fake.create_file("/root/0.rs", "").unwrap();
+
+        let files = list_files(&fake, Path::new("/root"), true, 100);
+        let expected: Vec<PathBuf> =
+            vec!["/root/0.rs", "/root/a/2.rs", "/root/a/x/1.rs", "/root/b/1.rs"]
+                .into_iter()
+                .map(PathBuf::from)
+                .collect();
+        assert_eq!(files, expected);
+
+        let mut limited: Vec<PathBuf> = Vec::new();
+        assert!(fake.walk_files(Path::new("/root"), true, 2, &mut |path| limited.push(path)));
+        assert_eq!(limited, expected[..2].to_vec());
+    }
+}
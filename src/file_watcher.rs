@@ -0,0 +1,298 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// FileWatcher notices files created and removed in a project directory (a rename is both). It
+// lists the directory the same way the file index is built (respecting .gitignore) and compares
+// result with the previous listing.
+//
+// Watcher of a directory starts once its indexing is finished, from files the indexer found, so
+// files created after indexer listed them are reported by the first poll. start() runs it on a
+// separate thread, Interface gets the changes as IEvent::FilesChanged.
+//
+// The thread lists the directory again only when OS notifies it that something under the root was
+// created, removed or renamed; writes to existing files are not changes here. Notifications that
+// come while it waits between walks are handled by a single walk. Without notifications (e.g. the
+// limit of inotify watches is reached) it falls back to polling. Either way walks are at least
+// pause_after_walk apart, which grows with the time the walk took, so a large tree is not listed
+// over and over.
+
+use filesystem::OsFileSystem;
+#[cfg(test)]
+use filesystem::FakeFileSystem;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::file_indexer::list_files;
+use crate::interface::InterfaceNotifier;
+use crate::FileSystemType;
+
+const MIN_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_WATCH_INTERVAL: Duration = Duration::from_secs(60);
+// how many times longer than a walk the watcher waits before the next one.
+const WALK_TO_INTERVAL_RATIO: u32 = 20;
+// how long OS notifications are gathered before they are delivered.
+const NOTIFY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileChanges {
+    pub created: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+impl FileChanges {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.removed.is_empty()
+    }
+}
+
+pub struct FileWatcher {
+    fs: FileSystemType,
+    root: PathBuf,
+    enable_gitignore: bool,
+    file_index_limit: usize,
+    // files listed by previous poll, at first the ones found by indexer.
+    known: HashSet<PathBuf>,
+}
+
+impl FileWatcher {
+    pub fn new(
+        fs: FileSystemType,
+        root: PathBuf,
+        enable_gitignore: bool,
+        file_index_limit: usize,
+        known: Vec<PathBuf>,
+    ) -> Self {
+        let known: HashSet<PathBuf> = known.into_iter().collect();
+        FileWatcher { fs, root, enable_gitignore, file_index_limit, known }
+    }
+
+    /// Lists directory again and returns differences since previous call.
+    pub fn poll(&mut self) -> FileChanges {
+        let current: HashSet<PathBuf> =
+            list_files(&self.fs, &self.root, self.enable_gitignore, self.file_index_limit)
+                .into_iter()
+                .collect();
+
+        let mut created: Vec<PathBuf> = current.difference(&self.known).cloned().collect();
+        let mut removed: Vec<PathBuf> = self.known.difference(&current).cloned().collect();
+        created.sort();
+        removed.sort();
+
+        self.known = current;
+        FileChanges { created, removed }
+    }
+}
+
+/// Time to wait after a walk that took walk_time, before directory is listed again.
+pub fn pause_after_walk(walk_time: Duration) -> Duration {
+    (walk_time * WALK_TO_INTERVAL_RATIO).max(MIN_WATCH_INTERVAL).min(MAX_WATCH_INTERVAL)
+}
+
+/// OS notifications about files under a root, they stop when this is dropped.
+pub struct Notifications {
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+}
+
+impl Notifications {
+    /// Waits up to timeout for a file being created, removed or renamed (or for notifications
+    /// that could have been lost). Fails with Disconnected when notifications stopped.
+    pub fn wait_for_change(&self, timeout: Duration) -> Result<(), RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.events.recv_timeout(left)? {
+                DebouncedEvent::Create(_)
+                | DebouncedEvent::Remove(_)
+                | DebouncedEvent::Rename(_, _)
+                | DebouncedEvent::Rescan => return Ok(()),
+                DebouncedEvent::Error(e, path) => {
+                    warn!("file notification error {:?} for {:?}", e, path);
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Drops notifications that came so far, a walk that follows covers them.
+    pub fn discard_pending(&self) {
+        while self.events.try_recv().is_ok() {}
+    }
+}
+
+/// Notifications about changes in directories, which FileSystem trait does not cover.
+pub trait NotifySupport {
+    /// Starts notifications about files under root, None if they are not available.
+    fn notify(&self, root: &Path) -> Option<Notifications>;
+}
+
+impl NotifySupport for OsFileSystem {
+    fn notify(&self, root: &Path) -> Option<Notifications> {
+        let (sender, events) = channel();
+        let started = notify::watcher(sender, NOTIFY_DELAY).and_then(|mut watcher| {
+            watcher.watch(root, RecursiveMode::Recursive)?;
+            Ok(watcher)
+        });
+        match started {
+            Ok(watcher) => Some(Notifications { _watcher: watcher, events }),
+            Err(e) => {
+                warn!("no file notifications for {:?}, polling instead, because \"{}\"", root, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl NotifySupport for FakeFileSystem {
+    fn notify(&self, _root: &Path) -> Option<Notifications> {
+        None
+    }
+}
+
+/// Runs watcher on a separate thread, until interface is gone.
+pub fn start(mut watcher: FileWatcher, inot: InterfaceNotifier) {
+    thread::spawn(move || {
+        let mut notifications = watcher.fs.notify(&watcher.root);
+        loop {
+            let walk_started = Instant::now();
+            let changes = watcher.poll();
+            let pause = pause_after_walk(walk_started.elapsed());
+            if !changes.is_empty() {
+                debug!("files changed: {:?}", &changes);
+                if !inot.files_changed(changes) {
+                    break;
+                }
+            }
+
+            let notified = match notifications {
+                Some(ref notifications) => wait_for_change(notifications),
+                None => false,
+            };
+            if !notified {
+                notifications = None;
+            }
+            thread::sleep(pause);
+            if let Some(ref notifications) = notifications {
+                notifications.discard_pending();
+            }
+        }
+    });
+}
+
+// Returns false when notifications stopped.
+fn wait_for_change(notifications: &Notifications) -> bool {
+    loop {
+        match notifications.wait_for_change(MAX_WATCH_INTERVAL) {
+            Ok(()) => return true,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                warn!("file notifications stopped, polling instead");
+                return false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir::temp_dir;
+    use filesystem::FileSystem;
+    use std::fs;
+
+    fn fs_with_root(root: &str) -> FileSystemType {
+        let fs = FileSystemType::new();
+        fs.create_dir_all(root).unwrap();
+        fs
+    }
+
+    #[test]
+    fn reports_created_and_removed_files() {
+        let fs = fs_with_root("/project/src");
+        let root = PathBuf::from("/project");
+        fs.create_file("/project/src/old.rs", "").unwrap();
+
+        let indexed = list_files(&fs, &root, true, 100);
+        let mut watcher = FileWatcher::new(fs.clone(), root.clone(), true, 100, indexed);
+        let unchanged = watcher.poll();
+
+        fs.create_file("/project/src/new.rs", "").unwrap();
+        fs.remove_file("/project/src/old.rs").unwrap();
+        let changes = watcher.poll();
+        let after = watcher.poll();
+
+        assert!(unchanged.is_empty());
+        assert_eq!(
+            changes,
+            FileChanges {
+                created: vec![root.join("src/new.rs")],
+                removed: vec![root.join("src/old.rs")],
+            }
+        );
+        assert!(after.is_empty());
+    }
+
+    #[test]
+    fn files_created_after_indexing_are_reported_by_first_poll() {
+        let fs = fs_with_root("/project");
+        let root = PathBuf::from("/project");
+        fs.create_file("/project/old.rs", "").unwrap();
+
+        let indexed = list_files(&fs, &root, true, 100);
+        fs.create_file("/project/new.rs", "").unwrap();
+
+        let mut watcher = FileWatcher::new(fs.clone(), root.clone(), true, 100, indexed);
+        let changes = watcher.poll();
+
+        assert_eq!(changes, FileChanges { created: vec![root.join("new.rs")], removed: vec![] });
+    }
+
+    #[test]
+    fn pause_grows_with_walk_time() {
+        assert_eq!(pause_after_walk(Duration::from_millis(5)), MIN_WATCH_INTERVAL);
+        assert_eq!(pause_after_walk(Duration::from_millis(500)), Duration::from_secs(10));
+        assert_eq!(pause_after_walk(Duration::from_secs(30)), MAX_WATCH_INTERVAL);
+    }
+
+    #[test]
+    fn notifies_about_created_files_not_writes() {
+        let root = temp_dir("watcher_notify");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/old.rs"), "").unwrap();
+        let notifications = OsFileSystem::new().notify(&root).unwrap();
+        let timeout = NOTIFY_DELAY * 3;
+
+        fs::write(root.join("src/old.rs"), "changed").unwrap();
+        let written = notifications.wait_for_change(timeout);
+        fs::write(root.join("src/new.rs"), "").unwrap();
+        let created = notifications.wait_for_change(timeout);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(written, Err(RecvTimeoutError::Timeout));
+        assert_eq!(created, Ok(()));
+    }
+
+    #[test]
+    fn fake_filesystem_is_polled() {
+        assert!(fs_with_root("/project").notify(Path::new("/project")).is_none());
+    }
+}
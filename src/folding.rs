@@ -0,0 +1,420 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Code folding. A fold is a range of lines, the first of which (header) stays visible and is drawn
+// with a placeholder, and the rest is hidden.
+//
+// Fold candidates (regions that *can* be folded) come from one of sources:
+// - language server (textDocument/foldingRange), if it provided any. It knows best, so it wins.
+// - syntect scopes (block-like scopes, see BLOCK_SCOPES in highlight_worker.rs). These are
+//   available only for lines already highlighted.
+// - indentation, as a fallback for lines not covered by the above.
+//
+// Folds and language server ranges are kept in BufferState and are shifted on edits, so gutter
+// markers follow the text. Candidates from syntax and indentation are recomputed on demand.
+
+use crate::content_provider::RopeBasedContentProvider;
+use ropey::Rope;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+const INDENTATION_TAB_WIDTH: usize = 4;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct FoldRange {
+    pub first_line: usize, // header, inclusive, visible
+    pub last_line: usize,  // inclusive, hidden
+}
+
+impl FoldRange {
+    pub fn new(first_line: usize, last_line: usize) -> Self {
+        debug_assert!(first_line < last_line);
+        FoldRange { first_line, last_line }
+    }
+
+    pub fn contains(&self, line_no: usize) -> bool {
+        self.first_line <= line_no && line_no <= self.last_line
+    }
+
+    pub fn hides(&self, line_no: usize) -> bool {
+        self.first_line < line_no && line_no <= self.last_line
+    }
+
+    pub fn num_hidden_lines(&self) -> usize {
+        self.last_line - self.first_line
+    }
+
+    /// Shifts range after an edit that started at first_changed_line and changed number of lines
+    /// by delta. Returns None if range does not make sense anymore.
+    fn after_edit(&self, first_changed_line: usize, delta: isize) -> Option<FoldRange> {
+        let shift = |line: usize| (line as isize + delta) as usize;
+
+        if self.last_line < first_changed_line {
+            return Some(*self);
+        }
+
+        if self.first_line > first_changed_line {
+            // whole range moves, unless it got (partially) removed.
+            if delta < 0 && self.first_line as isize <= first_changed_line as isize - delta {
+                return None;
+            }
+            return Some(FoldRange::new(shift(self.first_line), shift(self.last_line)));
+        }
+
+        if self.first_line == first_changed_line && delta != 0 {
+            // header got split or merged, we cannot tell where it is now.
+            return None;
+        }
+
+        // edit within the range, only the end moves.
+        let last_line = std::cmp::max(self.last_line as isize + delta, first_changed_line as isize);
+        if last_line as usize > self.first_line {
+            Some(FoldRange::new(self.first_line, last_line as usize))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct FoldState {
+    // contract: sorted by first_line, no duplicates. Folds can be nested.
+    folded: Vec<FoldRange>,
+    lsp_ranges: Option<Vec<FoldRange>>,
+    // (content version, number of fresh rich lines) -> candidates
+    candidates_cache: RefCell<Option<((usize, usize), Rc<Vec<FoldRange>>)>>,
+}
+
+impl FoldState {
+    pub fn new() -> Self {
+        FoldState { folded: Vec::new(), lsp_ranges: None, candidates_cache: RefCell::new(None) }
+    }
+
+    /// Sets ranges provided by language server. They replace syntax and indentation based ones.
+    pub fn set_lsp_ranges(&mut self, mut ranges: Vec<FoldRange>) {
+        ranges.sort();
+        ranges.dedup();
+        self.lsp_ranges = Some(ranges);
+        self.candidates_cache.replace(None);
+    }
+
+    /// Returns all regions that can be folded, sorted by first_line. At most one per header.
+    pub fn candidates(&self, content: &RopeBasedContentProvider) -> Rc<Vec<FoldRange>> {
+        let key = (content.version(), content.fresh_rich_lines());
+
+        if let Some((ref cached_key, ref candidates)) = *self.candidates_cache.borrow() {
+            if *cached_key == key {
+                return candidates.clone();
+            }
+        }
+
+        let candidates: Vec<FoldRange> = match self.lsp_ranges {
+            Some(ref ranges) => {
+                let mut by_header: BTreeMap<usize, FoldRange> = BTreeMap::new();
+                for range in ranges {
+                    // the outermost range wins, same as with other sources.
+                    let entry = by_header.entry(range.first_line).or_insert(*range);
+                    if entry.last_line < range.last_line {
+                        *entry = *range;
+                    }
+                }
+                by_header.into_iter().map(|(_, range)| range).collect()
+            }
+            None => {
+                let mut by_header: BTreeMap<usize, FoldRange> = BTreeMap::new();
+                for range in syntax_folds(content) {
+                    by_header.insert(range.first_line, range);
+                }
+                for range in indentation_folds(content.get_lines()) {
+                    by_header.entry(range.first_line).or_insert(range);
+                }
+                by_header.into_iter().map(|(_, range)| range).collect()
+            }
+        };
+
+        let candidates = Rc::new(candidates);
+        self.candidates_cache.replace(Some((key, candidates.clone())));
+        candidates
+    }
+
+    pub fn folded(&self) -> &Vec<FoldRange> {
+        &self.folded
+    }
+
+    /// Returns the fold starting at line_no, if line_no is a header of a folded region.
+    pub fn folded_at(&self, line_no: usize) -> Option<&FoldRange> {
+        self.folded.iter().find(|range| range.first_line == line_no)
+    }
+
+    pub fn is_hidden(&self, line_no: usize) -> bool {
+        self.folded.iter().any(|range| range.hides(line_no))
+    }
+
+    /// Folds the innermost candidate containing line_no. Returns false if there is none.
+    pub fn fold_at(&mut self, content: &RopeBasedContentProvider, line_no: usize) -> bool {
+        let candidates = self.candidates(content);
+        let innermost = candidates
+            .iter()
+            .filter(|range| range.contains(line_no) && self.folded_at(range.first_line).is_none())
+            .min_by_key(|range| range.num_hidden_lines())
+            .cloned();
+
+        match innermost {
+            Some(range) => {
+                self.insert_fold(range);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Unfolds the innermost fold containing line_no. Returns false if there is none.
+    pub fn unfold_at(&mut self, line_no: usize) -> bool {
+        let innermost = self
+            .folded
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.contains(line_no))
+            .min_by_key(|(_, range)| range.num_hidden_lines())
+            .map(|(idx, _)| idx);
+
+        match innermost {
+            Some(idx) => {
+                self.folded.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn fold_all(&mut self, content: &RopeBasedContentProvider) {
+        for range in self.candidates(content).iter() {
+            if self.folded_at(range.first_line).is_none() {
+                self.insert_fold(*range);
+            }
+        }
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.folded.clear();
+    }
+
+    fn insert_fold(&mut self, range: FoldRange) {
+        let pos = match self.folded.binary_search(&range) {
+            Ok(_) => return,
+            Err(pos) => pos,
+        };
+        self.folded.insert(pos, range);
+    }
+
+    /// Returns up to limit visible lines, starting with from (or first visible line after it).
+    pub fn visible_lines(&self, from: usize, limit: usize, len_lines: usize) -> Vec<usize> {
+        let mut result: Vec<usize> = Vec::new();
+        let mut line_no = from;
+        while line_no < len_lines && result.len() < limit {
+            if !self.is_hidden(line_no) {
+                result.push(line_no);
+            }
+            line_no += 1;
+        }
+        result
+    }
+
+    /// Returns line that is delta visible lines away from line_no. If there is not enough visible
+    /// lines, the result is out of [0, len_lines) range (so caller can tell it hit the boundary).
+    pub fn move_by_visible(&self, line_no: usize, delta: isize, len_lines: usize) -> isize {
+        let step: isize = if delta > 0 { 1 } else { -1 };
+        let mut steps_left = delta.abs();
+        let mut current = line_no as isize;
+
+        while steps_left > 0 {
+            current += step;
+            if current < 0 || current >= len_lines as isize {
+                return current;
+            }
+            if !self.is_hidden(current as usize) {
+                steps_left -= 1;
+            }
+        }
+
+        current
+    }
+
+    /// Updates folds after an edit, so they follow the text they were made for.
+    pub fn on_edit(
+        &mut self,
+        first_changed_line: usize,
+        old_len_lines: usize,
+        new_len_lines: usize,
+    ) {
+        let delta = new_len_lines as isize - old_len_lines as isize;
+
+        let mut folded: Vec<FoldRange> = self
+            .folded
+            .iter()
+            .filter_map(|range| range.after_edit(first_changed_line, delta))
+            .filter(|range| range.last_line < new_len_lines)
+            .collect();
+        folded.sort();
+        folded.dedup();
+        self.folded = folded;
+
+        if let Some(ref mut ranges) = self.lsp_ranges {
+            *ranges = ranges
+                .iter()
+                .filter_map(|range| range.after_edit(first_changed_line, delta))
+                .filter(|range| range.last_line < new_len_lines)
+                .collect();
+            ranges.sort();
+            ranges.dedup();
+        }
+
+        self.candidates_cache.replace(None);
+    }
+}
+
+fn indentation_of(rope: &Rope, line_no: usize) -> Option<usize> {
+    let mut indent: usize = 0;
+    for c in rope.line(line_no).chars() {
+        match c {
+            ' ' => indent += 1,
+            '\t' => indent += INDENTATION_TAB_WIDTH,
+            '\n' | '\r' => return None,
+            _ => return Some(indent),
+        }
+    }
+    None // blank line
+}
+
+/// Returns regions of lines indented deeper than the line preceding them. Blank lines in the
+/// middle belong to region, trailing ones don't.
+pub fn indentation_folds(rope: &Rope) -> Vec<FoldRange> {
+    let mut result: Vec<FoldRange> = Vec::new();
+    // (header line, its indentation)
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    let mut last_non_blank: usize = 0;
+
+    for line_no in 0..rope.len_lines() {
+        let indent = match indentation_of(rope, line_no) {
+            Some(indent) => indent,
+            None => continue,
+        };
+
+        while let Some(&(header, header_indent)) = stack.last() {
+            if header_indent < indent {
+                break;
+            }
+            stack.pop();
+            if last_non_blank > header {
+                result.push(FoldRange::new(header, last_non_blank));
+            }
+        }
+
+        stack.push((line_no, indent));
+        last_non_blank = line_no;
+    }
+
+    while let Some((header, _)) = stack.pop() {
+        if last_non_blank > header {
+            result.push(FoldRange::new(header, last_non_blank));
+        }
+    }
+
+    result.sort();
+    result
+}
+
+/// Returns regions of block scopes spanning multiple lines, using only lines that are already
+/// highlighted. Line closing the block stays visible.
+pub fn syntax_folds(content: &RopeBasedContentProvider) -> Vec<FoldRange> {
+    let mut result: Vec<FoldRange> = Vec::new();
+    // lines where currently open blocks begun
+    let mut stack: Vec<usize> = Vec::new();
+
+    for line_no in 0..content.fresh_rich_lines() {
+        let rich_line = match content.get_fresh_rich_line(line_no) {
+            Some(rich_line) => rich_line,
+            None => break,
+        };
+        let (min_depth, end_depth) = rich_line.block_depth();
+
+        while stack.len() > min_depth {
+            let header = stack.pop().unwrap();
+            if line_no > header + 1 {
+                result.push(FoldRange::new(header, line_no - 1));
+            }
+        }
+
+        while stack.len() < end_depth {
+            stack.push(line_no);
+        }
+    }
+
+    result.sort();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indentation_folds_test() {
+        let rope = Rope::from_str("fn a\n    b\n\n    c\n        d\ne\n");
+        assert_eq!(indentation_folds(&rope), vec![FoldRange::new(0, 4), FoldRange::new(3, 4)]);
+    }
+
+    #[test]
+    fn range_after_edit() {
+        let range = FoldRange::new(5, 10);
+        // edit below
+        assert_eq!(range.after_edit(11, 3), Some(range));
+        // lines added above
+        assert_eq!(range.after_edit(2, 2), Some(FoldRange::new(7, 12)));
+        // lines removed above
+        assert_eq!(range.after_edit(1, -2), Some(FoldRange::new(3, 8)));
+        // removal reaching header
+        assert_eq!(range.after_edit(3, -2), None);
+        // edit within
+        assert_eq!(range.after_edit(7, 1), Some(FoldRange::new(5, 11)));
+        // header split
+        assert_eq!(range.after_edit(5, 1), None);
+        // header edited in place
+        assert_eq!(range.after_edit(5, 0), Some(range));
+    }
+
+    #[test]
+    fn move_by_visible_skips_folds() {
+        let mut state = FoldState::new();
+        state.insert_fold(FoldRange::new(1, 3));
+
+        assert_eq!(state.move_by_visible(0, 1, 10), 1);
+        assert_eq!(state.move_by_visible(1, 1, 10), 4);
+        assert_eq!(state.move_by_visible(4, -1, 10), 1);
+        assert_eq!(state.move_by_visible(4, -3, 10), -1);
+        assert_eq!(state.visible_lines(0, 3, 10), vec![0, 1, 4]);
+    }
+
+    #[test]
+    fn folds_follow_edits() {
+        let mut state = FoldState::new();
+        state.insert_fold(FoldRange::new(4, 6));
+        state.on_edit(0, 10, 12);
+        assert_eq!(state.folded(), &vec![FoldRange::new(6, 8)]);
+        assert!(state.is_hidden(7));
+        assert!(!state.is_hidden(6));
+    }
+}
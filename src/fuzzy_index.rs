@@ -14,13 +14,11 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-// TODO(njskalski) limit is ignored now
-
 extern crate fst;
 extern crate fst_regex;
 
 use self::fst::map;
-use self::fst::{IntoStreamer, Map, Streamer};
+use self::fst::{IntoStreamer, Map, MapBuilder, Streamer};
 use self::fst_regex::Regex;
 use std::char::{ToLowercase, ToUppercase};
 use std::iter::FromIterator;
@@ -28,18 +26,25 @@ use serde::de::Unexpected::Option as SerdeOption;
 use std::cell::*;
 use std::collections::HashMap;
 use std::collections::*;
+use std::cmp;
 use std::fmt;
 use std::rc::Rc;
 use std::sync::mpsc;
 use std::sync::mpsc::*;
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 use std::thread::ThreadId;
 use crate::fuzzy_view_item::ViewItem;
 use crate::fuzzy_index_trait::FuzzyIndexTrait;
+use crate::fuzzy_score::fuzzy_match;
 use crate::interface::InterfaceNotifier;
 
 const MAX_CACHE_SIZE: usize = 30;
+// overlay bigger than this (added plus removed items) triggers rebuild of fst immediately.
+const MAX_OVERLAY_SIZE: usize = 200;
+// otherwise fst is rebuilt this long after first change, see rebuild_if_due.
+const REBUILD_DELAY: Duration = Duration::from_secs(30);
 pub const HARD_QUERY_LIMIT: usize = 50;
 
 /*
@@ -50,19 +55,58 @@ example: multiple files with SAME NAME
 example: mutliple methods with same names from different files.
 
 It is not possible to add items after a fst::Map has been built.
+
+Ranking:
+fst is used only to prefilter headers (file names) with a case-insensitive ".*a.*b.*" regex.
+Every item of a matching header is then scored with fuzzy_score (contiguous runs, word, separator
+and camelCase boundaries). Query without '/' is scored against the header. Query with '/' is
+scored against the whole marker (for files that's the path), while the part after the last '/'
+still has to match the file name, so "src/main" finds ".../src/main.rs". Equally scored items are
+ordered by length of marker, so shorter paths come first.
+
+Search runs in a worker thread that streams scored items to FuzzySearchTask, which keeps the
+ones received so far sorted. That way results of large indexes are displayed (and get better)
+while the search is still running.
+
+Changes:
+Items added after fst was built go to overlay, which is scored on every query (it's small), and
+removed ones are filtered out of results by marker. Once enough changes pile up, or they are old
+enough, the whole index is rebuilt with a new fst. "Enough" grows with the index, so filling it up
+in batches (see file_indexer.rs) rebuilds fst only a logarithmic number of times.
+
+Rebuild groups items by header right away, but sorting headers and building fst happen in a
+worker thread. Meanwhile the old fst and overlay keep answering queries, and changes made since are
+recorded. New fst is swapped in by rebuild_if_due (on tick), then recorded changes are applied on
+top of it again.
 */
 pub struct FuzzyIndex {
     index: Arc<Map>, /* this is Map<String, u64>. It's Arc, because queries are ran in
                       * worker threads. */
     items: HashMap<u64, Vec<Rc<ViewItem>>>,
-    items_sizes: Arc<HashMap<u64, usize>>, /* this field is used by workers to determine
-                                            * whether they hit the limit
-                                            * of records or not. */
+    markers: Arc<HashMap<u64, Vec<String>>>, /* markers of items[key], in the same order. Used
+                                              * by workers to score items, as ViewItems can't
+                                              * be sent to other threads. */
     cache: HashMap<String, FuzzySearchTask>,
     /// used to know in what order clear the cache. Does not contain empty query, which is computed
     /// in cache immediately. Also, cache_order and cache sizes are not synchronized, as
     /// cache_order can contain duplicates in rare situations.
     cache_order: LinkedList<String>,
+    overlay: Vec<Rc<ViewItem>>,
+    removed: HashSet<String>, // markers of items in fst, that are gone.
+    changed_at: Option<Instant>, // first change not in fst yet.
+    fst_markers: HashSet<String>,     // all markers of items, to check updates quickly.
+    overlay_markers: HashSet<String>, // same for overlay.
+    status: Option<String>,
+    rebuild_op: Option<Rebuild>,
+}
+
+/// Rebuild in progress, see "Changes" above.
+struct Rebuild {
+    receiver: mpsc::Receiver<Result<Vec<u8>, fst::Error>>,
+    // items[key] share header mapped to key in fst being built.
+    items: Vec<Vec<Rc<ViewItem>>>,
+    // changes made since rebuild started (added items, removed markers).
+    updates: Vec<(Vec<Rc<ViewItem>>, Vec<String>)>,
 }
 
 impl fmt::Debug for FuzzyIndex {
@@ -78,63 +122,99 @@ impl FuzzyIndexTrait for FuzzyIndex {
         limit_op: Option<usize>,
         inot_op: Option<InterfaceNotifier>,
     ) -> Vec<Rc<ViewItem>> {
-        let mut results: Vec<Rc<ViewItem>> = Vec::new();
+        let mut results: Vec<(i64, usize, Rc<ViewItem>)> = Vec::new();
 
         // this has no effect if we already had such task in progress.
         self.start_search(query, limit_op, inot_op);
         let task = self.cache.get(query).unwrap(); // unwrap always succeeds, see line above
 
-        let result_ids = task.get_result_ids();
+        let scored_items = task.get_results();
+
+        for scored in scored_items.iter() {
+            assert!(self.items.contains_key(&scored.key));
+            let item = &self.items[&scored.key][scored.idx];
+            if !self.removed.contains(item.get_marker()) {
+                results.push((scored.score, scored.marker_len, item.clone()));
+            }
+        }
 
-        for id in result_ids.iter() {
-            assert!(self.items.contains_key(id));
-            for item in self.items[id].iter() {
-                results.push(item.clone());
+        if !self.overlay.is_empty() {
+            for item in self.overlay.iter() {
+                let markers = vec![item.get_marker().clone()];
+                for scored in score_items(query, item.get_header(), &markers, 0) {
+                    results.push((scored.score, scored.marker_len, item.clone()));
+                }
             }
+            // stable, so fst results keep their order.
+            results.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        }
+
+        if let Some(limit) = limit_op {
+            results.truncate(limit);
         }
+        let results: Vec<Rc<ViewItem>> = results.into_iter().map(|(_, _, item)| item).collect();
 
         //        debug!("returning {} results for query {}", results.len(), query);
         results
     }
+
+    fn status(&self) -> Option<String> {
+        self.status.clone()
+    }
 }
 
 impl FuzzyIndex {
     pub fn new(word_list: Vec<ViewItem>) -> FuzzyIndex {
-        // TODO we can consume word_list here instead of calling ci.copy() below
-        let mut items: HashMap<u64, Vec<Rc<ViewItem>>> = HashMap::new();
+        let mut items: Vec<Vec<ViewItem>> = Vec::new();
         let mut header_to_key: HashMap<String, u64> = HashMap::new();
-        let mut key = 0;
         for ci in word_list {
-            let header: &String = &ci.get_header();
-
-            if header_to_key.contains_key(header) {
-                let id: u64 = header_to_key[header];
-                items.get_mut(&id).unwrap().push(Rc::new(ci.clone()));
+            if header_to_key.contains_key(ci.get_header()) {
+                let id: u64 = header_to_key[ci.get_header()];
+                items[id as usize].push(ci);
             } else {
-                let mut vec: Vec<Rc<ViewItem>> = Vec::new();
-                vec.push(Rc::new(ci.clone()));
-                header_to_key.insert(header.clone(), key);
-                items.insert(key, vec);
-                key += 1;
+                header_to_key.insert(ci.get_header().clone(), items.len() as u64);
+                items.push(vec![ci]);
             }
         }
 
-        let mut header_to_key_sorted: Vec<(String, u64)> =
-            header_to_key.iter().map(|item| (item.0.clone(), item.1.clone())).collect();
+        let mut header_to_key_sorted: Vec<(String, u64)> = header_to_key.into_iter().collect();
         header_to_key_sorted.sort();
         let map = Map::from_iter(header_to_key_sorted).unwrap();
 
-        let mut item_sizes: HashMap<u64, usize> = HashMap::new();
+        FuzzyIndex::from_map(map, items)
+    }
+
+    /// Creates index out of already built fst (like one read from file_index_cache.rs). Map has
+    /// to map headers to indices in items, and items of each index have to share its header.
+    pub fn from_map(map: Map, items: Vec<Vec<ViewItem>>) -> FuzzyIndex {
+        let items: Vec<Vec<Rc<ViewItem>>> =
+            items.into_iter().map(|item_vec| item_vec.into_iter().map(Rc::new).collect()).collect();
+        FuzzyIndex::from_shared_items(map, items)
+    }
+
+    fn from_shared_items(map: Map, items: Vec<Vec<Rc<ViewItem>>>) -> FuzzyIndex {
+        let items: HashMap<u64, Vec<Rc<ViewItem>>> =
+            items.into_iter().enumerate().map(|(key, item_vec)| (key as u64, item_vec)).collect();
+
+        let mut markers: HashMap<u64, Vec<String>> = HashMap::new();
         for (k, v) in items.iter() {
-            item_sizes.insert(*k, v.len());
+            markers.insert(*k, v.iter().map(|item| item.get_marker().clone()).collect());
         }
+        let fst_markers: HashSet<String> = markers.values().flatten().cloned().collect();
 
         let mut i = FuzzyIndex {
             index: Arc::new(map),
             items: items,
-            items_sizes: Arc::new(item_sizes),
+            markers: Arc::new(markers),
             cache: HashMap::new(),
             cache_order: LinkedList::new(),
+            overlay: Vec::new(),
+            removed: HashSet::new(),
+            changed_at: None,
+            fst_markers,
+            overlay_markers: HashSet::new(),
+            status: None,
+            rebuild_op: None,
         };
         // we do not pass inot_op below, since we don't want to necessarily update interface on
         // creation, but we do want our "" results to be available immediately.
@@ -142,41 +222,145 @@ impl FuzzyIndex {
         i
     }
 
+    /// Adds and removes (by marker) items. Changes are kept in overlay until fst is rebuilt.
+    pub fn update(&mut self, added: Vec<ViewItem>, removed: Vec<String>) {
+        let added: Vec<Rc<ViewItem>> = added.into_iter().map(Rc::new).collect();
+        if let Some(ref mut rebuild) = self.rebuild_op {
+            rebuild.updates.push((added.clone(), removed.clone()));
+        }
+        self.apply_update(added, removed);
+
+        let max_overlay_size = cmp::max(MAX_OVERLAY_SIZE, self.fst_markers.len() / 4);
+        if self.overlay.len() + self.removed.len() > max_overlay_size {
+            self.start_rebuild();
+        }
+    }
+
+    fn apply_update(&mut self, added: Vec<Rc<ViewItem>>, removed: Vec<String>) {
+        for marker in removed {
+            if self.overlay_markers.remove(&marker) {
+                self.overlay.retain(|item| *item.get_marker() != marker);
+            } else if self.fst_markers.contains(&marker) {
+                self.removed.insert(marker);
+            }
+        }
+
+        for item in added {
+            // back again (removed and re-created).
+            if self.removed.remove(item.get_marker()) {
+                continue;
+            }
+            let marker = item.get_marker().clone();
+            if !self.fst_markers.contains(&marker) && self.overlay_markers.insert(marker) {
+                self.overlay.push(item);
+            }
+        }
+
+        if self.overlay.is_empty() && self.removed.is_empty() {
+            self.changed_at = None;
+        } else if self.changed_at.is_none() {
+            self.changed_at = Some(Instant::now());
+        }
+    }
+
+    /// Sets status displayed next to query, see FuzzyIndexTrait::status.
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.status = status;
+    }
+
+    /// Swaps in fst rebuilt in background once it's ready, and starts rebuilding if there are
+    /// changes older than REBUILD_DELAY.
+    pub fn rebuild_if_due(&mut self, now: Instant) {
+        self.finish_rebuild();
+        if let Some(changed_at) = self.changed_at {
+            if now.duration_since(changed_at) >= REBUILD_DELAY {
+                self.start_rebuild();
+            }
+        }
+    }
+
+    /// Starts building new fst out of current items, merging overlay into it. Does nothing if a
+    /// rebuild is already in progress.
+    fn start_rebuild(&mut self) {
+        if self.rebuild_op.is_some() {
+            return;
+        }
+        debug!("rebuilding index, {} added {} removed", self.overlay.len(), self.removed.len());
+
+        let mut items: Vec<Vec<Rc<ViewItem>>> = Vec::new();
+        let mut header_to_key: HashMap<String, u64> = HashMap::new();
+        let removed = &self.removed;
+        let fst_items =
+            self.items.values().flatten().filter(|item| !removed.contains(item.get_marker()));
+        for item in fst_items.chain(self.overlay.iter()) {
+            let new_key = items.len() as u64;
+            let key = *header_to_key.entry(item.get_header().clone()).or_insert(new_key);
+            if key == new_key {
+                items.push(Vec::new());
+            }
+            items[key as usize].push(item.clone());
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut headers: Vec<(String, u64)> = header_to_key.into_iter().collect();
+            headers.sort();
+            let mut builder = MapBuilder::memory();
+            let result = builder.extend_iter(headers).and_then(|_| builder.into_inner());
+            // index may be gone already.
+            sender.send(result).ok();
+        });
+
+        self.rebuild_op = Some(Rebuild { receiver, items, updates: Vec::new() });
+    }
+
+    /// Replaces index with the rebuilt one, if its fst is ready.
+    fn finish_rebuild(&mut self) {
+        let received = match self.rebuild_op {
+            Some(ref rebuild) => rebuild.receiver.try_recv(),
+            None => return,
+        };
+        let result = match received {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {
+                error!("rebuilding index failed, worker is gone");
+                self.rebuild_op = None;
+                return;
+            }
+        };
+
+        // changes are still in overlay, so the next rebuild will try again.
+        let rebuild = self.rebuild_op.take().unwrap();
+        let map = match result.and_then(Map::from_bytes) {
+            Ok(map) => map,
+            Err(e) => {
+                error!("rebuilding index failed, because \"{}\"", e);
+                return;
+            }
+        };
+
+        let status = self.status.take();
+        *self = FuzzyIndex::from_shared_items(map, rebuild.items);
+        self.status = status;
+        for (added, removed) in rebuild.updates {
+            self.apply_update(added, removed);
+        }
+    }
+
     fn start_search(
         &mut self,
         query: &String,
         limit_op: Option<usize>,
         inot_op: Option<InterfaceNotifier>,
     ) {
-        // TODO(njskalski): while it is possible to update limit as query runs, resuming query is
-        // not implemented yet, so I just restart it now.
+        // Search always goes through whole index (limit is applied when results are read), so a
+        // task in cache is good enough regardless of its limit.
         if let Some(ref mut runner) = self.cache.get(query) {
             if let Some(ref inot) = inot_op {
                 runner.update_inot(inot.clone());
             }
-
-            if let Some(old_limit) = runner.limit() {
-                if let Some(ref new_limit) = limit_op {
-                    if old_limit >= new_limit {
-                        return; // Old limit bigger than new one, no need to restart.
-                    }
-                } else {
-                    // here should be limit update and resume of thread, but not implemented.
-                    // this scenario actually moves ahead with re-starting search.
-                }
-            } else {
-                return; // no limit in the old one? It's going to grab all. No need to restart.
-            }
-        }
-
-        // We either have no cache or cache is not good enough.
-
-        if self.cache.contains_key(query) {
-            self.cache.remove(query);
-            // We *should* drop query from cache_order here, but we do not. Here is reason:
-            // linked list has no remove, so cache and cache_order can become desynchronized. I
-            // don't rely on them being in sync, so don't worry.
-            // self.cache_order.remove(&query)
+            return;
         }
 
         let task: FuzzySearchTask = FuzzySearchTask::new(query.clone(), self, limit_op, inot_op);
@@ -189,8 +373,7 @@ impl FuzzyIndex {
                 // -1 and condition above stand for the fact I want to keep empty query computed all
                 // the time!
                 let oldest_query = self.cache_order.pop_front().unwrap();
-                // this doesn't have to succeed, the cache_order can become a little longer than
-                // cache, look at the top of the function why (duplicates possible)
+                // this doesn't have to succeed, cache and cache_order are not kept in sync.
                 self.cache.remove(&oldest_query);
             }
         }
@@ -200,14 +383,58 @@ impl FuzzyIndex {
 #[derive(Clone, Debug)]
 enum FuzzySearchTaskUpdate {
     Inot(InterfaceNotifier),
-    NewLimit(usize),
 }
 
-// TODO(njskalski): add resume (re-spawning thread) if limit is bigger.
+/// Item (items[key][idx]) matched by a query.
+#[derive(Clone, Debug)]
+struct ScoredItem {
+    score: i64,
+    marker_len: usize,
+    key: u64,
+    idx: usize,
+}
+
+impl ScoredItem {
+    /// Better items first: higher score, then shorter marker (path).
+    fn rank_cmp(&self, other: &ScoredItem) -> cmp::Ordering {
+        other
+            .score
+            .cmp(&self.score)
+            .then(self.marker_len.cmp(&other.marker_len))
+            .then(self.key.cmp(&other.key))
+            .then(self.idx.cmp(&other.idx))
+    }
+}
+
+/// Scores markers of items matching header, see "Ranking" above.
+fn score_items(query: &str, header: &str, markers: &[String], key: u64) -> Vec<ScoredItem> {
+    let path_query = query.contains('/');
+    let header_query = query.rsplit('/').next().unwrap_or("");
+    let header_score_op = fuzzy_match(header_query, header);
+    if header_score_op.is_none() {
+        return Vec::new();
+    }
+
+    markers
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, marker)| {
+            let score_op = if path_query {
+                fuzzy_match(query, marker).map(|m| m.score)
+            } else {
+                header_score_op.as_ref().map(|m| m.score)
+            };
+            score_op.map(|score| ScoredItem { score, marker_len: marker.len(), key, idx })
+        })
+        .collect()
+}
+
+// Limit is applied only when results are read, search always goes through whole index, as ranking
+// needs all of it anyway.
 struct FuzzySearchTask {
-    receiver: mpsc::Receiver<u64>,
+    receiver: mpsc::Receiver<ScoredItem>,
     query: String,
-    item_ids: RefCell<Vec<u64>>,
+    results: RefCell<Vec<ScoredItem>>,
     done: Cell<bool>,
     limit_op: Option<usize>,
     update_stram_sender: Sender<FuzzySearchTaskUpdate>,
@@ -224,17 +451,17 @@ impl FuzzySearchTask {
     pub fn new(
         query: String,
         index: &FuzzyIndex,
-        mut limit_op: Option<usize>,
+        limit_op: Option<usize>,
         mut inot_op: Option<InterfaceNotifier>,
     ) -> FuzzySearchTask {
-        let (sender, receiver) = channel::<u64>();
-        let item_ids = Vec::new();
+        let (sender, receiver) = channel::<ScoredItem>();
+        let results = Vec::new();
 
         let has_inot = inot_op.is_some();
 
         let index_ref_copy = index.index.clone();
         let query_copy = query.clone();
-        let items_sizes_ref = index.items_sizes.clone();
+        let markers_ref = index.markers.clone();
 
         let (update_stream_sender, update_stream_receiver) = channel::<FuzzySearchTaskUpdate>();
 
@@ -243,31 +470,26 @@ impl FuzzySearchTask {
             inot_op.as_ref().map(|inot| inot.worker_start(workerId));
 
             debug!("worker {} {:}: created", workerId, &query_copy);
-            let regex = query_to_regex(&query_copy);
+            // part after the last '/' (whole query if there's none) has to match file name.
+            let header_query = query_copy.rsplit('/').next().unwrap_or("").to_string();
+            let regex = query_to_regex(&header_query);
             let stream_builder: map::StreamBuilder<Regex> = index_ref_copy.search(regex);
             let mut stream = stream_builder.into_stream();
 
             debug!("worker {} {:}: start search", workerId, &query_copy);
-            let mut results: Vec<&ViewItem> = Vec::new();
-            let mut it: usize = 0;
             while let Some((header, key)) = stream.next() {
-                if sender.send(key).is_err() {
-                    error!("unable to send key in FuzzySearchTask internal worker");
-                    return;
+                let header = String::from_utf8_lossy(header);
+                for scored in score_items(&query_copy, &header, &markers_ref[&key], key) {
+                    if sender.send(scored).is_err() {
+                        error!("unable to send item in FuzzySearchTask internal worker");
+                        return;
+                    }
                 }
-                it += items_sizes_ref[&key];
 
                 while let Ok(update) = update_stream_receiver.try_recv() {
                     /// this gets hit many time because "get_results_for" is called on display.
                     //                    debug!("worker {} {:}: got update {:?}", workerId, &query_copy, &update);
                     match update {
-                        FuzzySearchTaskUpdate::NewLimit(new_limit) => {
-                            if let Some(old_limit) = limit_op {
-                                if old_limit < new_limit {
-                                    limit_op = Some(new_limit);
-                                }
-                            }
-                        }
                         FuzzySearchTaskUpdate::Inot(inot) => {
                             inot_op = Some(inot);
                         }
@@ -278,15 +500,6 @@ impl FuzzySearchTask {
                     //                    debug!("worker {} {:}: refresh", workerId, &query_copy);
                     inot.worker_refresh(workerId);
                 });
-
-                if let Some(limit) = limit_op {
-                    if it < limit {
-                        it += 1;
-                    } else {
-                        error!("worker {} {:}: limit {:} reached.", workerId, &query_copy, &limit);
-                        break;
-                    }
-                }
             } //while
 
             debug!("worker {} finished", workerId);
@@ -295,7 +508,7 @@ impl FuzzySearchTask {
 
         FuzzySearchTask {
             receiver: receiver,
-            item_ids: RefCell::new(item_ids),
+            results: RefCell::new(results),
             done: Cell::new(false),
             query: query,
             limit_op: limit_op,
@@ -304,11 +517,14 @@ impl FuzzySearchTask {
         }
     }
 
-    pub fn get_result_ids(&self) -> Ref<Vec<u64>> {
+    /// Returns items received so far, best first.
+    pub fn get_results(&self) -> Ref<Vec<ScoredItem>> {
+        let mut received = false;
         while !self.done.get() {
             match self.receiver.try_recv() {
-                Ok(string) => {
-                    self.item_ids.borrow_mut().push(string);
+                Ok(scored) => {
+                    self.results.borrow_mut().push(scored);
+                    received = true;
                 }
                 Err(TryRecvError::Empty) => {
                     break;
@@ -319,7 +535,11 @@ impl FuzzySearchTask {
             }
         }
 
-        self.item_ids.borrow()
+        if received {
+            self.results.borrow_mut().sort_by(|a, b| a.rank_cmp(b));
+        }
+
+        self.results.borrow()
     }
 
     /// If runner is done, results in noop.
@@ -372,3 +592,96 @@ fn query_to_regex(query: &String) -> Regex {
 
     regex
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzzy_view_item::file_list_to_items;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn results_when_done(index: &mut FuzzyIndex, query: &str) -> Vec<String> {
+        let query = query.to_string();
+        index.get_results_for(&query, None, None);
+        while !index.cache[&query].is_done() {
+            thread::sleep(Duration::from_millis(1));
+            index.cache[&query].get_results();
+        }
+        index
+            .get_results_for(&query, None, None)
+            .iter()
+            .map(|item| item.get_marker().clone())
+            .collect()
+    }
+
+    fn index_of(paths: Vec<&str>) -> FuzzyIndex {
+        let paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+        FuzzyIndex::new(file_list_to_items(&paths))
+    }
+
+    #[test]
+    fn results_are_ranked() {
+        let mut index =
+            index_of(vec!["/a/domain.rs", "/a/deep/nested/src/main.rs", "/a/src/main.rs"]);
+
+        assert_eq!(
+            results_when_done(&mut index, "main"),
+            vec!["/a/src/main.rs", "/a/deep/nested/src/main.rs", "/a/domain.rs"]
+        );
+    }
+
+    #[test]
+    fn query_with_slash_matches_path() {
+        let mut index = index_of(vec!["/a/src/main.rs", "/a/tests/main.rs", "/a/src/domain.rs"]);
+
+        assert_eq!(results_when_done(&mut index, "tests/main"), vec!["/a/tests/main.rs"]);
+        assert_eq!(
+            results_when_done(&mut index, "src/"),
+            vec!["/a/src/main.rs", "/a/src/domain.rs"]
+        );
+    }
+
+    #[test]
+    fn update_adds_and_removes_items() {
+        let mut index = index_of(vec!["/a/src/main.rs", "/a/src/lib.rs"]);
+
+        let added = file_list_to_items(&vec![PathBuf::from("/a/main.rs")]);
+        index.update(added, vec!["/a/src/main.rs".to_string()]);
+        assert_eq!(results_when_done(&mut index, "main"), vec!["/a/main.rs"]);
+
+        // after rebuild it's all in fst.
+        index.rebuild_if_due(Instant::now() + REBUILD_DELAY);
+        wait_for_rebuild(&mut index);
+        assert!(index.overlay.is_empty() && index.removed.is_empty());
+        assert_eq!(results_when_done(&mut index, "main"), vec!["/a/main.rs"]);
+        assert_eq!(results_when_done(&mut index, "lib"), vec!["/a/src/lib.rs"]);
+    }
+
+    fn wait_for_rebuild(index: &mut FuzzyIndex) {
+        while index.rebuild_op.is_some() {
+            thread::sleep(Duration::from_millis(1));
+            index.finish_rebuild();
+        }
+    }
+
+    #[test]
+    fn updates_during_rebuild_are_kept() {
+        let mut index = index_of(vec!["/a/src/main.rs", "/a/src/lib.rs"]);
+
+        let added = file_list_to_items(&vec![PathBuf::from("/a/main.rs")]);
+        index.update(added, vec![]);
+        index.rebuild_if_due(Instant::now() + REBUILD_DELAY);
+        assert!(index.rebuild_op.is_some());
+
+        // one change to item of the new fst, one to the old one and one new item.
+        let added = file_list_to_items(&vec![PathBuf::from("/a/tests/main.rs")]);
+        index.update(added, vec!["/a/main.rs".to_string(), "/a/src/lib.rs".to_string()]);
+        wait_for_rebuild(&mut index);
+
+        assert_eq!(
+            results_when_done(&mut index, "main"),
+            vec!["/a/src/main.rs", "/a/tests/main.rs"]
+        );
+        assert!(results_when_done(&mut index, "lib").is_empty());
+    }
+}
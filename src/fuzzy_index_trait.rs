@@ -39,4 +39,10 @@ pub trait FuzzyIndexTrait: Debug {
         limit_op: Option<usize>,
         inot_op: Option<InterfaceNotifier>,
     ) -> Vec<Rc<ViewItem>>;
+
+    /// Short description of what index is busy with (like "indexing: 1200 files"), displayed
+    /// next to the query. None if index is complete.
+    fn status(&self) -> Option<String> {
+        None
+    }
 }
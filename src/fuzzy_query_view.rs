@@ -52,6 +52,7 @@ use std::fmt;
 use std::marker::Sized;
 use std::sync::Arc;
 use crate::fuzzy_index_trait::FuzzyIndexTrait;
+use crate::fuzzy_score::fuzzy_match;
 use crate::settings::Settings;
 use crate::fuzzy_view_item::ViewItem;
 use crate::view_handle::ViewHandle;
@@ -77,6 +78,8 @@ pub struct FuzzyQueryView {
     handle: ViewHandle,
     result: Option<Result<FuzzyQueryResult, FuzzyQueryError>>,
     inot: InterfaceNotifier,
+    history: Vec<String>, // previous queries, oldest first.
+    history_pos: Option<usize>,
 }
 
 impl FuzzyQueryView {
@@ -86,6 +89,7 @@ impl FuzzyQueryView {
         channel: IChannel,
         settings: Rc<RefCell<Settings>>,
         inot: InterfaceNotifier,
+        history: Vec<String>,
     ) -> IdView<Self> {
         let res = FuzzyQueryView {
             context: "context".to_string(),
@@ -102,6 +106,8 @@ impl FuzzyQueryView {
             handle: ViewHandle::new(),
             result: None,
             inot: inot,
+            history,
+            history_pos: None,
         };
 
         IdView::new(res.handle(), res)
@@ -167,29 +173,9 @@ impl FuzzyQueryView {
         if line_no == 0 {
             //drawing header
             let header = us::graphemes(item.get_header().as_str(), true).collect::<Vec<&str>>();
-            let query = us::graphemes(self.query.as_str(), true).collect::<Vec<&str>>();
-            // debug!("header : {:?}\nquery : {:?}", header, query);
-            let mut query_pos = 0;
+            let matched = matched_graphemes(&self.query, item.get_header());
             for header_pos in 0..header.len() {
-                let highlighted = {
-                    if query_pos >= query.len() {
-                        // debug!("query pos {:?}, query len {:?}", query_pos, query.len());
-                        false
-                    } else {
-                        if header[header_pos].to_lowercase() == query[query_pos].to_lowercase() {
-                            // debug!("matched {:?} with {:?}", (*header[header_pos]).to_string(),
-                            // (*query[query_pos]).to_string());
-                            query_pos += 1;
-                            true
-                        } else {
-                            // debug!("not matched {:?} with {:?}",
-                            // (*header[header_pos]).to_string(),
-                            // (*query[query_pos]).to_string());
-                            false
-                        }
-                    }
-                };
-
+                let highlighted = matched.contains(&header_pos);
                 let colorstyle = self.get_item_colorstyle(selected, highlighted);
                 printer.with_color(colorstyle, |printer| {
                     printer.print((header_pos, 0), header[header_pos]);
@@ -209,12 +195,28 @@ impl FuzzyQueryView {
             // limit of 1.
             let colorstyle = self.get_item_colorstyle(selected, false);
 
+            // query is highlighted in description only if it didn't match header (for example
+            // a buffer found by its path).
+            let header_matched = fuzzy_match(&self.query, item.get_header()).is_some();
+
             let desc_len = match item.get_description() {
                 &Some(ref desc) => match desc.lines().skip(line_no - 1).next() {
                     Some(line) => {
+                        let matched = if header_matched {
+                            Vec::new()
+                        } else {
+                            matched_graphemes(&self.query, line)
+                        };
+                        let graphemes = us::graphemes(line, true).collect::<Vec<&str>>();
+                        for (x, grapheme) in graphemes.iter().enumerate() {
+                            let colorstyle =
+                                self.get_item_colorstyle(selected, matched.contains(&x));
+                            printer.with_color(colorstyle, |printer| {
+                                printer.print((x, 0), grapheme);
+                            });
+                        }
                         printer.with_color(colorstyle, |printer| {
-                            printer.print((0, 0), line);
-                            for x in line.len()..row_width {
+                            for x in graphemes.len()..row_width {
                                 printer.print((x, 0), " ");
                             }
                         });
@@ -272,6 +274,30 @@ impl FuzzyQueryView {
         self.try_update_scrollbase();
     }
 
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Replaces query with older (back) or newer one from history. Going past the newest one
+    /// brings back empty query.
+    fn browse_history(&mut self, back: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() - 1;
+        self.history_pos = match (self.history_pos, back) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(pos), true) => Some(pos.saturating_sub(1)),
+            (Some(pos), false) if pos < last => Some(pos + 1),
+            (Some(_), false) => None,
+        };
+        self.query = self.history_pos.map(|pos| self.history[pos].clone()).unwrap_or_default();
+        self.selected = 0;
+        self.clear_cache();
+        self.try_update_scrollbase();
+    }
+
     fn backspace(&mut self) {
         self.old_selection = self.get_current_items().get(self.selected).map(|x| x.clone());
         self.query.pop();
@@ -291,8 +317,11 @@ impl View for FuzzyQueryView {
     fn draw(&self, printer: &Printer) {
         ifdebug!("fqv redraw");
         //draw context
-        printer
-            .print((2, 0), &format!("Context : {:?}    query: {:?}", &self.context, &self.query));
+        let mut header = format!("Context : {:?}    query: {:?}", &self.context, &self.query);
+        if let Some(status) = self.index.borrow().status() {
+            header.push_str(&format!("    ({})", status));
+        }
+        printer.print((2, 0), &header);
 
         // debug!("size: {:?}", self.size);
         // debug!("items: {:?}", self.get_current_items());
@@ -327,6 +356,12 @@ impl View for FuzzyQueryView {
                 self.backspace();
                 // debug!("hit backspace");
             }
+            Event::Ctrl(Key::Up) => {
+                self.browse_history(true);
+            }
+            Event::Ctrl(Key::Down) => {
+                self.browse_history(false);
+            }
             Event::Key(Key::Up) => {
                 if self.selected > 0 {
                     self.selected -= 1;
@@ -434,6 +469,25 @@ impl SlyView for FuzzyQueryView {
 }
 
 //TODO tests
+/// Returns indices of graphemes of text matched by query, for highlighting.
+fn matched_graphemes(query: &str, text: &str) -> Vec<usize> {
+    let positions = match fuzzy_match(query, text) {
+        Some(fuzzy_match) => fuzzy_match.positions,
+        None => return Vec::new(),
+    };
+
+    let mut result: Vec<usize> = Vec::new();
+    let mut char_idx = 0;
+    for (grapheme_idx, grapheme) in us::graphemes(text, true).enumerate() {
+        let len = grapheme.chars().count();
+        if positions.iter().any(|pos| *pos >= char_idx && *pos < char_idx + len) {
+            result.push(grapheme_idx);
+        }
+        char_idx += len;
+    }
+    result
+}
+
 fn count_items_lines<I, T>(items: I) -> usize
 where
     T: AsRef<ViewItem>,
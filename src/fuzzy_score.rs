@@ -0,0 +1,174 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Scoring of fuzzy matches. A query matches a text if all its characters appear in the text in the
+// same order, ignoring case. Of all the ways to match, the one with best score is chosen:
+// - every matched character is worth MATCH_SCORE,
+// - matches at beginning of words (after separators like '/' or '_', or camelCase humps) get a
+//   bonus, so "fqv" prefers "Fuzzy Query View" to letters found in the middle of words,
+// - runs of consecutive matches get a bonus,
+// - every skipped character between first and last match costs GAP_PENALTY.
+//
+// Positions of the chosen match are returned too, so views can highlight them.
+//
+// It's a straightforward dynamic programming over (query char, text char), intended for short
+// texts like file names and paths.
+
+const MATCH_SCORE: i64 = 16;
+const BOUNDARY_BONUS: i64 = 8;
+const CAMEL_CASE_BONUS: i64 = 6;
+const FIRST_CHAR_BONUS: i64 = 4;
+const CONSECUTIVE_BONUS: i64 = 4;
+const GAP_PENALTY: i64 = 1;
+
+const SEPARATORS: &'static str = "/\\_-. :";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Indices (in chars) of matched characters of text, ascending.
+    pub positions: Vec<usize>,
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn position_bonus(text: &[char], idx: usize) -> i64 {
+    if idx == 0 {
+        return BOUNDARY_BONUS + FIRST_CHAR_BONUS;
+    }
+    let prev = text[idx - 1];
+    let cur = text[idx];
+    if SEPARATORS.contains(prev) {
+        BOUNDARY_BONUS
+    } else if prev.is_lowercase() && cur.is_uppercase() {
+        CAMEL_CASE_BONUS
+    } else {
+        0
+    }
+}
+
+/// Returns best match of query in text, or None if query is not a subsequence of text (ignoring
+/// case). Empty query matches everything with score 0.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query.chars().map(lowercase).collect();
+    let text: Vec<char> = text.chars().collect();
+
+    if query.is_empty() {
+        return Some(FuzzyMatch { score: 0, positions: Vec::new() });
+    }
+    if query.len() > text.len() {
+        return None;
+    }
+
+    let lower_text: Vec<char> = text.iter().cloned().map(lowercase).collect();
+    let n = text.len();
+
+    // scores[i][j]: best score of matching query[..=i] with query[i] matched at text[j].
+    // from[i][j]: where query[i - 1] was matched in that case.
+    let mut scores: Vec<Vec<Option<i64>>> = vec![vec![None; n]; query.len()];
+    let mut from: Vec<Vec<usize>> = vec![vec![0; n]; query.len()];
+
+    for j in 0..n {
+        if lower_text[j] == query[0] {
+            scores[0][j] = Some(MATCH_SCORE + position_bonus(&text, j));
+        }
+    }
+
+    for i in 1..query.len() {
+        // best (score, position) of previous query char matched before j - 1, with gap penalty
+        // already applied up to j.
+        let mut best_gapped: Option<(i64, usize)> = None;
+        for j in 1..n {
+            if let Some((score, pos)) = best_gapped {
+                best_gapped = Some((score - GAP_PENALTY, pos));
+            }
+
+            if lower_text[j] == query[i] {
+                let bonus = MATCH_SCORE + position_bonus(&text, j);
+                let consecutive = scores[i - 1][j - 1].map(|s| (s + CONSECUTIVE_BONUS, j - 1));
+                let best = match (consecutive, best_gapped) {
+                    (Some(c), Some(g)) => Some(if c.0 >= g.0 { c } else { g }),
+                    (c, g) => c.or(g),
+                };
+                if let Some((score, pos)) = best {
+                    scores[i][j] = Some(score + bonus);
+                    from[i][j] = pos;
+                }
+            }
+
+            // text[j - 1] becomes available as gapped predecessor for text[j + 1] on (penalty is
+            // applied at the beginning of next iteration).
+            if let Some(score) = scores[i - 1][j - 1] {
+                if best_gapped.map(|(best, _)| score > best).unwrap_or(true) {
+                    best_gapped = Some((score, j - 1));
+                }
+            }
+        }
+    }
+
+    let last = query.len() - 1;
+    let mut best: Option<(i64, usize)> = None;
+    for j in 0..n {
+        if let Some(score) = scores[last][j] {
+            if best.map(|(best_score, _)| score > best_score).unwrap_or(true) {
+                best = Some((score, j));
+            }
+        }
+    }
+
+    best.map(|(score, mut pos)| {
+        let mut positions = vec![pos];
+        for i in (1..query.len()).rev() {
+            pos = from[i][pos];
+            positions.push(pos);
+        }
+        positions.reverse();
+        FuzzyMatch { score, positions }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_subsequence_ignoring_case() {
+        assert!(fuzzy_match("FQV", "fuzzy_query_view.rs").is_some());
+        assert!(fuzzy_match("vqf", "fuzzy_query_view.rs").is_none());
+        assert_eq!(fuzzy_match("", "anything").unwrap().score, 0);
+    }
+
+    #[test]
+    fn prefers_word_beginnings_and_runs() {
+        let m = fuzzy_match("qv", "fuzzy_query_view.rs").unwrap();
+        assert_eq!(m.positions, vec![6, 12]);
+
+        let m = fuzzy_match("view", "vxiew_view").unwrap();
+        assert_eq!(m.positions, vec![6, 7, 8, 9]);
+
+        let boundary = fuzzy_match("mr", "main.rs").unwrap().score;
+        let middle = fuzzy_match("mr", "xmxr").unwrap().score;
+        assert!(boundary > middle);
+    }
+
+    #[test]
+    fn camel_case_humps_are_boundaries() {
+        let m = fuzzy_match("sv", "SplitView").unwrap();
+        assert_eq!(m.positions, vec![0, 5]);
+    }
+}
@@ -0,0 +1,417 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Read-only view of files that are not UTF-8 text (see BufferState::open). Every row shows
+// offset, BYTES_PER_ROW bytes in hex and the same bytes as ASCII (non-printable ones as '.').
+//
+// Keys:
+// - arrows, page up/down, home/end move the cursor,
+// - 'g' asks for an offset to go to (decimal, or hex with "0x" prefix),
+// - '/' asks for a pattern to search for: hex bytes ("de ad be ef") or quoted text ("\"PNG\""),
+//   'n' jumps to the next match,
+// - 't' reopens the file as (lossy) text,
+// - esc closes the view (or the prompt).
+
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::ColorStyle;
+use cursive::vec::Vec2;
+use cursive::view::View;
+use cursive::views::IdView;
+use cursive::Printer;
+use std::error;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::buffer_id::BufferId;
+use crate::overlay_dialog::OverlayDialog;
+use crate::sly_view::SlyView;
+use crate::view_handle::ViewHandle;
+
+pub const BYTES_PER_ROW: usize = 16;
+const HELP: &'static str =
+    "g: go to offset  /: search  n: next match  t: open as text  esc: close";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum HexViewResult {
+    Close,
+    ReopenAsText(BufferId),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HexViewError;
+
+impl fmt::Display for HexViewError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HexViewError (not defined)")
+    }
+}
+
+impl std::error::Error for HexViewError {
+    fn description(&self) -> &str {
+        "HexViewError (not defined)"
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Prompt {
+    GoTo,
+    Search,
+}
+
+pub struct HexView {
+    buffer_id: BufferId,
+    name: String,
+    bytes: Arc<Vec<u8>>,
+    cursor: usize,
+    first_row: usize,
+    size: Option<Vec2>,
+    prompt: Option<(Prompt, String)>,
+    pattern: Option<Vec<u8>>,
+    message: Option<String>,
+    handle: ViewHandle,
+    result: Option<Result<HexViewResult, HexViewError>>,
+}
+
+impl HexView {
+    pub fn new(buffer_id: BufferId, name: String, bytes: Arc<Vec<u8>>) -> IdView<Self> {
+        let view = HexView {
+            buffer_id,
+            name,
+            bytes,
+            cursor: 0,
+            first_row: 0,
+            size: None,
+            prompt: None,
+            pattern: None,
+            message: None,
+            handle: ViewHandle::new(),
+            result: None,
+        };
+
+        IdView::new(view.handle(), view)
+    }
+
+    pub fn buffer_id(&self) -> &BufferId {
+        &self.buffer_id
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    fn num_rows(&self) -> usize {
+        // one row for header, one for prompt or help.
+        self.size.map(|size| size.y.saturating_sub(2)).unwrap_or(1).max(1)
+    }
+
+    fn move_to(&mut self, offset: usize) {
+        self.cursor = if self.bytes.is_empty() { 0 } else { offset.min(self.bytes.len() - 1) };
+
+        let row = self.cursor / BYTES_PER_ROW;
+        if row < self.first_row {
+            self.first_row = row;
+        }
+        if row >= self.first_row + self.num_rows() {
+            self.first_row = row + 1 - self.num_rows();
+        }
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let target = self.cursor as isize + delta;
+        if target >= 0 {
+            self.move_to(target as usize);
+        } else {
+            self.move_to(0);
+        }
+    }
+
+    fn find_next(&mut self) {
+        let from = self.cursor + 1;
+        self.search_from(from);
+    }
+
+    fn search_from(&mut self, from: usize) {
+        let pattern = match self.pattern {
+            Some(ref pattern) => pattern.clone(),
+            None => {
+                self.message = Some("nothing to search for".to_string());
+                return;
+            }
+        };
+
+        match find_pattern(&self.bytes, &pattern, from) {
+            Some(offset) => {
+                self.message = None;
+                self.move_to(offset);
+            }
+            None => self.message = Some("pattern not found".to_string()),
+        }
+    }
+
+    fn confirm_prompt(&mut self) {
+        let (prompt, input) = match self.prompt.take() {
+            Some(p) => p,
+            None => return,
+        };
+
+        match prompt {
+            Prompt::GoTo => match parse_offset(&input) {
+                Some(offset) if offset < self.bytes.len() => {
+                    self.message = None;
+                    self.move_to(offset);
+                }
+                Some(_) => self.message = Some("offset beyond end of file".to_string()),
+                None => self.message = Some(format!("invalid offset {:?}", input)),
+            },
+            Prompt::Search => match parse_byte_pattern(&input) {
+                Some(pattern) => {
+                    self.pattern = Some(pattern);
+                    // new search includes current position, next ones do not.
+                    let from = self.cursor;
+                    self.search_from(from);
+                }
+                None => self.message = Some(format!("invalid pattern {:?}", input)),
+            },
+        }
+    }
+
+    fn on_prompt_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Char(c) => {
+                self.prompt.as_mut().map(|(_, input)| input.push(c));
+            }
+            Event::Key(Key::Backspace) => {
+                self.prompt.as_mut().map(|(_, input)| input.pop());
+            }
+            Event::Key(Key::Enter) => self.confirm_prompt(),
+            Event::Key(Key::Esc) => self.prompt = None,
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+
+    fn draw_row(&self, printer: &Printer, y: usize, row: usize) {
+        let begin = row * BYTES_PER_ROW;
+        let end = (begin + BYTES_PER_ROW).min(self.bytes.len());
+
+        printer.with_color(ColorStyle::secondary(), |printer| {
+            printer.print((0, y), &format!("{:08x}", begin));
+        });
+
+        let hex_x = 10;
+        let ascii_x = hex_x + BYTES_PER_ROW * 3 + 2;
+        for offset in begin..end {
+            let idx = offset - begin;
+            let byte = self.bytes[offset];
+            // extra space splits row in halves.
+            let x = hex_x + idx * 3 + if idx >= BYTES_PER_ROW / 2 { 1 } else { 0 };
+            let style =
+                if offset == self.cursor { ColorStyle::highlight() } else { ColorStyle::primary() };
+
+            printer.with_color(style, |printer| {
+                printer.print((x, y), &format!("{:02x}", byte));
+                printer.print((ascii_x + idx, y), &printable(byte).to_string());
+            });
+        }
+    }
+}
+
+impl View for HexView {
+    fn draw(&self, printer: &Printer) {
+        let header = format!(
+            "{} ({} bytes, read-only)  offset 0x{:x}",
+            self.name,
+            self.bytes.len(),
+            self.cursor
+        );
+        printer.with_color(ColorStyle::title_primary(), |printer| {
+            printer.print((0, 0), &header);
+        });
+
+        let total_rows = (self.bytes.len() + BYTES_PER_ROW - 1) / BYTES_PER_ROW;
+        for y in 0..self.num_rows() {
+            let row = self.first_row + y;
+            if row >= total_rows {
+                break;
+            }
+            self.draw_row(printer, y + 1, row);
+        }
+
+        let status = match (&self.prompt, &self.message) {
+            (Some((Prompt::GoTo, input)), _) => format!("go to offset: {}", input),
+            (Some((Prompt::Search, input)), _) => format!("search (hex or \"text\"): {}", input),
+            (None, Some(message)) => message.clone(),
+            (None, None) => HELP.to_string(),
+        };
+        printer.with_color(ColorStyle::secondary(), |printer| {
+            printer.print((0, self.num_rows() + 1), &status);
+        });
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        self.size = Some(constraint);
+        constraint
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        if self.prompt.is_some() {
+            return self.on_prompt_event(event);
+        }
+
+        let page = (self.num_rows() * BYTES_PER_ROW) as isize;
+        let row = BYTES_PER_ROW as isize;
+        match event {
+            Event::Key(Key::Left) => self.move_by(-1),
+            Event::Key(Key::Right) => self.move_by(1),
+            Event::Key(Key::Up) => self.move_by(-row),
+            Event::Key(Key::Down) => self.move_by(row),
+            Event::Key(Key::PageUp) => self.move_by(-page),
+            Event::Key(Key::PageDown) => self.move_by(page),
+            Event::Key(Key::Home) => self.move_to(self.cursor - self.cursor % BYTES_PER_ROW),
+            Event::Key(Key::End) => {
+                self.move_to(self.cursor - self.cursor % BYTES_PER_ROW + BYTES_PER_ROW - 1)
+            }
+            Event::Char('g') => self.prompt = Some((Prompt::GoTo, String::new())),
+            Event::Char('/') => self.prompt = Some((Prompt::Search, String::new())),
+            Event::Char('n') => self.find_next(),
+            Event::Char('t') => {
+                self.result = Some(Ok(HexViewResult::ReopenAsText(self.buffer_id.clone())))
+            }
+            Event::Key(Key::Esc) => self.result = Some(Ok(HexViewResult::Close)),
+            _ => {
+                debug!("hex view got unhandled event {:?}", &event);
+                return EventResult::Ignored;
+            }
+        }
+        EventResult::Consumed(None)
+    }
+}
+
+impl OverlayDialog<HexViewResult, HexViewError> for HexView {
+    fn is_displayed(&self) -> bool {
+        self.result.is_none()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+
+    fn get_result(&self) -> Option<Result<HexViewResult, HexViewError>> {
+        self.result.clone()
+    }
+
+    fn cancel(&mut self) {
+        self.result = Some(Ok(HexViewResult::Close))
+    }
+}
+
+impl SlyView for HexView {
+    fn handle(&self) -> ViewHandle {
+        self.handle.clone()
+    }
+}
+
+fn printable(byte: u8) -> char {
+    if byte >= 0x20 && byte < 0x7f {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+/// Parses offset given either in decimal, or in hex with "0x" prefix.
+pub fn parse_offset(input: &str) -> Option<usize> {
+    let input = input.trim();
+    if input.starts_with("0x") || input.starts_with("0X") {
+        usize::from_str_radix(&input[2..], 16).ok()
+    } else {
+        input.parse::<usize>().ok()
+    }
+}
+
+/// Parses search pattern: text in double quotes is taken as is, anything else has to be hex
+/// bytes, whitespace between them is ignored.
+pub fn parse_byte_pattern(input: &str) -> Option<Vec<u8>> {
+    let input = input.trim();
+    if input.len() >= 2 && input.starts_with('"') && input.ends_with('"') {
+        let text = &input[1..input.len() - 1];
+        return if text.is_empty() { None } else { Some(text.as_bytes().to_vec()) };
+    }
+
+    let digits: Vec<char> = input.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || digits.len() % 2 != 0 {
+        return None;
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let byte: String = pair.iter().collect();
+            u8::from_str_radix(&byte, 16).ok()
+        })
+        .collect()
+}
+
+/// Returns offset of the first occurrence of pattern at or after from, wrapping around to the
+/// beginning of bytes.
+pub fn find_pattern(bytes: &[u8], pattern: &[u8], from: usize) -> Option<usize> {
+    if pattern.is_empty() || pattern.len() > bytes.len() {
+        return None;
+    }
+
+    let last_start = bytes.len() - pattern.len();
+    let matches_at = |offset: usize| &bytes[offset..offset + pattern.len()] == pattern;
+    let from = from.min(last_start + 1);
+
+    (from..last_start + 1).chain(0..from).find(|&offset| matches_at(offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_offset_test() {
+        assert_eq!(parse_offset("123"), Some(123));
+        assert_eq!(parse_offset("0x1f"), Some(31));
+        assert_eq!(parse_offset(" 0X10 "), Some(16));
+        assert_eq!(parse_offset("zz"), None);
+    }
+
+    #[test]
+    fn parse_byte_pattern_test() {
+        assert_eq!(parse_byte_pattern("de ad BE ef"), Some(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(parse_byte_pattern("\"PNG\""), Some(b"PNG".to_vec()));
+        assert_eq!(parse_byte_pattern("abc"), None);
+        assert_eq!(parse_byte_pattern("xy"), None);
+        assert_eq!(parse_byte_pattern("\"\""), None);
+    }
+
+    #[test]
+    fn find_pattern_test() {
+        let bytes = b"abcabc";
+        assert_eq!(find_pattern(bytes, b"bc", 0), Some(1));
+        assert_eq!(find_pattern(bytes, b"bc", 2), Some(4));
+        // wraps around
+        assert_eq!(find_pattern(bytes, b"bc", 5), Some(1));
+        assert_eq!(find_pattern(bytes, b"x", 0), None);
+        assert_eq!(find_pattern(bytes, b"abcabcabc", 0), None);
+    }
+}
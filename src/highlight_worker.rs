@@ -0,0 +1,304 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Syntax highlighting is computed here, in a separate thread, so drawing never waits for
+// syntect. Worker works on a Rope snapshot (cloning Rope is O(1)), and every result is stamped
+// with version of the snapshot it was computed from, so RichContent can drop results that
+// belong to content that no longer exists.
+//
+// Worker parses lines in order, in chunks of PARSING_MILESTONE lines, and remembers parser state
+// at the beginning of each chunk. After an edit, parsing is resumed from the last remembered state
+// preceding the first changed line.
+//
+// Besides colours, worker marks strings and comments (NON_CODE_SCOPES), so bracket matching can
+// skip brackets within them, and counts open block scopes (BLOCK_SCOPES), which folding uses.
+//
+// Worker parses only as far as it was asked to (see HighlightJob::ParseUpTo), so jumping to the
+// end of a big file still requires parsing everything above, but it does not freeze the editor.
+
+use crate::interface::InterfaceNotifier;
+use crate::rich_content::HighlightSettings;
+use crate::rich_content::RichLine;
+use cursive::theme::Color;
+use ropey::Rope;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::str::FromStr;
+use syntect::highlighting::{HighlightIterator, HighlightState, Highlighter, ScopeSelectors, Style};
+use syntect::parsing::{ParseState, Scope, ScopeStack, ScopeStackOp};
+
+pub const PARSING_MILESTONE: usize = 10;
+const NON_CODE_SCOPES: &'static str = "string, comment";
+const BLOCK_SCOPES: [&'static str; 4] =
+    ["meta.block", "meta.braces", "meta.mapping", "meta.sequence"];
+
+//docs: https://docs.rs/uid/0.1.4/uid/struct.Id.html
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct WorkerIdType(());
+
+type Id = uid::Id<WorkerIdType>;
+
+#[derive(Debug)]
+pub enum HighlightJob {
+    /// New snapshot of content. Lines before first_changed_line are guaranteed to be identical
+    /// with previous snapshot.
+    Update { version: usize, rope: Rope, first_changed_line: usize },
+    /// Request to have all lines up to line_no (inclusive) highlighted.
+    ParseUpTo { version: usize, line_no: usize },
+}
+
+#[derive(Debug)]
+pub struct HighlightResult {
+    pub version: usize,
+    pub first_line: usize,
+    pub lines: Vec<Arc<RichLine>>,
+}
+
+#[derive(Clone, Debug)]
+struct ParseCacheRecord {
+    line_to_parse: usize,
+    parse_state: ParseState,
+    highlight_state: HighlightState,
+    // highlight_state keeps its own stack, but does not expose it.
+    scope_stack: ScopeStack,
+}
+
+impl ParseCacheRecord {
+    pub fn new(parse_state: ParseState, highlighter: &Highlighter) -> Self {
+        let scope_stack = ScopeStack::new();
+        let highlight_state = HighlightState::new(highlighter, scope_stack.clone());
+
+        ParseCacheRecord { line_to_parse: 0, parse_state, highlight_state, scope_stack }
+    }
+}
+
+/// Starts a worker thread. Worker finishes when the returned Sender gets dropped.
+pub fn start_highlight_worker(
+    settings: Arc<HighlightSettings>,
+    rope: Rope,
+    version: usize,
+    inot_op: Option<InterfaceNotifier>,
+) -> (Sender<HighlightJob>, Receiver<HighlightResult>) {
+    let (job_sender, job_receiver) = mpsc::channel::<HighlightJob>();
+    let (result_sender, result_receiver) = mpsc::channel::<HighlightResult>();
+
+    thread::spawn(move || {
+        let worker = HighlightWorker {
+            settings,
+            rope,
+            version,
+            cache: Vec::new(),
+            target: 0,
+            job_receiver,
+            result_sender,
+            inot_op,
+            worker_id: Id::new().get(),
+            non_code_selectors: ScopeSelectors::from_str(NON_CODE_SCOPES).unwrap(),
+            block_scopes: BLOCK_SCOPES.iter().map(|s| Scope::new(s).unwrap()).collect(),
+        };
+        worker.run();
+    });
+
+    (job_sender, result_receiver)
+}
+
+struct HighlightWorker {
+    settings: Arc<HighlightSettings>,
+    rope: Rope,
+    version: usize,
+    // contract: sorted by line_to_parse, the last record is where parsing is to be resumed.
+    cache: Vec<ParseCacheRecord>,
+    // number of lines that are requested to be parsed.
+    target: usize,
+    job_receiver: Receiver<HighlightJob>,
+    result_sender: Sender<HighlightResult>,
+    inot_op: Option<InterfaceNotifier>,
+    worker_id: usize,
+    non_code_selectors: ScopeSelectors,
+    block_scopes: Vec<Scope>,
+}
+
+impl HighlightWorker {
+    fn run(mut self) {
+        debug!("highlight worker {} started", self.worker_id);
+        loop {
+            // When there is nothing to do, we block waiting for the next job.
+            let job_op: Option<HighlightJob> = if self.parsed_lines() < self.target() {
+                match self.job_receiver.try_recv() {
+                    Ok(job) => Some(job),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            } else {
+                match self.job_receiver.recv() {
+                    Ok(job) => Some(job),
+                    Err(_) => break,
+                }
+            };
+
+            if let Some(job) = job_op {
+                self.process_job(job);
+                continue; // first let's consume all the jobs, the newest matter most.
+            }
+
+            if !self.parse_chunk() {
+                break;
+            }
+        }
+        debug!("highlight worker {} finished", self.worker_id);
+    }
+
+    fn target(&self) -> usize {
+        std::cmp::min(self.target, self.rope.len_lines())
+    }
+
+    fn parsed_lines(&self) -> usize {
+        self.cache.last().map(|rec| rec.line_to_parse).unwrap_or(0)
+    }
+
+    fn process_job(&mut self, job: HighlightJob) {
+        match job {
+            HighlightJob::Update { version, rope, first_changed_line } => {
+                self.version = version;
+                self.rope = rope;
+                // Record with line_to_parse == L depends only on lines [0, L).
+                while self.cache.last().map(|rec| rec.line_to_parse > first_changed_line)
+                    == Some(true)
+                {
+                    self.cache.pop();
+                }
+            }
+            HighlightJob::ParseUpTo { version, line_no } => {
+                if version == self.version {
+                    let new_target = (line_no / PARSING_MILESTONE + 1) * PARSING_MILESTONE;
+                    self.target = std::cmp::max(self.target, new_target);
+                }
+            }
+        }
+    }
+
+    /// Returns false if results cannot be delivered anymore.
+    fn parse_chunk(&mut self) -> bool {
+        let highlighter = Highlighter::new(self.settings.theme());
+
+        let mut record = match self.cache.last() {
+            Some(rec) => rec.clone(),
+            None => {
+                ParseCacheRecord::new(ParseState::new(self.settings.syntax()), &highlighter)
+            }
+        };
+
+        let first_line = record.line_to_parse;
+        let line_limit = std::cmp::min(
+            (first_line / PARSING_MILESTONE + 1) * PARSING_MILESTONE,
+            self.rope.len_lines(),
+        );
+
+        let mut lines: Vec<Arc<RichLine>> = Vec::new();
+        for line_no in first_line..line_limit {
+            let line_str = self.rope.line(line_no).to_string();
+            let ops = record.parse_state.parse_line(&line_str, self.settings.syntax_set());
+
+            let ranges: Vec<(Style, &str)> = HighlightIterator::new(
+                &mut record.highlight_state,
+                &ops[..],
+                &line_str,
+                &highlighter,
+            )
+            .collect();
+
+            let body: Vec<(Color, String)> = ranges
+                .into_iter()
+                .map(|(style, words)| (simplify_style(&style), words.to_string()))
+                .collect();
+
+            let (non_code, block_depth) =
+                self.analyse_scopes(&mut record.scope_stack, &ops[..], &line_str);
+
+            lines.push(Arc::new(RichLine::new(line_no, body, non_code, block_depth)));
+        }
+
+        record.line_to_parse = line_limit;
+        self.cache.push(record);
+
+        let result = HighlightResult { version: self.version, first_line, lines };
+        if self.result_sender.send(result).is_err() {
+            return false;
+        }
+
+        self.inot_op.as_ref().map(|inot| inot.worker_refresh(self.worker_id));
+        true
+    }
+
+    /// Applies ops to scope_stack, returning char ranges of line that are strings or comments, and
+    /// (minimal, final) number of block scopes open within line.
+    fn analyse_scopes(
+        &self,
+        scope_stack: &mut ScopeStack,
+        ops: &[(usize, ScopeStackOp)],
+        line: &str,
+    ) -> (Vec<(usize, usize)>, (usize, usize)) {
+        let char_idx = |byte_offset: usize| line[..byte_offset].chars().count();
+        let mut result: Vec<(usize, usize)> = Vec::new();
+        let mut min_depth = self.block_depth(scope_stack);
+
+        // byte offset where current non-code range begins. Stack is inherited from previous line.
+        let mut begin_op: Option<usize> =
+            if self.is_non_code(scope_stack) { Some(0) } else { None };
+
+        for (byte_offset, op) in ops {
+            scope_stack.apply(op);
+            min_depth = std::cmp::min(min_depth, self.block_depth(scope_stack));
+
+            match (self.is_non_code(scope_stack), begin_op) {
+                (true, None) => begin_op = Some(*byte_offset),
+                (false, Some(begin)) => {
+                    if begin < *byte_offset {
+                        result.push((char_idx(begin), char_idx(*byte_offset)));
+                    }
+                    begin_op = None;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(begin) = begin_op {
+            if begin < line.len() {
+                result.push((char_idx(begin), char_idx(line.len())));
+            }
+        }
+
+        (result, (min_depth, self.block_depth(scope_stack)))
+    }
+
+    fn block_depth(&self, scope_stack: &ScopeStack) -> usize {
+        scope_stack
+            .as_slice()
+            .iter()
+            .filter(|scope| self.block_scopes.iter().any(|block| block.is_prefix_of(**scope)))
+            .count()
+    }
+
+    fn is_non_code(&self, scope_stack: &ScopeStack) -> bool {
+        self.non_code_selectors.does_match(scope_stack.as_slice()).is_some()
+    }
+}
+
+fn simplify_style(style: &Style) -> Color {
+    Color::Rgb(style.foreground.r, style.foreground.g, style.foreground.b)
+}
@@ -28,11 +28,13 @@ use std::cell::Ref;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::error;
 use std::error::Error;
 use std::ffi::OsStr;
 use std::ffi::OsString;
 use std::fmt;
+use std::io;
 use std::ops::DerefMut;
 use std::path::Path;
 use std::path::PathBuf;
@@ -40,7 +42,7 @@ use std::rc::{Rc, Weak};
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread::ThreadId;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use crate::app_state::AppState;
 use crate::events::{IEvent, IChannel};
 use cursive::{Cursive, CbSink};
@@ -54,13 +56,60 @@ use cursive::traits::View;
 use crate::file_dialog::{FileDialogResult, FileDialog, FileDialogVariant};
 use crate::fuzzy_query_view::{FuzzyQueryResult, FuzzyQueryView};
 use crate::simple_fuzzy_index::SimpleIndex;
+use crate::fuzzy_view_item::ViewItem;
+use crate::syntax_loader::PLAIN_TEXT_SYNTAX;
 use crate::settings::Settings;
 use crate::overlay_dialog::OverlayDialog;
+use crate::hex_view::{HexView, HexViewResult};
+use crate::buffer_switcher::{BufferSwitcher, BufferSwitcherResult};
+use crate::choice_dialog::{ChoiceDialog, ChoiceDialogResult};
+use crate::autosave::{AutosavePolicy, AutosaveTimer};
+use crate::recovery::RecoveryRecord;
+use crate::app_state::ExternalChange;
+use crate::disk_check::DiskChange;
+use crate::text_diff::TextDiff;
+use crate::session;
+use crate::session::{BufferSession, LayoutS, Session};
+use crate::cursor_set::CursorSet;
+use crate::content_provider::EditEvent;
+use crate::split_view::{PaneLayout, SplitOrientation, SplitView};
+use crate::tab_bar::TabBar;
+use crate::file_watcher;
+use crate::file_watcher::FileChanges;
+use crate::buffer_state_observer::BufferStateObserver;
+use cursive::vec::Vec2;
+use std::cmp;
+use ropey::Rope;
 use std::borrow::Borrow;
 
 const FILE_BAR_MARKER: &'static str = "file_bar";
 const BUFFER_LIST_MARKER: &'static str = "buffer_list";
 const ALL_COMMANDS_MARKER: &'static str = "all_commands";
+const SYNTAX_LIST_MARKER: &'static str = "syntax_list";
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+const PANE_RESIZE_STEP: isize = 5; // percent
+
+/// What a question displayed in ChoiceDialog was about.
+enum PendingChoice {
+    // save failed for lack of permissions, path is None for "save", not "save as".
+    SaveAsRoot(BufferId, Option<PathBuf>),
+    // password for saving as root, see SaveAsRoot.
+    RootPassword(BufferId, Option<PathBuf>),
+    // recovery files left by a crashed session.
+    Recover(Vec<(PathBuf, RecoveryRecord)>),
+    // file of modified buffer was changed on disk.
+    FileChanged(BufferId),
+    // closing target would lose unsaved changes of listed buffers.
+    UnsavedChanges(CloseTarget, Vec<BufferId>),
+    // same, asking about the first of listed buffers only.
+    ReviewUnsaved(CloseTarget, Vec<BufferId>),
+}
+
+/// What is closed once unsaved changes are dealt with, see Interface::request_close.
+enum CloseTarget {
+    Quit,
+    Buffer(BufferId),
+}
 
 /*
 At this moment I have not decided on whether interface holds premise before siv or other way around.
@@ -72,7 +121,8 @@ pub struct Interface {
     state: AppState,
     channel: (mpsc::Sender<IEvent>, mpsc::Receiver<IEvent>),
     siv: Cursive,
-    active_editor_handle: ViewHandle,
+    split_view_handle: ViewHandle,
+    // editors not displayed in any pane, at most one per buffer. They keep cursors of buffers.
     inactive_editors: HashMap<BufferId, IdView<SlyTextView>>,
     path_to_buffer_id: HashMap<PathBuf, BufferId>,
     done: bool,
@@ -80,8 +130,36 @@ pub struct Interface {
     file_bar_handle: Option<ViewHandle>,
     all_commands_bar_handle: Option<ViewHandle>,
     buffer_list_handle: Option<ViewHandle>,
+    syntax_list_handle: Option<ViewHandle>,
+    hex_view_handle: Option<ViewHandle>,
+    choice_dialog_handle: Option<ViewHandle>,
+    buffer_switcher_handle: Option<ViewHandle>,
+    pending_choice: Option<PendingChoice>,
+    // closing that continues once save as dialog succeeds.
+    closing_after_save_as: Option<(CloseTarget, Vec<BufferId>)>,
+    // fuzzy bar marker -> previous queries, oldest first.
+    query_history: HashMap<String, Vec<String>>,
+    // buffers of standard input and files given on command line.
+    given_buffers: Vec<BufferId>,
+    // exit once all given_buffers are closed (--wait).
+    exit_with_given_buffers: bool,
+    // content of this buffer is printed on exit (--stdout), it's never asked to be saved.
+    output_buffer: Option<BufferId>,
+    tab_bar_enabled: bool,
+    autosave_timer: AutosaveTimer,
+    // buffers changed on disk while modified, waiting for user's decision.
+    conflicts_to_ask: VecDeque<BufferId>,
+    // same, but user chose to see the diff first. They are asked again when buffer is focused.
+    deferred_conflicts: HashSet<BufferId>,
     lsp_clients: Vec<LspClient>, //TODO(njskalski): temporary storage to avoid removal
+    // buffers edited since language servers were last told about them, synced on tick.
+    lsp_outdated_buffers: HashSet<BufferId>,
     active_workers: HashSet<usize>,
+    // workers of file_indexer still running, and number of files they found so far.
+    indexing_workers: HashSet<usize>,
+    num_files_indexed: usize,
+    // project directories get file watchers once indexed, set by main.
+    watch_files: bool,
 }
 
 fn find_view_with_handle<V>(siv: &mut Cursive, handle_op: &Option<ViewHandle>) -> Option<ViewRef<V>>
@@ -94,6 +172,34 @@ where
     }
 }
 
+fn buffer_session(path: PathBuf, view: &SlyTextView) -> BufferSession {
+    let position = view.position();
+    BufferSession { path, cursors: view.cursors().clone(), position: (position.x, position.y) }
+}
+
+/// Returns layout of panes to be saved in session. Panes without BufferSession (unnamed buffers)
+/// are left out.
+fn layout_session(
+    layout: &PaneLayout,
+    panes: &HashMap<ViewHandle, BufferSession>,
+) -> Option<LayoutS> {
+    match layout {
+        PaneLayout::Pane(pane_id) => panes.get(pane_id).cloned().map(LayoutS::Pane),
+        PaneLayout::Split { orientation, share, first, second } => {
+            match (layout_session(first, panes), layout_session(second, panes)) {
+                (Some(first), Some(second)) => Some(LayoutS::Split {
+                    orientation: *orientation,
+                    share: *share,
+                    first: Box::new(first),
+                    second: Box::new(second),
+                }),
+                (Some(part), None) | (None, Some(part)) => Some(part),
+                (None, None) => None,
+            }
+        }
+    }
+}
+
 impl fmt::Display for InterfaceError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "InterfaceError (not defined)")
@@ -113,30 +219,66 @@ impl Interface {
         let channel = mpsc::channel();
         siv.set_theme(theme);
 
+        // Has to be done before any buffer is created, so their workers can wake up interface.
+        state.set_interface_notifier(InterfaceNotifier {
+            siv_cb_sink: siv.cb_sink().clone(),
+            ichan: channel.0.clone(),
+        });
+
         let buffer_observer = state.get_first_buffer().unwrap(); // TODO(njskalski): panics. Semantics unclear.
+        let first_buffer_is_binary = buffer_observer.is_binary();
+        let first_buffer_id = buffer_observer.buffer_id();
         let sly_text_view =
             SlyTextView::new(state.settings_rc().clone(), buffer_observer, channel.0.clone());
-        let active_editor = sly_text_view.handle().clone();
+        let split_view = SplitView::new(sly_text_view);
+        let split_view_handle = split_view.handle();
+
+        siv.add_fullscreen_layer(split_view);
 
-        siv.add_fullscreen_layer(sly_text_view);
-        siv.focus_id(&active_editor.to_string());
+        let autosave_timer = AutosaveTimer::from_settings(&state.settings_ref(), Instant::now());
+        let tab_bar_enabled = state.settings_ref().get_bool("interface/tab_bar");
+        state.mark_focused(&first_buffer_id);
 
         let mut i = Interface {
             state: state,
             channel: channel,
             siv: siv,
-            active_editor_handle: active_editor,
+            split_view_handle,
             inactive_editors: HashMap::new(),
             path_to_buffer_id: HashMap::new(),
             done: false,
             file_dialog_handle: None,
             file_bar_handle: None,
             buffer_list_handle: None,
+            syntax_list_handle: None,
+            hex_view_handle: None,
+            choice_dialog_handle: None,
+            buffer_switcher_handle: None,
+            pending_choice: None,
+            closing_after_save_as: None,
+            query_history: HashMap::new(),
+            given_buffers: Vec::new(),
+            exit_with_given_buffers: false,
+            output_buffer: None,
+            tab_bar_enabled,
+            autosave_timer,
+            conflicts_to_ask: VecDeque::new(),
+            deferred_conflicts: HashSet::new(),
             all_commands_bar_handle: None,
             lsp_clients: Vec::new(),
+            lsp_outdated_buffers: HashSet::new(),
             active_workers: HashSet::new(),
+            indexing_workers: HashSet::new(),
+            num_files_indexed: 0,
+            watch_files: false,
         };
 
+        // Workers are marked active right away, so tests can wait for the index to fill up.
+        let indexing_workers = i.state.start_indexing(i.inot());
+        i.active_workers.extend(indexing_workers.iter().cloned());
+        i.indexing_workers.extend(indexing_workers.into_iter());
+        i.update_indexing_status();
+
         // let known_actions = vec!["show_everything_bar"];
         //TODO filter unknown actions
         let keybindings = i.settings_ref().get_keybindings("global");
@@ -159,6 +301,11 @@ impl Interface {
                         ch.send(IEvent::QuitSly).unwrap();
                     });
                 }
+                "close_buffer" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::CloseCurrentBuffer).unwrap();
+                    });
+                }
                 "show_buffer_list" => {
                     i.siv.add_global_callback(event, move |_| {
                         ch.send(IEvent::ShowBufferList).unwrap();
@@ -184,17 +331,85 @@ impl Interface {
                         ch.send(IEvent::CloseWindow).unwrap();
                     });
                 }
+                "split_vertically" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::SplitPane(SplitOrientation::Vertical)).unwrap();
+                    });
+                }
+                "split_horizontally" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::SplitPane(SplitOrientation::Horizontal)).unwrap();
+                    });
+                }
+                "close_pane" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::ClosePane).unwrap();
+                    });
+                }
+                "next_pane" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::FocusNextPane).unwrap();
+                    });
+                }
+                "previous_pane" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::FocusPreviousPane).unwrap();
+                    });
+                }
+                "grow_pane" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::ResizePane(PANE_RESIZE_STEP)).unwrap();
+                    });
+                }
+                "shrink_pane" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::ResizePane(-PANE_RESIZE_STEP)).unwrap();
+                    });
+                }
+                "next_buffer" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::NextBuffer).unwrap();
+                    });
+                }
+                "previous_buffer" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::PreviousBuffer).unwrap();
+                    });
+                }
+                "buffer_switcher" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::ShowBufferSwitcher).unwrap();
+                    });
+                }
+                "toggle_tab_bar" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::ToggleTabBar).unwrap();
+                    });
+                }
                 "start_lsp" => {
                     i.siv.add_global_callback(event, move |_| {
                         ch.send(IEvent::EnableLSP).unwrap();
                     });
                 }
+                "set_syntax" => {
+                    i.siv.add_global_callback(event, move |_| {
+                        ch.send(IEvent::ShowSyntaxList).unwrap();
+                    });
+                }
                 _ => {
                     debug!("unknown action {:?} bound with event global {:?}", action, event);
                 }
             }
         }
 
+        if first_buffer_is_binary {
+            i.show_hex_view(&first_buffer_id);
+        }
+
+        i.open_scheduled_files();
+        i.restore_session();
+        i.offer_recovery();
+
         i
     }
 
@@ -202,21 +417,14 @@ impl Interface {
         !self.active_workers.is_empty()
     }
 
-    // TODO(njskalski): error handling, borrow instead of copy etc.
-    fn replace_current_editor_view(
-        &mut self,
-        new_editor: IdView<SlyTextView>,
-    ) -> IdView<SlyTextView> {
-        // removing old view
-        let handle = self.active_editor_handle.clone();
-        let old_view = self.remove_window::<SlyTextView>(&handle);
-
-        // inserting new view
-        self.active_editor_handle = new_editor.handle();
-        self.siv.screen_mut().add_fullscreen_layer(new_editor);
+    fn split_view(&mut self) -> ViewRef<SplitView> {
+        self.siv.find_id(&self.split_view_handle.to_string()).unwrap()
+    }
 
-        // returning old view
-        old_view.unwrap()
+    /// Keeps editor removed from a pane, unless its buffer has an inactive editor already.
+    fn hide_editor(&mut self, mut editor: IdView<SlyTextView>) {
+        let buffer_id = editor.get_mut().buffer_obs().buffer_id();
+        self.inactive_editors.entry(buffer_id).or_insert(editor);
     }
 
     fn remove_window<T>(&mut self, handle: &ViewHandle) -> Option<IdView<T>>
@@ -242,8 +450,7 @@ impl Interface {
             }
         }
 
-        let obs = self.state.buffer_obs(buffer_id).unwrap(); //TODO panics
-        let mut view = SlyTextView::new(self.settings_rc().clone(), obs, self.event_sink());
+        let view = self.new_editor_for_buffer(buffer_id);
         if self.inactive_editors.insert(buffer_id.clone(), view).is_some() {
             panic!("insertion failed, object already present");
         }
@@ -251,6 +458,28 @@ impl Interface {
         Ok(())
     }
 
+    /// Creates editor of buffer. If buffer is displayed in a pane, the new editor starts with
+    /// cursors and position of that pane (preferably the focused one).
+    fn new_editor_for_buffer(&mut self, buffer_id: &BufferId) -> IdView<SlyTextView> {
+        let obs = self.state.buffer_obs(buffer_id).unwrap(); //TODO panics
+        let mut view = SlyTextView::new(self.settings_rc().clone(), obs, self.event_sink());
+
+        let focused = self.split_view().focused();
+        let mut view_state_op: Option<(CursorSet, Vec2)> = None;
+        for (pane_id, editor) in self.split_view().editors() {
+            if editor.buffer_obs().buffer_id() == *buffer_id
+                && (view_state_op.is_none() || pane_id == focused)
+            {
+                view_state_op = Some((editor.cursors().clone(), editor.position()));
+            }
+        }
+        if let Some((cursors, position)) = view_state_op {
+            view.get_mut().restore_view_state(cursors, position);
+        }
+
+        view
+    }
+
     fn process_events(&mut self) {
         while let Ok(msg) = self.channel.1.try_recv() {
             debug!("processing event {:?}", msg);
@@ -262,14 +491,48 @@ impl Interface {
                     self.show_file_bar();
                 }
                 IEvent::QuitSly => {
-                    self.done = true;
+                    self.request_close(CloseTarget::Quit);
+                }
+                IEvent::CloseCurrentBuffer => {
+                    let buffer_id = self.active_editor().buffer_obs().buffer_id();
+                    self.request_close(CloseTarget::Buffer(buffer_id));
                 }
                 IEvent::CloseWindow => {
                     self.cancel_floating_windows();
                 }
-                IEvent::BufferEditEvent(view_handle, events) => {
-                    //TODO now I just send to active editor, ignoring view_handle
-                    self.active_editor().buffer_obs().submit_edit_events_to_buffer(events);
+                IEvent::Tick => {
+                    self.on_tick();
+                }
+                IEvent::BufferEditEvent(buffer_id, view_handle, events) => {
+                    self.autosave_timer.on_edit(Instant::now());
+                    self.submit_edit_events(&buffer_id, &view_handle, events);
+                }
+                IEvent::SplitPane(orientation) => {
+                    self.split_pane(orientation);
+                }
+                IEvent::ClosePane => {
+                    self.close_pane();
+                }
+                IEvent::FocusNextPane => {
+                    self.focus_next_pane(true);
+                }
+                IEvent::FocusPreviousPane => {
+                    self.focus_next_pane(false);
+                }
+                IEvent::ResizePane(delta) => {
+                    self.split_view().resize_focused(delta);
+                }
+                IEvent::NextBuffer => {
+                    self.focus_next_buffer(true);
+                }
+                IEvent::PreviousBuffer => {
+                    self.focus_next_buffer(false);
+                }
+                IEvent::ShowBufferSwitcher => {
+                    self.show_buffer_switcher();
+                }
+                IEvent::ToggleTabBar => {
+                    self.tab_bar_enabled = !self.tab_bar_enabled;
                 }
                 IEvent::SaveCurrentBuffer => {
                     self.save_current_buffer();
@@ -283,9 +546,40 @@ impl Interface {
                 IEvent::ShowBufferList => {
                     self.show_buffer_list();
                 }
+                IEvent::ShowSyntaxList => {
+                    self.show_syntax_list();
+                }
                 IEvent::EnableLSP => {
                     self.enable_lsp();
                 }
+                IEvent::FoldingRanges(buffer_id, content_version, ranges) => {
+                    self.state.set_lsp_folding_ranges(&buffer_id, content_version, ranges);
+                }
+                IEvent::LspInitialized => {
+                    for lsp in &mut self.lsp_clients {
+                        if let Err(e) = lsp.on_initialized() {
+                            error!("initializing language server failed, because \"{}\"", e);
+                        }
+                    }
+                }
+                IEvent::FileLoaded(buffer_id, result) => {
+                    self.on_file_loaded(buffer_id, result);
+                }
+                IEvent::FilesChanged(created, removed) => {
+                    self.on_files_changed(created, removed);
+                }
+                IEvent::FilesIndexed(root, files) => {
+                    self.on_files_indexed(root, files);
+                }
+                IEvent::IndexingFinished(worker_id, root, num_files, limit_hit) => {
+                    self.on_indexing_finished(worker_id, root, num_files, limit_hit);
+                }
+                IEvent::DiskChecked(worker_id, changes) => {
+                    self.on_disk_checked(worker_id, changes);
+                }
+                IEvent::PrivilegedSaved(worker_id, buffer_id, path, version, result) => {
+                    self.on_privileged_saved(worker_id, buffer_id, path, version, result);
+                }
                 IEvent::WorkerStart(workerId) => {
                     self.active_workers.insert(workerId);
                 }
@@ -309,15 +603,13 @@ impl Interface {
             let mut file_dialog = self.file_dialog().unwrap();
 
             if let Some(result) = file_dialog.get_result() {
+                let mut saved = false;
                 match result {
                     Ok(FileDialogResult::Cancel) => {}
                     Ok(FileDialogResult::FileSave(buffer_id, path)) => {
-                        match self.state.save_buffer_as(&buffer_id, path) {
-                            Ok(()) => {}
-                            Err(e) => {
-                                dbg!(&e);
-                                error!("file save failed, because \"{}\"", e)
-                            }
+                        match self.state.save_buffer_as(&buffer_id, path.clone()) {
+                            Ok(()) => saved = true,
+                            Err(e) => self.on_save_failed(buffer_id, Some(path), e),
                         }
                     }
                     Ok(FileDialogResult::FileOpen(path)) => {
@@ -332,6 +624,13 @@ impl Interface {
 
                 let handle = self.file_dialog_handle.take().unwrap();
                 self.remove_window::<FileDialog>(&handle);
+
+                // closing is abandoned if user did not save.
+                if let Some((target, buffer_ids)) = self.closing_after_save_as.take() {
+                    if saved {
+                        self.review_unsaved(target, buffer_ids);
+                    }
+                }
             }
         }
 
@@ -342,6 +641,7 @@ impl Interface {
                 match result {
                     Ok(FuzzyQueryResult::Cancel) => {}
                     Ok(FuzzyQueryResult::Selected(_, item_marker)) => {
+                        self.remember_query(FILE_BAR_MARKER, file_bar.query());
                         debug!("selected file {:?}", &item_marker);
                         self.open_and_or_focus_file(item_marker);
                     }
@@ -361,6 +661,7 @@ impl Interface {
                 match result {
                     Ok(FuzzyQueryResult::Cancel) => {}
                     Ok(FuzzyQueryResult::Selected(_, buffer_id_str)) => {
+                        self.remember_query(BUFFER_LIST_MARKER, buffer_list.query());
                         debug!("selected buffer {}", &buffer_id_str);
                         self.open_and_or_focus(&BufferId::from_string(&buffer_id_str).unwrap());
                     }
@@ -373,6 +674,87 @@ impl Interface {
             }
         }
 
+        if self.syntax_list_handle.is_some() {
+            let mut syntax_list = self.syntax_list().unwrap();
+
+            if let Some(result) = syntax_list.get_result() {
+                match result {
+                    Ok(FuzzyQueryResult::Cancel) => {}
+                    Ok(FuzzyQueryResult::Selected(_, syntax_name)) => {
+                        self.remember_query(SYNTAX_LIST_MARKER, syntax_list.query());
+                        let buffer_id = self.active_editor().buffer_obs().buffer_id();
+                        debug!("setting syntax {:?} for buffer {}", &syntax_name, &buffer_id);
+                        if !self.state.set_buffer_syntax(&buffer_id, &syntax_name) {
+                            error!("unknown syntax {:?}", &syntax_name);
+                        }
+                    }
+                    Err(e) => {
+                        error!("setting syntax failed, because \"{}\"", e);
+                    }
+                }
+                let handle = self.syntax_list_handle.take().unwrap();
+                self.remove_window::<FuzzyQueryView>(&handle);
+            }
+        }
+
+        if self.hex_view_handle.is_some() {
+            let result_op = self.hex_view().unwrap().get_result();
+
+            if let Some(result) = result_op {
+                let handle = self.hex_view_handle.take().unwrap();
+                self.remove_window::<HexView>(&handle);
+
+                match result {
+                    Ok(HexViewResult::Close) => {}
+                    Ok(HexViewResult::ReopenAsText(buffer_id)) => {
+                        self.state.reopen_as_lossy_text(&buffer_id);
+                        self.open_and_or_focus(&buffer_id);
+                    }
+                    Err(e) => {
+                        error!("hex view failed, because \"{}\"", e);
+                    }
+                }
+            }
+        }
+
+        if self.choice_dialog_handle.is_some() {
+            let result_op = self.choice_dialog().unwrap().get_result();
+
+            if let Some(result) = result_op {
+                let handle = self.choice_dialog_handle.take().unwrap();
+                self.remove_window::<ChoiceDialog>(&handle);
+                let pending = self.pending_choice.take().unwrap();
+
+                match result {
+                    Ok(ChoiceDialogResult::Chosen(key)) => self.on_choice(pending, key),
+                    Ok(ChoiceDialogResult::Entered(text)) => self.on_entered(pending, text),
+                    Ok(ChoiceDialogResult::Cancel) => {}
+                    Err(e) => {
+                        error!("choice dialog failed, because \"{}\"", e);
+                    }
+                }
+            }
+        }
+
+        if self.buffer_switcher_handle.is_some() {
+            let result_op = self.buffer_switcher().unwrap().get_result();
+
+            if let Some(result) = result_op {
+                let handle = self.buffer_switcher_handle.take().unwrap();
+                self.remove_window::<BufferSwitcher>(&handle);
+
+                match result {
+                    Ok(BufferSwitcherResult::Selected(buffer_id)) => {
+                        self.open_and_or_focus(&buffer_id)
+                    }
+                    Ok(BufferSwitcherResult::Cancel) => {}
+                    Err(e) => {
+                        error!("buffer switcher failed, because \"{}\"", e);
+                    }
+                }
+            }
+        }
+
         if self.all_commands_bar_handle.is_some() {
             debug!("handling all actions bar handle is not implemented");
         }
@@ -382,14 +764,172 @@ impl Interface {
 
     /// This updates interface and SIV!
     fn open_and_or_focus(&mut self, buffer_id: &BufferId) {
-        if !self.inactive_editors.contains_key(buffer_id) {
-            self.create_editor_for_buffer_id(buffer_id);
+        // binary files are not edited as text, they get a hex view on top of editors instead.
+        if self.state.buffer_obs(buffer_id).map(|obs| obs.is_binary()) == Some(true) {
+            self.show_hex_view(buffer_id);
+            return;
         }
 
-        let new_editor = self.inactive_editors.remove(buffer_id).unwrap();
-        let mut old_editor = self.replace_current_editor_view(new_editor);
-        let old_editor_buffer_id = old_editor.get_mut().buffer_obs().buffer_id().clone();
-        self.inactive_editors.insert(old_editor_buffer_id, old_editor);
+        let old_buffer_id = self.active_editor().buffer_obs().buffer_id();
+        if old_buffer_id != *buffer_id {
+            let new_editor = match self.inactive_editors.remove(buffer_id) {
+                Some(editor) => editor,
+                None => self.new_editor_for_buffer(buffer_id),
+            };
+            let old_editor = self.split_view().replace_focused(new_editor);
+            self.hide_editor(old_editor);
+        }
+
+        self.after_focus_change(&old_buffer_id);
+    }
+
+    /// Called once focused pane or buffer displayed in it changed, old_buffer_id was displayed
+    /// there before.
+    fn after_focus_change(&mut self, old_buffer_id: &BufferId) {
+        let buffer_id = self.active_editor().buffer_obs().buffer_id();
+        self.state.mark_focused(&buffer_id);
+
+        let focus_change_autosave = self.autosave_timer.policy() == AutosavePolicy::FocusChange;
+        if focus_change_autosave && *old_buffer_id != buffer_id {
+            if let Err(e) = self.state.autosave_buffer(old_buffer_id) {
+                self.report_error(format!("autosave failed, because \"{}\"", e));
+            }
+        }
+
+        self.request_folding_ranges();
+
+        if self.deferred_conflicts.remove(&buffer_id) {
+            self.conflicts_to_ask.push_back(buffer_id);
+            self.ask_about_conflicts();
+        }
+    }
+
+    /// Divides focused pane in two, the new one displays the same buffer and gets focus.
+    fn split_pane(&mut self, orientation: SplitOrientation) {
+        let buffer_id = self.active_editor().buffer_obs().buffer_id();
+        let editor = self.new_editor_for_buffer(&buffer_id);
+        self.split_view().split_focused(orientation, editor);
+    }
+
+    fn close_pane(&mut self) {
+        let old_buffer_id = self.active_editor().buffer_obs().buffer_id();
+        let closed_op = self.split_view().close_focused();
+        match closed_op {
+            Some(editor) => {
+                self.hide_editor(editor);
+                self.after_focus_change(&old_buffer_id);
+            }
+            None => self.active_editor().set_notice("the last pane cannot be closed".to_string()),
+        }
+    }
+
+    fn focus_next_pane(&mut self, forward: bool) {
+        let old_buffer_id = self.active_editor().buffer_obs().buffer_id();
+        self.split_view().focus_neighbour(forward);
+        self.after_focus_change(&old_buffer_id);
+    }
+
+    /// Displays next (or previous) buffer in order of opening in focused pane. Binary buffers are
+    /// skipped, they are not displayed in panes.
+    fn focus_next_buffer(&mut self, forward: bool) {
+        let buffers: Vec<BufferId> = self
+            .state
+            .get_buffers()
+            .into_iter()
+            .filter(|id| self.state.buffer_obs(id).map(|obs| obs.is_binary()) == Some(false))
+            .collect();
+        if buffers.len() < 2 {
+            return;
+        }
+
+        let current = self.active_editor().buffer_obs().buffer_id();
+        let idx = buffers.iter().position(|id| *id == current).unwrap_or(0);
+        let next_idx = if forward {
+            (idx + 1) % buffers.len()
+        } else {
+            (idx + buffers.len() - 1) % buffers.len()
+        };
+        self.open_and_or_focus(&buffers[next_idx]);
+    }
+
+    /// Shows buffers ordered by last focus. If it's already displayed, moves selection to the next
+    /// buffer instead.
+    fn show_buffer_switcher(&mut self) {
+        if let Some(mut switcher) = self.buffer_switcher() {
+            switcher.select_next();
+            return;
+        }
+        if self.num_open_dialogs() > 0 {
+            debug!("show_buffer_switcher: not showing, because another dialog is open.");
+            return;
+        }
+
+        let buffers: Vec<(BufferId, String)> = self
+            .state
+            .mru_buffers()
+            .into_iter()
+            .filter_map(|id| self.state.buffer_obs(&id))
+            .map(|obs| {
+                let label = match obs.get_path() {
+                    Some(path) => path.to_string_lossy().to_string(),
+                    None => "[no name]".to_string(),
+                };
+                let marker = if obs.modified() { " *" } else { "" };
+                (obs.buffer_id(), format!("{}{}", label, marker))
+            })
+            .collect();
+
+        let switcher = BufferSwitcher::new(buffers);
+        self.buffer_switcher_handle = Some(switcher.handle());
+        self.siv.add_layer(switcher);
+    }
+
+    /// Shows or hides tab bar according to tab_bar_enabled, and refreshes its tabs.
+    fn update_tab_bar(&mut self) {
+        let active = self.active_editor().buffer_obs().buffer_id();
+        let tabs: Vec<BufferStateObserver> = self
+            .state
+            .get_buffers()
+            .iter()
+            .filter_map(|id| self.state.buffer_obs(id))
+            .collect();
+
+        let mut split_view = self.split_view();
+        if !self.tab_bar_enabled {
+            split_view.set_tab_bar(None);
+            return;
+        }
+        if split_view.tab_bar_mut().is_none() {
+            split_view.set_tab_bar(Some(TabBar::new()));
+        }
+        split_view.tab_bar_mut().unwrap().set_tabs(tabs, active);
+    }
+
+    /// Applies edit made in editor sender to buffer. Other editors of the buffer (in other panes
+    /// or inactive) move their cursors along.
+    fn submit_edit_events(
+        &mut self,
+        buffer_id: &BufferId,
+        sender: &ViewHandle,
+        events: Vec<EditEvent>,
+    ) {
+        let obs = match self.state.buffer_obs(buffer_id) {
+            Some(obs) => obs,
+            None => {
+                debug!("ignoring edit of buffer {}, as it's closed.", buffer_id);
+                return;
+            }
+        };
+
+        self.with_editor_mut(buffer_id, |editor| {
+            if editor.handle() != *sender {
+                editor.adjust_after_edits(&events);
+            }
+        });
+        obs.submit_edit_events_to_buffer(events);
+        if !self.lsp_clients.is_empty() {
+            self.lsp_outdated_buffers.insert(buffer_id.clone());
+        }
     }
 
     //TODO error handling!
@@ -413,10 +953,9 @@ impl Interface {
         self.open_and_or_focus(&buffer_id);
     }
 
+    /// Returns editor of focused pane.
     fn active_editor(&mut self) -> ViewRef<SlyTextView> {
-        let editor = self.siv.find_id(&self.active_editor_handle.to_string()).unwrap()
-            as ViewRef<SlyTextView>;
-        editor
+        self.split_view().focused_editor()
     }
 
     fn focus_buffer(&mut self, buffer_id: BufferId) {}
@@ -433,25 +972,387 @@ impl Interface {
         find_view_with_handle(&mut self.siv, &self.buffer_list_handle)
     }
 
+    fn syntax_list(&mut self) -> Option<ViewRef<FuzzyQueryView>> {
+        find_view_with_handle(&mut self.siv, &self.syntax_list_handle)
+    }
+
+    fn hex_view(&mut self) -> Option<ViewRef<HexView>> {
+        find_view_with_handle(&mut self.siv, &self.hex_view_handle)
+    }
+
     fn cancel_floating_windows(&mut self) {
         self.file_dialog().map(|mut file_dialog_ref| file_dialog_ref.borrow_mut().cancel());
+        self.hex_view().map(|mut hex_view_ref| hex_view_ref.borrow_mut().cancel());
+        self.choice_dialog().map(|mut dialog_ref| dialog_ref.borrow_mut().cancel());
+        self.buffer_switcher().map(|mut switcher_ref| switcher_ref.borrow_mut().cancel());
+    }
+
+    fn buffer_switcher(&mut self) -> Option<ViewRef<BufferSwitcher>> {
+        find_view_with_handle(&mut self.siv, &self.buffer_switcher_handle)
+    }
+
+    fn choice_dialog(&mut self) -> Option<ViewRef<ChoiceDialog>> {
+        find_view_with_handle(&mut self.siv, &self.choice_dialog_handle)
+    }
+
+    fn show_choice_dialog(
+        &mut self,
+        pending: PendingChoice,
+        title: &str,
+        message: &str,
+        choices: Vec<(char, String)>,
+    ) {
+        if self.choice_dialog_handle.is_some() {
+            debug!("show_choice_dialog: not showing, because another question is pending.");
+            return;
+        }
+
+        let dialog = ChoiceDialog::new(title.to_string(), message, choices);
+        self.choice_dialog_handle = Some(dialog.handle());
+        self.pending_choice = Some(pending);
+        self.siv.add_layer(dialog);
+    }
+
+    fn show_hex_view(&mut self, buffer_id: &BufferId) {
+        let obs = match self.state.buffer_obs(buffer_id) {
+            Some(obs) => obs,
+            None => return,
+        };
+        let bytes = match obs.binary() {
+            Some(bytes) => bytes,
+            None => return,
+        };
+
+        // only one hex view at a time, the old one is replaced.
+        if let Some(handle) = self.hex_view_handle.take() {
+            self.remove_window::<HexView>(&handle);
+        }
+
+        let name = obs
+            .get_filename()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or("<unnamed>".to_string());
+        let hex_view = HexView::new(buffer_id.clone(), name, bytes);
+        self.hex_view_handle = Some(hex_view.handle());
+        self.siv.add_fullscreen_layer(hex_view);
     }
 
     pub fn done(&self) -> bool {
         self.done
     }
 
+    /// Makes sly exit once buffers of all files given on command line are closed, the way
+    /// programs calling $EDITOR expect it (--wait).
+    pub fn exit_when_given_buffers_closed(&mut self) {
+        self.exit_with_given_buffers = true;
+    }
+
+    /// Makes the buffer of standard input output of sly: it's not asked to be saved on quit, and
+    /// its content is returned by output (--stdout). Buffers of files are never output, so they
+    /// are not quit without asking.
+    pub fn set_stdin_buffer_as_output(&mut self) {
+        let first_op = self.given_buffers.first().cloned();
+        self.output_buffer = first_op.filter(|buffer_id| {
+            self.state.buffer_obs(buffer_id).map(|obs| obs.get_path().is_none()) == Some(true)
+        });
+    }
+
+    /// Returns content of output buffer (see set_stdin_buffer_as_output), if it's still open.
+    pub fn output(&self) -> Option<String> {
+        let buffer_id = self.output_buffer.as_ref()?;
+        let obs = self.state.buffer_obs(buffer_id)?;
+        let text = obs.borrow_content().get_lines().to_string();
+        Some(text)
+    }
+
     pub fn siv(&self) -> &Cursive {
         &self.siv
     }
 
     /// Main program method
     pub fn main(&mut self) {
+        self.start_ticker();
+        self.watch_files = self.settings_ref().get_bool("files/watch_project_files");
         while !self.done() {
             self.main_step();
         }
     }
 
+    /// Starts thread sending IEvent::Tick every TICK_INTERVAL, until interface is gone. Tests
+    /// drive interface step by step, so it's started only by main().
+    fn start_ticker(&self) {
+        let inot = self.inot();
+        thread::spawn(move || loop {
+            thread::sleep(TICK_INTERVAL);
+            if !inot.tick() {
+                break;
+            }
+        });
+    }
+
+    fn on_tick(&mut self) {
+        let now = Instant::now();
+        if self.autosave_timer.should_autosave(now) {
+            for (buffer_id, e) in self.state.autosave_all() {
+                self.report_error(format!("autosave of {} failed, because \"{}\"", buffer_id, e));
+            }
+        }
+        if self.autosave_timer.should_write_recovery(now) {
+            self.state.write_recovery_files();
+        }
+        if self.settings_ref().get_bool("files/check_external_changes") {
+            if let Some(worker_id) = self.state.start_disk_check(self.inot()) {
+                self.active_workers.insert(worker_id);
+            }
+        }
+        self.state.refresh_file_index(now);
+
+        let outdated: Vec<BufferId> = self.lsp_outdated_buffers.iter().cloned().collect();
+        for buffer_id in outdated {
+            self.request_folding_ranges_for(&buffer_id);
+        }
+    }
+
+    /// Files in project directories were created or removed (renamed is both).
+    fn on_files_changed(&mut self, created: Vec<PathBuf>, removed: Vec<PathBuf>) {
+        self.state.apply_file_changes(&created, &removed);
+        self.file_dialog().map(|mut file_dialog_ref| file_dialog_ref.borrow_mut().refresh());
+    }
+
+    fn on_files_indexed(&mut self, root: PathBuf, files: Vec<PathBuf>) {
+        self.num_files_indexed += files.len();
+        self.state.add_indexed_files(&root, files);
+        self.update_indexing_status();
+    }
+
+    fn on_indexing_finished(
+        &mut self,
+        worker_id: usize,
+        root: PathBuf,
+        num_files: usize,
+        limit_hit: bool,
+    ) {
+        self.indexing_workers.remove(&worker_id);
+        self.active_workers.remove(&worker_id);
+        let found = self.state.finish_indexing(&root);
+        self.update_indexing_status();
+        if self.watch_files {
+            file_watcher::start(self.state.file_watcher(&root, found), self.inot());
+        }
+
+        if limit_hit {
+            let message = format!(
+                "indexed only first {} files of {:?}, raise performance/max_files_indexed to \
+                 index more",
+                num_files, root
+            );
+            warn!("{}", message);
+            self.active_editor().set_notice(message);
+        }
+    }
+
+    /// Shows progress of indexing in file bar.
+    fn update_indexing_status(&mut self) {
+        let status = if self.indexing_workers.is_empty() {
+            None
+        } else {
+            Some(format!("indexing: {} files", self.num_files_indexed))
+        };
+        self.state.set_file_index_status(status);
+    }
+
+    fn on_disk_checked(&mut self, worker_id: usize, changes: Vec<DiskChange>) {
+        self.active_workers.remove(&worker_id);
+        for change in self.state.apply_disk_changes(changes) {
+            match change {
+                ExternalChange::Reloaded(buffer_id, old, diff) => {
+                    self.adjust_editors_after_reload(&buffer_id, &old, &diff);
+                }
+                ExternalChange::Conflict(buffer_id) => {
+                    self.deferred_conflicts.remove(&buffer_id);
+                    if !self.conflicts_to_ask.contains(&buffer_id) {
+                        self.conflicts_to_ask.push_back(buffer_id);
+                    }
+                }
+            }
+        }
+        self.ask_about_conflicts();
+    }
+
+    fn adjust_editors_after_reload(&mut self, buffer_id: &BufferId, old: &Rope, diff: &TextDiff) {
+        self.with_editor_mut(buffer_id, |editor| editor.adjust_after_reload(old, diff));
+    }
+
+    /// Calls f with every editor of buffer, be it displayed in a pane or inactive.
+    fn with_editor_mut<F>(&mut self, buffer_id: &BufferId, mut f: F)
+    where
+        F: FnMut(&mut SlyTextView),
+    {
+        for (_, mut editor) in self.split_view().editors() {
+            if editor.buffer_obs().buffer_id() == *buffer_id {
+                f(&mut *editor);
+            }
+        }
+        if let Some(editor) = self.inactive_editors.get_mut(buffer_id) {
+            editor.with_view_mut(|view| f(view));
+        }
+    }
+
+    /// Opens the rest of files given on command line, and places cursors where it asked.
+    fn open_scheduled_files(&mut self) {
+        let first_buffer_id = self.active_editor().buffer_obs().buffer_id();
+        self.apply_start_position(&first_buffer_id);
+        self.given_buffers.push(first_buffer_id);
+
+        for (path, result) in self.state.open_scheduled_files() {
+            match result {
+                Ok(buffer_id) => {
+                    self.given_buffers.push(buffer_id.clone());
+                    let is_binary = self.state.buffer_obs(&buffer_id).map(|obs| obs.is_binary());
+                    if is_binary == Some(false) && !self.inactive_editors.contains_key(&buffer_id) {
+                        self.create_editor_for_buffer_id(&buffer_id);
+                    }
+                    self.apply_start_position(&buffer_id);
+                }
+                Err(e) => {
+                    self.report_error(format!("opening {:?} failed, because \"{}\"", path, e))
+                }
+            }
+        }
+    }
+
+    /// Places cursor of buffer given as file:line:column on command line. Buffers read in
+    /// background get it once they are loaded.
+    fn apply_start_position(&mut self, buffer_id: &BufferId) {
+        let obs = match self.state.buffer_obs(buffer_id) {
+            Some(obs) => obs,
+            None => return,
+        };
+        if obs.is_loading() {
+            return;
+        }
+        let (line, column) = match self.state.take_start_position(buffer_id) {
+            Some(position) => position,
+            None => return,
+        };
+
+        let (line_idx, offset) = {
+            let content = obs.borrow_content();
+            let rope = content.get_lines();
+            let line_idx = cmp::min(line.saturating_sub(1), rope.len_lines() - 1);
+            let line_text = rope.line(line_idx).to_string();
+            let line_len = line_text.trim_end_matches(|c| c == '\n' || c == '\r').chars().count();
+            (line_idx, rope.line_to_char(line_idx) + cmp::min(column.saturating_sub(1), line_len))
+        };
+
+        let cursors = CursorSet::new(vec![offset.into()]);
+        self.with_editor_mut(buffer_id, |editor| {
+            editor.restore_view_state(cursors.clone(), Vec2::new(0, line_idx))
+        });
+    }
+
+    /// Fills placeholder of file read in background, or drops it if reading failed.
+    fn on_file_loaded(&mut self, buffer_id: BufferId, result: Result<Vec<u8>, String>) {
+        if let Err(e) = self.state.finish_loading(&buffer_id, result) {
+            let name = self.buffer_display_name(&buffer_id);
+            self.close_buffer(&buffer_id);
+            self.report_error(format!("loading {} failed, because \"{}\"", name, e));
+            return;
+        }
+
+        let is_binary = self.state.buffer_obs(&buffer_id).map(|obs| obs.is_binary()) == Some(true);
+        if is_binary && self.active_editor().buffer_obs().buffer_id() == buffer_id {
+            self.show_hex_view(&buffer_id);
+        }
+        self.apply_start_position(&buffer_id);
+    }
+
+    /// Asks what to do with the first buffer changed on disk while modified, unless another
+    /// question is displayed.
+    fn ask_about_conflicts(&mut self) {
+        if self.num_open_dialogs() > 0 {
+            return;
+        }
+        let buffer_id = match self.conflicts_to_ask.pop_front() {
+            Some(buffer_id) => buffer_id,
+            None => return,
+        };
+        let name = self
+            .state
+            .buffer_obs(&buffer_id)
+            .and_then(|obs| obs.get_path())
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        self.show_choice_dialog(
+            PendingChoice::FileChanged(buffer_id),
+            "File changed on disk",
+            &format!("{}\nwas changed on disk, and it has unsaved changes here.", name),
+            vec![
+                ('k', "keep mine".to_string()),
+                ('t', "take theirs".to_string()),
+                ('d', "view diff".to_string()),
+            ],
+        );
+    }
+
+    fn show_diff_with_disk(&mut self, buffer_id: BufferId) {
+        let diff = match self.state.diff_with_disk(&buffer_id) {
+            Some(diff) => diff,
+            None => {
+                self.report_error("file on disk cannot be read.".to_string());
+                return;
+            }
+        };
+        let diff_buffer_id = self.state.open_read_only_text(&diff);
+        self.state.set_buffer_syntax(&diff_buffer_id, "Diff");
+        self.deferred_conflicts.insert(buffer_id);
+        self.open_and_or_focus(&diff_buffer_id);
+    }
+
+    /// Asks whether to restore buffers from recovery files left by a crashed session, if any.
+    fn offer_recovery(&mut self) {
+        let records = self.state.find_recovery_files();
+        if records.is_empty() {
+            return;
+        }
+
+        let names: Vec<String> = records
+            .iter()
+            .map(|(_, record)| match record.state.path() {
+                Some(path) => path.to_string_lossy().to_string(),
+                None => "[no name]".to_string(),
+            })
+            .collect();
+        let message = format!(
+            "Unsaved changes of {} buffer(s) survived previous session:\n{}",
+            records.len(),
+            names.join("\n")
+        );
+        self.show_choice_dialog(
+            PendingChoice::Recover(records),
+            "Recovery",
+            &message,
+            vec![
+                ('r', "restore".to_string()),
+                ('d', "discard".to_string()),
+                ('l', "decide later".to_string()),
+            ],
+        );
+    }
+
+    fn recover(&mut self, records: Vec<(PathBuf, RecoveryRecord)>) {
+        let mut last_id_op: Option<BufferId> = None;
+        for (path, record) in records {
+            if let Some(id) = self.state.restore_from_recovery(&path, record) {
+                last_id_op = Some(id);
+            }
+        }
+        if let Some(id) = last_id_op {
+            self.open_and_or_focus(&id);
+        }
+    }
+
     pub fn main_step(&mut self) {
         // first, let's finish whatever action have been started in a previous frame.
         self.process_dialogs();
@@ -459,6 +1360,7 @@ impl Interface {
         self.process_events();
 
         if !self.done() {
+            self.update_tab_bar();
             self.siv.step();
         }
     }
@@ -467,7 +1369,10 @@ impl Interface {
         (if self.file_dialog_handle.is_some() { 1 } else { 0 })
             + (if self.buffer_list_handle.is_some() { 1 } else { 0 })
             + (if self.file_bar_handle.is_some() { 1 } else { 0 })
+            + (if self.syntax_list_handle.is_some() { 1 } else { 0 })
             + (if self.all_commands_bar_handle.is_some() { 1 } else { 0 })
+            + (if self.choice_dialog_handle.is_some() { 1 } else { 0 })
+            + (if self.buffer_switcher_handle.is_some() { 1 } else { 0 })
     }
 
     pub fn event_sink(&self) -> IChannel {
@@ -510,6 +1415,7 @@ impl Interface {
             self.event_sink(),
             self.settings_rc().clone(),
             self.inot(),
+            self.query_history_for(ALL_COMMANDS_MARKER),
         );
 
         self.all_commands_bar_handle = Some(all_commands_bar.get_mut().handle().clone());
@@ -555,6 +1461,7 @@ impl Interface {
             self.event_sink(),
             self.settings_rc().clone(),
             self.inot(),
+            self.query_history_for(FILE_BAR_MARKER),
         );
 
         self.file_bar_handle = Some(file_bar.get_mut().handle().clone());
@@ -573,32 +1480,586 @@ impl Interface {
             self.event_sink(),
             self.settings_rc().clone(),
             self.inot(),
+            self.query_history_for(BUFFER_LIST_MARKER),
         );
 
         self.buffer_list_handle = Some(buffer_list.get_mut().handle().clone());
         self.siv.add_layer(buffer_list);
     }
 
+    fn show_syntax_list(&mut self) {
+        if self.syntax_list_handle.is_some() {
+            debug!("show_syntax_list: not showing syntax_list, because it's already opened.");
+            return;
+        }
+
+        let mut names = vec![PLAIN_TEXT_SYNTAX.to_string()];
+        names.append(
+            &mut self
+                .state
+                .syntax_loader()
+                .syntax_names()
+                .into_iter()
+                .filter(|name| name != PLAIN_TEXT_SYNTAX)
+                .collect(),
+        );
+
+        let items: Vec<Rc<ViewItem>> = names
+            .into_iter()
+            .map(|name| Rc::new(ViewItem::new(name.clone(), None, name, None)))
+            .collect();
+
+        let mut syntax_list = FuzzyQueryView::new(
+            Arc::new(RefCell::new(SimpleIndex::new(items))),
+            SYNTAX_LIST_MARKER.to_string(),
+            self.event_sink(),
+            self.settings_rc().clone(),
+            self.inot(),
+            self.query_history_for(SYNTAX_LIST_MARKER),
+        );
+
+        self.syntax_list_handle = Some(syntax_list.get_mut().handle().clone());
+        self.siv.add_layer(syntax_list);
+    }
+
     fn enable_lsp(&mut self) {
         let lsp =
             LspClient::new(OsStr::new("rls"), self.event_sink(), Some(self.state.directories()));
         self.lsp_clients.push(lsp.unwrap());
+        self.request_folding_ranges();
+    }
+
+    /// Asks language servers (if any is running) for folding ranges of buffer in active editor.
+    fn request_folding_ranges(&mut self) {
+        let buffer_id = self.active_editor().buffer_obs().buffer_id();
+        self.request_folding_ranges_for(&buffer_id);
+    }
+
+    /// Asks language servers for folding ranges of buffer, sending them its current text.
+    fn request_folding_ranges_for(&mut self, buffer_id: &BufferId) {
+        self.lsp_outdated_buffers.remove(buffer_id);
+        if self.lsp_clients.is_empty() {
+            return;
+        }
+
+        let obs = match self.state.buffer_obs(buffer_id) {
+            Some(obs) => obs,
+            None => return,
+        };
+        let path = match obs.get_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let (content_version, text) = {
+            let content = obs.borrow_content();
+            (content.version(), content.get_lines().to_string())
+        };
+
+        for lsp in &mut self.lsp_clients {
+            let result =
+                lsp.request_folding_ranges(buffer_id.clone(), &path, content_version, text.clone());
+            if let Err(e) = result {
+                error!("requesting folding ranges failed, because \"{}\"", e);
+            }
+        }
     }
 
     fn save_current_buffer(&mut self) {
         let path = self.active_editor().buffer_obs().get_path();
-        if path.is_none() {
+        // buffer user made read-only can only be written somewhere else. The one of a file they
+        // cannot write fails to save, and then saving as root is offered.
+        let read_only_by_hand = {
+            let editor = self.active_editor();
+            editor.buffer_obs().read_only() && !editor.buffer_obs().read_only_by_file()
+        };
+        if path.is_none() || read_only_by_hand {
             self.show_save_as()
         } else {
             let buffer_id = self.active_editor().buffer_obs().buffer_id();
-            match self.state.save_buffer(&buffer_id) {
-                Ok(()) => {}
+            if let Err(e) = self.state.save_buffer(&buffer_id) {
+                self.on_save_failed(buffer_id, None, e);
+            }
+        }
+    }
+
+    /// Offers saving as root if save failed for lack of permissions, reports error otherwise.
+    /// Buffers user made read-only by hand are not offered that.
+    fn on_save_failed(&mut self, buffer_id: BufferId, path: Option<PathBuf>, e: io::Error) {
+        let read_only_by_hand = match self.state.buffer_obs(&buffer_id) {
+            Some(obs) => obs.read_only() && !obs.read_only_by_file(),
+            None => true,
+        };
+        if e.kind() != io::ErrorKind::PermissionDenied || read_only_by_hand {
+            self.report_error(format!("file save failed, because \"{}\"", e));
+            return;
+        }
+
+        let target = path.clone().or(self.state.buffer_obs(&buffer_id).and_then(|o| o.get_path()));
+        let command = self.settings_ref().get_string_list("files/privileged_save_command");
+        let message = format!(
+            "No permission to write {}.\nSave it with \"{}\"?",
+            target.map(|p| p.to_string_lossy().to_string()).unwrap_or_default(),
+            command.join(" ")
+        );
+        self.show_choice_dialog(
+            PendingChoice::SaveAsRoot(buffer_id, path),
+            "Permission denied",
+            &message,
+            vec![('r', "save as root".to_string()), ('c', "cancel".to_string())],
+        );
+    }
+
+    /// Acts on answer (key of the choice) to question displayed by show_choice_dialog.
+    fn on_choice(&mut self, pending: PendingChoice, key: char) {
+        match (pending, key) {
+            (PendingChoice::SaveAsRoot(buffer_id, path), 'r') => {
+                self.ask_root_password(buffer_id, path)
+            }
+            (PendingChoice::Recover(records), 'r') => self.recover(records),
+            (PendingChoice::FileChanged(buffer_id), 't') => {
+                if let Some((old, diff)) = self.state.reload_buffer(&buffer_id) {
+                    self.adjust_editors_after_reload(&buffer_id, &old, &diff);
+                }
+            }
+            (PendingChoice::FileChanged(buffer_id), 'd') => self.show_diff_with_disk(buffer_id),
+            (PendingChoice::Recover(records), 'd') => {
+                for (path, _) in records {
+                    self.state.discard_recovery_file(&path);
+                }
+            }
+            (PendingChoice::UnsavedChanges(target, buffer_ids), 's') => {
+                self.save_all_and_close(target, buffer_ids)
+            }
+            (PendingChoice::UnsavedChanges(target, _), 'd') => self.finish_close(target),
+            (PendingChoice::UnsavedChanges(target, buffer_ids), 'r') => {
+                self.review_unsaved(target, buffer_ids)
+            }
+            (PendingChoice::ReviewUnsaved(target, buffer_ids), 's') => {
+                self.save_reviewed(target, buffer_ids)
+            }
+            (PendingChoice::ReviewUnsaved(target, mut buffer_ids), 'd') => {
+                let buffer_id = buffer_ids.remove(0);
+                self.close_buffer(&buffer_id);
+                self.review_unsaved(target, buffer_ids);
+            }
+            _ => {}
+        }
+    }
+
+    /// Closes target right away if none of buffers it covers has unsaved changes, asks what to do
+    /// with them otherwise.
+    fn request_close(&mut self, target: CloseTarget) {
+        let output_buffer = self.output_buffer.clone();
+        let modified = match target {
+            CloseTarget::Quit => self
+                .state
+                .modified_buffers()
+                .into_iter()
+                .filter(|buffer_id| Some(buffer_id) != output_buffer.as_ref())
+                .collect(),
+            CloseTarget::Buffer(ref buffer_id) => {
+                if self.state.buffer_obs(buffer_id).map(|obs| obs.modified()) == Some(true) {
+                    vec![buffer_id.clone()]
+                } else {
+                    Vec::new()
+                }
+            }
+        };
+
+        if modified.is_empty() {
+            self.finish_close(target);
+            return;
+        }
+        if self.num_open_dialogs() > 0 {
+            debug!("request_close: not asking about unsaved changes, because a dialog is opened.");
+            let what = match target {
+                CloseTarget::Quit => "quit",
+                CloseTarget::Buffer(_) => "close buffer",
+            };
+            let notice = format!("unsaved changes, close the dialog first to {}", what);
+            self.active_editor().set_notice(notice);
+            return;
+        }
+        if modified.len() == 1 {
+            self.review_unsaved(target, modified);
+            return;
+        }
+
+        let names: Vec<String> = modified.iter().map(|id| self.buffer_display_name(id)).collect();
+        let message =
+            format!("{} buffers have unsaved changes:\n{}", modified.len(), names.join("\n"));
+        self.show_choice_dialog(
+            PendingChoice::UnsavedChanges(target, modified),
+            "Unsaved changes",
+            &message,
+            vec![
+                ('s', "save all".to_string()),
+                ('d', "discard all".to_string()),
+                ('r', "review each".to_string()),
+                ('c', "cancel".to_string()),
+            ],
+        );
+    }
+
+    /// Saves buffers that have a writable path, the ones left (unnamed, read-only or failed to
+    /// save) are reviewed one by one.
+    fn save_all_and_close(&mut self, target: CloseTarget, buffer_ids: Vec<BufferId>) {
+        let mut left: Vec<BufferId> = Vec::new();
+        for buffer_id in buffer_ids {
+            if !self.can_save_in_place(&buffer_id) {
+                left.push(buffer_id);
+                continue;
+            }
+            if let Err(e) = self.state.save_buffer(&buffer_id) {
+                self.report_error(format!("file save failed, because \"{}\"", e));
+                left.push(buffer_id);
+            }
+        }
+        self.review_unsaved(target, left);
+    }
+
+    /// Shows first of buffer_ids and asks whether to save or discard it, then goes on with the
+    /// rest. Target is closed once all of them are dealt with.
+    fn review_unsaved(&mut self, target: CloseTarget, mut buffer_ids: Vec<BufferId>) {
+        // some could have been saved or closed in the meantime.
+        let state = &self.state;
+        buffer_ids.retain(|id| state.buffer_obs(id).map(|obs| obs.modified()) == Some(true));
+
+        let buffer_id = match buffer_ids.first() {
+            Some(buffer_id) => buffer_id.clone(),
+            None => {
+                self.finish_close(target);
+                return;
+            }
+        };
+
+        self.open_and_or_focus(&buffer_id);
+        let message = format!("{}\nhas unsaved changes.", self.buffer_display_name(&buffer_id));
+        self.show_choice_dialog(
+            PendingChoice::ReviewUnsaved(target, buffer_ids),
+            "Unsaved changes",
+            &message,
+            vec![
+                ('s', "save".to_string()),
+                ('d', "discard".to_string()),
+                ('c', "cancel".to_string()),
+            ],
+        );
+    }
+
+    /// Saves first of buffer_ids (displayed in active editor by review_unsaved) and goes on with
+    /// the review. Buffers without writable path go through save as dialog first.
+    fn save_reviewed(&mut self, target: CloseTarget, buffer_ids: Vec<BufferId>) {
+        let buffer_id = buffer_ids[0].clone();
+        if !self.can_save_in_place(&buffer_id) {
+            self.closing_after_save_as = Some((target, buffer_ids));
+            self.show_save_as();
+            return;
+        }
+
+        match self.state.save_buffer(&buffer_id) {
+            Ok(()) => self.review_unsaved(target, buffer_ids),
+            Err(e) => self.report_error(format!("file save failed, because \"{}\"", e)),
+        }
+    }
+
+    fn can_save_in_place(&self, buffer_id: &BufferId) -> bool {
+        match self.state.buffer_obs(buffer_id) {
+            Some(obs) => obs.get_path().is_some() && !obs.read_only(),
+            None => false,
+        }
+    }
+
+    fn finish_close(&mut self, target: CloseTarget) {
+        match target {
+            CloseTarget::Quit => {
+                let session = self.session();
+                self.state.save_session(&session);
+                self.state.remove_recovery_files();
+                self.done = true;
+            }
+            CloseTarget::Buffer(buffer_id) => self.close_buffer(&buffer_id),
+        }
+    }
+
+    /// Drops buffer along with its editor and unsaved changes. If it was displayed, another
+    /// buffer (or a new empty one) takes its place.
+    fn close_buffer(&mut self, buffer_id: &BufferId) {
+        if !self.state.close_buffer(buffer_id) {
+            return;
+        }
+        self.conflicts_to_ask.retain(|id| id != buffer_id);
+        self.deferred_conflicts.remove(buffer_id);
+
+        let panes: Vec<ViewHandle> = self
+            .split_view()
+            .editors()
+            .into_iter()
+            .filter(|(_, editor)| editor.buffer_obs().buffer_id() == *buffer_id)
+            .map(|(pane_id, _)| pane_id)
+            .collect();
+
+        if !panes.is_empty() {
+            // buffers that already have an editor go first, binary ones are not displayed in
+            // editors at all.
+            let next_op = self
+                .state
+                .get_buffers()
+                .into_iter()
+                .filter(|id| self.state.buffer_obs(id).map(|obs| obs.is_binary()) == Some(false))
+                .max_by_key(|id| self.inactive_editors.contains_key(id));
+            let next = match next_op {
+                Some(next) => next,
+                None => self.state.new_empty_buffer(),
+            };
+
+            // every pane displaying the buffer gets the next one, focus stays where it was.
+            let focused = self.split_view().focused();
+            for pane_id in panes {
+                self.split_view().focus(&pane_id);
+                self.open_and_or_focus(&next);
+            }
+            self.split_view().focus(&focused);
+        }
+
+        self.inactive_editors.remove(buffer_id);
+
+        if self.exit_with_given_buffers
+            && self.given_buffers.iter().all(|id| self.state.buffer_obs(id).is_none())
+        {
+            self.finish_close(CloseTarget::Quit);
+        }
+    }
+
+    fn query_history_for(&self, marker: &str) -> Vec<String> {
+        self.query_history.get(marker).cloned().unwrap_or_default()
+    }
+
+    fn remember_query(&mut self, marker: &str, query: &str) {
+        session::remember_query(self.query_history.entry(marker.to_string()).or_default(), query);
+    }
+
+    /// Returns what is to be restored on next start, see session.rs. Only buffers with a path are
+    /// included.
+    fn session(&mut self) -> Session {
+        let focused = self.split_view().focused();
+        let mut panes = self.split_view().editors();
+        // focused pane goes first, so it's the one remembered if a buffer is in more panes.
+        panes.sort_by_key(|(pane_id, _)| *pane_id != focused);
+
+        let mut pane_sessions: HashMap<ViewHandle, BufferSession> = HashMap::new();
+        let mut editors: Vec<(PathBuf, BufferSession, bool)> = Vec::new();
+        for (pane_id, editor) in panes {
+            if let Some(path) = editor.buffer_obs().get_path() {
+                let buffer_session = buffer_session(path.clone(), &editor);
+                pane_sessions.insert(pane_id.clone(), buffer_session.clone());
+                if !editors.iter().any(|(p, _, _)| *p == path) {
+                    editors.push((path, buffer_session, pane_id == focused));
+                }
+            }
+        }
+        for editor in self.inactive_editors.values_mut() {
+            let view = editor.get_mut();
+            if let Some(path) = view.buffer_obs().get_path() {
+                if !editors.iter().any(|(p, _, _)| *p == path) {
+                    editors.push((path.clone(), buffer_session(path, &view), false));
+                }
+            }
+        }
+        editors.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let layout = if self.split_view().num_panes() > 1 {
+            let pane_layout = self.split_view().pane_layout().clone();
+            layout_session(&pane_layout, &pane_sessions).unwrap_or(LayoutS::Single)
+        } else {
+            LayoutS::Single
+        };
+
+        Session {
+            directories: self.state.directories().clone(),
+            active: editors.iter().position(|(_, _, is_active)| *is_active),
+            buffers: editors.into_iter().map(|(_, buffer_session, _)| buffer_session).collect(),
+            layout,
+            query_history: self.query_history.clone(),
+        }
+    }
+
+    /// Reopens files of previous session, along with cursors, and brings back query history. If
+    /// no file was given on command line, active buffer of that session becomes active again.
+    fn restore_session(&mut self) {
+        let session = match self.state.load_session() {
+            Some(session) => session,
+            None => return,
+        };
+        self.query_history = session.query_history;
+
+        let mut active_op: Option<BufferId> = None;
+        for (idx, buffer_session) in session.buffers.into_iter().enumerate() {
+            let buffer_id = match self.state.open_or_get_file(&buffer_session.path) {
+                Ok(buffer_id) => buffer_id,
                 Err(e) => {
-                    dbg!(&e);
-                    error!("file save failed, because \"{}\"", e)
+                    debug!("not restoring {:?}, because \"{}\"", &buffer_session.path, e);
+                    continue;
                 }
+            };
+            if self.state.buffer_obs(&buffer_id).map(|obs| obs.is_binary()) != Some(false) {
+                continue;
+            }
+
+            let position = Vec2::new(buffer_session.position.0, buffer_session.position.1);
+            let is_active = self.active_editor().buffer_obs().buffer_id() == buffer_id;
+            if !is_active && !self.inactive_editors.contains_key(&buffer_id) {
+                self.create_editor_for_buffer_id(&buffer_id);
+            }
+            self.with_editor_mut(&buffer_id, |editor| {
+                editor.restore_view_state(buffer_session.cursors.clone(), position)
+            });
+
+            if session.active == Some(idx) {
+                active_op = Some(buffer_id);
             }
         }
+
+        // started without files, empty buffer is replaced with active one of session.
+        let first_id = self.active_editor().buffer_obs().buffer_id();
+        let first_is_empty = self
+            .state
+            .buffer_obs(&first_id)
+            .map(|obs| obs.get_path().is_none() && !obs.modified())
+            == Some(true);
+        if !first_is_empty {
+            return;
+        }
+        if self.restore_layout(session.layout, active_op.as_ref()) {
+            self.close_buffer(&first_id);
+        } else if let Some(active_id) = active_op {
+            self.open_and_or_focus(&active_id);
+            self.close_buffer(&first_id);
+        }
+    }
+
+    /// Replaces panes with the ones remembered in session, focusing one displaying active buffer.
+    /// Returns false if layout has no panes that could be restored.
+    fn restore_layout(&mut self, layout: LayoutS, active_op: Option<&BufferId>) -> bool {
+        let mut editors: Vec<(ViewHandle, IdView<SlyTextView>)> = Vec::new();
+        let pane_layout = match self.restore_panes(layout, &mut editors) {
+            Some(pane_layout) => pane_layout,
+            None => return false,
+        };
+
+        let focused = editors
+            .iter_mut()
+            .find(|(_, editor)| Some(&editor.get_mut().buffer_obs().buffer_id()) == active_op)
+            .map(|(pane_id, _)| pane_id.clone())
+            .unwrap_or(editors[0].0.clone());
+
+        let old_editors = self.split_view().set_layout(pane_layout, editors, focused);
+        for editor in old_editors {
+            self.hide_editor(editor);
+        }
+        true
+    }
+
+    /// Creates editors for panes of layout, adding them to editors. Panes of files that cannot be
+    /// opened (or are binary) are left out.
+    fn restore_panes(
+        &mut self,
+        layout: LayoutS,
+        editors: &mut Vec<(ViewHandle, IdView<SlyTextView>)>,
+    ) -> Option<PaneLayout> {
+        match layout {
+            LayoutS::Single => None,
+            LayoutS::Pane(buffer_session) => {
+                let buffer_id = self.state.open_or_get_file(&buffer_session.path).ok()?;
+                if self.state.buffer_obs(&buffer_id).map(|obs| obs.is_binary()) != Some(false) {
+                    return None;
+                }
+                let obs = self.state.buffer_obs(&buffer_id)?;
+                let mut editor =
+                    SlyTextView::new(self.settings_rc().clone(), obs, self.event_sink());
+                let position = Vec2::new(buffer_session.position.0, buffer_session.position.1);
+                editor.get_mut().restore_view_state(buffer_session.cursors, position);
+
+                let pane_id = ViewHandle::new();
+                editors.push((pane_id.clone(), editor));
+                Some(PaneLayout::Pane(pane_id))
+            }
+            LayoutS::Split { orientation, share, first, second } => {
+                let first_op = self.restore_panes(*first, editors);
+                let second_op = self.restore_panes(*second, editors);
+                match (first_op, second_op) {
+                    (Some(first), Some(second)) => Some(PaneLayout::Split {
+                        orientation,
+                        share,
+                        first: Box::new(first),
+                        second: Box::new(second),
+                    }),
+                    (Some(part), None) | (None, Some(part)) => Some(part),
+                    (None, None) => None,
+                }
+            }
+        }
+    }
+
+    fn buffer_display_name(&self, buffer_id: &BufferId) -> String {
+        self.state
+            .buffer_obs(buffer_id)
+            .and_then(|obs| obs.get_path())
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or("[no name]".to_string())
+    }
+
+    /// Helper cannot ask for password on terminal (Cursive reads it), so it's asked here.
+    fn ask_root_password(&mut self, buffer_id: BufferId, path: Option<PathBuf>) {
+        let command = self.settings_ref().get_string_list("files/privileged_save_command");
+        let message = format!("Password for \"{}\":", command.join(" "));
+        let dialog = ChoiceDialog::password("Save as root".to_string(), &message);
+        self.choice_dialog_handle = Some(dialog.handle());
+        self.pending_choice = Some(PendingChoice::RootPassword(buffer_id, path));
+        self.siv.add_layer(dialog);
+    }
+
+    /// Acts on text entered in dialog displayed by ask_root_password.
+    fn on_entered(&mut self, pending: PendingChoice, text: String) {
+        match pending {
+            PendingChoice::RootPassword(buffer_id, path) => {
+                self.save_as_root(buffer_id, path, text)
+            }
+            _ => {}
+        }
+    }
+
+    fn save_as_root(&mut self, buffer_id: BufferId, path: Option<PathBuf>, password: String) {
+        match self.state.start_privileged_save(&buffer_id, path, Some(password), self.inot()) {
+            Ok(worker_id) => {
+                self.active_workers.insert(worker_id);
+            }
+            Err(e) => self.report_error(format!("saving as root failed, because \"{}\"", e)),
+        }
+    }
+
+    fn on_privileged_saved(
+        &mut self,
+        worker_id: usize,
+        buffer_id: BufferId,
+        path: PathBuf,
+        version: usize,
+        result: Result<(), String>,
+    ) {
+        self.active_workers.remove(&worker_id);
+        // helper can still print something on terminal (like sudo's lecture), screen is drawn from
+        // scratch.
+        self.siv.clear();
+
+        if let Err(e) = self.state.finish_privileged_save(&buffer_id, path, version, result) {
+            self.report_error(format!("saving as root failed, because \"{}\"", e));
+        }
+    }
+
+    /// Logs message and shows it in status bar of active editor.
+    fn report_error(&mut self, message: String) {
+        error!("{}", message);
+        self.active_editor().set_notice(message);
     }
 
     fn settings_rc(&self) -> &Rc<RefCell<Settings>> {
@@ -612,6 +2073,11 @@ impl Interface {
     pub fn state(&self) -> &AppState {
         &self.state
     }
+
+    #[cfg(test)]
+    pub fn state_mut(&mut self) -> &mut AppState {
+        &mut self.state
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -635,6 +2101,62 @@ impl InterfaceNotifier {
         self.siv_cb_sink.send_timeout(Box::new(|s: &mut Cursive| {}), Duration::new(0, 0));
     }
 
+    /// Returns false if interface is gone.
+    pub fn tick(&self) -> bool {
+        if self.ichan.send(IEvent::Tick).is_err() {
+            return false;
+        }
+        self.siv_cb_sink.send_timeout(Box::new(|s: &mut Cursive| {}), Duration::new(0, 0));
+        true
+    }
+
+    /// Returns false if interface is gone.
+    pub fn files_changed(&self, changes: FileChanges) -> bool {
+        if self.ichan.send(IEvent::FilesChanged(changes.created, changes.removed)).is_err() {
+            return false;
+        }
+        self.siv_cb_sink.send_timeout(Box::new(|s: &mut Cursive| {}), Duration::new(0, 0));
+        true
+    }
+
+    pub fn files_indexed(&self, root: PathBuf, files: Vec<PathBuf>) {
+        self.ichan.send(IEvent::FilesIndexed(root, files));
+        self.siv_cb_sink.send_timeout(Box::new(|s: &mut Cursive| {}), Duration::new(0, 0));
+    }
+
+    pub fn indexing_finished(
+        &self,
+        worker_id: usize,
+        root: PathBuf,
+        num_files: usize,
+        limit_hit: bool,
+    ) {
+        self.ichan.send(IEvent::IndexingFinished(worker_id, root, num_files, limit_hit));
+        self.siv_cb_sink.send_timeout(Box::new(|s: &mut Cursive| {}), Duration::new(0, 0));
+    }
+
+    pub fn disk_checked(&self, worker_id: usize, changes: Vec<DiskChange>) {
+        self.ichan.send(IEvent::DiskChecked(worker_id, changes));
+        self.siv_cb_sink.send_timeout(Box::new(|s: &mut Cursive| {}), Duration::new(0, 0));
+    }
+
+    pub fn privileged_saved(
+        &self,
+        worker_id: usize,
+        buffer_id: BufferId,
+        path: PathBuf,
+        version: usize,
+        result: Result<(), String>,
+    ) {
+        self.ichan.send(IEvent::PrivilegedSaved(worker_id, buffer_id, path, version, result));
+        self.siv_cb_sink.send_timeout(Box::new(|s: &mut Cursive| {}), Duration::new(0, 0));
+    }
+
+    pub fn file_loaded(&self, buffer_id: BufferId, result: Result<Vec<u8>, String>) {
+        self.ichan.send(IEvent::FileLoaded(buffer_id, result));
+        self.siv_cb_sink.send_timeout(Box::new(|s: &mut Cursive| {}), Duration::new(0, 0));
+    }
+
     pub fn worker_finished(&self, workedId: usize) {
         self.ichan.send(IEvent::WorkerFinished(workedId));
         self.siv_cb_sink.send_timeout(Box::new(|s: &mut Cursive| {}), Duration::new(0, 0));
@@ -0,0 +1,294 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// This file describes how lines of text are laid out on screen.
+//
+// Unit of layout is a grapheme cluster (Glyph), not a char: "e" followed by a combining accent is
+// a single glyph, and so is "\r\n". Glyph takes unicode-width columns, so CJK and most emoji take
+// two, tabs expand to the next tab stop, and control characters take one (they are drawn with a
+// replacement symbol). Offsets are in chars, as everywhere else, columns are counted from the
+// beginning of a line.
+//
+// With soft wrap, a logical line (ended with newline) can be displayed as a number of visual
+// rows, see wrap_points.
+
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Glyph {
+    pub char_begin: usize, // within line
+    pub char_len: usize,
+    pub column: usize,
+    pub width: usize,
+}
+
+impl Glyph {
+    pub fn char_end(&self) -> usize {
+        self.char_begin + self.char_len
+    }
+
+    pub fn column_end(&self) -> usize {
+        self.column + self.width
+    }
+}
+
+fn glyph_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        return tab_width - (column % tab_width);
+    }
+    // newlines, control characters and lone combining marks still need a cell, so they can be
+    // seen and cursor can stand on them.
+    std::cmp::max(1, UnicodeWidthStr::width(grapheme))
+}
+
+/// Lays out a single line (can contain trailing newline).
+pub fn layout_line(line: &str, tab_width: usize) -> Vec<Glyph> {
+    let mut result: Vec<Glyph> = Vec::new();
+    let mut char_begin: usize = 0;
+    let mut column: usize = 0;
+
+    for grapheme in line.graphemes(true) {
+        let char_len = grapheme.chars().count();
+        let width = glyph_width(grapheme, column, tab_width);
+        result.push(Glyph { char_begin, char_len, column, width });
+        char_begin += char_len;
+        column += width;
+    }
+
+    result
+}
+
+pub fn layout_rope_line(rope: &Rope, line_no: usize, tab_width: usize) -> Vec<Glyph> {
+    layout_line(&rope.line(line_no).to_string(), tab_width)
+}
+
+/// Returns column of char_idx (within line). Positions after the last glyph are assumed to be
+/// one column wide each.
+pub fn column_of(glyphs: &[Glyph], char_idx: usize) -> usize {
+    for glyph in glyphs {
+        if glyph.char_end() > char_idx {
+            return glyph.column;
+        }
+    }
+    let line_end = glyphs.last().map(|g| (g.char_end(), g.column_end())).unwrap_or((0, 0));
+    line_end.1 + (char_idx - line_end.0)
+}
+
+/// Returns char offset (within line) of glyph covering column. Columns after the last glyph are
+/// assumed to be one char each.
+pub fn char_at_column(glyphs: &[Glyph], column: usize) -> usize {
+    for glyph in glyphs {
+        if glyph.column_end() > column {
+            return glyph.char_begin;
+        }
+    }
+    let line_end = glyphs.last().map(|g| (g.char_end(), g.column_end())).unwrap_or((0, 0));
+    line_end.0 + (column - line_end.1)
+}
+
+/// Returns offsets (within line) where grapheme clusters begin, followed by length of the line.
+fn grapheme_boundaries(rope: &Rope, line_no: usize) -> Vec<usize> {
+    let line = rope.line(line_no).to_string();
+    let mut result: Vec<usize> = Vec::new();
+    let mut offset: usize = 0;
+    for grapheme in line.graphemes(true) {
+        result.push(offset);
+        offset += grapheme.chars().count();
+    }
+    result.push(offset);
+    result
+}
+
+/// Returns offset of the beginning of next grapheme cluster, or len_chars if there is none.
+pub fn next_grapheme_boundary(rope: &Rope, char_idx: usize) -> usize {
+    if char_idx >= rope.len_chars() {
+        return rope.len_chars();
+    }
+    let line_no = rope.char_to_line(char_idx);
+    let line_begin = rope.line_to_char(line_no);
+    let local = char_idx - line_begin;
+
+    grapheme_boundaries(rope, line_no)
+        .into_iter()
+        .find(|&b| b > local)
+        .map(|b| line_begin + b)
+        .unwrap_or(rope.len_chars())
+}
+
+/// Returns offset of the beginning of grapheme cluster preceding char_idx, or 0.
+pub fn prev_grapheme_boundary(rope: &Rope, char_idx: usize) -> usize {
+    if char_idx == 0 {
+        return 0;
+    }
+    let target = std::cmp::min(char_idx, rope.len_chars()) - 1;
+    let line_no = rope.char_to_line(target);
+    let line_begin = rope.line_to_char(line_no);
+    let local = target - line_begin;
+
+    grapheme_boundaries(rope, line_no)
+        .into_iter()
+        .filter(|&b| b <= local)
+        .last()
+        .map(|b| line_begin + b)
+        .unwrap_or(0)
+}
+
+/// Returns char offsets (within line) where visual rows begin, first one is always 0. Line is
+/// expected without newline character. Rows are at most width columns wide. Breaks happen at word
+/// boundaries, unless a single word is wider than width, then it's broken at grapheme boundary.
+/// Whitespace never starts a new row, it can "hang" after the last word of a row.
+pub fn wrap_points(line: &str, width: usize, tab_width: usize) -> Vec<usize> {
+    let mut result: Vec<usize> = vec![0];
+    if width == 0 {
+        return result;
+    }
+
+    let glyphs = layout_line(line, tab_width);
+    let mut glyph_idx: usize = 0;
+    let mut row_column: usize = 0; // column where current row begins
+    let mut char_offset: usize = 0;
+
+    for word in line.split_word_bounds() {
+        let word_char_end = char_offset + word.chars().count();
+        let first_glyph = glyph_idx;
+        while glyph_idx < glyphs.len() && glyphs[glyph_idx].char_begin < word_char_end {
+            glyph_idx += 1;
+        }
+        let word_glyphs = &glyphs[first_glyph..glyph_idx];
+        char_offset = word_char_end;
+
+        if word_glyphs.is_empty() || word.chars().all(char::is_whitespace) {
+            continue;
+        }
+
+        let word_column = word_glyphs[0].column;
+        let word_width = word_glyphs.last().unwrap().column_end() - word_column;
+
+        if word_column + word_width > row_column + width && word_column > row_column {
+            result.push(word_glyphs[0].char_begin);
+            row_column = word_column;
+        }
+
+        for glyph in word_glyphs {
+            if glyph.column_end() > row_column + width && glyph.column > row_column {
+                result.push(glyph.char_begin);
+                row_column = glyph.column;
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns wrap points (see wrap_points) of given line of rope.
+pub fn line_wrap_points(rope: &Rope, line_no: usize, width: usize, tab_width: usize) -> Vec<usize> {
+    let line = rope.line(line_no).to_string();
+    wrap_points(line.trim_end_matches(|c| c == '\n' || c == '\r'), width, tab_width)
+}
+
+/// Returns index of visual row containing char_idx.
+pub fn row_of(wrap_points: &[usize], char_idx: usize) -> usize {
+    match wrap_points.binary_search(&char_idx) {
+        Ok(idx) => idx,
+        Err(idx) => idx - 1, // wrap_points[0] == 0, so idx > 0.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(line: &str) -> Vec<(usize, usize)> {
+        layout_line(line, 4).iter().map(|g| (g.column, g.width)).collect()
+    }
+
+    #[test]
+    fn layout_of_wide_and_combining() {
+        // "e" + combining acute accent is a single glyph
+        assert_eq!(layout_line("e\u{301}x", 4).len(), 2);
+        assert_eq!(columns("e\u{301}x"), vec![(0, 1), (1, 1)]);
+        // CJK takes two columns
+        assert_eq!(columns("a\u{4e2d}b"), vec![(0, 1), (1, 2), (3, 1)]);
+    }
+
+    #[test]
+    fn tabs_expand_to_tab_stops() {
+        assert_eq!(columns("\tx"), vec![(0, 4), (4, 1)]);
+        assert_eq!(columns("ab\tx"), vec![(0, 1), (1, 1), (2, 2), (4, 1)]);
+    }
+
+    #[test]
+    fn column_and_char_conversions() {
+        let glyphs = layout_line("a\u{4e2d}e\u{301}\n", 4);
+        assert_eq!(column_of(&glyphs, 0), 0);
+        assert_eq!(column_of(&glyphs, 1), 1);
+        assert_eq!(column_of(&glyphs, 2), 3);
+        assert_eq!(column_of(&glyphs, 3), 3); // combining mark is a part of glyph
+        assert_eq!(char_at_column(&glyphs, 2), 1); // within wide glyph
+        assert_eq!(char_at_column(&glyphs, 3), 2);
+        assert_eq!(char_at_column(&glyphs, 4), 4); // newline
+    }
+
+    #[test]
+    fn grapheme_boundaries_test() {
+        let rope = Rope::from_str("e\u{301}x\r\ny");
+        assert_eq!(next_grapheme_boundary(&rope, 0), 2);
+        assert_eq!(next_grapheme_boundary(&rope, 2), 3);
+        assert_eq!(next_grapheme_boundary(&rope, 3), 5); // \r\n is a single cluster
+        assert_eq!(prev_grapheme_boundary(&rope, 5), 3);
+        assert_eq!(prev_grapheme_boundary(&rope, 2), 0);
+        assert_eq!(prev_grapheme_boundary(&rope, 6), 5);
+    }
+
+    #[test]
+    fn short_line_is_not_wrapped() {
+        assert_eq!(wrap_points("hello world", 20, 4), vec![0]);
+        assert_eq!(wrap_points("", 20, 4), vec![0]);
+    }
+
+    #[test]
+    fn wraps_at_word_boundaries() {
+        // "hello " | "world " | "again"
+        assert_eq!(wrap_points("hello world again", 8, 4), vec![0, 6, 12]);
+        // whitespace hangs over the edge
+        assert_eq!(wrap_points("hello world", 5, 4), vec![0, 6]);
+    }
+
+    #[test]
+    fn breaks_long_words() {
+        assert_eq!(wrap_points("abcdefghij", 4, 4), vec![0, 4, 8]);
+        assert_eq!(wrap_points("ab abcdefghij", 4, 4), vec![0, 3, 7, 11]);
+    }
+
+    #[test]
+    fn wraps_wide_characters_by_columns() {
+        // four CJK characters are 8 columns, they are a single "word" each.
+        assert_eq!(wrap_points("\u{4e2d}\u{4e2d}\u{4e2d}\u{4e2d}", 5, 4), vec![0, 2]);
+    }
+
+    #[test]
+    fn row_of_test() {
+        let points = vec![0, 6, 12];
+        assert_eq!(row_of(&points, 0), 0);
+        assert_eq!(row_of(&points, 5), 0);
+        assert_eq!(row_of(&points, 6), 1);
+        assert_eq!(row_of(&points, 20), 2);
+    }
+}
@@ -0,0 +1,167 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Line endings and byte order mark.
+//
+// Content is kept in Rope exactly as it was read (minus the BOM), so "\r\n" stays "\r\n", and
+// saving writes it back unchanged. Ropey counts "\r\n" as a single line break, so the only thing
+// cursor maths needs is to know where a line ends, see line_end.
+
+use ropey::Rope;
+
+pub const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    /// Both kinds were found. New lines are inserted with "\n".
+    Mixed,
+}
+
+impl Default for LineEnding {
+    fn default() -> Self {
+        LineEnding::Lf
+    }
+}
+
+impl LineEnding {
+    /// What gets inserted when user hits enter.
+    pub fn newline(&self) -> &'static str {
+        match self {
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Lf | LineEnding::Mixed => "\n",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+            LineEnding::Mixed => "mixed",
+        }
+    }
+
+    /// Target of "convert line endings" command.
+    pub fn toggled(&self) -> LineEnding {
+        match self {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf | LineEnding::Mixed => LineEnding::Lf,
+        }
+    }
+}
+
+/// Returns line ending used in bytes. File with no line breaks is assumed to be LF.
+pub fn detect_line_ending(bytes: &[u8]) -> LineEnding {
+    let mut lf: usize = 0;
+    let mut crlf: usize = 0;
+
+    for (idx, b) in bytes.iter().enumerate() {
+        if *b == b'\n' {
+            if idx > 0 && bytes[idx - 1] == b'\r' {
+                crlf += 1;
+            } else {
+                lf += 1;
+            }
+        }
+    }
+
+    match (lf, crlf) {
+        (_, 0) => LineEnding::Lf,
+        (0, _) => LineEnding::CrLf,
+        _ => LineEnding::Mixed,
+    }
+}
+
+/// Returns whether bytes begin with UTF-8 byte order mark, and bytes without it.
+pub fn strip_bom(bytes: Vec<u8>) -> (bool, Vec<u8>) {
+    if bytes.starts_with(&UTF8_BOM) {
+        (true, bytes[UTF8_BOM.len()..].to_vec())
+    } else {
+        (false, bytes)
+    }
+}
+
+/// Returns text with all line endings replaced with line_ending (Mixed is treated as LF).
+pub fn convert_line_endings(text: &str, line_ending: LineEnding) -> String {
+    let unified = text.replace("\r\n", "\n");
+    match line_ending {
+        LineEnding::CrLf => unified.replace('\n', "\r\n"),
+        LineEnding::Lf | LineEnding::Mixed => unified,
+    }
+}
+
+fn is_line_break(c: char) -> bool {
+    match c {
+        '\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}' => true,
+        _ => false,
+    }
+}
+
+/// Returns number of chars of line break ending line_no, "\r\n" is 2. Last line has none.
+pub fn line_break_len(rope: &Rope, line_no: usize) -> usize {
+    let line = rope.line(line_no);
+    let len = line.len_chars();
+    if len == 0 || !is_line_break(line.char(len - 1)) {
+        return 0;
+    }
+    if len >= 2 && line.char(len - 1) == '\n' && line.char(len - 2) == '\r' {
+        2
+    } else {
+        1
+    }
+}
+
+/// Returns char offset of line break ending line_no, or len_chars for the last line. This is the
+/// last position of a cursor within a line.
+pub fn line_end(rope: &Rope, line_no: usize) -> usize {
+    rope.line_to_char(line_no) + rope.line(line_no).len_chars() - line_break_len(rope, line_no)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_line_ending_test() {
+        assert_eq!(detect_line_ending(b"a\nb\n"), LineEnding::Lf);
+        assert_eq!(detect_line_ending(b"a\r\nb\r\n"), LineEnding::CrLf);
+        assert_eq!(detect_line_ending(b"a\r\nb\n"), LineEnding::Mixed);
+        assert_eq!(detect_line_ending(b"ab"), LineEnding::Lf);
+    }
+
+    #[test]
+    fn strip_bom_test() {
+        assert_eq!(strip_bom(b"\xEF\xBB\xBFab".to_vec()), (true, b"ab".to_vec()));
+        assert_eq!(strip_bom(b"ab".to_vec()), (false, b"ab".to_vec()));
+    }
+
+    #[test]
+    fn convert_line_endings_test() {
+        assert_eq!(convert_line_endings("a\r\nb\nc", LineEnding::CrLf), "a\r\nb\r\nc");
+        assert_eq!(convert_line_endings("a\r\nb\nc", LineEnding::Lf), "a\nb\nc");
+    }
+
+    #[test]
+    fn line_end_test() {
+        let rope = Rope::from_str("ab\r\ncd\nef");
+        assert_eq!(rope.len_lines(), 3);
+        assert_eq!(line_break_len(&rope, 0), 2);
+        assert_eq!(line_end(&rope, 0), 2);
+        assert_eq!(line_end(&rope, 1), 6);
+        assert_eq!(line_end(&rope, 2), 9);
+    }
+}
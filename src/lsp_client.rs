@@ -19,7 +19,7 @@ limitations under the License.
 // crate.
 
 use languageserver_types as lst;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::ffi::{OsStr, OsString};
 use std::io;
@@ -27,15 +27,19 @@ use std::io::BufRead;
 use std::io::BufReader;
 use std::io::Read;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::process::{Child, Command};
+use std::process::{Child, ChildStdin, Command};
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
 
+use crate::buffer_id::BufferId;
 use crate::events::IChannel;
 use crate::events::IEvent;
+use crate::folding::FoldRange;
+use crate::utils::stable_hash;
 use jsonrpc_core::types as jt;
 use jsonrpc_core::Output;
 use languageserver_types;
@@ -45,6 +49,27 @@ pub struct LspClient {
     is_initialized: bool,
     i_event_sink: IChannel,
     channel: (Sender<LSPEvent>, Receiver<LSPEvent>),
+    stdin: ChildStdin,
+    next_id: u64,
+    // ids of pending textDocument/foldingRange requests, and buffers (with version of their
+    // content) they were sent for.
+    pending_folding_requests: Arc<Mutex<HashMap<u64, (BufferId, usize)>>>,
+    opened_documents: HashMap<PathBuf, OpenedDocument>,
+    // requests made before server answered initialize, sent once it does.
+    queued_folding_requests: Vec<FoldingRequest>,
+}
+
+// what server knows about a document.
+struct OpenedDocument {
+    version: u64,
+    text_hash: u64,
+}
+
+struct FoldingRequest {
+    buffer_id: BufferId,
+    path: PathBuf,
+    content_version: usize,
+    text: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -54,6 +79,7 @@ pub enum LSPEvent {
 
 const ID_INIT: u64 = 0; // it's always a first message.
 const ID_COMPLETION: u64 = 0;
+const ID_FIRST_REQUEST: u64 = 1; // ids of further requests are assigned incrementally.
 
 impl LspClient {
     pub fn new(
@@ -71,12 +97,23 @@ impl LspClient {
                 .collect::<Vec<lst::WorkspaceFolder>>()
         });
 
+        let capabilities = lst::ClientCapabilities {
+            text_document: Some(lst::TextDocumentClientCapabilities {
+                folding_range: Some(lst::FoldingRangeCapability {
+                    line_folding_only: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
         let init = languageserver_types::InitializeParams {
             process_id: Some(u64::from(process::id())),
             root_path: Some("./".to_string()),
             root_uri: None,
             initialization_options: None,
-            capabilities: languageserver_types::ClientCapabilities::default(),
+            capabilities,
             trace: Some(lst::TraceOption::Verbose), /* TODO(njskalski) */
             workspace_folders: workspace_folders_op,
         };
@@ -102,53 +139,71 @@ impl LspClient {
         let lsp_channel = channel::<LSPEvent>();
         let lsp_sink = lsp_channel.0.clone();
 
-        let handle = thread::spawn(move || {
-            let mut headers: HashMap<String, String> = HashMap::new();
-            loop {
-                headers.clear();
-                loop {
-                    let mut header = String::new();
-                    if reader.read_line(&mut header).unwrap() == 0 {
-                        return;
-                    }
-                    let header = header.trim();
-                    if header.is_empty() {
-                        break;
-                    }
-                    let parts: Vec<&str> = header.split(": ").collect();
-                    if parts.len() != 2 {
-                        return;
-                    }
-                    headers.insert(parts[0].to_string(), parts[1].to_string());
+        let pending_folding_requests: Arc<Mutex<HashMap<u64, (BufferId, usize)>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+        let pending_folding = pending_folding_requests.clone();
+        let i_event_sink = event_sink.clone();
+
+        let handle = thread::spawn(move || loop {
+            let msg = match read_message(&mut reader) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    warn!("lsp: connection to language server closed, because \"{}\"", e);
+                    return;
                 }
-                let content_len = headers["Content-Length"].parse().unwrap();
-                let mut content = vec![0; content_len];
-                reader.read_exact(&mut content).unwrap();
-
-                let msg = String::from_utf8(content).unwrap();
-
-                let output: serde_json::Result<Output> = serde_json::from_str(&msg);
-                debug!("dd : {:?}", &output);
-                match output {
-                    Ok(jt::Output::Success(suc)) => {
-                        if suc.id == jsonrpc_core::id::Id::Num(ID_INIT) {
-                            lsp_sink.send(LSPEvent::Initialized).unwrap();
-                        } else if suc.id == jsonrpc_core::id::Id::Num(ID_COMPLETION) {
-                            let completion = serde_json::from_value::<
-                                languageserver_types::CompletionResponse,
-                            >(suc.result)
-                            .unwrap();
-
-                            //                        let mut completion =
-                            // extract_completion(completion);
-                            //                        tx.send(completion).unwrap();
+            };
+
+            let output: serde_json::Result<Output> = serde_json::from_str(&msg);
+            debug!("dd : {:?}", &output);
+            let delivered = match output {
+                Ok(jt::Output::Success(suc)) => {
+                    let folding_request_op = match suc.id {
+                        jsonrpc_core::id::Id::Num(id) => {
+                            pending_folding.lock().unwrap().remove(&id)
                         }
+                        _ => None,
+                    };
+
+                    if let Some((buffer_id, content_version)) = folding_request_op {
+                        match serde_json::from_value::<Option<Vec<lst::FoldingRange>>>(suc.result) {
+                            Ok(ranges) => {
+                                let ranges: Vec<FoldRange> = ranges
+                                    .unwrap_or(vec![])
+                                    .into_iter()
+                                    .filter(|r| r.end_line > r.start_line)
+                                    .map(|r| {
+                                        FoldRange::new(r.start_line as usize, r.end_line as usize)
+                                    })
+                                    .collect();
+                                let event =
+                                    IEvent::FoldingRanges(buffer_id, content_version, ranges);
+                                i_event_sink.send(event).is_ok()
+                            }
+                            Err(e) => {
+                                debug!("lsp: unable to parse folding ranges {:?}", e);
+                                true
+                            }
+                        }
+                    } else if suc.id == jsonrpc_core::id::Id::Num(ID_INIT) {
+                        lsp_sink.send(LSPEvent::Initialized).is_ok()
+                            && i_event_sink.send(IEvent::LspInitialized).is_ok()
+                    } else {
+                        true
                     }
-                    Ok(jt::Output::Failure(f)) => {
-                        debug!("lsp: unable to parse \n{}\nfailure:\n{:?}\n", msg, f)
-                    }
-                    Err(e) => debug!("lsp: unable to parse \n{}\nerrror:\n{:?}\n", msg, e),
-                };
+                }
+                Ok(jt::Output::Failure(f)) => {
+                    debug!("lsp: unable to parse \n{}\nfailure:\n{:?}\n", msg, f);
+                    true
+                }
+                Err(e) => {
+                    debug!("lsp: unable to parse \n{}\nerrror:\n{:?}\n", msg, e);
+                    true
+                }
+            };
+
+            if !delivered {
+                debug!("lsp: interface is gone, stopping reader.");
+                return;
             }
         });
 
@@ -157,8 +212,163 @@ impl LspClient {
             is_initialized: false,
             i_event_sink: event_sink,
             channel: lsp_channel,
+            stdin,
+            next_id: ID_FIRST_REQUEST,
+            pending_folding_requests,
+            opened_documents: HashMap::new(),
+            queued_folding_requests: Vec::new(),
         })
     }
+
+    /// Finishes initialization if server responded to initialize request: sends initialized
+    /// notification and requests made so far. Called on IEvent::LspInitialized.
+    pub fn on_initialized(&mut self) -> Result<(), Box<Error>> {
+        if self.is_initialized {
+            return Ok(());
+        }
+        match self.channel.1.try_recv() {
+            Ok(LSPEvent::Initialized) => {}
+            Err(_) => return Ok(()),
+        }
+
+        send_notify::<_, lst::notification::Initialized>(
+            &mut self.stdin,
+            lst::InitializedParams {},
+        )?;
+        self.is_initialized = true;
+
+        for request in std::mem::replace(&mut self.queued_folding_requests, Vec::new()) {
+            self.send_folding_request(request)?;
+        }
+        Ok(())
+    }
+
+    /// Asks server for folding ranges of a file. Response is delivered as IEvent::FoldingRanges,
+    /// with content_version, so ranges computed for older text can be dropped. Document is opened
+    /// on the server with the first request and updated (whole text) with the next ones.
+    pub fn request_folding_ranges(
+        &mut self,
+        buffer_id: BufferId,
+        path: &Path,
+        content_version: usize,
+        text: String,
+    ) -> Result<(), Box<Error>> {
+        let request = FoldingRequest { buffer_id, path: path.to_owned(), content_version, text };
+        if self.is_initialized {
+            self.send_folding_request(request)
+        } else {
+            // only the latest request of a buffer matters.
+            self.queued_folding_requests.retain(|queued| queued.buffer_id != request.buffer_id);
+            self.queued_folding_requests.push(request);
+            Ok(())
+        }
+    }
+
+    fn send_folding_request(&mut self, request: FoldingRequest) -> Result<(), Box<Error>> {
+        let path = request.path.as_path();
+        let uri = lst::Url::from_file_path(path)
+            .map_err(|_| format!("unable to convert path {:?} to uri", path))?;
+        let text_hash = stable_hash(request.text.as_bytes());
+
+        match self.opened_documents.get_mut(path) {
+            None => {
+                let params = lst::DidOpenTextDocumentParams {
+                    text_document: lst::TextDocumentItem {
+                        uri: uri.clone(),
+                        language_id: language_id(path),
+                        version: 0,
+                        text: request.text,
+                    },
+                };
+                send_notify::<_, lst::notification::DidOpenTextDocument>(&mut self.stdin, params)?;
+                let document = OpenedDocument { version: 0, text_hash };
+                self.opened_documents.insert(path.to_owned(), document);
+            }
+            Some(ref mut document) if document.text_hash != text_hash => {
+                document.version += 1;
+                document.text_hash = text_hash;
+                let params = lst::DidChangeTextDocumentParams {
+                    text_document: lst::VersionedTextDocumentIdentifier {
+                        uri: uri.clone(),
+                        version: Some(document.version),
+                    },
+                    content_changes: vec![lst::TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: request.text,
+                    }],
+                };
+                send_notify::<_, lst::notification::DidChangeTextDocument>(
+                    &mut self.stdin,
+                    params,
+                )?;
+            }
+            Some(_) => {}
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending_folding_requests
+            .lock()
+            .unwrap()
+            .insert(id, (request.buffer_id, request.content_version));
+
+        let params =
+            lst::FoldingRangeParams { text_document: lst::TextDocumentIdentifier { uri } };
+        send_request::<_, lst::request::FoldingRangeRequest>(&mut self.stdin, id, params)?;
+        Ok(())
+    }
+}
+
+/// Returns LSP language identifier of file, guessed by extension.
+fn language_id(path: &Path) -> String {
+    let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+    let id = match extension.as_ref().map(|ext| ext.as_str()) {
+        Some("rs") => "rust",
+        Some("c") | Some("h") => "c",
+        Some("cc") | Some("cpp") | Some("cxx") | Some("hpp") | Some("hh") => "cpp",
+        Some("go") => "go",
+        Some("java") => "java",
+        Some("js") => "javascript",
+        Some("ts") => "typescript",
+        Some("py") => "python",
+        Some("rb") => "ruby",
+        Some("sh") | Some("bash") => "shellscript",
+        Some("md") => "markdown",
+        Some("json") => "json",
+        Some("toml") => "toml",
+        Some("yaml") | Some("yml") => "yaml",
+        Some("html") | Some("htm") => "html",
+        Some("css") => "css",
+        _ => "plaintext",
+    };
+    id.to_string()
+}
+
+/// Reads one message (headers and content) sent by language server.
+fn read_message<T: BufRead>(reader: &mut T) -> Result<String, Box<Error>> {
+    let mut content_length_op: Option<usize> = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Err("end of stream".into());
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        let mut parts = header.splitn(2, ": ");
+        match (parts.next(), parts.next()) {
+            (Some("Content-Length"), Some(value)) => content_length_op = Some(value.parse()?),
+            (Some(_), Some(_)) => {}
+            _ => return Err(format!("malformed header \"{}\"", header).into()),
+        }
+    }
+
+    let content_length = content_length_op.ok_or("missing Content-Length header")?;
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content)?;
+    Ok(String::from_utf8(content)?)
 }
 
 fn send_request<T: Write, R: lst::request::Request>(
@@ -203,3 +413,30 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_messages_and_rejects_malformed_ones() {
+        let mut input: &[u8] =
+            b"Content-Length: 2\r\nContent-Type: x\r\n\r\n{}Content-Length: 9\r\n\r\n{}";
+        assert_eq!(read_message(&mut input).unwrap(), "{}");
+        // content shorter than announced.
+        assert!(read_message(&mut input).is_err());
+
+        let mut input: &[u8] = b"Content-Length: many\r\n\r\n{}";
+        assert!(read_message(&mut input).is_err());
+
+        let mut input: &[u8] = b"garbage\r\n\r\n";
+        assert!(read_message(&mut input).is_err());
+    }
+
+    #[test]
+    fn language_ids() {
+        assert_eq!(language_id(Path::new("/a/main.rs")), "rust");
+        assert_eq!(language_id(Path::new("/a/lib.HPP")), "cpp");
+        assert_eq!(language_id(Path::new("/a/Makefile")), "plaintext");
+    }
+}
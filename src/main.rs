@@ -41,32 +41,55 @@ mod macros;
 mod abstract_clipboard;
 mod action;
 mod app_state;
+mod autosave;
+mod bracket_matching;
 mod buffer_id;
 mod buffer_index;
 mod buffer_state;
 mod buffer_state_observer;
+mod buffer_switcher;
+mod choice_dialog;
 mod color_view_wrapper;
 mod content_provider;
 mod cursor_set;
 mod default_settings;
 mod dir_tree;
+mod disk_check;
 mod events;
 mod file_dialog;
+mod file_index_cache;
+mod file_indexer;
+mod file_watcher;
+mod folding;
 mod fuzzy_index;
 mod fuzzy_index_trait;
 mod fuzzy_query_view;
+mod fuzzy_score;
 mod fuzzy_view_item;
+mod hex_view;
+mod highlight_worker;
 mod interface;
 mod keyboard_shortcut;
+mod layout;
+mod line_endings;
 mod lsp_client;
 mod overlay_dialog;
+mod recovery;
 mod rich_content;
+mod safe_save;
+mod session;
 mod settings;
 mod simple_fuzzy_index;
 mod sly_text_view;
 mod sly_view;
+mod split_view;
+mod syntax_loader;
+mod tab_bar;
+mod terminal;
 mod test_utils;
+mod text_diff;
 mod view_handle;
+mod whitespace;
 use crate::dir_tree::TreeNode;
 
 #[cfg(test)]
@@ -101,6 +124,8 @@ extern crate serde;
 #[macro_use]
 extern crate yaml_rust;
 extern crate filesystem;
+extern crate libc;
+extern crate notify;
 
 use crate::app_state::AppState;
 use cursive::Cursive;
@@ -110,6 +135,7 @@ use std::borrow::Borrow;
 use std::borrow::BorrowMut;
 use std::env;
 use std::fs;
+use std::io::Write;
 use std::path;
 use std::path::Path;
 use std::path::PathBuf;
@@ -150,6 +176,15 @@ pub type FileSystemType = FakeFileSystem;
 pub type FileSystemType = OsFileSystem;
 
 fn main() {
+    // sly is askpass helper of itself when saving as root, see safe_save.rs.
+    if let Some(fd) = env::var_os(safe_save::ASKPASS_FD_VAR) {
+        if let Err(e) = safe_save::print_askpass_password(&fd) {
+            eprintln!("askpass failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let yml = clap::load_yaml!("clap.yml");
     let mut app = clap::App::from_yaml(yml)
         .author("Andrzej J Skalski <ajskalski@google.com>")
@@ -179,32 +214,52 @@ fn main() {
 
     let mut directories: Vec<PathBuf> = Vec::new();
     let mut files: Vec<PathBuf> = Vec::new();
+    let mut start_positions: Vec<(PathBuf, (usize, usize))> = Vec::new();
+    let mut read_stdin = false;
 
     if matches.is_present("files_and_directories") {
         for value in matches.values_of("files_and_directories").unwrap() {
-            let path_arg = Path::new(value).to_path_buf();
+            // "-" stands for standard input.
+            if value == "-" {
+                read_stdin = true;
+                continue;
+            }
+
+            // "file:12:5" is a position in file, unless there is a file named like that.
+            let (path_arg, position_op) = if Path::new(value).exists() {
+                (Path::new(value).to_path_buf(), None)
+            } else {
+                utils::split_file_position(value)
+            };
+
             let path = match fs::canonicalize(&path_arg) {
                 Ok(path) => path,
-                _ => {
-                    info!("unable to canonicalize \"{:?}\", ignoring.", path_arg);
-                    continue;
-                }
+                // file does not exist yet, it will be created on save.
+                _ => match env::current_dir() {
+                    Ok(dir) => dir.join(&path_arg),
+                    Err(e) => {
+                        info!("unable to resolve {:?}, because {:?}, ignoring.", path_arg, e);
+                        continue;
+                    }
+                },
             };
 
-            if !path.exists() {
-                info!("{:?} does not exist, now ignoring.", value);
-                continue; // TODO(njskalski) stop ignoring new files.
-            }
-
             if path.is_dir() {
                 directories.push(path);
-            } else if path.is_file() {
-                files.push(path);
+            } else if path.is_file() || !path.exists() {
+                if let Some(position) = position_op {
+                    start_positions.push((path.clone(), position));
+                }
+                if !files.contains(&path) {
+                    files.push(path);
+                }
             } else {
                 info!("{:?} is neither a file nor directory. Ignoring.", value);
             }
         }
-    } else {
+    }
+
+    if directories.is_empty() && files.is_empty() {
         // if no directory is specified, we take current directory as "project root".
         match env::current_dir() {
             Ok(path) => directories.push(path),
@@ -217,9 +272,36 @@ fn main() {
         &directories, &files, git_files_included
     );
 
+    // only text that came in can go out, files given for editing are saved as usual.
+    if matches.is_present("stdout") && !read_stdin {
+        eprintln!("--stdout requires standard input to be read (\"-\").");
+        return;
+    }
+
+    let stdin_bytes = if read_stdin {
+        match terminal::read_stdin_and_reopen_tty() {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                eprintln!("unable to read standard input: {}", e);
+                return;
+            }
+        }
+    } else {
+        None
+    };
+
+    // output of sly goes to the original standard output, terminal gets the interface.
+    let original_stdout = match terminal::redirect_stdout_to_tty() {
+        Ok(original_op) => original_op,
+        Err(e) => {
+            eprintln!("standard output is not a terminal, and terminal is unavailable: {}", e);
+            return;
+        }
+    };
+
     let dir_file_tree = LazyTreeNode::new(directories.clone(), files.clone()).as_ref();
 
-    let app_state = AppState::new(
+    let mut app_state = AppState::new(
         FileSystemType::new(),
         directories,
         files,
@@ -227,9 +309,50 @@ fn main() {
         git_files_included == false,
     );
 
+    for (path, (line, column)) in start_positions {
+        app_state.set_start_position(path, line, column);
+    }
+
+    if let Some(bytes) = stdin_bytes {
+        app_state.set_stdin_text(String::from_utf8_lossy(&bytes).to_string());
+    }
+
+    let wait = matches.is_present("wait");
+    let write_stdout = matches.is_present("stdout");
+
+    // a one-off edit called by another program should not replace session of the project.
+    if matches.is_present("no_session") || wait || write_stdout {
+        app_state.set_session_path(None);
+    }
+
     let mut siv = Cursive::default();
     let mut interface = Interface::new(app_state, siv);
+    if wait {
+        interface.exit_when_given_buffers_closed();
+    }
+    if write_stdout {
+        interface.set_stdin_buffer_as_output();
+    }
     interface.main();
+
+    // Cursive restores the terminal when dropped, output is written after that.
+    let output_op = interface.output();
+    drop(interface);
+
+    if let Some(output) = output_op {
+        let result = match original_stdout {
+            Some(mut file) => file.write_all(output.as_bytes()).and_then(|_| file.flush()),
+            None => {
+                let stdout = std::io::stdout();
+                let mut lock = stdout.lock();
+                lock.write_all(output.as_bytes()).and_then(|_| lock.flush())
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("unable to write output: {}", e);
+        }
+    }
+
     if profiling_enabled {
         stop_profiling();
     };
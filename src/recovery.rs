@@ -0,0 +1,255 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Recovery ("swap") files keep unsaved work safe from crashes.
+//
+// Every modified buffer (named or not) is periodically written to the recovery directory as a
+// single JSON file named after its BufferId, containing BufferStateS and content. File is removed
+// once buffer is saved, and all of them are removed on a clean exit. Files found on start are
+// leftovers of a crash, and they are offered for restoring if they are newer than the file on
+// disk they were made from.
+//
+// Recovery directory is shared by all sly processes of the user, so every record keeps pid of
+// its writer, and records of running processes are left alone. Two processes started at once can
+// both offer the same leftover: the one that manages to remove the file restores it. Records
+// hold unsaved text of whatever user edits, so only the user can read them: the directory is
+// 0700 and files are 0600.
+
+use filesystem::FileSystem;
+use serde_json;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+use crate::buffer_id::BufferId;
+use crate::buffer_state::BufferStateS;
+use crate::safe_save::{save_file, SaveSupport};
+use crate::utils::{data_dir, is_process_alive, modification_time, MetadataSupport};
+use crate::FileSystemType;
+
+const RECOVERY_EXTENSION: &'static str = "json";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryRecord {
+    pub state: BufferStateS,
+    pub content: String,
+    pub written_at: SystemTime,
+    /// Process that wrote the record.
+    pub pid: u32,
+}
+
+impl RecoveryRecord {
+    pub fn new(state: BufferStateS, content: String) -> Self {
+        RecoveryRecord { state, content, written_at: SystemTime::now(), pid: process::id() }
+    }
+
+    /// Returns whether record holds something worth restoring: it's newer than the file it was
+    /// made from (file_modified), or there is no such file. Times are compared with full
+    /// precision, so a file saved in the same second as the record is not mistaken for older.
+    pub fn is_newer_than(&self, file_modified: Option<SystemTime>) -> bool {
+        match file_modified {
+            Some(time) => self.written_at > time,
+            None => true,
+        }
+    }
+
+    /// Like is_newer_than, with modification time of the file taken from fs.
+    pub fn is_newer_than_file<F: MetadataSupport>(&self, fs: &F) -> bool {
+        self.is_newer_than(self.state.path().and_then(|path| modification_time(fs, path)))
+    }
+
+    /// Returns whether other sly process that wrote the record still runs, so it's not a
+    /// leftover. A record of this very process can only be left by a crashed one that had the
+    /// same pid before.
+    pub fn is_owner_alive(&self) -> bool {
+        self.pid != process::id() && is_process_alive(self.pid)
+    }
+}
+
+/// Returns $XDG_DATA_HOME/sly/recovery (see utils::data_dir).
+pub fn recovery_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("recovery"))
+}
+
+fn record_path(dir: &Path, buffer_id: &BufferId) -> PathBuf {
+    dir.join(format!("{}.{}", buffer_id, RECOVERY_EXTENSION))
+}
+
+pub fn write_record<F: FileSystem + SaveSupport>(
+    fs: &F,
+    dir: &Path,
+    buffer_id: &BufferId,
+    record: &RecoveryRecord,
+) -> Result<(), io::Error> {
+    if !fs.is_dir(dir) {
+        fs.create_dir_all(dir)?;
+    }
+    // also directories left by versions that did not restrict it. Until file is made private,
+    // nobody else can get to it through the directory.
+    fs.make_private(dir)?;
+
+    let path = record_path(dir, buffer_id);
+    let json = serde_json::to_vec(record)?;
+    save_file(fs, &path, &json, false)?;
+    fs.make_private(&path)
+}
+
+pub fn remove_record(fs: &FileSystemType, dir: &Path, buffer_id: &BufferId) {
+    let path = record_path(dir, buffer_id);
+    if fs.is_file(&path) {
+        if let Err(e) = fs.remove_file(&path) {
+            warn!("failed to remove recovery file {:?}, because \"{}\"", &path, e);
+        }
+    }
+}
+
+/// Returns all readable records in dir along with their paths. Unreadable ones are skipped.
+pub fn read_records(fs: &FileSystemType, dir: &Path) -> Vec<(PathBuf, RecoveryRecord)> {
+    let read_dir = match fs.read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut result: Vec<(PathBuf, RecoveryRecord)> = Vec::new();
+    for entry in read_dir.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.extension().map(|ext| ext == RECOVERY_EXTENSION) != Some(true) {
+            continue;
+        }
+
+        let parsed = fs.read_file(&path).and_then(|bytes| {
+            serde_json::from_slice::<RecoveryRecord>(&bytes).map_err(|e| e.into())
+        });
+        match parsed {
+            Ok(record) => result.push((path, record)),
+            Err(e) => warn!("skipping recovery file {:?}, because \"{}\"", &path, e),
+        }
+    }
+
+    result.sort_by_key(|(path, _)| path.clone());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir::temp_dir;
+    use filesystem::OsFileSystem;
+    use std::time::Duration;
+
+    #[test]
+    fn records_round_trip() {
+        let fs = FileSystemType::new();
+        let dir = Path::new("/recovery");
+        let id = BufferId::new();
+        let state = BufferStateS::new(Some(PathBuf::from("/project/a.txt")));
+
+        write_record(&fs, dir, &id, &RecoveryRecord::new(state, "unsaved".to_string())).unwrap();
+        let records = read_records(&fs, dir);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].1.content, "unsaved");
+        assert_eq!(records[0].1.state.path(), Some(&PathBuf::from("/project/a.txt")));
+
+        remove_record(&fs, dir, &id);
+        assert!(read_records(&fs, dir).is_empty());
+    }
+
+    #[test]
+    fn corrupted_records_are_skipped() {
+        let fs = FileSystemType::new();
+        fs.create_dir_all("/recovery").unwrap();
+        fs.create_file("/recovery/broken.json", "{ not json").unwrap();
+        assert!(read_records(&fs, Path::new("/recovery")).is_empty());
+    }
+
+    #[test]
+    fn owner_is_alive_while_it_runs() {
+        let mut record = RecoveryRecord::new(BufferStateS::new(None), String::new());
+        assert!(!record.is_owner_alive());
+
+        let mut child = process::Command::new("sleep").arg("10").spawn().unwrap();
+        record.pid = child.id();
+        assert!(record.is_owner_alive());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!record.is_owner_alive());
+    }
+
+    #[test]
+    fn is_newer_than_test() {
+        let record = RecoveryRecord::new(BufferStateS::new(None), String::new());
+        let now = SystemTime::now();
+        assert!(record.is_newer_than(None));
+        assert!(record.is_newer_than(Some(now - Duration::from_secs(60))));
+        assert!(!record.is_newer_than(Some(now + Duration::from_secs(60))));
+        // within the same second.
+        assert!(record.is_newer_than(Some(record.written_at - Duration::from_millis(10))));
+        assert!(!record.is_newer_than(Some(record.written_at + Duration::from_millis(10))));
+        assert!(!record.is_newer_than(Some(record.written_at)));
+    }
+
+    #[test]
+    fn record_on_disk_is_compared_with_file_on_disk() {
+        let dir = temp_dir("recovery_age");
+        let file = dir.join("a.txt");
+        let fs = OsFileSystem::new();
+        // file times are taken from a coarse clock, a few milliseconds behind SystemTime::now.
+        let pause = || std::thread::sleep(Duration::from_millis(50));
+
+        std::fs::write(&file, "saved").unwrap();
+        pause();
+        let state = BufferStateS::new(Some(file.clone()));
+        let record = RecoveryRecord::new(state, "unsaved".to_string());
+        let newer_than_file = record.is_newer_than_file(&fs);
+
+        pause();
+        std::fs::write(&file, "saved again").unwrap();
+        let newer_than_saved_again = record.is_newer_than_file(&fs);
+
+        std::fs::remove_file(&file).unwrap();
+        let newer_than_removed = record.is_newer_than_file(&fs);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(newer_than_file);
+        assert!(!newer_than_saved_again);
+        assert!(newer_than_removed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn records_on_disk_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let parent = temp_dir("recovery_private");
+        let dir = parent.join("recovery");
+        let fs = OsFileSystem::new();
+        let id = BufferId::new();
+        let record = RecoveryRecord::new(BufferStateS::new(None), "unsaved".to_string());
+        write_record(&fs, &dir, &id, &record).unwrap();
+        // rewritten, as it happens on every autosave.
+        write_record(&fs, &dir, &id, &record).unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let dir_mode = mode(&dir);
+        let file_mode = mode(&record_path(&dir, &id));
+        std::fs::remove_dir_all(&parent).unwrap();
+
+        assert_eq!(dir_mode, 0o700);
+        assert_eq!(file_mode, 0o600);
+    }
+}
@@ -20,47 +20,50 @@ limitations under the License.
 
 // TODO(njskalski) secure with accessors after fixing the format.
 
-//TODO(njskalski) NEXT STEPS:
-// 1) replace Vec in cache with rpds Vector, this way implementing versioning of colors
-// 2) then you can remove Rc from Rich Line (probably) and give indexing again (not sure what for)
+// Highlighting itself is done by highlight_worker, RichContent only keeps the most recent results
+// and asks worker for more when a line that is not up-to-date is requested. Lines are versioned
+// with a counter bumped on every edit. Results computed for older versions are dropped, but lines
+// already displayed are kept (stale) until fresh ones arrive, so the text does not blink.
 
-use crate::content_provider::RopeBasedContentProvider;
+use crate::highlight_worker::{start_highlight_worker, HighlightJob, HighlightResult};
+use crate::interface::InterfaceNotifier;
 use ropey::Rope;
-use std::borrow::Borrow;
-use std::cell::Ref;
 use std::iter::{ExactSizeIterator, Iterator};
-use std::marker::Copy;
-use std::ops::{Index, IndexMut};
 
 use cursive::theme::Color;
-use std::rc::Rc;
 
 use std::cell::Cell;
 use std::cell::RefCell;
-use std::collections::HashMap;
-use syntect::highlighting::{
-    HighlightIterator, HighlightState, Highlighter, Style, Theme, ThemeSet,
-};
-use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
-
-const PARSING_MILESTONE: usize = 10;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use syntect::highlighting::Theme;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
 
 #[derive(Debug)]
 pub struct RichLine {
     line_no: usize,
     length: usize,
     body: Vec<(Color, String)>,
+    // char ranges [begin, end) of strings and comments, sorted. Used by bracket matching.
+    non_code: Vec<(usize, usize)>,
+    // (minimal, final) number of open block scopes within this line. Used by folding.
+    block_depth: (usize, usize),
 }
 
 //TODO(njskalski): optimise, rethink api. maybe even drop the content.
 impl RichLine {
-    pub fn new(line_no: usize, body: Vec<(Color, String)>) -> Self {
+    pub fn new(
+        line_no: usize,
+        body: Vec<(Color, String)>,
+        non_code: Vec<(usize, usize)>,
+        block_depth: (usize, usize),
+    ) -> Self {
         let mut len: usize = 0;
         for piece in &body {
             len += piece.1.len()
         }
 
-        RichLine { line_no, length: len, body }
+        RichLine { line_no, length: len, body, non_code, block_depth }
     }
 
     pub fn len(&self) -> usize {
@@ -83,103 +86,113 @@ impl RichLine {
     pub fn get_line_no(&self) -> usize {
         self.line_no
     }
+
+    /// Returns (minimal, final) number of block scopes open within this line. Minimum includes
+    /// state at the beginning of the line.
+    pub fn block_depth(&self) -> (usize, usize) {
+        self.block_depth
+    }
+
+    /// Returns false if char at idx is a part of string or comment.
+    pub fn is_code_at(&self, idx: usize) -> bool {
+        !self.non_code.iter().any(|&(b, e)| b <= idx && idx < e)
+    }
 }
 
 #[derive(Debug)]
 pub struct HighlightSettings {
     theme: Theme,
     syntax: SyntaxReference,
-    syntax_set: SyntaxSet,
+    syntax_set: Arc<SyntaxSet>,
 }
 
-//TODO move const strings to settings parameters.
 impl HighlightSettings {
-    pub fn new(extension: &str) -> Option<Self> {
-        let syntax_set = SyntaxSet::load_defaults_newlines().clone();
-        let ts = &ThemeSet::load_defaults();
-
-        let syntax = syntax_set.find_syntax_by_extension(extension)?.clone();
-        let theme = ts.themes["base16-ocean.dark"].clone();
+    /// SyntaxSet is shared between all buffers, only the SyntaxReference is buffer specific.
+    pub fn new(syntax: SyntaxReference, syntax_set: Arc<SyntaxSet>, theme: Theme) -> Self {
+        HighlightSettings { theme, syntax, syntax_set }
+    }
 
-        Some(HighlightSettings { theme: theme, syntax: syntax, syntax_set: syntax_set })
+    pub fn syntax_name(&self) -> &str {
+        &self.syntax.name
     }
-}
 
-#[derive(Clone, Debug)]
-struct ParseCacheRecord {
-    line_to_parse: usize,
-    parse_state: ParseState,
-    highlight_state: HighlightState,
-}
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
 
-impl ParseCacheRecord {
-    pub fn new(parse_state: ParseState, highlighter: &Highlighter) -> Self {
-        let scope_stack = ScopeStack::new();
-        let highlight_state = HighlightState::new(highlighter, scope_stack);
+    pub fn syntax(&self) -> &SyntaxReference {
+        &self.syntax
+    }
 
-        ParseCacheRecord { line_to_parse: 0, parse_state, highlight_state }
+    pub fn syntax_set(&self) -> &SyntaxSet {
+        &self.syntax_set
     }
 }
 
-#[derive(Clone)]
 pub struct RichContent {
-    highlight_settings: Rc<HighlightSettings>,
+    highlight_settings: Arc<HighlightSettings>,
     raw_content: Rope,
-    // the key corresponds to number of next line to parse by ParseState. NOT DONE, bc I don't want
-    // negative numbers.
-    parse_cache: RefCell<Vec<ParseCacheRecord>>, //TODO is it possible to remove RefCell?
-    lines: RefCell<Vec<Rc<RichLine>>>,
+    version: usize,
+    // lines as last delivered by worker, possibly stale (see fresh_lines) or missing.
+    lines: RefCell<Vec<Option<Arc<RichLine>>>>,
+    // contract: lines [0, fresh_lines) are computed for current version.
+    fresh_lines: Cell<usize>,
+    // highest line already requested from worker in current version, to avoid flooding it.
+    requested_line: Cell<Option<usize>>,
+    job_sender: Sender<HighlightJob>,
+    result_receiver: Receiver<HighlightResult>,
 }
 
 impl RichContent {
-    pub fn new(settings: Rc<HighlightSettings>, rope: Rope) -> Self {
+    pub fn new(
+        settings: Arc<HighlightSettings>,
+        rope: Rope,
+        inot_op: Option<InterfaceNotifier>,
+    ) -> Self {
+        let version: usize = 0;
+        let (job_sender, result_receiver) =
+            start_highlight_worker(settings.clone(), rope.clone(), version, inot_op);
+
         RichContent {
             highlight_settings: settings,
             raw_content: rope,
-            //contract: sorted.
-            parse_cache: RefCell::new(Vec::new()),
-            //contract: max key of parse_cache < len(lines)
+            version,
             lines: RefCell::new(Vec::new()),
+            fresh_lines: Cell::new(0),
+            requested_line: Cell::new(None),
+            job_sender,
+            result_receiver,
         }
     }
 
-    //TODO(njskalski): it should have some kind of observer, but reference to ContentProvider
-    // creates a nightmare of lifetimes escalation. Maybe it can be merged with "drop lines"?
-    pub fn update_raw_content(&mut self, rope: Rope) {
-        self.raw_content = rope;
-    }
+    /// Informs RichContent (and worker) that content has changed. Lines before first_changed_line
+    /// are expected to be identical to ones in previous version.
+    pub fn update_raw_content(&mut self, rope: Rope, first_changed_line: usize) {
+        let old_len = self.raw_content.len_lines();
+        let new_len = rope.len_lines();
+
+        self.version += 1;
+        self.raw_content = rope.clone();
+        self.requested_line.set(None);
+        self.fresh_lines.set(std::cmp::min(self.fresh_lines.get(), first_changed_line));
 
-    // Drop lines removes cache after lines_to_save. Since it operates on cache, it's non mutable.
-    // lines_to_save is *exclusive*. It corresponds to number of lines to save. So 0 means
-    // "no lines to save", therefore line #0 is subject to drop as well.
-    pub fn drop_lines(&self, lines_to_save: usize) {
-        debug!("dropping all lines from (exclusive) {:}", lines_to_save);
-        //dropping cache.
+        // Shifting stale lines, so they stay (more or less) next to text they were computed for.
         {
-            if lines_to_save > 0 {
-                // this is index of ParseCacheRecord in cache vector, not in lines.
-                let idx_op = self.get_cache_idx(lines_to_save - 1);
-                let mut parse_cache = self.parse_cache.borrow_mut();
-                match idx_op {
-                    Some(idx) => {
-                        // split_off is exclusive, our ParseCacheRecord index is inclusive.
-                        if parse_cache.len() >= idx + 1 {
-                            parse_cache.split_off(idx + 1);
-                        }
-                    }
-                    None => parse_cache.clear(),
-                }
-            } else {
-                let mut parse_cache = self.parse_cache.borrow_mut();
-                parse_cache.clear();
+            let mut lines = self.lines.borrow_mut();
+            let pivot = std::cmp::min(first_changed_line + 1, lines.len());
+            if new_len > old_len {
+                let added = new_len - old_len;
+                lines.splice(pivot..pivot, std::iter::repeat(None).take(added));
+            } else if old_len > new_len {
+                let removed = std::cmp::min(old_len - new_len, lines.len() - pivot);
+                lines.drain(pivot..pivot + removed);
             }
+            lines.truncate(new_len);
         }
-        //dropping lines.
-        {
-            let mut lines = self.lines.borrow_mut();
-            //            if lines.len() >= lines_to_save {
-            lines.split_off(lines_to_save);
-            //            }
+
+        let job = HighlightJob::Update { version: self.version, rope, first_changed_line };
+        if self.job_sender.send(job).is_err() {
+            error!("highlight worker is gone.");
         }
     }
 
@@ -187,122 +200,77 @@ impl RichContent {
         self.raw_content.len_lines()
     }
 
-    /// Returns index of last valid ParseCacheRecord given the last non-modified line index.
-    /// Every ParseCacheRecord with index higher than the one returned is going to be invalid after
-    /// line (#line_no + 1) gets modified.
-    // TODO unit tests.
-    fn get_cache_idx(&self, line_no: usize) -> Option<usize> {
-        let parse_cache: Ref<Vec<ParseCacheRecord>> = self.parse_cache.borrow();
-
-        debug!("parse cache len = {}", parse_cache.len());
-
-        if !parse_cache.is_empty() {
-            let cache: Option<usize> =
-                match parse_cache.binary_search_by(|rec| rec.line_to_parse.cmp(&line_no)) {
-                    Ok(idx) => Some(idx),
-                    Err(higher_index) => {
-                        if higher_index == 0 {
-                            None
-                        } else {
-                            Some(higher_index - 1)
-                        }
-                    }
-                };
-            cache
-        } else {
-            None
-        }
+    pub fn version(&self) -> usize {
+        self.version
     }
 
-    /// Returns last valid ParseCacheRecord given the last non-modified line index.
-    /// ParseCacheRecord.line_to_parse > line_no (*strictly higher* guaranteed).
-    pub fn get_cache(&self, line_no: usize) -> Option<ParseCacheRecord> {
-        let idx_op = self.get_cache_idx(line_no);
-        //        debug!("idx_op : {:?}", &idx_op);
-        idx_op.and_then(|x| self.parse_cache.borrow().get(x).map(|cache| cache.clone()))
+    /// Returns number of lines (from the beginning) that are highlighted according to the current
+    /// version of content.
+    pub fn fresh_lines(&self) -> usize {
+        self.consume_results();
+        self.fresh_lines.get()
     }
 
-    pub fn get_line(&self, line_no: usize) -> Option<Rc<RichLine>> {
-        //        debug!("ask for rich line {:}", line_no);
-
-        if self.lines.borrow().len() > line_no {
-            return self.lines.borrow().get(line_no).map(|x| x.clone());
+    /// Like get_line, but returns only up-to-date lines and never asks worker for more.
+    pub fn get_fresh_line(&self, line_no: usize) -> Option<Arc<RichLine>> {
+        if line_no >= self.fresh_lines() {
+            return None;
         }
+        self.lines.borrow().get(line_no).and_then(|x| x.clone())
+    }
+
+    /// Returns whether given line is highlighted according to the current version of content.
+    pub fn is_fresh(&self, line_no: usize) -> bool {
+        self.consume_results();
+        line_no < self.fresh_lines.get()
+    }
+
+    /// Consumes results delivered by worker, dropping ones computed for older versions.
+    fn consume_results(&self) {
+        while let Ok(result) = self.result_receiver.try_recv() {
+            if result.version != self.version {
+                debug!(
+                    "dropping highlight result of version {} (now {})",
+                    result.version, self.version
+                );
+                continue;
+            }
 
-        // It could be cached, but escalating <'a> everywhere is just a nightmare.
-        let highlighter = Highlighter::new(&self.highlight_settings.theme);
-
-        // see contracts
-        let mut parse_cache: ParseCacheRecord = match self.get_cache(line_no) {
-            None => {
-                //                debug!("cache miss for line_no {:}", line_no);
-                ParseCacheRecord::new(
-                    ParseState::new(&self.highlight_settings.syntax),
-                    &highlighter,
-                )
+            let mut lines = self.lines.borrow_mut();
+            let end = result.first_line + result.lines.len();
+            if lines.len() < end {
+                lines.resize(end, None);
+            }
+            for (idx, line) in result.lines.into_iter().enumerate() {
+                lines[result.first_line + idx] = Some(line);
             }
-            Some(x) => {
-                //                debug!("cache HIT for line_no {:} ({:})", line_no, x.line_to_parse);
-                x
+
+            if result.first_line <= self.fresh_lines.get() {
+                self.fresh_lines.set(std::cmp::max(self.fresh_lines.get(), end));
             }
-        };
-
-        let line_limit = std::cmp::min(
-            (line_no / PARSING_MILESTONE + 1) * PARSING_MILESTONE,
-            self.raw_content.len_lines(),
-        );
-        let first_line = std::cmp::min(self.lines.borrow().len(), parse_cache.line_to_parse);
-
-        self.drop_lines(first_line);
-
-        debug!("generating lines from [{:}, {:})", first_line, line_limit);
-        for current_line_no in (first_line)..(line_limit) {
-            let line_str = self.raw_content.line(current_line_no).to_string();
-            let ops =
-                parse_cache.parse_state.parse_line(&line_str, &self.highlight_settings.syntax_set);
-
-            let ranges: Vec<(Style, &str)> = {
-                HighlightIterator::new(
-                    &mut parse_cache.highlight_state,
-                    &ops[..],
-                    &line_str,
-                    &highlighter,
-                )
-                .collect()
-            };
-
-            let new_line: Vec<(Color, String)> = ranges
-                .into_iter()
-                .map(|(style, words)| (simplify_style(&style), words.to_string()))
-                .collect();
-            let rc_rich_line = Rc::new(RichLine::new(current_line_no, new_line));
-
-            //            debug!("generated line {:?}", rc_rich_line);
-            self.lines.borrow_mut().push(rc_rich_line);
         }
+    }
 
-        // cache, unless it was a last line.
-        if line_limit < self.raw_content.len_lines() {
-            let mut copy_to_cache = parse_cache.clone();
-            copy_to_cache.line_to_parse = line_limit;
+    /// Never blocks. Returns the most recent highlighting of given line, which can be stale or
+    /// missing. In such case worker is asked to compute it.
+    pub fn get_line(&self, line_no: usize) -> Option<Arc<RichLine>> {
+        if line_no >= self.raw_content.len_lines() {
+            return None;
+        }
 
-            // just checking.
+        self.consume_results();
 
-            if !self.parse_cache.borrow().is_empty() {
-                let prev_line = self.parse_cache.borrow().last().unwrap().line_to_parse;
-                let new_line = line_limit;
-                //                debug!("prev_line {:}, new_line {:}", prev_line, new_line);
-                if prev_line >= line_limit {
-                    error!("expeced prev_line {:} < new_line {:}", prev_line, new_line);
-                }
+        if line_no >= self.fresh_lines.get()
+            && self.requested_line.get().map(|l| l < line_no).unwrap_or(true)
+        {
+            self.requested_line.set(Some(line_no));
+            let job = HighlightJob::ParseUpTo { version: self.version, line_no };
+            if self.job_sender.send(job).is_err() {
+                error!("highlight worker is gone.");
             }
-
-            debug!("caching {:?}", &copy_to_cache);
-            self.parse_cache.borrow_mut().push(copy_to_cache);
         }
 
-        debug!("returning line {:}", line_no);
-        self.lines.borrow().get(line_no).map(|x| x.clone())
+        self.lines.borrow().get(line_no).and_then(|x| x.clone())
     }
 }
 
@@ -312,7 +280,7 @@ struct RichLinesIterator<'a> {
 }
 
 impl<'a> Iterator for RichLinesIterator<'a> {
-    type Item = Rc<RichLine>;
+    type Item = Arc<RichLine>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let old_line_no = self.line_no;
@@ -328,22 +296,52 @@ impl<'a> ExactSizeIterator for RichLinesIterator<'a> {
     }
 }
 
-//impl <'a> Index<usize> for RichLinesIterator<'a> {
-//    type Output = Rc<RichLine>;
-//
-//    //panics //TODO format docs.
-//    fn index(&self, idx : usize) -> &Rc<RichLine> {
-//        self.content.get_line(idx).unwrap()
-//    }
-//}
-
-fn simplify_style(style: &Style) -> Color {
-    Color::Rgb(style.foreground.r, style.foreground.g, style.foreground.b)
-}
-
-pub mod tests {
+#[cfg(test)]
+mod tests {
     use super::*;
+    use crate::settings::Settings;
+    use crate::syntax_loader::SyntaxLoader;
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    fn rust_content(text: &str) -> RichContent {
+        let loader = SyntaxLoader::new(&Settings::load_default(), None);
+        let settings = loader.detect(Some(Path::new("main.rs")), None).unwrap();
+        RichContent::new(settings, Rope::from_str(text), None)
+    }
+
+    fn wait_for_fresh(content: &RichContent, line_no: usize) {
+        for _ in 0..500 {
+            content.get_line(line_no);
+            if content.is_fresh(line_no) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("line {} did not get highlighted in time", line_no);
+    }
 
-    fn basic_setup() {}
+    #[test]
+    fn lines_get_highlighted_in_background() {
+        let content = rust_content("fn main() {\n    let x = 1;\n}\n");
+        assert!(!content.is_fresh(0));
 
+        wait_for_fresh(&content, 2);
+        assert!(content.get_line(1).is_some());
+        assert_eq!(content.get_line(1).unwrap().get_line_no(), 1);
+    }
+
+    #[test]
+    fn edit_makes_lines_stale_but_keeps_them() {
+        let mut content = rust_content("fn main() {\n    let x = 1;\n}\n");
+        wait_for_fresh(&content, 2);
+
+        content.update_raw_content(Rope::from_str("fn main() {\n    let y = 1;\n}\n"), 1);
+        assert!(content.is_fresh(0));
+        assert!(!content.is_fresh(1));
+        assert!(content.get_line(1).is_some()); // stale, but there.
+
+        wait_for_fresh(&content, 2);
+    }
 }
@@ -0,0 +1,620 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// This file implements saving that never leaves a half-written file behind.
+//
+// Content is written to a temporary file next to the target (so rename does not cross
+// filesystems), flushed to disk and renamed over the target. Until rename happens, the old file
+// is intact, and rename itself is atomic. If target is a symlink, the file it points to is
+// replaced, not the link. New file gets mode bits and owner of the old one (owner only if we are
+// allowed to set it).
+//
+// Rename is not always possible or wanted, then the file is overwritten in place (which is not
+// atomic): when temporary file cannot be created (writable file in a directory that is not), and
+// when file has other hard links, which would keep pointing to the old content after rename.
+//
+// Parts FileSystem trait does not cover (symlinks, permissions, fsync) are in SaveSupport. On
+// FakeFileSystem, used under test, they do nothing, OsFileSystem is tested on a temp dir.
+//
+// Files user has no permission to write can be saved with save_file_privileged, that pipes
+// content into external helper (like "sudo -A tee <path>"). It works on real filesystem only.
+// Helper must not talk to the user on terminal, as Cursive keeps reading it. Password is asked by
+// sly instead, and handed over with askpass: SUDO_ASKPASS points to sly executable, that prints
+// password and exits (see main.rs). Password never goes into environment (other processes of the
+// user can read it there), it's written into an anonymous pipe helper inherits, and askpass
+// reads it from descriptor given in ASKPASS_FD_VAR. Helper has to pass the descriptor on to
+// askpass, like sudo does.
+
+#[cfg(test)]
+use filesystem::FakeFileSystem;
+use filesystem::{FileSystem, OsFileSystem};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::buffer_id::BufferId;
+use crate::interface::InterfaceNotifier;
+
+//docs: https://docs.rs/uid/0.1.4/uid/struct.Id.html
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct WorkerIdType(());
+
+type Id = uid::Id<WorkerIdType>;
+
+/// Environment variable with descriptor askpass helper reads password from.
+pub const ASKPASS_FD_VAR: &'static str = "SLY_ASKPASS_FD";
+
+/// Suffix of backup files, like in emacs and vim.
+pub const BACKUP_SUFFIX: &'static str = "~";
+
+/// Returns path of temporary file used while saving path.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.sly-save-{}", file_name, process::id()))
+}
+
+/// Returns path of backup file of path ("file~").
+pub fn backup_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}{}", file_name, BACKUP_SUFFIX))
+}
+
+/// Replaces content of file at path with bytes (or creates it). If backup is set and file already
+/// existed, its previous version is kept in backup_path(path).
+pub fn save_file<F: FileSystem + SaveSupport>(
+    fs: &F,
+    path: &Path,
+    bytes: &[u8],
+    backup: bool,
+) -> Result<(), io::Error> {
+    save_file_with(fs, path, bytes, backup)
+}
+
+fn save_file_with<F: FileSystem + SaveSupport>(
+    fs: &F,
+    path: &Path,
+    bytes: &[u8],
+    backup: bool,
+) -> Result<(), io::Error> {
+    let target = fs.resolve_symlinks(path);
+    let existed = fs.is_file(&target);
+    if existed && fs.link_count(&target) > 1 {
+        return write_in_place(fs, &target, bytes, backup);
+    }
+
+    let temp = temp_path(&target);
+    // leftover of a crashed save.
+    if fs.is_file(&temp) {
+        fs.remove_file(&temp)?;
+    }
+
+    if let Err(e) = fs.create_synced(&temp, bytes) {
+        if !existed {
+            return Err(e);
+        }
+        debug!("cannot create {:?}, because \"{}\", writing {:?} in place", &temp, e, &target);
+        return write_in_place(fs, &target, bytes, backup);
+    }
+
+    let result = replace_with_temp(fs, &target, &temp, existed, backup);
+    if result.is_err() && fs.is_file(&temp) {
+        if let Err(e) = fs.remove_file(&temp) {
+            warn!("failed to remove temporary file {:?}, because \"{}\"", &temp, e);
+        }
+    }
+    result
+}
+
+fn make_backup<F: FileSystem>(fs: &F, target: &Path) -> Result<(), io::Error> {
+    let backup = backup_path(target);
+    if fs.is_file(&backup) {
+        fs.remove_file(&backup)?;
+    }
+    fs.copy_file(target, &backup)
+}
+
+/// Overwrites target, keeping its inode (and so links, owner and mode). Backup is best effort
+/// here, as directory may not be writable.
+fn write_in_place<F: FileSystem + SaveSupport>(
+    fs: &F,
+    target: &Path,
+    bytes: &[u8],
+    backup: bool,
+) -> Result<(), io::Error> {
+    if backup {
+        if let Err(e) = make_backup(fs, target) {
+            warn!("failed to keep backup of {:?}, because \"{}\"", target, e);
+        }
+    }
+    fs.overwrite_synced(target, bytes)
+}
+
+fn replace_with_temp<F: FileSystem + SaveSupport>(
+    fs: &F,
+    target: &Path,
+    temp: &Path,
+    existed: bool,
+    backup: bool,
+) -> Result<(), io::Error> {
+    if existed {
+        fs.copy_ownership_and_mode(target, temp)?;
+    }
+
+    if existed && backup {
+        make_backup(fs, target)?;
+    }
+
+    fs.rename(temp, target)?;
+
+    // makes rename itself durable. Not all filesystems support that, so it's not an error.
+    if let Some(dir) = target.parent() {
+        if let Err(e) = fs.sync_dir(dir) {
+            debug!("failed to sync directory {:?}, because \"{}\"", dir, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes bytes to path by running command (first element is program, the rest are arguments)
+/// with path appended as the last argument, and bytes on its standard input. Password, if given,
+/// is available to helper through askpass (see top of the file). Error output of helper is
+/// returned in error. It waits for helper to finish, so it's to be called on a worker thread.
+pub fn save_file_privileged(
+    command: &[String],
+    path: &Path,
+    bytes: Vec<u8>,
+    password: Option<&str>,
+) -> Result<(), io::Error> {
+    let (program, args) = match command.split_first() {
+        Some(split) => split,
+        None => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "No save helper configured."))
+        }
+    };
+
+    let mut command_builder = Command::new(program);
+    command_builder.args(args).arg(path);
+    // kept open until helper is started, it's the child's copy askpass reads from.
+    let _password_pipe = match password {
+        Some(password) => {
+            let pipe = password_pipe(password)?;
+            command_builder.env("SUDO_ASKPASS", std::env::current_exe()?);
+            pass_to_askpass(&mut command_builder, &pipe);
+            Some(pipe)
+        }
+        None => None,
+    };
+    let mut child = command_builder
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // helper can fill up its error output before it reads all of its input, so input is written
+    // on another thread while error output is read here. Dropping stdin closes it, so helper
+    // knows content has ended.
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(&bytes));
+    let output = child.wait_with_output()?;
+    let written = writer.join().unwrap_or(Ok(()));
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("\"{}\" failed with {}: {}", command.join(" "), output.status, stderr.trim()),
+        ));
+    }
+    // helper that succeeded without reading all of the content did not write all of it.
+    written
+}
+
+/// Runs save_file_privileged on a separate thread, returns id of the worker. It ends with
+/// IEvent::PrivilegedSaved, that carries version of buffer content that was written.
+pub fn start_privileged_save(
+    command: Vec<String>,
+    buffer_id: BufferId,
+    path: PathBuf,
+    version: usize,
+    bytes: Vec<u8>,
+    password: Option<String>,
+    inot: InterfaceNotifier,
+) -> usize {
+    let worker_id: usize = Id::new().get();
+    thread::spawn(move || {
+        let result = save_file_privileged(&command, &path, bytes, password.as_deref())
+            .map_err(|e| e.to_string());
+        inot.privileged_saved(worker_id, buffer_id, path, version, result);
+    });
+    worker_id
+}
+
+/// Returns read end of a pipe holding password. Both ends are closed on exec, pass_to_askpass
+/// makes the child keep the read end. Password fits in pipe buffer, so nothing blocks on write.
+#[cfg(unix)]
+fn password_pipe(password: &str) -> Result<std::fs::File, io::Error> {
+    use std::os::unix::io::FromRawFd;
+
+    let mut fds: [libc::c_int; 2] = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let (read_end, mut write_end) =
+        unsafe { (std::fs::File::from_raw_fd(fds[0]), std::fs::File::from_raw_fd(fds[1])) };
+    for fd in fds.iter() {
+        if unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    write_end.write_all(password.as_bytes())?;
+    Ok(read_end)
+}
+
+#[cfg(not(unix))]
+fn password_pipe(_password: &str) -> Result<std::fs::File, io::Error> {
+    Err(io::Error::new(io::ErrorKind::Other, "Password cannot be handed to helper here."))
+}
+
+/// Makes pipe inherited by helper (and askpass it starts), and tells askpass its descriptor.
+#[cfg(unix)]
+fn pass_to_askpass(command: &mut Command, pipe: &std::fs::File) {
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::process::CommandExt;
+
+    let fd = pipe.as_raw_fd();
+    command.env(ASKPASS_FD_VAR, fd.to_string());
+    unsafe {
+        command.pre_exec(move || {
+            if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn pass_to_askpass(_command: &mut Command, _pipe: &std::fs::File) {}
+
+/// Askpass mode of sly (see main.rs): prints password read from descriptor in ASKPASS_FD_VAR.
+#[cfg(unix)]
+pub fn print_askpass_password(fd: &std::ffi::OsStr) -> Result<(), io::Error> {
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+
+    let fd: libc::c_int = match fd.to_str().and_then(|fd| fd.parse().ok()) {
+        Some(fd) => fd,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid descriptor.")),
+    };
+    let mut password = String::new();
+    unsafe { std::fs::File::from_raw_fd(fd) }.read_to_string(&mut password)?;
+    println!("{}", password);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn print_askpass_password(_fd: &std::ffi::OsStr) -> Result<(), io::Error> {
+    Err(io::Error::new(io::ErrorKind::Other, "Askpass is not supported here."))
+}
+
+/// What saving needs besides FileSystem: symlinks, hard links, permissions and fsync.
+pub trait SaveSupport {
+    /// Creates file at path with bytes, flushed to disk before it's closed.
+    fn create_synced(&self, path: &Path, bytes: &[u8]) -> Result<(), io::Error>;
+    /// Replaces content of existing file at path with bytes, flushed to disk before it's closed.
+    fn overwrite_synced(&self, path: &Path, bytes: &[u8]) -> Result<(), io::Error>;
+    /// Returns path of the file path points to, following symlinks.
+    fn resolve_symlinks(&self, path: &Path) -> PathBuf;
+    /// Returns number of hard links to file.
+    fn link_count(&self, path: &Path) -> u64;
+    fn copy_ownership_and_mode(&self, from: &Path, to: &Path) -> Result<(), io::Error>;
+    fn sync_dir(&self, dir: &Path) -> Result<(), io::Error>;
+    /// Makes file (or directory) accessible to its owner only.
+    fn make_private(&self, path: &Path) -> Result<(), io::Error>;
+}
+
+impl SaveSupport for OsFileSystem {
+    fn create_synced(&self, path: &Path, bytes: &[u8]) -> Result<(), io::Error> {
+        let mut file = std::fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+        file.write_all(bytes)?;
+        // on this handle, as file may not be writable anymore once its mode is copied.
+        file.sync_all()
+    }
+
+    fn overwrite_synced(&self, path: &Path, bytes: &[u8]) -> Result<(), io::Error> {
+        let mut file = std::fs::OpenOptions::new().write(true).truncate(true).open(path)?;
+        file.write_all(bytes)?;
+        file.sync_all()
+    }
+
+    fn resolve_symlinks(&self, path: &Path) -> PathBuf {
+        // canonicalize fails if path does not exist, and then there is no symlink to follow.
+        std::fs::canonicalize(path).unwrap_or(path.to_path_buf())
+    }
+
+    #[cfg(unix)]
+    fn link_count(&self, path: &Path) -> u64 {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::metadata(path).map(|metadata| metadata.nlink()).unwrap_or(1)
+    }
+
+    #[cfg(not(unix))]
+    fn link_count(&self, _path: &Path) -> u64 {
+        1
+    }
+
+    fn copy_ownership_and_mode(&self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        copy_ownership_and_mode(from, to)
+    }
+
+    #[cfg(unix)]
+    fn sync_dir(&self, dir: &Path) -> Result<(), io::Error> {
+        std::fs::File::open(dir)?.sync_all()
+    }
+
+    #[cfg(not(unix))]
+    fn sync_dir(&self, _dir: &Path) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn make_private(&self, path: &Path) -> Result<(), io::Error> {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = if std::fs::metadata(path)?.is_dir() { 0o700 } else { 0o600 };
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    fn make_private(&self, _path: &Path) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+/// FakeFileSystem has no links, permissions or disk to flush to.
+#[cfg(test)]
+impl SaveSupport for FakeFileSystem {
+    fn create_synced(&self, path: &Path, bytes: &[u8]) -> Result<(), io::Error> {
+        self.create_file(path, bytes)
+    }
+
+    fn overwrite_synced(&self, path: &Path, bytes: &[u8]) -> Result<(), io::Error> {
+        self.overwrite_file(path, bytes)
+    }
+
+    fn resolve_symlinks(&self, path: &Path) -> PathBuf {
+        path.to_path_buf()
+    }
+
+    fn link_count(&self, _path: &Path) -> u64 {
+        1
+    }
+
+    fn copy_ownership_and_mode(&self, _from: &Path, _to: &Path) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn sync_dir(&self, _dir: &Path) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn make_private(&self, _path: &Path) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn copy_ownership_and_mode(from: &Path, to: &Path) -> Result<(), io::Error> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(from)?;
+
+    // only root (or owner, when changing group to one of theirs) can do that, so failure is
+    // expected and ignored: file will belong to whoever saved it, as it would with any editor.
+    let c_path = CString::new(to.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let chown_result = unsafe { libc::chown(c_path.as_ptr(), metadata.uid(), metadata.gid()) };
+    if chown_result != 0 {
+        debug!("could not preserve owner of {:?}: {}", from, io::Error::last_os_error());
+    }
+
+    // after chown, as it can clear setuid and setgid bits.
+    std::fs::set_permissions(to, metadata.permissions())
+}
+
+#[cfg(not(unix))]
+fn copy_ownership_and_mode(from: &Path, to: &Path) -> Result<(), io::Error> {
+    std::fs::set_permissions(to, std::fs::metadata(from)?.permissions())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir::temp_dir;
+    use crate::FileSystemType;
+
+    fn fs_with_file(path: &str, content: &str) -> FileSystemType {
+        let fs = FileSystemType::new();
+        fs.create_dir_all("/project").unwrap();
+        fs.create_file(path, content).unwrap();
+        fs
+    }
+
+    #[test]
+    fn save_replaces_content_and_leaves_no_temp_file() {
+        let fs = fs_with_file("/project/a.txt", "old");
+        save_file(&fs, Path::new("/project/a.txt"), b"new", false).unwrap();
+
+        assert_eq!(fs.read_file_to_string("/project/a.txt").unwrap(), "new");
+        assert!(!fs.is_file(temp_path(Path::new("/project/a.txt"))));
+        assert!(!fs.is_file("/project/a.txt~"));
+    }
+
+    #[test]
+    fn save_creates_new_file() {
+        let fs = fs_with_file("/project/a.txt", "old");
+        save_file(&fs, Path::new("/project/b.txt"), b"new", true).unwrap();
+
+        assert_eq!(fs.read_file_to_string("/project/b.txt").unwrap(), "new");
+        assert!(!fs.is_file("/project/b.txt~"));
+    }
+
+    #[test]
+    fn save_keeps_backup() {
+        let fs = fs_with_file("/project/a.txt", "old");
+        fs.create_file("/project/a.txt~", "older").unwrap();
+        save_file(&fs, Path::new("/project/a.txt"), b"new", true).unwrap();
+
+        assert_eq!(fs.read_file_to_string("/project/a.txt").unwrap(), "new");
+        assert_eq!(fs.read_file_to_string("/project/a.txt~").unwrap(), "old");
+    }
+
+    fn command(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn privileged_save_pipes_content_to_helper() {
+        let path = std::env::temp_dir().join(format!("sly_privileged_save_{}", process::id()));
+        save_file_privileged(&command(&["tee"]), &path, b"saved by helper".to_vec(), None).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"saved by helper".to_vec());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn privileged_save_reads_error_output_while_writing_content() {
+        let path = std::env::temp_dir().join(format!("sly_privileged_chatty_{}", process::id()));
+        // more than fits in pipe buffers, both ways.
+        let script = "head -c 1000000 /dev/zero >&2; cat > \"$0\"";
+        let content = vec![b'x'; 1000000];
+        let result = save_file_privileged(&command(&["sh", "-c", script]), &path, content, None);
+        let written = std::fs::metadata(&path).map(|metadata| metadata.len());
+        std::fs::remove_file(&path).ok();
+
+        result.unwrap();
+        assert_eq!(written.unwrap(), 1000000);
+    }
+
+    #[test]
+    fn privileged_save_hands_password_to_askpass() {
+        let path = std::env::temp_dir().join(format!("sly_askpass_{}", process::id()));
+        // path is $0 of the script, it reads the pipe like askpass does.
+        let script = "cat > /dev/null; cat /dev/fd/$SLY_ASKPASS_FD > \"$0\"; echo >> \"$0\"; \
+                      printenv SUDO_ASKPASS >> \"$0\"; env | grep -c secret >> \"$0\" || true";
+        let helper = command(&["sh", "-c", script]);
+        let result = save_file_privileged(&helper, &path, b"x".to_vec(), Some("secret"));
+        let output = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).ok();
+
+        result.unwrap();
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(output.unwrap(), format!("secret\n{}\n0\n", exe.to_string_lossy()));
+    }
+
+    #[test]
+    fn privileged_save_reports_failure() {
+        let path = std::env::temp_dir().join("sly_privileged_save_never_written");
+        assert!(save_file_privileged(&command(&["false"]), &path, b"x".to_vec(), None).is_err());
+        assert!(save_file_privileged(&command(&[]), &path, b"x".to_vec(), None).is_err());
+
+        let helper = command(&["sh", "-c", "cat > /dev/null; echo no password given >&2; exit 1"]);
+        let error = save_file_privileged(&helper, &path, b"x".to_vec(), None).unwrap_err();
+        assert!(error.to_string().ends_with(": no password given"));
+    }
+
+    #[test]
+    fn save_on_disk_follows_symlink_and_keeps_mode() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = temp_dir("safe_save_symlink");
+        let target = dir.join("a.txt");
+        let link = dir.join("link.txt");
+        std::fs::write(&target, "old").unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o444)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        let result = save_file_with(&OsFileSystem::new(), &link, b"new", true);
+        let content = std::fs::read_to_string(&target);
+        let backup = std::fs::read_to_string(backup_path(&target));
+        let mode = std::fs::metadata(&target).unwrap().permissions().mode() & 0o777;
+        let link_kept = std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink();
+        let leftovers: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().contains(".sly-save-"))
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        result.unwrap();
+        assert_eq!(content.unwrap(), "new");
+        assert_eq!(backup.unwrap(), "old");
+        assert_eq!(mode, 0o444);
+        assert!(link_kept);
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn save_on_disk_writes_through_hard_link() {
+        let dir = temp_dir("safe_save_hard_link");
+        let target = dir.join("a.txt");
+        let other = dir.join("b.txt");
+        std::fs::write(&target, "old").unwrap();
+        std::fs::hard_link(&target, &other).unwrap();
+
+        let result = save_file_with(&OsFileSystem::new(), &target, b"new", true);
+        let content = std::fs::read_to_string(&other);
+        let backup = std::fs::read_to_string(backup_path(&target));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        result.unwrap();
+        assert_eq!(content.unwrap(), "new");
+        assert_eq!(backup.unwrap(), "old");
+    }
+
+    #[test]
+    fn save_on_disk_works_in_unwritable_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("safe_save_unwritable_dir");
+        let target = dir.join("a.txt");
+        std::fs::write(&target, "old").unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        // root can create the temporary file anyway, then this checks the usual way.
+        let result = save_file_with(&OsFileSystem::new(), &target, b"new", true);
+        let content = std::fs::read_to_string(&target);
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        result.unwrap();
+        assert_eq!(content.unwrap(), "new");
+    }
+
+    #[test]
+    fn failed_save_keeps_original() {
+        let fs = fs_with_file("/project/a.txt", "old");
+        // nowhere to put temporary file.
+        assert!(save_file(&fs, Path::new("/missing/a.txt"), b"new", false).is_err());
+        assert_eq!(fs.read_file_to_string("/project/a.txt").unwrap(), "old");
+    }
+}
@@ -0,0 +1,248 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Session is what user was looking at when sly was closed: opened files, cursors and scroll
+// positions in them, which one was active, how editors were laid out and what was typed into
+// fuzzy bars. It's written on exit and read on start, one file per project.
+//
+// Project is identified by its directories (the ones given on command line, or current one).
+// Session file is named after a hash of them and also lists them, so a hash collision does not
+// bring in a foreign session. Unnamed buffers and their content are not a part of session,
+// recovery files (see recovery.rs) take care of unsaved work.
+
+use filesystem::FileSystem;
+use serde_json;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cursor_set::CursorSet;
+use crate::safe_save::save_file;
+use crate::split_view::SplitOrientation;
+use crate::utils::{data_dir, stable_hash};
+use crate::FileSystemType;
+
+/// Number of queries remembered per fuzzy bar.
+pub const MAX_QUERY_HISTORY: usize = 50;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BufferSession {
+    pub path: PathBuf,
+    pub cursors: CursorSet,
+    /// (column, line) of upper left corner of editor, like SlyTextView::position.
+    pub position: (usize, usize),
+}
+
+/// Arrangement of editors on screen, see split_view.rs.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LayoutS {
+    /// One editor, showing the active buffer.
+    Single,
+    /// Editor showing a file, with its own cursors (the same file can be in more than one pane).
+    Pane(BufferSession),
+    Split {
+        orientation: SplitOrientation,
+        share: usize,
+        first: Box<LayoutS>,
+        second: Box<LayoutS>,
+    },
+}
+
+impl Default for LayoutS {
+    fn default() -> Self {
+        LayoutS::Single
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    pub directories: Vec<PathBuf>,
+    pub buffers: Vec<BufferSession>,
+    /// Index in buffers.
+    pub active: Option<usize>,
+    #[serde(default)]
+    pub layout: LayoutS,
+    /// Fuzzy bar marker -> queries, oldest first.
+    #[serde(default)]
+    pub query_history: HashMap<String, Vec<String>>,
+}
+
+/// Adds query to history, moving it to the end if it was there already.
+pub fn remember_query(history: &mut Vec<String>, query: &str) {
+    if query.is_empty() {
+        return;
+    }
+    history.retain(|old| old != query);
+    history.push(query.to_string());
+    if history.len() > MAX_QUERY_HISTORY {
+        let excess = history.len() - MAX_QUERY_HISTORY;
+        history.drain(..excess);
+    }
+}
+
+/// Returns path of session file of project consisting of directories, within dir. There is no
+/// session without directories.
+pub fn session_path(dir: &Path, directories: &[PathBuf]) -> Option<PathBuf> {
+    if directories.is_empty() {
+        return None;
+    }
+    let mut key = String::new();
+    for directory in directories {
+        key.push_str(&directory.to_string_lossy());
+        key.push('\n');
+    }
+    Some(dir.join(format!("{:016x}.json", stable_hash(key.as_bytes()))))
+}
+
+/// Returns $XDG_DATA_HOME/sly/sessions (see utils::data_dir).
+pub fn sessions_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("sessions"))
+}
+
+pub fn write_session(
+    fs: &FileSystemType,
+    path: &Path,
+    session: &Session,
+) -> Result<(), io::Error> {
+    if let Some(dir) = path.parent() {
+        if !fs.is_dir(dir) {
+            fs.create_dir_all(dir)?;
+        }
+    }
+    let json = serde_json::to_vec(session)?;
+    save_file(fs, path, &json, false)
+}
+
+/// Returns session stored in path, if it's readable and belongs to directories.
+pub fn read_session(
+    fs: &FileSystemType,
+    path: &Path,
+    directories: &[PathBuf],
+) -> Option<Session> {
+    if !fs.is_file(path) {
+        return None;
+    }
+    let parsed = fs
+        .read_file(path)
+        .and_then(|bytes| serde_json::from_slice::<Session>(&bytes).map_err(|e| e.into()));
+    match parsed {
+        Ok(ref session) if session.directories.as_slice() != directories => {
+            warn!("session file {:?} belongs to other project, ignoring.", path);
+            None
+        }
+        Ok(session) => Some(session),
+        Err(e) => {
+            warn!("ignoring session file {:?}, because \"{}\"", path, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dirs(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn session_round_trip() {
+        let fs = FileSystemType::new();
+        let directories = dirs(&["/project"]);
+        let path = session_path(Path::new("/sessions"), &directories).unwrap();
+
+        let mut session = Session::default();
+        session.directories = directories.clone();
+        session.buffers.push(BufferSession {
+            path: PathBuf::from("/project/a.txt"),
+            cursors: CursorSet::new(vec![3usize.into(), (1usize, 4usize, 4usize).into()]),
+            position: (0, 10),
+        });
+        session.active = Some(0);
+        remember_query(session.query_history.entry("file_bar".to_string()).or_default(), "a.t");
+
+        write_session(&fs, &path, &session).unwrap();
+        let read = read_session(&fs, &path, &directories).unwrap();
+        assert_eq!(read.buffers[0].path, PathBuf::from("/project/a.txt"));
+        assert_eq!(read.buffers[0].cursors, session.buffers[0].cursors);
+        assert_eq!(read.buffers[0].position, (0, 10));
+        assert_eq!(read.active, Some(0));
+        assert_eq!(read.layout, LayoutS::Single);
+        assert_eq!(read.query_history["file_bar"], vec!["a.t".to_string()]);
+    }
+
+    #[test]
+    fn split_layout_round_trip() {
+        let fs = FileSystemType::new();
+        let directories = dirs(&["/project"]);
+        let path = session_path(Path::new("/sessions"), &directories).unwrap();
+
+        let pane = |line: usize| {
+            LayoutS::Pane(BufferSession {
+                path: PathBuf::from("/project/a.txt"),
+                cursors: CursorSet::single(),
+                position: (0, line),
+            })
+        };
+        let mut session = Session::default();
+        session.directories = directories.clone();
+        session.layout = LayoutS::Split {
+            orientation: SplitOrientation::Vertical,
+            share: 30,
+            first: Box::new(pane(0)),
+            second: Box::new(pane(100)),
+        };
+
+        write_session(&fs, &path, &session).unwrap();
+        let read = read_session(&fs, &path, &directories).unwrap();
+        assert_eq!(read.layout, session.layout);
+    }
+
+    #[test]
+    fn session_paths_differ_per_project() {
+        let dir = Path::new("/sessions");
+        assert_eq!(session_path(dir, &[]), None);
+        assert_ne!(
+            session_path(dir, &dirs(&["/project"])),
+            session_path(dir, &dirs(&["/other_project"]))
+        );
+    }
+
+    #[test]
+    fn foreign_and_corrupted_sessions_are_ignored() {
+        let fs = FileSystemType::new();
+        let path = PathBuf::from("/sessions/s.json");
+        let mut session = Session::default();
+        session.directories = dirs(&["/project"]);
+        write_session(&fs, &path, &session).unwrap();
+        assert!(read_session(&fs, &path, &dirs(&["/other_project"])).is_none());
+
+        fs.remove_file(&path).unwrap();
+        fs.create_file(&path, "{ not json").unwrap();
+        assert!(read_session(&fs, &path, &dirs(&["/project"])).is_none());
+    }
+
+    #[test]
+    fn remember_query_moves_repeated_to_end() {
+        let mut history: Vec<String> = Vec::new();
+        remember_query(&mut history, "a");
+        remember_query(&mut history, "b");
+        remember_query(&mut history, "a");
+        remember_query(&mut history, "");
+        assert_eq!(history, vec!["b".to_string(), "a".to_string()]);
+    }
+}
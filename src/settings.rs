@@ -142,6 +142,67 @@ impl Settings {
         color
     }
 
+    /// Returns list of colors, like rainbow bracket colors. Not cached.
+    pub fn get_color_list(&self, selector: &'static str) -> Vec<cursive::theme::Color> {
+        match self.get_value(selector) {
+            Some(&sj::Value::Array(ref items)) => items
+                .iter()
+                .map(|item| match item {
+                    &sj::Value::String(ref color_string) => color_hex_to_rgb(color_string)
+                        .expect(&format!("failed parsing color {:?} : {:?}", selector, item)),
+                    _ => panic!("expected color, got {:?} in path {:?}", item, selector),
+                })
+                .collect(),
+            anything_else => panic!(
+                "expected array of colors, got {:?} in path {:?} (or earlier)",
+                anything_else, selector
+            ),
+        }
+    }
+
+    pub fn get_usize(&self, selector: &'static str) -> usize {
+        match self.get_value(selector).and_then(|value| value.as_u64()) {
+            Some(value) => value as usize,
+            None => panic!("expected unsigned integer in path {:?} (or earlier)", selector),
+        }
+    }
+
+    pub fn get_string(&self, selector: &'static str) -> String {
+        match self.get_value(selector) {
+            Some(&sj::Value::String(ref value)) => value.clone(),
+            anything_else => panic!(
+                "expected string, got {:?} in path {:?} (or earlier)",
+                anything_else, selector
+            ),
+        }
+    }
+
+    pub fn get_string_list(&self, selector: &'static str) -> Vec<String> {
+        match self.get_value(selector) {
+            Some(&sj::Value::Array(ref items)) => items
+                .iter()
+                .map(|item| match item {
+                    &sj::Value::String(ref value) => value.clone(),
+                    _ => panic!("expected string, got {:?} in path {:?}", item, selector),
+                })
+                .collect(),
+            anything_else => panic!(
+                "expected array of strings, got {:?} in path {:?} (or earlier)",
+                anything_else, selector
+            ),
+        }
+    }
+
+    pub fn get_bool(&self, selector: &'static str) -> bool {
+        match self.get_value(selector) {
+            Some(&sj::Value::Bool(value)) => value,
+            anything_else => panic!(
+                "expected bool, got {:?} in path {:?} (or earlier)",
+                anything_else, selector
+            ),
+        }
+    }
+
     pub fn auto_highlighting_enabled(&self) -> bool {
         self.auto_highlighting
     }
@@ -173,10 +234,48 @@ impl Settings {
         }
     }
 
+    /// Returns a map from a key (file name or extension) to syntax names to try, in given order.
+    /// Values can be either a single string or an array of strings.
+    pub fn get_syntax_map(&self, selector: &'static str) -> HashMap<String, Vec<String>> {
+        let mut result: HashMap<String, Vec<String>> = HashMap::new();
+
+        let map = match self.get_value(selector) {
+            Some(&sj::Value::Object(ref map)) => map,
+            None => return result,
+            anything_else => {
+                panic!("expected object, got {:?} in path {:?}", anything_else, selector)
+            }
+        };
+
+        for (key, value) in map.iter() {
+            let names: Vec<String> = match value {
+                &sj::Value::String(ref name) => vec![name.clone()],
+                &sj::Value::Array(ref names) => names
+                    .iter()
+                    .map(|name| {
+                        name.as_str()
+                            .expect(&format!("{}/{} contains non-string value", selector, key))
+                            .to_string()
+                    })
+                    .collect(),
+                _ => panic!("{}/{} is neither a string nor an array!", selector, key),
+            };
+            result.insert(key.clone(), names);
+        }
+
+        result
+    }
+
+    /// Maximum number of files indexed in each project directory.
     pub fn file_index_limit(&self) -> usize {
         self.file_index_limit
     }
 
+    #[cfg(test)]
+    pub fn set_file_index_limit(&mut self, limit: usize) {
+        self.file_index_limit = limit;
+    }
+
     // TODO(njskalski): add cache.
     pub fn get_keybindings(&self, context: &str) -> KeybindingsType {
         let known_keys = get_known_keys();
@@ -27,13 +27,12 @@ limitations under the License.
 // - python script in replace
 
 // TODO(njskalski) never allow overlapping cursors
-// TODO(njskalski) update cursors on autoreload from hard drive (autoreload enabled if non-modified,
-// and not disabled in options) TODO(njskalski) use View::layout instead of View::required_size to
-// determine window size.
+// TODO(njskalski) use View::layout instead of View::required_size to determine window size.
 
 use crate::abstract_clipboard::ClipboardType;
 use time;
 
+use crate::bracket_matching::{bracket_near_anchor, BracketCache};
 use crate::buffer_state_observer::BufferStateObserver;
 use clipboard;
 use clipboard::ClipboardProvider;
@@ -61,6 +60,7 @@ use std::cell::RefCell;
 use std::cmp;
 use std::cmp::min;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::iter;
 use std::rc::Rc;
 use std::usize::MAX;
@@ -68,14 +68,39 @@ use unicode_segmentation;
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 use crate::view_handle::ViewHandle;
-use crate::cursor_set::CursorSet;
+use crate::cursor_set::{Cursor, CursorSet};
+use crate::text_diff::TextDiff;
+use crate::line_endings::line_end;
+use crate::whitespace::{
+    indentation_end, trailing_whitespace_begin, whitespace_kind, WhitespaceStyle,
+};
+use crate::layout::{
+    column_of, layout_line, layout_rope_line, line_wrap_points, prev_grapheme_boundary, Glyph,
+};
 use core::borrow::Borrow;
 
 const INDEX_MARGIN: usize = 1;
+const WRAP_MARKER: &'static str = "\u{21AA}";
+const FOLDED_MARKER: &'static str = "\u{25B8}";
+const FOLDABLE_MARKER: &'static str = "\u{25BE}";
+const FOLD_PLACEHOLDER: &'static str = "\u{22EF}";
 const PAGE_WIDTH: usize = 80;
+const REPLACEMENT_SYMBOL: &'static str = "\u{FFFD}";
+const COMBINING_BASE: &'static str = "\u{25CC}";
+const STATUS_BAR_HEIGHT: usize = 1;
+const READ_ONLY_NOTICE: &'static str = "buffer is read-only";
 
 //const NEWLINE_DRAWING : char = '\u{2424}';
 
+/// Part of a line displayed in a single row of screen. begin and end are char offsets within line.
+struct ScreenRow {
+    line_no: usize,
+    begin: usize,
+    end: usize,
+    is_first: bool,
+    is_last: bool,
+}
+
 pub struct SlyTextView {
     channel: IChannel, // interface feedback channel
     buffer: BufferStateObserver,
@@ -84,9 +109,11 @@ pub struct SlyTextView {
     last_view_size: Option<Vec2>, //not sure if using properly
     settings: Rc<RefCell<Settings>>,
     clipboard_context: ClipboardType,
-    special_char_mappings: HashMap<char, char>,
+    whitespace: WhitespaceStyle,
     handle: ViewHandle,
     syntax_highlighting: bool, //local override of global setting.
+    notice: Option<String>,    // displayed in status bar until next event.
+    brackets: RefCell<BracketCache>, // draw is &self.
 }
 
 impl SlyView for SlyTextView {
@@ -107,6 +134,12 @@ impl SlyTextView {
             setting_borrowed.borrow().auto_highlighting_enabled()
         };
 
+
+        let whitespace = {
+            let setting_borrowed: &RefCell<Settings> = settings.borrow();
+            WhitespaceStyle::from_settings(&setting_borrowed.borrow())
+        };
+
         let mut view = SlyTextView {
             channel: channel,
             buffer: buffer,
@@ -115,9 +148,11 @@ impl SlyTextView {
             last_view_size: None,
             settings: settings,
             clipboard_context: ClipboardType::new(),
-            special_char_mappings: hashmap!['\n' => '\u{21B5}'],
+            whitespace,
             handle: ViewHandle::new(),
             syntax_highlighting: syntax_highlighting,
+            notice: None,
+            brackets: RefCell::new(BracketCache::new()),
         };
 
         if syntax_highlighting && !view.syntax_highlighting_on() {
@@ -136,7 +171,8 @@ impl SlyTextView {
     }
 
     fn submit_events(&mut self, events: Vec<EditEvent>) {
-        self.channel.send(IEvent::BufferEditEvent(self.buffer.buffer_id(), events)).unwrap()
+        let buffer_id = self.buffer.buffer_id();
+        self.channel.send(IEvent::BufferEditEvent(buffer_id, self.handle(), events)).unwrap()
     }
 
     /// Returns the position of the cursor in the content string.
@@ -144,6 +180,69 @@ impl SlyTextView {
         &self.cursor_set
     }
 
+    /// Returns number of columns lines are wrapped at, or None if soft wrap is off.
+    fn wrap_width(&self, index_length: usize) -> Option<usize> {
+        if !self.buffer.soft_wrap() {
+            return None;
+        }
+
+        let wrap_column = self.settings_ref().get_usize("text_view/wrap_column");
+        if wrap_column > 0 {
+            return Some(wrap_column);
+        }
+
+        // one column is left for newline symbol (or cursor after the last character).
+        let view_width = self.last_view_size.map(|size| size.x).unwrap_or(PAGE_WIDTH);
+        Some(cmp::max(1, view_width.saturating_sub(index_length + INDEX_MARGIN + 1)))
+    }
+
+    /// Returns position of upper left corner of view in file.
+    pub fn position(&self) -> Vec2 {
+        self.position
+    }
+
+    /// Sets cursors and position, like the ones remembered in session. They are limited to
+    /// current content of buffer.
+    pub fn restore_view_state(&mut self, mut cursors: CursorSet, position: Vec2) {
+        let (len_chars, len_lines) = {
+            let content = self.buffer.borrow_content();
+            (content.get_lines().len_chars(), content.get_lines().len_lines())
+        };
+        cursors.limit_to(len_chars);
+        self.cursor_set = cursors;
+        self.position = Vec2::new(position.x, cmp::min(position.y, len_lines.saturating_sub(1)));
+    }
+
+    /// Moves cursors after buffer was reloaded from disk, see CursorSet::adjust_after_reload.
+    pub fn adjust_after_reload(&mut self, old: &Rope, diff: &TextDiff) {
+        let content = self.buffer.borrow_content();
+        self.cursor_set.adjust_after_reload(old, content.get_lines(), diff);
+    }
+
+    /// Moves cursors after buffer was edited in another editor, see CursorSet::adjust_after_edits.
+    pub fn adjust_after_edits(&mut self, events: &[EditEvent]) {
+        self.cursor_set.adjust_after_edits(events);
+    }
+
+    /// Sets message displayed in status bar until next event.
+    pub fn set_notice(&mut self, notice: String) {
+        self.notice = Some(notice);
+    }
+
+    /// Returns true if buffer can be edited, otherwise leaves a notice for the user.
+    fn check_writable(&mut self) -> bool {
+        if self.buffer.read_only() {
+            self.notice = Some(READ_ONLY_NOTICE.to_string());
+            false
+        } else {
+            true
+        }
+    }
+
+    fn index_length(&self) -> usize {
+        self.buffer.borrow_content().len_lines().to_string().len()
+    }
+
     pub fn syntax_highlighting_on(&self) -> bool {
         self.syntax_highlighting && self.buffer.borrow_content().is_rich_content_enabled()
     }
@@ -171,18 +270,52 @@ impl View for SlyTextView {
 
         let cursors = &self.cursor_set;
         let lines = content.get_lines();
+        let tab_width = self.buffer.tab_width();
+        let whitespace = &self.whitespace;
 
         let view_size = self.last_view_size.expect("view size not known.");
+        let text_height = view_size.y.saturating_sub(STATUS_BAR_HEIGHT);
+
+        // folded lines are skipped, so y is not a function of line_no.
+        let folds = self.buffer.borrow_folds();
+        let visible_lines: Vec<usize> =
+            folds.visible_lines(self.position.y, text_height, lines.len_lines());
+        let fold_candidates = folds.candidates(&content);
+        let foldable_lines: HashSet<usize> =
+            fold_candidates.iter().map(|range| range.first_line).collect();
+
+        // with soft wrap, a line can take more than one row of screen.
+        let wrap_width_op = self.wrap_width(index_length);
+        let mut rows: Vec<ScreenRow> = Vec::new();
+        for &line_no in visible_lines.iter() {
+            //this allow a cursor *after* the last character. It's actually needed.
+            let add = if line_no == lines.len_lines() - 1 { 1 } else { 0 };
+            let line_len = lines.line(line_no).len_chars() + add;
+
+            let points = match wrap_width_op {
+                Some(width) => line_wrap_points(lines, line_no, width, tab_width),
+                None => vec![0],
+            };
+
+            for (row_idx, &begin) in points.iter().enumerate() {
+                let is_last = row_idx + 1 == points.len();
+                let end = if is_last { line_len } else { points[row_idx + 1] };
+                rows.push(ScreenRow { line_no, begin, end, is_first: row_idx == 0, is_last });
+            }
+        }
+        rows.truncate(text_height);
 
         //index + INDEX_MARGIN ----------------------------------------------------------------
-        for line_no in
-            (self.position.y)..(cmp::min(lines.len_lines(), self.position.y + view_size.y))
-        {
+        for (y, row) in rows.iter().enumerate() {
+            let line_no = row.line_no;
             let mut x: usize = 0;
 
-            let y = line_no - self.position.y;
-            let line_desc = (line_no + 1).to_string();
-            let local_index_length = line_desc.len(); //logarithm? never heard of it.
+            let line_desc = if row.is_first {
+                (line_no + 1).to_string()
+            } else {
+                WRAP_MARKER.to_string()
+            };
+            let local_index_length = line_desc.chars().count(); //logarithm? never heard of it.
 
             printer.with_color(ColorStyle::secondary(), |printer| {
                 for _ in 0..(index_length - local_index_length) {
@@ -191,8 +324,18 @@ impl View for SlyTextView {
                 }
                 printer.print((x, y), &line_desc);
                 x += local_index_length;
-                for _ in 0..INDEX_MARGIN {
-                    printer.print((x, y), " ");
+                for margin_idx in 0..INDEX_MARGIN {
+                    // fold marker goes into the first column of margin.
+                    let symbol = if margin_idx > 0 || !row.is_first {
+                        " "
+                    } else if folds.folded_at(line_no).is_some() {
+                        FOLDED_MARKER
+                    } else if foldable_lines.contains(&line_no) {
+                        FOLDABLE_MARKER
+                    } else {
+                        " "
+                    };
+                    printer.print((x, y), symbol);
                     x += 1;
                 }
             });
@@ -203,43 +346,118 @@ impl View for SlyTextView {
 
         //line --------------------------------------------------------------------------------
 
-        for line_no in
-            (self.position.y)..(cmp::min(lines.len_lines(), self.position.y + view_size.y))
-        {
-            let y = line_no - self.position.y;
+        let settings = self.settings_ref();
+        let highlight_current_line = settings.get_bool("text_view/highlight_current_line");
+        let cursor_lines: HashSet<usize> = if highlight_current_line {
+            cursors.set().iter().map(|c| lines.char_to_line(c.a)).collect()
+        } else {
+            HashSet::new()
+        };
+        let current_line_background =
+            ColorType::Color(settings.get_color("theme/text_view/current_line_background_color"));
+        let matching_bracket_style = settings.get_colorstyle(
+            "theme/text_view/matching_bracket_text_color",
+            "theme/text_view/matching_bracket_background_color",
+        );
+        let matching_brackets = self.matching_brackets(&content);
+
+        let last_visible_line = visible_lines.last().map(|l| l + 1).unwrap_or(self.position.y);
+        let rainbow_colors: Vec<Color> = if settings.get_bool("text_view/rainbow_brackets") {
+            settings.get_color_list("theme/text_view/rainbow_bracket_colors")
+        } else {
+            vec![]
+        };
+        let bracket_depths: HashMap<usize, usize> = if rainbow_colors.is_empty() {
+            HashMap::new()
+        } else {
+            let end = if last_visible_line < lines.len_lines() {
+                lines.line_to_char(last_visible_line)
+            } else {
+                lines.len_chars()
+            };
+            let range = lines.line_to_char(self.position.y)..end;
+            self.brackets.borrow_mut().bracket_depths(
+                content.code_revision(),
+                lines,
+                range,
+                |pos| content.is_code_at(pos),
+            )
+        };
+
+        for (y, row) in rows.iter().enumerate() {
+            let line_no = row.line_no;
             let line_offset = &content.get_lines().line_to_char(line_no);
             let line = &content.get_lines().line(line_no);
+            // Can be stale (computed before recent edit) or missing, highlighting is done in
+            // background. We draw what we have, worker will wake us up when it has more.
             let rich_line_op = self.buffer.borrow_content().get_rich_line(line_no);
-
-            if rich_line_op.is_some()
-                && rich_line_op.as_ref().map(|r| r.get_line_no()).unwrap() != line_no
-            {
-                error!("rich line {:?}: {:?}", line_no, rich_line_op);
+            let line_string = line.to_string();
+            let trailing_begin = trailing_whitespace_begin(&line_string);
+            let indentation_end_op = indentation_end(&line_string);
+
+            // position after the last char of buffer is drawn as an empty cell.
+            let mut glyphs = layout_line(&line_string, tab_width);
+            if row.end > line.len_chars() {
+                let column = column_of(&glyphs, line.len_chars());
+                glyphs.push(Glyph { char_begin: line.len_chars(), char_len: 0, column, width: 1 });
             }
+            let row_column = column_of(&glyphs, row.begin);
+            let mut row_end_column = row_column;
 
-            //this allow a cursor *after* the last character. It's actually needed.
-            let add = if line_no == lines.len_lines() - 1 { 1 } else { 0 };
-
-            for char_idx in 0..(line.len_chars() + add) {
+            let row_glyphs =
+                glyphs.iter().filter(|g| g.char_begin >= row.begin && g.char_begin < row.end);
+            for glyph in row_glyphs {
+                let char_idx = glyph.char_begin;
                 let char_offset = line_offset + char_idx;
+                row_end_column = glyph.column_end();
 
+                let grapheme = line.slice(char_idx..glyph.char_end()).to_string();
+                let first_char_op = grapheme.chars().next();
                 let mut special_char = false;
-                let symbol: char = if line.len_chars() > char_idx {
-                    let c = line.char(char_idx);
-                    if !self.special_char_mappings.contains_key(&c) {
-                        c
-                    } else {
+                // whitespace drawn with a symbol has its own colour.
+                let mut whitespace_color_op: Option<Color> = None;
+                let is_indent_guide = whitespace.indent_guides()
+                    && indentation_end_op.map(|end| char_idx < end) == Some(true)
+                    && glyph.column % tab_width == 0;
+
+                let symbol: String = match (first_char_op, whitespace_kind(&grapheme)) {
+                    (None, _) => " ".to_string(),
+                    (Some(_), Some(_)) if is_indent_guide => {
+                        let (guide, color) = whitespace.indent_guide();
+                        whitespace_color_op = Some(color);
+                        format!("{}{}", guide, " ".repeat(glyph.width - 1))
+                    }
+                    (Some(_), Some(kind)) => {
+                        if whitespace.is_visible(char_idx, trailing_begin) {
+                            let (ws_symbol, color) = whitespace.symbol(kind);
+                            whitespace_color_op = Some(color);
+                            format!("{}{}", ws_symbol, " ".repeat(glyph.width - 1))
+                        } else {
+                            " ".repeat(glyph.width)
+                        }
+                    }
+                    (Some(c), None) if c.is_control() => {
                         special_char = true;
-                        self.special_char_mappings[&c]
+                        REPLACEMENT_SYMBOL.to_string()
                     }
-                } else {
-                    ' '
+                    // lone combining mark gets a base to sit on.
+                    (Some(_), None) if UnicodeWidthStr::width(grapheme.as_str()) == 0 => {
+                        format!("{}{}", COMBINING_BASE, grapheme)
+                    }
+                    (Some(_), None) => grapheme,
                 };
 
                 let color_style: ColorStyle = if self.had_cursor_at(&char_offset) {
                     ColorStyle::highlight()
+                } else if matching_brackets.contains(&char_offset) {
+                    matching_bracket_style
                 } else {
-                    if char_idx <= 80 && !special_char {
+                    let within_page = glyph.column <= PAGE_WIDTH || wrap_width_op.is_some();
+                    let mut someColor = if let Some(color) = whitespace_color_op {
+                        let mut someColor = ColorStyle::primary();
+                        someColor.front = ColorType::Color(color);
+                        someColor
+                    } else if within_page && !special_char {
                         let mut someColor = ColorStyle::primary();
 
                         match &rich_line_op {
@@ -254,7 +472,18 @@ impl View for SlyTextView {
                         someColor
                     } else {
                         ColorStyle::secondary()
+                    };
+
+                    if let Some(depth) = bracket_depths.get(&char_offset) {
+                        someColor.front =
+                            ColorType::Color(rainbow_colors[depth % rainbow_colors.len()]);
                     }
+
+                    if cursor_lines.contains(&line_no) {
+                        someColor.back = current_line_background;
+                    }
+
+                    someColor
                 };
 
                 // let effect = if self.cursors.contains(&char_offset) {
@@ -267,14 +496,41 @@ impl View for SlyTextView {
                 printer.with_color(color_style, |printer| {
                     printer.with_effect(effect, |printer| {
                         printer.print(
-                            (char_idx + index_length + INDEX_MARGIN, y),
-                            &symbol.to_string(),
+                            (glyph.column - row_column + index_length + INDEX_MARGIN, y),
+                            &symbol,
                         );
                     });
                 });
             }
+
+            let mut x = row_end_column - row_column + index_length + INDEX_MARGIN;
+
+            // folded region is represented by a placeholder after its header line.
+            if let Some(fold) = folds.folded_at(line_no).filter(|_| row.is_last) {
+                let placeholder =
+                    format!(" {} {} lines", FOLD_PLACEHOLDER, fold.num_hidden_lines());
+                printer.with_color(ColorStyle::secondary(), |printer| {
+                    printer.print((x, y), &placeholder);
+                });
+                x += placeholder.chars().count();
+            }
+
+            // current line background spans whole width of view, not only the text.
+            if cursor_lines.contains(&line_no) {
+                let mut style = ColorStyle::primary();
+                style.back = current_line_background;
+                printer.with_color(style, |printer| {
+                    for x in x..view_size.x {
+                        printer.print((x, y), " ");
+                    }
+                });
+            }
         }
         //end of line ------------------------------------------------------------------------
+
+        if view_size.y >= STATUS_BAR_HEIGHT {
+            self.draw_status_bar(printer, text_height, view_size.x);
+        }
     }
 
     fn required_size(&mut self, constraint: Vec2) -> Vec2 {
@@ -284,6 +540,8 @@ impl View for SlyTextView {
     }
 
     fn on_event(&mut self, event: Event) -> EventResult {
+        self.notice = None;
+
         let text_keybindings = self.settings_ref().get_keybindings("text");
         if text_keybindings.event_to_marker().contains_key(&event) {
             let action: &String = &text_keybindings.event_to_marker()[&event];
@@ -318,6 +576,72 @@ impl View for SlyTextView {
 
             let mut consumed = true;
             match action.as_str() {
+                "toggle_soft_wrap" => {
+                    let mut buffer_state = self.buffer.borrow_state();
+                    let old_value = buffer_state.soft_wrap();
+                    buffer_state.set_soft_wrap(!old_value);
+                }
+                "toggle_read_only" => {
+                    let read_only = self.buffer.read_only();
+                    if !self.buffer.borrow_state().set_read_only(!read_only) {
+                        self.notice = Some("binary buffers cannot be made writable".to_string());
+                    }
+                }
+                "convert_line_endings" if self.check_writable() => {
+                    let mut buffer_state = self.buffer.borrow_state();
+                    // cursors keep their (line, position in line), as offsets change.
+                    let positions: Vec<(usize, usize)> = {
+                        let rope = buffer_state.get_content().get_lines();
+                        self.cursor_set
+                            .set()
+                            .iter()
+                            .map(|c| {
+                                let line_no = rope.char_to_line(c.a);
+                                (line_no, c.a - rope.line_to_char(line_no))
+                            })
+                            .collect()
+                    };
+
+                    let target = buffer_state.line_ending().toggled();
+                    buffer_state.convert_line_endings(target);
+
+                    let rope = buffer_state.get_content().get_lines();
+                    let cursors: Vec<Cursor> = positions
+                        .into_iter()
+                        .map(|(line_no, idx)| {
+                            let begin = rope.line_to_char(line_no);
+                            cmp::min(begin + idx, line_end(rope, line_no)).into()
+                        })
+                        .collect();
+                    self.cursor_set = CursorSet::new(cursors);
+                }
+                // read-only buffer, notice is already set.
+                "convert_line_endings" => {}
+                "fold" | "unfold" => {
+                    let mut buffer_state = self.buffer.borrow_state();
+                    let rope = buffer_state.get_content().get_lines().clone();
+                    for c in self.cursor_set.set() {
+                        let line_no = rope.char_to_line(c.a);
+                        if action == "fold" {
+                            buffer_state.fold_at(line_no);
+                        } else {
+                            buffer_state.unfold_at(line_no);
+                        }
+                    }
+                    self.cursor_set.leave_folded_regions(&buffer_state);
+                }
+                "fold_all" => {
+                    let mut buffer_state = self.buffer.borrow_state();
+                    buffer_state.fold_all();
+                    self.cursor_set.leave_folded_regions(&buffer_state);
+                }
+                "unfold_all" => {
+                    self.buffer.borrow_state().unfold_all();
+                }
+                "jump_to_matching_bracket" => {
+                    let buffer_state = self.buffer.borrow_state();
+                    self.cursor_set.jump_to_matching_bracket(&buffer_state);
+                }
                 "toggle_syntax_highlighting" => {
                     debug!("toggle syntax highlight");
                     let old_value = self.syntax_highlighting_on();
@@ -339,37 +663,34 @@ impl View for SlyTextView {
                 &self.add_text(&c.to_string());
             }
             Event::Key(Key::Enter) => {
-                &self.add_text(&'\n'.to_string());
+                let newline = self.buffer.borrow_state().line_ending().newline().to_string();
+                &self.add_text(&newline);
             }
             Event::Key(Key::Backspace) => {
                 &self.backspace();
                 debug!("hit backspace");
             }
             Event::Key(Key::Left) => {
-                &self.cursor_set.move_left();
+                let buffer_state = self.buffer.borrow_state();
+                &self.cursor_set.move_left(&buffer_state);
             }
             Event::Key(Key::Right) => {
                 let buffer_state = self.buffer.borrow_state();
                 &self.cursor_set.move_right(&buffer_state);
             }
             Event::Key(Key::Up) => {
-                let buffer_state = self.buffer.borrow_state();
-                &self.cursor_set.move_vertically_by(&buffer_state, -1);
+                self.move_vertically_by(-1);
             }
             Event::Key(Key::Down) => {
-                let buffer_state = self.buffer.borrow_state();
-                &self.cursor_set.move_vertically_by(&buffer_state, 1);
+                self.move_vertically_by(1);
             }
             Event::Key(Key::PageUp) => {
                 let height = self.last_view_size.unwrap().y as isize;
-                let buffer_state = self.buffer.borrow_state();
-                &self.cursor_set.move_vertically_by(&buffer_state, -height);
-
+                self.move_vertically_by(-height);
             }
             Event::Key(Key::PageDown) => {
                 let height = self.last_view_size.unwrap().y as isize;
-                let buffer_state = self.buffer.borrow_state();
-                &self.cursor_set.move_vertically_by(&buffer_state, height);
+                self.move_vertically_by(height);
             }
             _ => {
                 debug!("unhandled event (in sly_text_view) {:?}", event);
@@ -386,8 +707,22 @@ impl View for SlyTextView {
 
 impl SlyTextView {
 
+    /// Moves cursors by l rows of screen, which are lines unless soft wrap is on.
+    fn move_vertically_by(&mut self, l: isize) {
+        let wrap_width_op = self.wrap_width(self.index_length());
+        let buffer_state = self.buffer.borrow_state();
+        match wrap_width_op {
+            Some(width) => self.cursor_set.move_visually_by(&buffer_state, l, width),
+            None => self.cursor_set.move_vertically_by(&buffer_state, l),
+        }
+    }
+
     // These are work-in-progress implementations.
     fn add_text(&mut self, text: &String) {
+        if !self.check_writable() {
+            return;
+        }
+
         let mut edit_events: Vec<EditEvent> = self
             .cursor_set
             .set()
@@ -405,25 +740,113 @@ impl SlyTextView {
     }
 
     // TODO(njskalski): fix, test
+    /// Removes grapheme cluster preceding each cursor.
     fn backspace(&mut self) {
-        let mut edit_events: Vec<EditEvent> = self
-            .cursor_set
-            .set()
-            .iter()
-            .filter(|&cursor| cursor.a > 0)
-            .map(|ref cursor| EditEvent::Change {
-                offset: cursor.a - 1,
-                length: 1,
-                content: "".to_string(),
-            })
-            .collect();
+        if !self.check_writable() {
+            return;
+        }
+
+        let mut edit_events: Vec<EditEvent> = {
+            let content = self.buffer.borrow_content();
+            let rope = content.get_lines();
+            self.cursor_set
+                .set()
+                .iter()
+                .filter(|&cursor| cursor.a > 0)
+                .map(|ref cursor| {
+                    let begin = prev_grapheme_boundary(rope, cursor.a);
+                    EditEvent::Change {
+                        offset: begin,
+                        length: cursor.a - begin,
+                        content: "".to_string(),
+                    }
+                })
+                .collect()
+        };
 
-        self.cursor_set.move_left();
+        {
+            let buffer_state = self.buffer.borrow_state();
+            self.cursor_set.move_left(&buffer_state);
+        }
 
         edit_events.reverse();
         self.submit_events(edit_events);
     }
 
+    /// Draws a single row with name of the buffer, its flags, position of the first cursor and
+    /// notice (if any).
+    fn draw_status_bar(&self, printer: &Printer, y: usize, width: usize) {
+        let name = self
+            .buffer
+            .get_filename()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or("[no name]".to_string());
+        let modified = if self.buffer.modified() { " *" } else { "" };
+        let read_only = if self.buffer.is_loading() {
+            " [loading]"
+        } else if self.buffer.read_only() {
+            " [RO]"
+        } else {
+            ""
+        };
+        let out_of_sync = if self.buffer.out_of_sync() { " [changed on disk]" } else { "" };
+
+        let position = match self.cursor_set.set().first() {
+            Some(cursor) => {
+                let content = self.buffer.borrow_content();
+                let rope = content.get_lines();
+                let offset = cmp::min(cursor.a, rope.len_chars());
+                let line_no = rope.char_to_line(offset);
+                let glyphs = layout_rope_line(rope, line_no, self.buffer.tab_width());
+                let column = column_of(&glyphs, offset - rope.line_to_char(line_no));
+                format!("{}:{}", line_no + 1, column + 1)
+            }
+            None => String::new(),
+        };
+
+        let left = format!(" {}{}{}{}", name, modified, read_only, out_of_sync);
+        let right = format!("{}  {} ", position, self.buffer.line_ending().name());
+
+        // status bar of the focused editor stands out when there are more of them (split panes).
+        let style =
+            if printer.focused { ColorStyle::title_primary() } else { ColorStyle::secondary() };
+        printer.with_color(style, |printer| {
+            printer.print_hline((0, y), width, " ");
+            printer.print((0, y), &left);
+            let right_x = width.saturating_sub(right.chars().count());
+            printer.print((right_x, y), &right);
+        });
+
+        if let Some(ref notice) = self.notice {
+            let x = left.chars().count() + 2;
+            printer.with_color(ColorStyle::highlight(), |printer| {
+                printer.print((x, y), &format!(" {} ", notice));
+            });
+        }
+    }
+
+    /// Returns offsets of brackets under cursors together with their matching pairs. Brackets with
+    /// no pair are not highlighted.
+    fn matching_brackets(&self, content: &RopeBasedContentProvider) -> HashSet<usize> {
+        let rope = content.get_lines();
+        let revision = content.code_revision();
+        let mut brackets = self.brackets.borrow_mut();
+        let mut result: HashSet<usize> = HashSet::new();
+
+        for c in self.cursor_set.set() {
+            if let Some(bracket) = bracket_near_anchor(rope, c.a) {
+                if let Some(matching) = brackets.matching_bracket(revision, rope, bracket, |pos| {
+                    content.is_code_at(pos)
+                }) {
+                    result.insert(bracket);
+                    result.insert(matching);
+                }
+            }
+        }
+
+        result
+    }
+
     // TODO(njskalski): color not only anchor, but also scope.
     fn had_cursor_at(&self, offset: &usize) -> bool {
         for c in self.cursor_set.set() {
@@ -0,0 +1,522 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// SplitView holds editors visible at the same time (panes). Space is divided recursively: a split
+// gives share percent of its space to the first part and the rest to the second one, so panes are
+// leaves of a binary tree (PaneLayout). Panes have their own ids, so an editor displayed in a pane
+// can be swapped without touching the layout.
+//
+// Exactly one pane is focused, its editor is the "active editor" of Interface and gets keyboard
+// events. Mouse click focuses pane under it. Panes side by side are separated with a vertical
+// line, panes one above another are separated by status bar of the upper one.
+//
+// Optionally a TabBar takes the first row, above all panes.
+
+use cursive::direction::Direction;
+use cursive::event::{Event, EventResult, MouseEvent};
+use cursive::theme::ColorStyle;
+use cursive::vec::Vec2;
+use cursive::view::{Selector, View};
+use cursive::views::{IdView, ViewRef};
+use cursive::Printer;
+use std::any::Any;
+use std::cmp;
+
+use crate::sly_text_view::SlyTextView;
+use crate::sly_view::SlyView;
+use crate::tab_bar::TabBar;
+use crate::view_handle::ViewHandle;
+
+const DEFAULT_SHARE: usize = 50;
+const MIN_SHARE: usize = 10;
+const MAX_SHARE: usize = 90;
+const SEPARATOR: &'static str = "\u{2502}";
+const SEPARATOR_WIDTH: usize = 1;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum SplitOrientation {
+    /// Parts side by side.
+    Vertical,
+    /// Parts one above another.
+    Horizontal,
+}
+
+/// Arrangement of panes, leaves are pane ids.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PaneLayout {
+    Pane(ViewHandle),
+    Split {
+        orientation: SplitOrientation,
+        share: usize, // percent of space taken by first.
+        first: Box<PaneLayout>,
+        second: Box<PaneLayout>,
+    },
+}
+
+impl PaneLayout {
+    /// Returns ids of panes, left to right and top to bottom.
+    pub fn pane_ids(&self) -> Vec<ViewHandle> {
+        match self {
+            PaneLayout::Pane(id) => vec![id.clone()],
+            PaneLayout::Split { first, second, .. } => {
+                let mut result = first.pane_ids();
+                result.append(&mut second.pane_ids());
+                result
+            }
+        }
+    }
+
+    fn contains(&self, id: &ViewHandle) -> bool {
+        self.pane_ids().contains(id)
+    }
+
+    /// Divides pane id into two, id being the first part. Returns false if there is no such pane.
+    fn split_pane(
+        &mut self,
+        id: &ViewHandle,
+        orientation: SplitOrientation,
+        new_id: &ViewHandle,
+    ) -> bool {
+        if *self == PaneLayout::Pane(id.clone()) {
+            *self = PaneLayout::Split {
+                orientation,
+                share: DEFAULT_SHARE,
+                first: Box::new(PaneLayout::Pane(id.clone())),
+                second: Box::new(PaneLayout::Pane(new_id.clone())),
+            };
+            return true;
+        }
+
+        match self {
+            PaneLayout::Pane(_) => false,
+            PaneLayout::Split { first, second, .. } => {
+                first.split_pane(id, orientation, new_id)
+                    || second.split_pane(id, orientation, new_id)
+            }
+        }
+    }
+
+    /// Removes pane id, its sibling takes place of their split. The only pane cannot be removed.
+    fn remove_pane(&mut self, id: &ViewHandle) -> bool {
+        let pane = PaneLayout::Pane(id.clone());
+        let sibling_op = match self {
+            PaneLayout::Pane(_) => return false,
+            PaneLayout::Split { first, second, .. } => {
+                if **first == pane {
+                    Some((**second).clone())
+                } else if **second == pane {
+                    Some((**first).clone())
+                } else {
+                    None
+                }
+            }
+        };
+
+        if let Some(sibling) = sibling_op {
+            *self = sibling;
+            return true;
+        }
+
+        match self {
+            PaneLayout::Pane(_) => false,
+            PaneLayout::Split { first, second, .. } => {
+                first.remove_pane(id) || second.remove_pane(id)
+            }
+        }
+    }
+
+    /// Makes pane id delta percent bigger (or smaller, if delta is negative) within the innermost
+    /// split it's a part of. Returns false if pane is not split.
+    fn resize_pane(&mut self, id: &ViewHandle, delta: isize) -> bool {
+        match self {
+            PaneLayout::Pane(_) => false,
+            PaneLayout::Split { share, first, second, .. } => {
+                let (inner, sign) = if first.contains(id) {
+                    (first.resize_pane(id, delta), 1)
+                } else if second.contains(id) {
+                    (second.resize_pane(id, delta), -1)
+                } else {
+                    return false;
+                };
+
+                if !inner {
+                    let new_share = *share as isize + sign * delta;
+                    *share = cmp::min(cmp::max(new_share, MIN_SHARE as isize), MAX_SHARE as isize)
+                        as usize;
+                }
+                true
+            }
+        }
+    }
+
+    /// Computes (id, offset, size) of panes and (offset, height) of separators, for layout
+    /// occupying size at offset.
+    fn place(
+        &self,
+        offset: Vec2,
+        size: Vec2,
+        panes: &mut Vec<(ViewHandle, Vec2, Vec2)>,
+        separators: &mut Vec<(Vec2, usize)>,
+    ) {
+        match self {
+            PaneLayout::Pane(id) => panes.push((id.clone(), offset, size)),
+            PaneLayout::Split { orientation, share, first, second }
+                if *orientation == SplitOrientation::Vertical =>
+            {
+                let available = size.x.saturating_sub(SEPARATOR_WIDTH);
+                let first_width = available * share / 100;
+                first.place(offset, Vec2::new(first_width, size.y), panes, separators);
+                separators.push((offset + (first_width, 0), size.y));
+                second.place(
+                    offset + (first_width + SEPARATOR_WIDTH, 0),
+                    Vec2::new(available - first_width, size.y),
+                    panes,
+                    separators,
+                );
+            }
+            PaneLayout::Split { share, first, second, .. } => {
+                let first_height = size.y * share / 100;
+                first.place(offset, Vec2::new(size.x, first_height), panes, separators);
+                second.place(
+                    offset + (0, first_height),
+                    Vec2::new(size.x, size.y - first_height),
+                    panes,
+                    separators,
+                );
+            }
+        }
+    }
+}
+
+struct Pane {
+    id: ViewHandle,
+    editor: IdView<SlyTextView>,
+    offset: Vec2,
+    size: Vec2,
+}
+
+impl Pane {
+    fn new(id: ViewHandle, editor: IdView<SlyTextView>) -> Self {
+        Pane { id, editor, offset: Vec2::zero(), size: Vec2::zero() }
+    }
+
+    fn covers(&self, position: Vec2) -> bool {
+        position.fits(self.offset) && (self.offset + self.size).fits(position + (1, 1))
+    }
+}
+
+pub struct SplitView {
+    handle: ViewHandle,
+    layout: PaneLayout,
+    panes: Vec<Pane>,
+    focused: ViewHandle,
+    separators: Vec<(Vec2, usize)>,
+    tab_bar: Option<TabBar>,
+}
+
+impl SlyView for SplitView {
+    fn handle(&self) -> ViewHandle {
+        self.handle.clone()
+    }
+}
+
+impl SplitView {
+    pub fn new(editor: IdView<SlyTextView>) -> IdView<Self> {
+        let pane_id = ViewHandle::new();
+        let view = SplitView {
+            handle: ViewHandle::new(),
+            layout: PaneLayout::Pane(pane_id.clone()),
+            panes: vec![Pane::new(pane_id.clone(), editor)],
+            focused: pane_id,
+            separators: Vec::new(),
+            tab_bar: None,
+        };
+        IdView::new(view.handle(), view)
+    }
+
+    /// Shows (Some) or hides (None) the tab bar.
+    pub fn set_tab_bar(&mut self, tab_bar: Option<TabBar>) {
+        self.tab_bar = tab_bar;
+    }
+
+    pub fn tab_bar_mut(&mut self) -> Option<&mut TabBar> {
+        self.tab_bar.as_mut()
+    }
+
+    fn panes_offset(&self) -> Vec2 {
+        if self.tab_bar.is_some() {
+            Vec2::new(0, 1)
+        } else {
+            Vec2::zero()
+        }
+    }
+
+    pub fn pane_layout(&self) -> &PaneLayout {
+        &self.layout
+    }
+
+    pub fn num_panes(&self) -> usize {
+        self.panes.len()
+    }
+
+    /// Returns id of focused pane.
+    pub fn focused(&self) -> ViewHandle {
+        self.focused.clone()
+    }
+
+    fn focused_idx(&self) -> usize {
+        self.panes.iter().position(|pane| pane.id == self.focused).unwrap()
+    }
+
+    pub fn focused_editor(&mut self) -> ViewRef<SlyTextView> {
+        let idx = self.focused_idx();
+        self.panes[idx].editor.get_mut()
+    }
+
+    /// Returns (pane id, editor) of all panes, left to right and top to bottom.
+    pub fn editors(&mut self) -> Vec<(ViewHandle, ViewRef<SlyTextView>)> {
+        let mut result = Vec::new();
+        for id in self.layout.pane_ids() {
+            let pane = self.panes.iter_mut().find(|pane| pane.id == id).unwrap();
+            result.push((id, pane.editor.get_mut()));
+        }
+        result
+    }
+
+    /// Focuses pane id, returns false if there is none.
+    pub fn focus(&mut self, id: &ViewHandle) -> bool {
+        if self.panes.iter().any(|pane| pane.id == *id) {
+            self.focused = id.clone();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Focuses next (or previous) pane, in order of pane_ids, wrapping around.
+    pub fn focus_neighbour(&mut self, forward: bool) {
+        let ids = self.layout.pane_ids();
+        let idx = ids.iter().position(|id| *id == self.focused).unwrap();
+        let next = if forward { (idx + 1) % ids.len() } else { (idx + ids.len() - 1) % ids.len() };
+        self.focused = ids[next].clone();
+    }
+
+    /// Displays editor in focused pane, returns the one displayed there before.
+    pub fn replace_focused(&mut self, editor: IdView<SlyTextView>) -> IdView<SlyTextView> {
+        let idx = self.focused_idx();
+        std::mem::replace(&mut self.panes[idx].editor, editor)
+    }
+
+    /// Divides focused pane in two, editor is displayed in the new (second) part and gets focus.
+    pub fn split_focused(&mut self, orientation: SplitOrientation, editor: IdView<SlyTextView>) {
+        let new_id = ViewHandle::new();
+        self.layout.split_pane(&self.focused, orientation, &new_id);
+        self.panes.push(Pane::new(new_id.clone(), editor));
+        self.focused = new_id;
+    }
+
+    /// Removes focused pane and returns its editor, neighbouring pane gets focus. The last pane
+    /// cannot be closed, None is returned then.
+    pub fn close_focused(&mut self) -> Option<IdView<SlyTextView>> {
+        if self.panes.len() == 1 {
+            return None;
+        }
+        let closed = self.focused.clone();
+        self.focus_neighbour(false);
+        self.layout.remove_pane(&closed);
+        let idx = self.panes.iter().position(|pane| pane.id == closed).unwrap();
+        Some(self.panes.remove(idx).editor)
+    }
+
+    /// Makes focused pane delta percent bigger (or smaller) within its split.
+    pub fn resize_focused(&mut self, delta: isize) -> bool {
+        let focused = self.focused.clone();
+        self.layout.resize_pane(&focused, delta)
+    }
+
+    /// Replaces all panes with (pane id, editor) arranged in layout, returns editors of old ones.
+    pub fn set_layout(
+        &mut self,
+        layout: PaneLayout,
+        editors: Vec<(ViewHandle, IdView<SlyTextView>)>,
+        focused: ViewHandle,
+    ) -> Vec<IdView<SlyTextView>> {
+        let old_panes = std::mem::replace(
+            &mut self.panes,
+            editors.into_iter().map(|(id, editor)| Pane::new(id, editor)).collect(),
+        );
+        self.layout = layout;
+        self.focused = focused;
+        old_panes.into_iter().map(|pane| pane.editor).collect()
+    }
+}
+
+impl View for SplitView {
+    fn draw(&self, printer: &Printer) {
+        if let Some(tab_bar) = self.tab_bar.as_ref() {
+            tab_bar.draw(&printer.cropped((printer.size.x, 1)));
+        }
+
+        for pane in self.panes.iter() {
+            let is_focused = printer.focused && pane.id == self.focused;
+            pane.editor.draw(&printer.offset(pane.offset).cropped(pane.size).focused(is_focused));
+        }
+
+        printer.with_color(ColorStyle::secondary(), |printer| {
+            for (offset, height) in self.separators.iter() {
+                printer.print_vline(*offset, *height, SEPARATOR);
+            }
+        });
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        constraint // all available space, like SlyTextView
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        let mut placed: Vec<(ViewHandle, Vec2, Vec2)> = Vec::new();
+        self.separators.clear();
+        let panes_offset = self.panes_offset();
+        if let Some(tab_bar) = self.tab_bar.as_mut() {
+            tab_bar.layout(Vec2::new(size.x, 1));
+        }
+        let panes_size = size.saturating_sub(panes_offset);
+        self.layout.place(panes_offset, panes_size, &mut placed, &mut self.separators);
+
+        for (id, offset, size) in placed {
+            if let Some(pane) = self.panes.iter_mut().find(|pane| pane.id == id) {
+                pane.offset = offset;
+                pane.size = size;
+                pane.editor.required_size(size);
+                pane.editor.layout(size);
+            }
+        }
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        // mouse events go to pane under pointer, click focuses it.
+        if let Event::Mouse { offset, position, event: mouse_event } = event {
+            if !position.fits(offset) {
+                return EventResult::Ignored;
+            }
+            let local = position - offset;
+            let pane_op = self.panes.iter_mut().find(|pane| pane.covers(local));
+            return match pane_op {
+                Some(pane) => {
+                    if let MouseEvent::Press(_) = mouse_event {
+                        self.focused = pane.id.clone();
+                    }
+                    pane.editor.on_event(Event::Mouse {
+                        offset: offset + pane.offset,
+                        position,
+                        event: mouse_event,
+                    })
+                }
+                None => EventResult::Ignored,
+            };
+        }
+
+        let idx = self.focused_idx();
+        self.panes[idx].editor.on_event(event)
+    }
+
+    fn call_on_any<'a>(&mut self, selector: &Selector, mut callback: Box<FnMut(&mut Any) + 'a>) {
+        for pane in self.panes.iter_mut() {
+            pane.editor.call_on_any(selector, Box::new(|any| callback(any)));
+        }
+    }
+
+    fn focus_view(&mut self, selector: &Selector) -> Result<(), ()> {
+        for pane in self.panes.iter() {
+            if let Selector::Id(id) = selector {
+                if pane.editor.handle().to_string() == *id {
+                    self.focused = pane.id.clone();
+                    return Ok(());
+                }
+            }
+        }
+        Err(())
+    }
+
+    fn take_focus(&mut self, _source: Direction) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(n: usize) -> Vec<ViewHandle> {
+        (0..n).map(|_| ViewHandle::new()).collect()
+    }
+
+    #[test]
+    fn split_and_remove_panes() {
+        let ids = ids(3);
+        let mut layout = PaneLayout::Pane(ids[0].clone());
+        assert!(layout.split_pane(&ids[0], SplitOrientation::Vertical, &ids[1]));
+        assert!(layout.split_pane(&ids[0], SplitOrientation::Horizontal, &ids[2]));
+        assert_eq!(layout.pane_ids(), vec![ids[0].clone(), ids[2].clone(), ids[1].clone()]);
+
+        assert!(layout.remove_pane(&ids[0]));
+        assert_eq!(layout.pane_ids(), vec![ids[2].clone(), ids[1].clone()]);
+        assert!(layout.remove_pane(&ids[1]));
+        assert_eq!(layout, PaneLayout::Pane(ids[2].clone()));
+        assert!(!layout.remove_pane(&ids[2]));
+    }
+
+    #[test]
+    fn place_side_by_side_and_stacked() {
+        let ids = ids(3);
+        let mut layout = PaneLayout::Pane(ids[0].clone());
+        layout.split_pane(&ids[0], SplitOrientation::Vertical, &ids[1]);
+        layout.split_pane(&ids[1], SplitOrientation::Horizontal, &ids[2]);
+
+        let mut panes = Vec::new();
+        let mut separators = Vec::new();
+        layout.place(Vec2::zero(), Vec2::new(81, 20), &mut panes, &mut separators);
+
+        assert_eq!(panes[0], (ids[0].clone(), Vec2::new(0, 0), Vec2::new(40, 20)));
+        assert_eq!(separators, vec![(Vec2::new(40, 0), 20)]);
+        assert_eq!(panes[1], (ids[1].clone(), Vec2::new(41, 0), Vec2::new(40, 10)));
+        assert_eq!(panes[2], (ids[2].clone(), Vec2::new(41, 10), Vec2::new(40, 10)));
+    }
+
+    #[test]
+    fn resize_changes_innermost_split() {
+        let ids = ids(3);
+        let mut layout = PaneLayout::Pane(ids[0].clone());
+        assert!(!layout.resize_pane(&ids[0], 5));
+
+        layout.split_pane(&ids[0], SplitOrientation::Vertical, &ids[1]);
+        layout.split_pane(&ids[1], SplitOrientation::Horizontal, &ids[2]);
+        // growing the second part shrinks the first one.
+        assert!(layout.resize_pane(&ids[2], 5));
+        assert!(layout.resize_pane(&ids[0], 100));
+
+        match layout {
+            PaneLayout::Split { share, second, .. } => {
+                assert_eq!(share, MAX_SHARE);
+                match *second {
+                    PaneLayout::Split { share, .. } => assert_eq!(share, DEFAULT_SHARE - 5),
+                    _ => panic!("expected split"),
+                }
+            }
+            _ => panic!("expected split"),
+        }
+    }
+}
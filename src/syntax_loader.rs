@@ -0,0 +1,238 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// SyntaxLoader owns the SyntaxSet (syntect defaults plus whatever user dropped into config
+// directory) and decides which syntax should be used for given file.
+//
+// Detection order:
+// 1) user overrides (settings "syntax/overrides"), by file name first, then by extension,
+// 2) well known file names (settings "syntax/filenames"), like Makefile or Cargo.lock,
+// 3) file extension,
+// 4) first line of the file: syntect's first_line_match (it covers most shebangs and things like
+//    "<?xml"), and then interpreter name taken from shebang ("#!/usr/bin/env python3").
+
+use crate::rich_content::HighlightSettings;
+use crate::settings::Settings;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+const THEME_NAME: &'static str = "base16-ocean.dark";
+pub const PLAIN_TEXT_SYNTAX: &'static str = "Plain Text";
+
+pub struct SyntaxLoader {
+    syntax_set: Arc<SyntaxSet>,
+    theme: Theme,
+    filenames: HashMap<String, Vec<String>>,
+    overrides: HashMap<String, Vec<String>>,
+}
+
+impl SyntaxLoader {
+    /// Loads default syntaxes, and if user_syntax_dir is provided, all .sublime-syntax files
+    /// found in it. Broken user definitions are logged and skipped, they never prevent startup.
+    pub fn new(settings: &Settings, user_syntax_dir: Option<&Path>) -> Self {
+        let defaults = SyntaxSet::load_defaults_newlines();
+
+        let syntax_set = match user_syntax_dir {
+            Some(dir) if dir.is_dir() => {
+                let mut builder = defaults.clone().into_builder();
+                match builder.add_from_folder(dir, true) {
+                    Ok(()) => {
+                        debug!("loaded user syntaxes from {:?}", dir);
+                        builder.build()
+                    }
+                    Err(e) => {
+                        error!("failed loading user syntaxes from {:?}: {:?}", dir, e);
+                        defaults
+                    }
+                }
+            }
+            _ => defaults,
+        };
+
+        let theme = ThemeSet::load_defaults().themes[THEME_NAME].clone();
+
+        SyntaxLoader {
+            syntax_set: Arc::new(syntax_set),
+            theme,
+            filenames: settings.get_syntax_map("syntax/filenames"),
+            overrides: settings.get_syntax_map("syntax/overrides"),
+        }
+    }
+
+    /// Returns names of all known syntaxes, sorted.
+    pub fn syntax_names(&self) -> Vec<String> {
+        let mut names: Vec<String> =
+            self.syntax_set.syntaxes().iter().map(|s| s.name.clone()).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<Arc<HighlightSettings>> {
+        self.syntax_set.find_syntax_by_name(name).map(|syntax| self.highlight_settings(syntax))
+    }
+
+    /// Figures out syntax for given file. first_line is expected to be the first line of the
+    /// file, if it's available.
+    pub fn detect(
+        &self,
+        path_op: Option<&Path>,
+        first_line_op: Option<&str>,
+    ) -> Option<Arc<HighlightSettings>> {
+        self.detect_syntax(path_op, first_line_op)
+            .filter(|syntax| syntax.name != PLAIN_TEXT_SYNTAX)
+            .map(|syntax| self.highlight_settings(syntax))
+    }
+
+    fn detect_syntax(
+        &self,
+        path_op: Option<&Path>,
+        first_line_op: Option<&str>,
+    ) -> Option<&SyntaxReference> {
+        let filename_op: Option<String> =
+            path_op.and_then(|p| p.file_name()).map(|f| f.to_string_lossy().to_string());
+        let extension_op: Option<String> =
+            path_op.and_then(|p| p.extension()).map(|e| e.to_string_lossy().to_string());
+
+        if let Some(ref filename) = filename_op {
+            if let Some(syntax) = self.first_known(self.overrides.get(filename)) {
+                return Some(syntax);
+            }
+        }
+
+        if let Some(ref extension) = extension_op {
+            if let Some(syntax) = self.first_known(self.overrides.get(extension)) {
+                return Some(syntax);
+            }
+        }
+
+        if let Some(ref filename) = filename_op {
+            if let Some(syntax) = self.first_known(self.filenames.get(filename)) {
+                return Some(syntax);
+            }
+        }
+
+        if let Some(ref extension) = extension_op {
+            if let Some(syntax) = self.syntax_set.find_syntax_by_extension(extension) {
+                return Some(syntax);
+            }
+        }
+
+        let first_line = first_line_op?;
+
+        if let Some(syntax) = self.syntax_set.find_syntax_by_first_line(first_line) {
+            return Some(syntax);
+        }
+
+        shebang_interpreter(first_line)
+            .and_then(|interpreter| self.syntax_set.find_syntax_by_token(&interpreter))
+    }
+
+    fn first_known(&self, names_op: Option<&Vec<String>>) -> Option<&SyntaxReference> {
+        for name in names_op? {
+            if let Some(syntax) = self.syntax_set.find_syntax_by_name(name) {
+                return Some(syntax);
+            }
+            debug!("syntax {:?} is not known, skipping.", name);
+        }
+        None
+    }
+
+    fn highlight_settings(&self, syntax: &SyntaxReference) -> Arc<HighlightSettings> {
+        Arc::new(HighlightSettings::new(
+            syntax.clone(),
+            self.syntax_set.clone(),
+            self.theme.clone(),
+        ))
+    }
+}
+
+/// Returns name of interpreter from shebang line, with version suffix removed, so
+/// "#!/usr/bin/env python3" gives "python", and "#!/bin/bash -e" gives "bash".
+pub fn shebang_interpreter(first_line: &str) -> Option<String> {
+    if !first_line.starts_with("#!") {
+        return None;
+    }
+
+    let mut words = first_line[2..].split_whitespace();
+    let mut program: &str = words.next()?;
+    program = program.rsplit('/').next()?;
+
+    if program == "env" {
+        // skipping options of env, like "env -S".
+        program = words.skip_while(|w| w.starts_with('-')).next()?;
+    }
+
+    let name = program.trim_end_matches(|c: char| c.is_digit(10) || c == '.');
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loader() -> SyntaxLoader {
+        SyntaxLoader::new(&Settings::load_default(), None)
+    }
+
+    fn detected_name(path: &str, first_line: Option<&str>) -> Option<String> {
+        loader()
+            .detect(Some(Path::new(path)), first_line)
+            .map(|hs| hs.syntax_name().to_string())
+    }
+
+    #[test]
+    fn shebang_interpreter_test() {
+        assert_eq!(shebang_interpreter("#!/bin/bash"), Some("bash".to_string()));
+        assert_eq!(shebang_interpreter("#!/bin/bash -e"), Some("bash".to_string()));
+        assert_eq!(shebang_interpreter("#!/usr/bin/env python3"), Some("python".to_string()));
+        assert_eq!(shebang_interpreter("#!/usr/bin/env -S perl -w"), Some("perl".to_string()));
+        assert_eq!(shebang_interpreter("#!/usr/bin/python2.7"), Some("python".to_string()));
+        assert_eq!(shebang_interpreter("# just a comment"), None);
+        assert_eq!(shebang_interpreter("#!"), None);
+    }
+
+    #[test]
+    fn detects_by_extension() {
+        assert_eq!(detected_name("/home/laura/main.rs", None), Some("Rust".to_string()));
+    }
+
+    #[test]
+    fn detects_by_filename() {
+        assert_eq!(detected_name("/home/laura/Makefile", None), Some("Makefile".to_string()));
+    }
+
+    #[test]
+    fn detects_by_shebang() {
+        assert_eq!(
+            detected_name("/home/laura/script", Some("#!/usr/bin/env python3\n")),
+            Some("Python".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_file_has_no_syntax() {
+        assert_eq!(detected_name("/home/laura/file1", Some("some text\n")), None);
+    }
+}
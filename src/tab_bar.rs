@@ -0,0 +1,119 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// A single row above editors with a tab per open buffer (file name and modified marker), in order
+// of opening. Tab of the active buffer is highlighted. If tabs do not fit, the strip is scrolled so
+// the active one is visible.
+//
+// It's display only: Interface sets the tabs, switching buffers is done with commands.
+
+use cursive::theme::ColorStyle;
+use cursive::vec::Vec2;
+use cursive::view::View;
+use cursive::Printer;
+
+use crate::buffer_id::BufferId;
+use crate::buffer_state_observer::BufferStateObserver;
+
+const MODIFIED_MARKER: &'static str = " *";
+
+pub struct TabBar {
+    tabs: Vec<BufferStateObserver>,
+    active: Option<BufferId>,
+}
+
+impl TabBar {
+    pub fn new() -> Self {
+        TabBar { tabs: Vec::new(), active: None }
+    }
+
+    pub fn set_tabs(&mut self, tabs: Vec<BufferStateObserver>, active: BufferId) {
+        self.tabs = tabs;
+        self.active = Some(active);
+    }
+
+    fn label(buffer: &BufferStateObserver) -> String {
+        let name = buffer
+            .get_filename()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or("[no name]".to_string());
+        format!(" {}{} ", name, if buffer.modified() { MODIFIED_MARKER } else { "" })
+    }
+}
+
+/// Returns index of the first tab to draw, so that tab active (and as many before it as possible)
+/// fits in width.
+fn first_visible(widths: &[usize], active: usize, width: usize) -> usize {
+    let mut first = 0;
+    let mut used: usize = widths[..=active].iter().sum();
+    while used > width && first < active {
+        used -= widths[first];
+        first += 1;
+    }
+    first
+}
+
+impl View for TabBar {
+    fn draw(&self, printer: &Printer) {
+        let labels: Vec<String> = self.tabs.iter().map(TabBar::label).collect();
+        let active_idx_op =
+            self.tabs.iter().position(|tab| Some(tab.buffer_id()) == self.active);
+
+        printer.with_color(ColorStyle::secondary(), |printer| {
+            printer.print_hline((0, 0), printer.size.x, " ");
+        });
+
+        let widths: Vec<usize> = labels.iter().map(|label| label.chars().count()).collect();
+        let first = match active_idx_op {
+            Some(active_idx) => first_visible(&widths, active_idx, printer.size.x),
+            None => 0,
+        };
+
+        let mut x: usize = 0;
+        for (idx, label) in labels.iter().enumerate().skip(first) {
+            if x >= printer.size.x {
+                break;
+            }
+            let style = if Some(idx) == active_idx_op {
+                ColorStyle::highlight()
+            } else {
+                ColorStyle::secondary()
+            };
+            printer.with_color(style, |printer| {
+                printer.print((x, 0), label);
+            });
+            x += widths[idx];
+        }
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        Vec2::new(constraint.x, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_tab_is_scrolled_into_view() {
+        let widths = vec![10, 10, 10, 10];
+        assert_eq!(first_visible(&widths, 1, 25), 0);
+        assert_eq!(first_visible(&widths, 3, 25), 2);
+        // too wide to fit at all, still drawn from its beginning.
+        assert_eq!(first_visible(&widths, 2, 5), 2);
+    }
+}
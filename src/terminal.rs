@@ -0,0 +1,73 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Sly can be a part of a pipeline ("cargo build 2>&1 | sly - --stdout | less"). Cursive talks to
+// the user over standard input and output, so when these are pipes, they are replaced with the
+// terminal (/dev/tty) before Cursive starts. Pipes are read or kept for later.
+
+use std::fs::File;
+use std::io;
+
+/// Reads whole standard input and replaces it with the terminal, so Cursive can read keys.
+#[cfg(unix)]
+pub fn read_stdin_and_reopen_tty() -> Result<Vec<u8>, io::Error> {
+    use std::io::Read;
+
+    let mut bytes: Vec<u8> = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    replace_fd_with_tty(libc::STDIN_FILENO)?;
+    Ok(bytes)
+}
+
+/// If standard output is not a terminal, replaces it with one (so Cursive draws there) and
+/// returns the original one. Returns None if standard output is a terminal already.
+#[cfg(unix)]
+pub fn redirect_stdout_to_tty() -> Result<Option<File>, io::Error> {
+    use std::os::unix::io::FromRawFd;
+
+    if unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1 {
+        return Ok(None);
+    }
+
+    let original = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if original < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let original = unsafe { File::from_raw_fd(original) };
+    replace_fd_with_tty(libc::STDOUT_FILENO)?;
+    Ok(Some(original))
+}
+
+#[cfg(unix)]
+fn replace_fd_with_tty(fd: libc::c_int) -> Result<(), io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let tty = std::fs::OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    if unsafe { libc::dup2(tty.as_raw_fd(), fd) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn read_stdin_and_reopen_tty() -> Result<Vec<u8>, io::Error> {
+    Err(io::Error::new(io::ErrorKind::Other, "reading standard input is not supported here."))
+}
+
+#[cfg(not(unix))]
+pub fn redirect_stdout_to_tty() -> Result<Option<File>, io::Error> {
+    Ok(None)
+}
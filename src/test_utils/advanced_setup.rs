@@ -52,6 +52,24 @@ pub mod tests {
 
     impl AdvancedSetup {
         pub fn with_files(files_to_open: Vec<&str>) -> Self {
+            Self::with_stdin_and_files(None, files_to_open)
+        }
+
+        /// Like with_files, with text read from standard input ("-") opened first.
+        pub fn with_stdin_and_files(stdin_op: Option<&str>, files_to_open: Vec<&str>) -> Self {
+            Self::with_prepared_state(files_to_open, |app_state| {
+                if let Some(stdin) = stdin_op {
+                    app_state.set_stdin_text(stdin.to_string());
+                }
+            })
+        }
+
+        /// Like with_files, prepare is called on AppState (with fake tree in its filesystem)
+        /// before interface starts, so before first files are opened and indexing begins.
+        pub fn with_prepared_state<F: FnOnce(&mut AppState)>(
+            files_to_open: Vec<&str>,
+            prepare: F,
+        ) -> Self {
             let basicSetup = BasicSetupSetupStruct::new();
 
             let (sender, receiver) = mpsc::channel::<IEvent>();
@@ -74,16 +92,17 @@ pub mod tests {
 
             fill_filesystem(&filetree, &filesystem);
 
-            let app_state = AppState::new(filesystem, dirs, files, filetree, false);
+            let mut app_state = AppState::new(filesystem, dirs, files, filetree, false);
+            prepare(&mut app_state);
 
             let mut siv = Cursive::new(move || backend);
 
             let mut interface = Interface::new(app_state, siv);
+            let ichannel = interface.event_sink();
+
             input.send(Some(Event::Refresh)).unwrap();
             interface.main_step();
 
-            let ichannel = interface.event_sink();
-
             AdvancedSetup {
                 ss: Box::new(basicSetup),
                 receiver,
@@ -176,6 +176,17 @@ pub mod tests {
             });
         }
 
+        /// Calls f on the tested view and draws the screen again.
+        pub fn call_on_view<V, F>(&mut self, f: F)
+        where
+            V: cursive::view::View + 'static,
+            F: FnOnce(&mut V),
+        {
+            self.siv.call_on_id(&self.handle.to_string(), f);
+            self.input.send(Some(Event::Refresh)).unwrap();
+            self.siv.step();
+        }
+
         pub fn hit_keystroke(&mut self, key: Key) {
             self.input.send(Some(Event::Key(key))).unwrap();
             self.siv.step();
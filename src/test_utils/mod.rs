@@ -17,3 +17,5 @@ limitations under the License.
 pub mod advanced_setup;
 pub mod basic_setup;
 pub mod fake_tree;
+#[cfg(test)]
+pub mod temp_dir;
@@ -18,8 +18,11 @@ limitations under the License.
 // it points to a character that will be replaced/preceded, not succeeded
 
 use crate::buffer_state::BufferState;
+use crate::content_provider::EditEvent;
 use crate::cursor_set::Cursor;
 use crate::cursor_set::CursorSet;
+use crate::text_diff::TextDiff;
+use ropey::Rope;
 use serde::de::Unexpected::Str;
 use std::borrow::Borrow;
 
@@ -148,8 +151,8 @@ fn apply(input: &str, f: fn(&mut CursorSet, &BufferState) -> ()) -> String {
 
 #[test]
 fn one_cursor_move_left() {
-    let f : fn(&mut CursorSet, &BufferState) = |c: &mut CursorSet, _| {
-        c.move_left();
+    let f : fn(&mut CursorSet, &BufferState) = |c: &mut CursorSet, bs : &BufferState| {
+        c.move_left(&bs);
     };
 
     assert_eq!(apply("text", f), "text");
@@ -161,8 +164,8 @@ fn one_cursor_move_left() {
 
 #[test]
 fn one_cursor_move_left_some() {
-    let f : fn(&mut CursorSet, &BufferState) = |c: &mut CursorSet, _| {
-        c.move_left_by(3);
+    let f : fn(&mut CursorSet, &BufferState) = |c: &mut CursorSet, bs : &BufferState| {
+        c.move_left_by(&bs, 3);
     };
 
     assert_eq!(apply("text", f), "text");
@@ -174,8 +177,8 @@ fn one_cursor_move_left_some() {
 
 #[test]
 fn multiple_cursor_move_left() {
-    let f : fn(&mut CursorSet, &BufferState) = |c: &mut CursorSet, _| {
-        c.move_left();
+    let f : fn(&mut CursorSet, &BufferState) = |c: &mut CursorSet, bs : &BufferState| {
+        c.move_left(&bs);
         c.reduce();
     };
 
@@ -186,8 +189,8 @@ fn multiple_cursor_move_left() {
 
 #[test]
 fn multiple_cursor_move_left_some() {
-    let f : fn(&mut CursorSet, &BufferState) = |c: &mut CursorSet, _| {
-        c.move_left_by(3);
+    let f : fn(&mut CursorSet, &BufferState) = |c: &mut CursorSet, bs : &BufferState| {
+        c.move_left_by(&bs, 3);
         c.reduce();
     };
 
@@ -436,4 +439,116 @@ fn single_cursor_move_up_by_some() {
 
         assert_eq!(apply(text, f), new_text);
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn jump_to_matching_bracket() {
+    let f : fn(&mut CursorSet, &BufferState) = |c: &mut CursorSet, bs : &BufferState| {
+        c.jump_to_matching_bracket(&bs);
+        c.reduce();
+    };
+
+    assert_eq!(apply("fn main#() {}", f), "fn main(#) {}");
+    assert_eq!(apply("fn main() {}#", f), "fn main() #{}");
+    assert_eq!(apply("#fn main() {}", f), "#fn main() {}");
+    assert_eq!(apply("a#[b(c)]\nd#{e}", f), "a[b(c)#]\nd{e#}");
+}
+
+#[test]
+fn move_vertically_skips_folded_lines() {
+    let (mut bs, mut cs) = text_to_buffer_cursors("#fn a\n    b\n    c\nd\n");
+    assert!(bs.fold_at(0));
+
+    cs.move_vertically_by(&bs, 1);
+    assert_eq!(buffer_cursors_to_text(&bs, &cs), "fn a\n    b\n    c\n#d\n");
+
+    cs.move_vertically_by(&bs, -1);
+    assert_eq!(buffer_cursors_to_text(&bs, &cs), "#fn a\n    b\n    c\nd\n");
+}
+
+#[test]
+fn move_visually_between_wrapped_rows() {
+    fn down_by(input: &str, l: isize) -> String {
+        let (bs, mut cs) = text_to_buffer_cursors(input);
+        cs.move_visually_by(&bs, l, 5);
+        buffer_cursors_to_text(&bs, &cs)
+    }
+
+    assert_eq!(down_by("a#aaa bbbb cccc\nd\n", 1), "aaaa b#bbb cccc\nd\n");
+    assert_eq!(down_by("a#aaa bbbb cccc\nd\n", 2), "aaaa bbbb c#ccc\nd\n");
+    assert_eq!(down_by("a#aaa bbbb cccc\nd\n", 3), "aaaa bbbb cccc\nd#\n");
+    assert_eq!(down_by("aaaa bbbb cccc\n#d\n", -1), "aaaa bbbb #cccc\nd\n");
+}
+
+#[test]
+fn horizontal_moves_step_over_grapheme_clusters() {
+    // "e" with combining accent, then CJK character (two columns wide).
+    let bs = BufferState::from_text("e\u{301}\u{4e2d}x");
+
+    let mut cs = a_to_c(vec![0]);
+    cs.move_right(&bs);
+    assert_eq!(cs.set()[0].a, 2);
+    cs.move_right_by(&bs, 2);
+    assert_eq!(cs.set()[0].a, 4);
+    cs.move_left_by(&bs, 2);
+    assert_eq!(cs.set()[0].a, 2);
+    cs.move_left(&bs);
+    assert_eq!(cs.set()[0].a, 0);
+}
+
+#[test]
+fn move_vertically_keeps_screen_column() {
+    // tab is 4 columns wide, "\u{4e2d}" is 2.
+    let bs = BufferState::from_text("\tab\n\u{4e2d}\u{4e2d}\u{4e2d}x\n");
+
+    // from "b" (column 5) down lands on the third wide character, which covers columns 4-5.
+    let mut cs = a_to_c(vec![2]);
+    cs.move_vertically_by(&bs, 1);
+    assert_eq!(cs.set()[0].a, 6);
+
+    // and back up to "b", thanks to preferred column.
+    cs.move_vertically_by(&bs, -1);
+    assert_eq!(cs.set()[0].a, 2);
+}
+
+#[test]
+fn crlf_is_a_single_line_break() {
+    let bs = BufferState::from_text("abc\r\nd\r\nef");
+
+    // "\r\n" is stepped over at once.
+    let mut cs = a_to_c(vec![3]);
+    cs.move_right(&bs);
+    assert_eq!(cs.set()[0].a, 5);
+    cs.move_left(&bs);
+    assert_eq!(cs.set()[0].a, 3);
+
+    // end of a line is before "\r", not between "\r" and "\n".
+    let mut cs = a_to_c(vec![3]);
+    cs.move_vertically_by(&bs, 1);
+    assert_eq!(cs.set()[0].a, 6);
+    cs.move_vertically_by(&bs, 1);
+    assert_eq!(cs.set()[0].a, 10);
+}
+
+#[test]
+fn adjust_after_reload_follows_lines() {
+    let old = Rope::from_str("a\nbc\nd\n");
+    let new = Rope::from_str("x\na\nbc\n");
+    let diff = TextDiff::new(&old, &new);
+
+    // "c" moves down with its line, cursor on removed "d" lands at the end of text.
+    let mut cs = a_to_c(vec![3, 5]);
+    cs.adjust_after_reload(&old, &new, &diff);
+    assert_eq!(cs.set().iter().map(|c| c.a).collect::<Vec<usize>>(), vec![5, 7]);
+}
+
+#[test]
+fn adjust_after_edits_follows_text() {
+    let mut cs = a_to_c(vec![0, 3, 6]);
+    cs.adjust_after_edits(&vec![
+        EditEvent::Insert { offset: 2, content: "xy".to_string() },
+        EditEvent::Change { offset: 6, length: 3, content: "z".to_string() },
+    ]);
+    // cursor at 3 moved by insertion to 5, the one at 6 (8 after insertion) was within change.
+    assert_eq!(cs.set().iter().map(|c| c.a).collect::<Vec<usize>>(), vec![0, 5, 6]);
+}
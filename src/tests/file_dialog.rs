@@ -114,6 +114,28 @@ fn expands_root() {
     }
 }
 
+#[test]
+fn refresh_keeps_expanded_dirs() {
+    let mut s = basic_setup(FileDialogVariant::OpenFile(None));
+
+    s.hit_keystroke(Key::Enter);
+    s.hit_keystroke(Key::Down);
+    s.hit_keystroke(Key::Enter);
+    s.hit_keystroke(Key::Up);
+    {
+        let screen = s.last_screen().unwrap();
+        assert_eq!(screen.find_occurences("▾ <root>").len(), 1);
+        assert_eq!(screen.find_occurences("▾ laura").len(), 1);
+    }
+
+    s.call_on_view(|file_dialog: &mut FileDialog| file_dialog.refresh());
+
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("▾ <root>").len(), 1);
+    assert_eq!(screen.find_occurences("▾ laura").len(), 1);
+    assert_eq!(screen.find_occurences("▸ bob").len(), 1);
+}
+
 #[test]
 fn save_file_as_points_to_dir() {
     let mut s = basic_setup(FileDialogVariant::SaveAsFile(
@@ -19,8 +19,10 @@ use cursive::event::Event;
 use cursive::event::Key;
 use cursive::Vec2;
 use crate::events::IEvent;
+use crate::file_index_cache::{read_cache, write_cache, CacheKey, CachedIndex};
 use crate::test_utils::advanced_setup::tests::AdvancedSetup;
 use filesystem::FileSystem;
+use std::path::{Path, PathBuf};
 
 #[test]
 fn first_interface_test() {
@@ -168,6 +170,26 @@ fn save_via_ctrls() {
     );
 }
 
+#[test]
+fn save_removes_recovery_file() {
+    let mut s = AdvancedSetup::with_files(vec!["/home/laura/file4.ini"]);
+    s.step2();
+    let recovery_dir = PathBuf::from("/home/laura/.recovery");
+    s.interface().state_mut().set_recovery_dir(recovery_dir.clone());
+
+    s.type_letters("edited by laura");
+    s.step2();
+    s.interface().state().write_recovery_files();
+    let num_records = |s: &mut AdvancedSetup| {
+        s.interface().state().filesystem().read_dir(&recovery_dir).unwrap().count()
+    };
+    assert_eq!(num_records(&mut s), 1);
+
+    s.input().send(Some(Event::CtrlChar('s'))).unwrap();
+    s.step2();
+    assert_eq!(num_records(&mut s), 0);
+}
+
 #[test]
 fn open_via_startup() {
     let mut s = AdvancedSetup::with_files(vec!["/home/laura/file4.ini"]);
@@ -181,6 +203,10 @@ fn open_via_startup() {
 #[test]
 fn open_via_fuzzy() {
     let mut s = AdvancedSetup::new();
+    // file bar lists what indexer found in the fake tree.
+    while s.has_running_workers() {
+        s.step2();
+    }
 
     s.input().send(Some(Event::CtrlChar('p'))).unwrap();
     s.step2();
@@ -250,6 +276,36 @@ fn open_via_dialog() {
     assert_eq!(screen.find_occurences("mock file content").len(), 1);
 }
 
+#[test]
+fn saving_protected_file_offers_save_as_root() {
+    let mut s = AdvancedSetup::new();
+    s.interface().state().filesystem().set_readonly("/home/laura/file4.ini", true).unwrap();
+    s.step2();
+
+    s.input().send(Some(Event::CtrlChar('d'))).unwrap();
+    s.step2();
+    s.input().send(Some(Event::Key(Key::Enter))).unwrap();
+    s.step2();
+    s.input().send(Some(Event::Key(Key::Down))).unwrap();
+    s.input().send(Some(Event::Key(Key::Enter))).unwrap();
+    s.step2();
+    s.input().send(Some(Event::Key(Key::Tab))).unwrap();
+    s.step2();
+    s.hit_enter();
+    s.step2();
+
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("mock file content").len(), 1);
+
+    s.input().send(Some(Event::CtrlChar('s'))).unwrap();
+    s.step2();
+
+    let screen = s.last_screen().unwrap();
+    // not the save as dialog, file is to be written where it is.
+    assert_eq!(screen.find_occurences("No permission to write /home/laura/file4.ini").len(), 1);
+    assert_eq!(screen.find_occurences("save as root").len(), 1);
+}
+
 #[test]
 fn fuzzy_buffer_list_displays() {
     let mut s = AdvancedSetup::new();
@@ -264,8 +320,7 @@ fn fuzzy_buffer_list_displays() {
     assert_eq!(screen.find_occurences("<unnamed>").len(), 1);
 }
 
-//    #[test]
-// TODO(njskalski): this fails now, since not all files passed are loaded. Some are just scheduled.
+#[test]
 fn loads_listed_files() {
     let mut s = AdvancedSetup::with_files(vec![
         "/home/laura/subdirectory2/file2.txt",
@@ -273,6 +328,7 @@ fn loads_listed_files() {
     ]);
 
     s.step2();
+    assert_eq!(s.interface().state().get_buffers().len(), 2);
 
     let screen = s.last_screen().unwrap();
     assert_eq!(
@@ -326,6 +382,9 @@ fn all_commands_bar_displays() {
 #[test]
 fn fuzzy_file_index_displays() {
     let mut s = AdvancedSetup::new();
+    while s.has_running_workers() {
+        s.step2();
+    }
 
     s.input().send(Some(Event::CtrlChar('p'))).unwrap();
     s.step2();
@@ -346,7 +405,339 @@ fn fuzzy_file_index_displays() {
         s.step2();
     }
 
-    // cannot test query results at this time, since the file index is empty without mocking
-    // ::path.
-    //        s.dump_debug();
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("file2.txt").len(), 1);
+    assert_eq!(screen.find_occurences("file4.ini").len(), 1);
+}
+
+#[test]
+fn indexing_stops_at_file_index_limit() {
+    let mut s = AdvancedSetup::with_prepared_state(vec![], |state| {
+        state.settings_rc().borrow_mut().set_file_index_limit(2);
+    });
+    while s.has_running_workers() {
+        s.step2();
+    }
+
+    // /home/laura is walked in order of paths: file4.ini, subdirectory2/file1, and here it stops.
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("indexed only first 2 files of \"/home/laura\"").len(), 1);
+
+    s.input().send(Some(Event::CtrlChar('p'))).unwrap();
+    s.step2();
+    s.type_letters("fi");
+    s.step2();
+    while s.has_running_workers() {
+        s.step2();
+    }
+
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("file4.ini").len(), 1);
+    assert_eq!(screen.find_occurences("file1").len(), 1);
+    assert_eq!(screen.find_occurences("file2.txt").len(), 0);
+}
+
+#[test]
+fn unmodified_buffer_follows_file_on_disk() {
+    let mut s = AdvancedSetup::with_files(vec!["/home/laura/file4.ini"]);
+    s.step2();
+
+    {
+        let fs = s.interface().state().filesystem();
+        fs.remove_file("/home/laura/file4.ini").unwrap();
+        fs.create_file("/home/laura/file4.ini", "rewritten by another program").unwrap();
+    }
+
+    s.ichannel().send(IEvent::Tick).unwrap();
+    s.step2();
+    while s.has_running_workers() {
+        s.step2();
+    }
+
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("rewritten by another program").len(), 1);
+    assert_eq!(screen.find_occurences("mock file content").len(), 0);
+}
+
+#[test]
+fn quit_asks_about_unsaved_changes() {
+    let mut s = AdvancedSetup::with_files(vec!["/home/laura/file4.ini"]);
+    s.step2();
+
+    s.type_letters("edited by laura");
+    s.step2();
+
+    s.input().send(Some(Event::CtrlChar('q'))).unwrap();
+    s.step2();
+
+    assert_eq!(s.interface().done(), false);
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("has unsaved changes").len(), 1);
+
+    s.input().send(Some(Event::Char('d'))).unwrap();
+    s.step2();
+
+    assert_eq!(s.interface().done(), true);
+    // discarded, not saved.
+    assert_eq!(
+        s.interface().state().filesystem().read_file("/home/laura/file4.ini").unwrap(),
+        "mock file content of \"/home/laura/file4.ini\"".as_bytes().to_vec()
+    );
+}
+
+#[test]
+fn quit_with_open_dialog_leaves_notice() {
+    let mut s = AdvancedSetup::with_files(vec!["/home/laura/file4.ini"]);
+    s.step2();
+
+    s.type_letters("edited by laura");
+    s.step2();
+
+    s.input().send(Some(Event::CtrlChar('o'))).unwrap();
+    s.step2();
+    s.input().send(Some(Event::CtrlChar('q'))).unwrap();
+    s.step2();
+
+    assert_eq!(s.interface().done(), false);
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("has unsaved changes").len(), 0);
+    assert_eq!(screen.find_occurences("close the dialog first to quit").len(), 1);
+}
+
+#[test]
+fn stdin_is_opened_before_given_files() {
+    let mut s =
+        AdvancedSetup::with_stdin_and_files(Some("piped text"), vec!["/home/laura/file4.ini"]);
+    s.step2();
+
+    assert_eq!(s.interface().state().get_buffers().len(), 2);
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("piped text").len(), 1);
+}
+
+#[test]
+fn wait_exits_once_given_files_are_closed() {
+    let mut s = AdvancedSetup::with_files(vec![
+        "/home/laura/subdirectory2/file2.txt",
+        "/home/laura/subdirectory2/file3.rs",
+    ]);
+    s.interface().exit_when_given_buffers_closed();
+    s.step2();
+
+    s.input().send(Some(Event::AltChar('c'))).unwrap();
+    s.step2();
+    assert_eq!(s.interface().done(), false);
+
+    s.input().send(Some(Event::AltChar('c'))).unwrap();
+    s.step2();
+    assert_eq!(s.interface().done(), true);
+}
+
+#[test]
+fn stdout_returns_stdin_buffer_without_asking() {
+    let mut s = AdvancedSetup::with_stdin_and_files(Some("piped text"), vec![]);
+    s.interface().set_stdin_buffer_as_output();
+    s.step2();
+
+    s.type_letters("edited ");
+    s.step2();
+
+    s.input().send(Some(Event::CtrlChar('q'))).unwrap();
+    s.step2();
+
+    assert_eq!(s.interface().done(), true);
+    assert_eq!(s.interface().output(), Some("edited piped text".to_string()));
+}
+
+#[test]
+fn stdout_never_takes_buffer_of_file() {
+    let mut s = AdvancedSetup::with_files(vec!["/home/laura/file4.ini"]);
+    s.interface().set_stdin_buffer_as_output();
+    s.step2();
+    assert_eq!(s.interface().output(), None);
+
+    s.type_letters("edited by laura");
+    s.step2();
+
+    s.input().send(Some(Event::CtrlChar('q'))).unwrap();
+    s.step2();
+
+    assert_eq!(s.interface().done(), false);
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("has unsaved changes").len(), 1);
+}
+
+#[test]
+fn close_unmodified_buffer() {
+    let mut s = AdvancedSetup::with_files(vec!["/home/laura/file4.ini"]);
+    s.step2();
+
+    s.input().send(Some(Event::AltChar('c'))).unwrap();
+    s.step2();
+
+    assert_eq!(s.interface().done(), false);
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("mock file content").len(), 0);
+    // the only buffer got replaced with an empty one.
+    assert_eq!(s.interface().state().get_buffers().len(), 1);
+}
+
+#[test]
+fn split_panes_show_the_same_buffer() {
+    let mut s = AdvancedSetup::new();
+    s.type_letters("first");
+    s.step2();
+
+    s.input().send(Some(Event::AltChar('v'))).unwrap();
+    s.step2();
+    s.type_letters(" second");
+    s.step2();
+
+    // edit made in the new pane is visible in both of them.
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("first second").len(), 2);
+
+    s.input().send(Some(Event::AltChar('x'))).unwrap();
+    s.step2();
+
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("first second").len(), 1);
+}
+
+#[test]
+fn new_pane_keeps_soft_wrap_of_buffer() {
+    let mut s = AdvancedSetup::new();
+    s.input().send(Some(Event::AltChar('w'))).unwrap();
+    s.step2();
+    s.input().send(Some(Event::AltChar('v'))).unwrap();
+    s.step2();
+
+    let id = s.interface().state().get_buffers()[0].clone();
+    assert_eq!(s.interface().state().buffer_obs(&id).unwrap().soft_wrap(), true);
+}
+
+#[test]
+fn new_pane_keeps_tab_width_of_buffer() {
+    let mut s = AdvancedSetup::new();
+    let id = s.interface().state().get_buffers()[0].clone();
+    s.interface().state().buffer_obs(&id).unwrap().borrow_state().set_tab_width(3);
+
+    s.input().send(Some(Event::AltChar('v'))).unwrap();
+    s.step2();
+
+    assert_eq!(s.interface().state().buffer_obs(&id).unwrap().tab_width(), 3);
+}
+
+#[test]
+fn log_files_are_read_only() {
+    let mut s = AdvancedSetup::new();
+    let state = s.interface().state();
+    assert!(state.is_read_only_path(&PathBuf::from("/home/laura/build.log")));
+    assert!(!state.is_read_only_path(&PathBuf::from("/home/laura/build.rs")));
+}
+
+#[test]
+fn switch_buffers_and_show_tab_bar() {
+    let mut s = AdvancedSetup::with_files(vec![
+        "/home/laura/subdirectory2/file2.txt",
+        "/home/laura/subdirectory2/file3.rs",
+    ]);
+    s.step2();
+
+    let file2_content = "mock file content of \"/home/laura/subdirectory2/file2.txt\"";
+    let file3_content = "mock file content of \"/home/laura/subdirectory2/file3.rs\"";
+
+    s.input().send(Some(Event::AltChar('N'))).unwrap();
+    s.step2();
+    assert_eq!(s.last_screen().unwrap().find_occurences(file3_content).len(), 1);
+
+    // wraps around.
+    s.input().send(Some(Event::AltChar('N'))).unwrap();
+    s.step2();
+    assert_eq!(s.last_screen().unwrap().find_occurences(file2_content).len(), 1);
+
+    // switcher starts at previously focused buffer.
+    s.input().send(Some(Event::AltChar('b'))).unwrap();
+    s.step2();
+    s.hit_enter();
+    s.step2();
+    assert_eq!(s.last_screen().unwrap().find_occurences(file3_content).len(), 1);
+
+    s.input().send(Some(Event::AltChar('t'))).unwrap();
+    s.step2();
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences(" file2.txt ").len(), 1);
+    assert_eq!(screen.find_occurences(" file3.rs ").len(), 1);
+}
+
+#[test]
+fn file_bar_follows_created_files() {
+    let mut s = AdvancedSetup::new();
+    s.step2();
+
+    let created = vec![PathBuf::from("/home/laura/brand_new.md")];
+    s.ichannel().send(IEvent::FilesChanged(created, vec![])).unwrap();
+    s.step2();
+
+    s.input().send(Some(Event::CtrlChar('p'))).unwrap();
+    s.step2();
+    s.type_letters("brand");
+    s.step2();
+
+    while s.has_running_workers() {
+        s.step2();
+    }
+
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("brand_new.md").len(), 1);
+}
+
+#[test]
+fn cached_files_gone_from_disk_leave_file_index() {
+    let root = PathBuf::from("/home/laura");
+    let mut key_op: Option<CacheKey> = None;
+    let mut s = AdvancedSetup::with_prepared_state(vec![], |state| {
+        let key = CacheKey {
+            root: root.clone(),
+            enable_gitignore: false,
+            file_index_limit: state.settings_ref().file_index_limit(),
+        };
+        let cached = vec![root.join("file4.ini"), root.join("gone.md")];
+        let index = CachedIndex::build(&cached).unwrap();
+        write_cache(state.filesystem(), Path::new("/cache"), &key, &index).unwrap();
+        state.set_file_index_cache_dir(PathBuf::from("/cache"));
+        key_op = Some(key);
+    });
+
+    // indexing of the fake tree is done, and it did not find gone.md.
+    while s.has_running_workers() {
+        s.step2();
+    }
+
+    s.input().send(Some(Event::CtrlChar('p'))).unwrap();
+    s.step2();
+    s.type_letters(".md");
+    s.step2();
+    while s.has_running_workers() {
+        s.step2();
+    }
+    let screen = s.last_screen().unwrap();
+    assert_eq!(screen.find_occurences("Context").len(), 1); // file bar is open.
+    assert_eq!(screen.find_occurences("gone.md").len(), 0);
+
+    // cache no longer matches the disk, so it's written again, in background.
+    let key = key_op.unwrap();
+    let fs = s.interface().state().filesystem().clone();
+    let rewritten = (0..100).any(|_| {
+        let files: Vec<PathBuf> = read_cache(&fs, Path::new("/cache"), &key)
+            .map(|index| index.all_files().cloned().collect())
+            .unwrap_or_default();
+        if files.contains(&root.join("subdirectory2/file2.txt")) {
+            assert!(!files.contains(&root.join("gone.md")));
+            return true;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        false
+    });
+    assert!(rewritten);
 }
@@ -0,0 +1,306 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Line based diff of two versions of a text, used when file changes on disk under an open
+// buffer. It turns into edit events (so folds and highlighting survive the reload), maps lines of
+// the old version to the new one (so cursors stay where they were) and prints as unified diff.
+//
+// Common prefix and suffix are cut off first, the rest is compared with longest common
+// subsequence. If the rest is too big for that, it's treated as a single changed block.
+
+use ropey::Rope;
+use std::ops::Range;
+
+use crate::content_provider::EditEvent;
+
+/// Above this number of (old lines * new lines), the changed part is not analyzed further.
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+/// Replaced block: lines old (of old version) became lines new (of new version). One of ranges can
+/// be empty (pure insertion or removal).
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TextDiff {
+    hunks: Vec<Hunk>,
+}
+
+fn lines_of(rope: &Rope) -> Vec<String> {
+    rope.lines().map(|line| line.to_string()).collect()
+}
+
+impl TextDiff {
+    pub fn new(old: &Rope, new: &Rope) -> Self {
+        TextDiff { hunks: diff_lines(&lines_of(old), &lines_of(new)) }
+    }
+
+    pub fn hunks(&self) -> &[Hunk] {
+        &self.hunks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty()
+    }
+
+    /// Returns line of new version corresponding to old_line. Lines within replaced blocks are
+    /// mapped to the same position within the new block, or its last line.
+    pub fn map_line(&self, old_line: usize) -> usize {
+        let mut delta: isize = 0;
+        for hunk in self.hunks.iter() {
+            if old_line < hunk.old.start {
+                break;
+            }
+            if old_line < hunk.old.end {
+                let within = old_line - hunk.old.start;
+                return hunk.new.start + within.min(hunk.new.len().saturating_sub(1));
+            }
+            delta += hunk.new.len() as isize - hunk.old.len() as isize;
+        }
+        (old_line as isize + delta) as usize
+    }
+
+    /// Returns events that turn old into new. They are ordered from the end of text, so applying
+    /// them one by one does not invalidate offsets of the following ones.
+    pub fn edit_events(&self, old: &Rope, new: &Rope) -> Vec<EditEvent> {
+        self.hunks
+            .iter()
+            .rev()
+            .map(|hunk| {
+                let begin = old.line_to_char(hunk.old.start);
+                let end = old.line_to_char(hunk.old.end);
+                let content = new
+                    .slice(new.line_to_char(hunk.new.start)..new.line_to_char(hunk.new.end))
+                    .to_string();
+                EditEvent::Change { offset: begin, length: end - begin, content }
+            })
+            .collect()
+    }
+
+    /// Returns diff in unified format, with context lines around every hunk.
+    pub fn unified(
+        &self,
+        old: &Rope,
+        new: &Rope,
+        old_name: &str,
+        new_name: &str,
+        context: usize,
+    ) -> String {
+        let old_lines = lines_of(old);
+        let new_lines = lines_of(new);
+        let mut result = format!("--- {}\n+++ {}\n", old_name, new_name);
+
+        // hunks closer than 2 * context are printed together.
+        let mut groups: Vec<Vec<&Hunk>> = Vec::new();
+        for hunk in self.hunks.iter() {
+            let joins_last = match groups.last().and_then(|group| group.last()) {
+                Some(last) => hunk.old.start <= last.old.end + 2 * context,
+                None => false,
+            };
+            if joins_last {
+                groups.last_mut().unwrap().push(hunk);
+            } else {
+                groups.push(vec![hunk]);
+            }
+        }
+
+        for group in groups {
+            let first = group[0];
+            let last = group[group.len() - 1];
+            let old_begin = first.old.start.saturating_sub(context);
+            let old_end = (last.old.end + context).min(old_lines.len());
+            let new_begin = first.new.start.saturating_sub(first.old.start - old_begin);
+            let new_end = last.new.end + (old_end - last.old.end);
+
+            result.push_str(&format!(
+                "@@ -{},{} +{},{} @@\n",
+                old_begin + 1,
+                old_end - old_begin,
+                new_begin + 1,
+                new_end - new_begin
+            ));
+
+            let mut old_idx = old_begin;
+            for hunk in group {
+                for line in &old_lines[old_idx..hunk.old.start] {
+                    push_line(&mut result, ' ', line);
+                }
+                for line in &old_lines[hunk.old.clone()] {
+                    push_line(&mut result, '-', line);
+                }
+                for line in &new_lines[hunk.new.clone()] {
+                    push_line(&mut result, '+', line);
+                }
+                old_idx = hunk.old.end;
+            }
+            for line in &old_lines[old_idx..old_end] {
+                push_line(&mut result, ' ', line);
+            }
+        }
+
+        result
+    }
+}
+
+fn push_line(result: &mut String, prefix: char, line: &str) {
+    result.push(prefix);
+    result.push_str(line.trim_end_matches(|c| c == '\n' || c == '\r'));
+    result.push('\n');
+}
+
+fn diff_lines(old: &[String], new: &[String]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    if old_mid.is_empty() && new_mid.is_empty() {
+        return Vec::new();
+    }
+
+    if old_mid.len().saturating_mul(new_mid.len()) > MAX_LCS_CELLS {
+        return vec![Hunk {
+            old: prefix..prefix + old_mid.len(),
+            new: prefix..prefix + new_mid.len(),
+        }];
+    }
+
+    lcs_hunks(old_mid, new_mid)
+        .into_iter()
+        .map(|hunk| Hunk {
+            old: hunk.old.start + prefix..hunk.old.end + prefix,
+            new: hunk.new.start + prefix..hunk.new.end + prefix,
+        })
+        .collect()
+}
+
+fn lcs_hunks(old: &[String], new: &[String]) -> Vec<Hunk> {
+    let (n, m) = (old.len(), new.len());
+    // lengths[i][j] is length of LCS of old[i..] and new[j..].
+    let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+    let idx = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[idx(i, j)] = if old[i] == new[j] {
+                lengths[idx(i + 1, j + 1)] + 1
+            } else {
+                lengths[idx(i + 1, j)].max(lengths[idx(i, j + 1)])
+            };
+        }
+    }
+
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let (mut hunk_i, mut hunk_j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            if hunk_i < i || hunk_j < j {
+                hunks.push(Hunk { old: hunk_i..i, new: hunk_j..j });
+            }
+            i += 1;
+            j += 1;
+            hunk_i = i;
+            hunk_j = j;
+        } else if j < m && (i == n || lengths[idx(i, j + 1)] >= lengths[idx(i + 1, j)]) {
+            j += 1;
+        } else {
+            i += 1;
+        }
+    }
+    if hunk_i < n || hunk_j < m {
+        hunks.push(Hunk { old: hunk_i..n, new: hunk_j..m });
+    }
+
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> TextDiff {
+        TextDiff::new(&Rope::from_str(old), &Rope::from_str(new))
+    }
+
+    fn apply(old: &str, new: &str) -> String {
+        let (old_rope, new_rope) = (Rope::from_str(old), Rope::from_str(new));
+        let mut rope = old_rope.clone();
+        for event in TextDiff::new(&old_rope, &new_rope).edit_events(&old_rope, &new_rope) {
+            match event {
+                EditEvent::Change { offset, length, content } => {
+                    rope.remove(offset..offset + length);
+                    rope.insert(offset, &content);
+                }
+                _ => panic!("unexpected event"),
+            }
+        }
+        rope.to_string()
+    }
+
+    #[test]
+    fn identical_texts_have_no_hunks() {
+        assert!(diff("a\nb\n", "a\nb\n").is_empty());
+    }
+
+    #[test]
+    fn hunks_of_changes() {
+        let d = diff("a\nb\nc\nd\n", "a\nx\nc\nd\ne\n");
+        assert_eq!(d.hunks(), &[Hunk { old: 1..2, new: 1..2 }, Hunk { old: 4..4, new: 4..5 }]);
+    }
+
+    #[test]
+    fn edit_events_turn_old_into_new() {
+        let cases = vec![
+            ("a\nb\nc\n", "a\nc\n"),
+            ("a\nb\nc\n", "x\na\nb\nc\ny"),
+            ("one\ntwo\nthree\nfour\n", "one\n2\nthree\n4\n5\n"),
+            ("", "new file\n"),
+            ("old\n", ""),
+        ];
+        for (old, new) in cases {
+            assert_eq!(apply(old, new), new);
+        }
+    }
+
+    #[test]
+    fn map_line_follows_insertions_and_removals() {
+        // two lines inserted at the top, "c" removed.
+        let d = diff("a\nb\nc\nd\n", "x\ny\na\nb\nd\n");
+        assert_eq!(d.map_line(0), 2);
+        assert_eq!(d.map_line(1), 3);
+        assert_eq!(d.map_line(3), 4);
+    }
+
+    #[test]
+    fn unified_format() {
+        let old = Rope::from_str("a\nb\nc\n");
+        let new = Rope::from_str("a\nB\nc\n");
+        let d = TextDiff::new(&old, &new);
+        assert_eq!(
+            d.unified(&old, &new, "mine", "disk", 1),
+            "--- mine\n+++ disk\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+    }
+}
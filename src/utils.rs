@@ -16,12 +16,17 @@ limitations under the License.
 
 // this is a collection of functions I expect to use in multiple places
 
-use crate::rich_content::HighlightSettings;
 use std::collections::HashMap;
+use std::env;
 use std::ffi::OsStr;
 use std::path::Path;
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::time::SystemTime;
+
+#[cfg(test)]
+use filesystem::{FakeFileSystem, FileSystem};
+use filesystem::OsFileSystem;
 
 #[macro_export]
 macro_rules! hashmap {
@@ -79,11 +84,188 @@ pub fn is_prefix_of(prefix: &Path, path: &Path) -> bool {
 //    x
 //}
 
-// TODO(njskalski): this should be somewhere else, but I have no brainpower to plan it now.
-pub fn highlight_settings_from_path(path: &Path) -> Option<Rc<HighlightSettings>> {
-    let ext = path.extension()?.to_string_lossy();
-    let settings = HighlightSettings::new(&ext)?;
-    Some(Rc::new(settings))
+/// Returns directory where user configuration (like additional syntax definitions) is kept.
+/// It's $XDG_CONFIG_HOME/sly, and if that's not set, $HOME/.config/sly.
+pub fn config_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(ref dir) if !dir.is_empty() => Some(Path::new(dir).join("sly")),
+        _ => env::var_os("HOME").map(|home| Path::new(&home).join(".config").join("sly")),
+    }
+}
+
+/// Returns directory where sly keeps its data (like recovery files). It's $XDG_DATA_HOME/sly, and
+/// if that's not set, $HOME/.local/share/sly.
+pub fn data_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_DATA_HOME") {
+        Some(ref dir) if !dir.is_empty() => Some(Path::new(dir).join("sly")),
+        _ => env::var_os("HOME")
+            .map(|home| Path::new(&home).join(".local").join("share").join("sly")),
+    }
+}
+
+/// Returns directory for data that can be recreated (like file index), $XDG_CACHE_HOME/sly, and
+/// if that's not set, $HOME/.cache/sly.
+pub fn cache_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CACHE_HOME") {
+        Some(ref dir) if !dir.is_empty() => Some(Path::new(dir).join("sly")),
+        _ => env::var_os("HOME").map(|home| Path::new(&home).join(".cache").join("sly")),
+    }
+}
+
+/// FNV-1a. Unlike DefaultHasher, it's guaranteed to stay the same between builds.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Returns whether process with pid runs (as far as it can be told, not at all outside unix).
+#[cfg(unix)]
+pub fn is_process_alive(pid: u32) -> bool {
+    // signal 0 only checks whether it could be sent. 0 would mean "own process group".
+    if pid == 0 || pid > libc::pid_t::max_value() as u32 {
+        return false;
+    }
+    if unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+        return true;
+    }
+    // exists, but belongs to someone else.
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+pub fn is_process_alive(_pid: u32) -> bool {
+    false
+}
+
+/// What FileSystem trait does not tell about files: modification time, length and whether user
+/// can write them. Like SaveSupport (see safe_save.rs), it's implemented for OsFileSystem and,
+/// under test, for FakeFileSystem.
+pub trait MetadataSupport {
+    /// Returns last modification time of file, None if it's unknown or there is no such file.
+    fn modification_time(&self, path: &Path) -> Option<SystemTime>;
+    /// Returns length of file, None if there is no such file (or it's a directory).
+    fn file_len(&self, path: &Path) -> Option<u64>;
+    /// Returns whether current user cannot write to existing file at path.
+    fn is_read_only(&self, path: &Path) -> bool;
+}
+
+impl MetadataSupport for OsFileSystem {
+    fn modification_time(&self, path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    fn file_len(&self, path: &Path) -> Option<u64> {
+        let metadata = std::fs::metadata(path).ok()?;
+        if metadata.is_file() {
+            Some(metadata.len())
+        } else {
+            None
+        }
+    }
+
+    /// Permission bits alone are not enough (root-owned 0644 file is not writable by others), so
+    /// it asks access(2).
+    #[cfg(unix)]
+    fn is_read_only(&self, path: &Path) -> bool {
+        use std::ffi::CString;
+        use std::io;
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(c_path) => c_path,
+            Err(_) => return false,
+        };
+        if unsafe { libc::access(c_path.as_ptr(), libc::W_OK) } == 0 {
+            return false;
+        }
+        let error = io::Error::last_os_error().raw_os_error();
+        [libc::EACCES, libc::EPERM, libc::EROFS, libc::ETXTBSY].iter().any(|e| error == Some(*e))
+    }
+
+    #[cfg(not(unix))]
+    fn is_read_only(&self, path: &Path) -> bool {
+        std::fs::metadata(path).map(|metadata| metadata.permissions().readonly()).unwrap_or(false)
+    }
+}
+
+/// FakeFileSystem keeps no modification times, and is read-only where set_readonly says so.
+#[cfg(test)]
+impl MetadataSupport for FakeFileSystem {
+    fn modification_time(&self, _path: &Path) -> Option<SystemTime> {
+        None
+    }
+
+    fn file_len(&self, path: &Path) -> Option<u64> {
+        if !self.is_file(path) {
+            return None;
+        }
+        self.read_file(path).ok().map(|bytes| bytes.len() as u64)
+    }
+
+    fn is_read_only(&self, path: &Path) -> bool {
+        self.readonly(path).unwrap_or(false)
+    }
+}
+
+/// Returns last modification time of file, None if it's unknown.
+pub fn modification_time<F: MetadataSupport>(fs: &F, path: &Path) -> Option<SystemTime> {
+    fs.modification_time(path)
+}
+
+/// What is known about a file on disk, to notice that someone else changed it.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    pub len: u64,
+}
+
+/// Returns stamp of file at path, or None if there is no such file. Where modification times are
+/// not kept (FakeFileSystem), only length is compared.
+pub fn file_stamp<F: MetadataSupport>(fs: &F, path: &Path) -> Option<FileStamp> {
+    let len = fs.file_len(path)?;
+    Some(FileStamp { modified: fs.modification_time(path), len })
+}
+
+/// Returns whether current user cannot write to existing file at path.
+pub fn is_read_only<F: MetadataSupport>(fs: &F, path: &Path) -> bool {
+    fs.is_read_only(path)
+}
+
+/// Expands leading "~" to $HOME. Paths are returned unchanged if $HOME is not set.
+pub fn expand_home(path: &str) -> PathBuf {
+    if path == "~" || path.starts_with("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return Path::new(&home).join(path[1..].trim_start_matches('/'));
+        }
+    }
+    PathBuf::from(path)
+}
+
+/// Splits "file:line" or "file:line:column" (the way compilers point at code) into path and
+/// (line, column), both counted from 1. Anything else is returned as a path without position.
+pub fn split_file_position(arg: &str) -> (PathBuf, Option<(usize, usize)>) {
+    // last parts first.
+    let parts: Vec<&str> = arg.rsplitn(3, ':').collect();
+    let number = |idx: usize| parts.get(idx).and_then(|part| part.parse::<usize>().ok());
+
+    if parts.len() == 3 && !parts[2].is_empty() {
+        if let (Some(line), Some(column)) = (number(1), number(0)) {
+            return (PathBuf::from(parts[2]), Some((line, column)));
+        }
+    }
+    if parts.len() >= 2 {
+        if let Some(line) = number(0) {
+            let path = &arg[..arg.len() - parts[0].len() - 1];
+            if !path.is_empty() {
+                return (PathBuf::from(path), Some((line, 1)));
+            }
+        }
+    }
+    (PathBuf::from(arg), None)
 }
 
 #[cfg(test)]
@@ -91,6 +273,81 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn expand_home_test() {
+        assert_eq!(expand_home("/usr/lib"), PathBuf::from("/usr/lib"));
+        if let Some(home) = env::var_os("HOME") {
+            assert_eq!(expand_home("~/.cargo"), Path::new(&home).join(".cargo"));
+        }
+    }
+
+    #[test]
+    fn split_file_position_test() {
+        assert_eq!(split_file_position("src/main.rs"), (PathBuf::from("src/main.rs"), None));
+        assert_eq!(
+            split_file_position("src/main.rs:12"),
+            (PathBuf::from("src/main.rs"), Some((12, 1)))
+        );
+        assert_eq!(
+            split_file_position("src/main.rs:12:5"),
+            (PathBuf::from("src/main.rs"), Some((12, 5)))
+        );
+        // only the last number is a position here.
+        assert_eq!(split_file_position("a:b:3"), (PathBuf::from("a:b"), Some((3, 1))));
+        assert_eq!(split_file_position("notes:todo"), (PathBuf::from("notes:todo"), None));
+        assert_eq!(split_file_position(":12"), (PathBuf::from(":12"), None));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn is_read_only_test() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join(format!("sly_read_only_test_{}", std::process::id()));
+        std::fs::write(&path, "text").unwrap();
+        let fs = OsFileSystem::new();
+        let writable = is_read_only(&fs, &path);
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o444)).unwrap();
+        let read_only = is_read_only(&fs, &path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!writable);
+        // root writes regardless of permission bits, and so does access(2) say.
+        let is_root = unsafe { libc::geteuid() } == 0;
+        assert_eq!(read_only, !is_root);
+
+        let fake = FakeFileSystem::new();
+        fake.create_file("/fake.txt", "").unwrap();
+        assert!(!is_read_only(&fake, Path::new("/fake.txt")));
+        fake.set_readonly("/fake.txt", true).unwrap();
+        assert!(is_read_only(&fake, Path::new("/fake.txt")));
+    }
+
+    #[test]
+    fn file_stamp_test() {
+        let path = env::temp_dir().join(format!("sly_file_stamp_test_{}", std::process::id()));
+        let fs = OsFileSystem::new();
+        std::fs::write(&path, "text").unwrap();
+        let first = file_stamp(&fs, &path);
+        std::fs::write(&path, "longer text").unwrap();
+        let second = file_stamp(&fs, &path);
+        let modified = modification_time(&fs, &path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(first.as_ref().map(|stamp| stamp.len), Some(4));
+        assert!(first.unwrap().modified.is_some());
+        assert_eq!(second.as_ref().map(|stamp| stamp.len), Some(11));
+        assert_eq!(second.unwrap().modified, modified);
+        assert_eq!(file_stamp(&fs, &path), None);
+        assert_eq!(file_stamp(&fs, &env::temp_dir()), None);
+
+        let fake = FakeFileSystem::new();
+        fake.create_file("/fake.txt", "text").unwrap();
+        assert_eq!(file_stamp(&fake, Path::new("/fake.txt")).map(|stamp| stamp.len), Some(4));
+        assert_eq!(modification_time(&fake, Path::new("/fake.txt")), None);
+        assert_eq!(file_stamp(&fake, Path::new("/missing.txt")), None);
+    }
+
     //    #[test]
     //    fn guess_format_test() {
     //        assert_eq!(guess_format(Path::new("/home/someone/rust.rs")), Some("rust"));
@@ -0,0 +1,210 @@
+/*
+Copyright 2018 Google LLC
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Visible whitespace. Depending on "text_view/whitespace" setting, whitespace is drawn with
+// symbols from "text_view/whitespace_symbols" everywhere ("all"), only after the last
+// non-whitespace character of a line ("trailing"), or not at all ("none"). Line endings count as
+// trailing whitespace. "\r\n" is drawn with the "cr" symbol, so CRLF lines can be told apart.
+//
+// Indent guides are drawn within indentation of non-blank lines, at every tab stop.
+
+use crate::settings::Settings;
+use cursive::theme::Color;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WhitespaceMode {
+    None,
+    Trailing,
+    All,
+}
+
+impl WhitespaceMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(WhitespaceMode::None),
+            "trailing" => Some(WhitespaceMode::Trailing),
+            "all" => Some(WhitespaceMode::All),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WhitespaceKind {
+    Space,
+    Tab,
+    Nbsp,
+    Cr,
+    Newline,
+}
+
+/// Returns kind of whitespace grapheme is, if it is one that has a symbol.
+pub fn whitespace_kind(grapheme: &str) -> Option<WhitespaceKind> {
+    match grapheme {
+        " " => Some(WhitespaceKind::Space),
+        "\t" => Some(WhitespaceKind::Tab),
+        "\u{a0}" | "\u{202f}" => Some(WhitespaceKind::Nbsp),
+        "\r" | "\r\n" => Some(WhitespaceKind::Cr),
+        "\n" => Some(WhitespaceKind::Newline),
+        _ => None,
+    }
+}
+
+/// Returns char index (within line) where trailing whitespace, including line ending, begins.
+pub fn trailing_whitespace_begin(line: &str) -> usize {
+    let mut result: usize = 0;
+    for (idx, c) in line.chars().enumerate() {
+        if !c.is_whitespace() {
+            result = idx + 1;
+        }
+    }
+    result
+}
+
+/// Returns char index (within line) of the first character that is not a part of indentation,
+/// or None if line is blank.
+pub fn indentation_end(line: &str) -> Option<usize> {
+    match line.chars().enumerate().find(|&(_, c)| c != ' ' && c != '\t') {
+        Some((_, '\n')) | Some((_, '\r')) | None => None,
+        Some((idx, _)) => Some(idx),
+    }
+}
+
+pub struct WhitespaceStyle {
+    mode: WhitespaceMode,
+    indent_guides: bool,
+    space: (String, Color),
+    tab: (String, Color),
+    nbsp: (String, Color),
+    cr: (String, Color),
+    newline: (String, Color),
+    indent_guide: (String, Color),
+}
+
+impl WhitespaceStyle {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let mode_name = settings.get_string("text_view/whitespace");
+        let mode = WhitespaceMode::from_name(&mode_name).unwrap_or_else(|| {
+            error!("unknown whitespace mode {:?}, using \"none\".", mode_name);
+            WhitespaceMode::None
+        });
+
+        WhitespaceStyle {
+            mode,
+            indent_guides: settings.get_bool("text_view/indent_guides"),
+            space: (
+                settings.get_string("text_view/whitespace_symbols/space"),
+                settings.get_color("theme/text_view/whitespace/space_color"),
+            ),
+            tab: (
+                settings.get_string("text_view/whitespace_symbols/tab"),
+                settings.get_color("theme/text_view/whitespace/tab_color"),
+            ),
+            nbsp: (
+                settings.get_string("text_view/whitespace_symbols/nbsp"),
+                settings.get_color("theme/text_view/whitespace/nbsp_color"),
+            ),
+            cr: (
+                settings.get_string("text_view/whitespace_symbols/cr"),
+                settings.get_color("theme/text_view/whitespace/cr_color"),
+            ),
+            newline: (
+                settings.get_string("text_view/whitespace_symbols/newline"),
+                settings.get_color("theme/text_view/whitespace/newline_color"),
+            ),
+            indent_guide: (
+                settings.get_string("text_view/whitespace_symbols/indent_guide"),
+                settings.get_color("theme/text_view/whitespace/indent_guide_color"),
+            ),
+        }
+    }
+
+    pub fn mode(&self) -> WhitespaceMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: WhitespaceMode) {
+        self.mode = mode;
+    }
+
+    pub fn indent_guides(&self) -> bool {
+        self.indent_guides
+    }
+
+    pub fn symbol(&self, kind: WhitespaceKind) -> (&str, Color) {
+        let (ref symbol, color) = match kind {
+            WhitespaceKind::Space => &self.space,
+            WhitespaceKind::Tab => &self.tab,
+            WhitespaceKind::Nbsp => &self.nbsp,
+            WhitespaceKind::Cr => &self.cr,
+            WhitespaceKind::Newline => &self.newline,
+        };
+        (symbol.as_str(), *color)
+    }
+
+    pub fn indent_guide(&self) -> (&str, Color) {
+        (self.indent_guide.0.as_str(), self.indent_guide.1)
+    }
+
+    /// Whether whitespace at char_idx (within line) is drawn with a symbol.
+    pub fn is_visible(&self, char_idx: usize, trailing_begin: usize) -> bool {
+        match self.mode {
+            WhitespaceMode::None => false,
+            WhitespaceMode::Trailing => char_idx >= trailing_begin,
+            WhitespaceMode::All => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whitespace_kind_test() {
+        assert_eq!(whitespace_kind(" "), Some(WhitespaceKind::Space));
+        assert_eq!(whitespace_kind("\u{a0}"), Some(WhitespaceKind::Nbsp));
+        assert_eq!(whitespace_kind("\r\n"), Some(WhitespaceKind::Cr));
+        assert_eq!(whitespace_kind("a"), None);
+    }
+
+    #[test]
+    fn trailing_whitespace_begin_test() {
+        assert_eq!(trailing_whitespace_begin("ab  \n"), 2);
+        assert_eq!(trailing_whitespace_begin("  ab"), 4);
+        assert_eq!(trailing_whitespace_begin("   \n"), 0);
+    }
+
+    #[test]
+    fn indentation_end_test() {
+        assert_eq!(indentation_end("\t  x\n"), Some(3));
+        assert_eq!(indentation_end("x"), Some(0));
+        assert_eq!(indentation_end("    \n"), None);
+        assert_eq!(indentation_end(""), None);
+    }
+
+    #[test]
+    fn visibility_by_mode() {
+        let mut style = WhitespaceStyle::from_settings(&Settings::load_default());
+        style.set_mode(WhitespaceMode::Trailing);
+        assert!(!style.is_visible(1, 2));
+        assert!(style.is_visible(2, 2));
+        style.set_mode(WhitespaceMode::None);
+        assert!(!style.is_visible(2, 2));
+        style.set_mode(WhitespaceMode::All);
+        assert!(style.is_visible(1, 2));
+    }
+}